@@ -0,0 +1,31 @@
+CREATE TABLE IF NOT EXISTS podcasts (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    feed_url       TEXT NOT NULL UNIQUE,
+    title          TEXT NOT NULL,
+    author         TEXT,
+    description    TEXT,
+    image_url      TEXT,
+    last_refreshed INTEGER,                        -- unix seconds, NULL until first refresh
+    created_at     INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+CREATE TABLE IF NOT EXISTS episodes (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    podcast_id   INTEGER NOT NULL,
+    guid         TEXT NOT NULL,                    -- falls back to the enclosure url when the feed has none
+    title        TEXT NOT NULL,
+    description  TEXT,
+    audio_url    TEXT NOT NULL,
+    published_at INTEGER,                          -- unix seconds
+    duration_ms  INTEGER NOT NULL DEFAULT 0,
+    file_path    TEXT,                             -- nullable, set once downloaded
+    position_ms  INTEGER NOT NULL DEFAULT 0,       -- resume position
+    played       BOOLEAN NOT NULL DEFAULT 0,
+
+    UNIQUE (podcast_id, guid),
+
+    FOREIGN KEY (podcast_id) REFERENCES podcasts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_episodes_podcast_published
+ON episodes(podcast_id, published_at DESC);
@@ -0,0 +1,41 @@
+-- chapters are stored per file, a book spanning many files orders them through book_tracks
+CREATE TABLE IF NOT EXISTS chapters (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    track_id  INTEGER NOT NULL,
+    position  INTEGER NOT NULL,                    -- order within the file (0-based)
+    title     TEXT NOT NULL,
+    start_ms  INTEGER NOT NULL,
+    end_ms    INTEGER NOT NULL,
+
+    UNIQUE (track_id, position),
+
+    FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS books (
+    id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+    title              TEXT NOT NULL,
+    artist_id          INTEGER NOT NULL DEFAULT 1,  -- author/narrator, Unknown Artist by default
+    resume_track_id    INTEGER,                     -- nullable, NULL until first played
+    resume_position_ms INTEGER NOT NULL DEFAULT 0,
+    created_at         INTEGER NOT NULL DEFAULT (unixepoch()),
+    updated_at         INTEGER NOT NULL DEFAULT (unixepoch()),
+
+    CHECK(title != ''),
+
+    FOREIGN KEY (artist_id)       REFERENCES artists(id) ON DELETE RESTRICT,
+    FOREIGN KEY (resume_track_id) REFERENCES tracks(id)  ON DELETE SET NULL
+);
+
+CREATE TABLE IF NOT EXISTS book_tracks (
+    book_id   INTEGER NOT NULL,
+    track_id  INTEGER NOT NULL,
+    position  INTEGER NOT NULL,                    -- file order within the book (0-based)
+
+    PRIMARY KEY (book_id, track_id),
+    UNIQUE (book_id, position),
+    UNIQUE (track_id),                             -- a file belongs to at most one book
+
+    FOREIGN KEY (book_id)  REFERENCES books(id)  ON DELETE CASCADE,
+    FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- one row per completed play, whichever client reported it
+CREATE TABLE IF NOT EXISTS play_history (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    track_id  INTEGER NOT NULL,
+    played_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    source    TEXT    NOT NULL DEFAULT 'app',    -- 'app', 'subsonic', ...
+
+    FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_play_history_track ON play_history(track_id);
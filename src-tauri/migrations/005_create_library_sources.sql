@@ -0,0 +1,32 @@
+-- where library rows come from. Row 1 is the local music folder and always exists.
+CREATE TABLE IF NOT EXISTS library_sources (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind          TEXT    NOT NULL,                 -- 'local' or 'subsonic'
+    name          TEXT    NOT NULL,
+    base_url      TEXT,                             -- remote sources only
+    username      TEXT,
+    password      TEXT,                             -- kept as-is, token auth needs it
+    cache_enabled BOOLEAN NOT NULL DEFAULT 0,       -- keep streamed files for offline use
+    last_synced   INTEGER,                          -- nullable, NULL until first sync
+    created_at    INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+INSERT OR IGNORE INTO library_sources (id, kind, name)
+VALUES (1, 'local', 'Local files');
+
+CREATE TRIGGER IF NOT EXISTS prevent_local_source_delete
+BEFORE DELETE ON library_sources
+WHEN OLD.id = 1
+BEGIN
+    SELECT RAISE(ABORT, 'Cannot delete the local library source');
+END;
+
+-- existing rows are all local. No REFERENCES clause: sqlite can't add a foreign key
+-- column with a non-NULL default, removing a source cleans up in code instead.
+-- Artists are shared between sources by name, theirs is the source that introduced them.
+ALTER TABLE artists ADD COLUMN source_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE albums  ADD COLUMN source_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE tracks  ADD COLUMN source_id INTEGER NOT NULL DEFAULT 1;
+
+CREATE INDEX IF NOT EXISTS idx_albums_source ON albums(source_id);
+CREATE INDEX IF NOT EXISTS idx_tracks_source ON tracks(source_id);
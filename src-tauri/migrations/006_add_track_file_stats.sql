@@ -0,0 +1,5 @@
+-- modification time (unix seconds) and exact size of the file when it was last
+-- read, so library scans can skip files that haven't changed.
+-- NULL for rows added before this and for remote tracks.
+ALTER TABLE tracks ADD COLUMN file_mtime INTEGER;
+ALTER TABLE tracks ADD COLUMN file_bytes INTEGER;
@@ -0,0 +1,6 @@
+-- unix seconds when a track's file disappeared from disk, NULL while it's there.
+-- Missing tracks keep their row, and with it their playlists and history, so they
+-- can be relinked once the file turns up again.
+ALTER TABLE tracks ADD COLUMN missing_since INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_tracks_missing ON tracks(missing_since) WHERE missing_since IS NOT NULL;
@@ -0,0 +1,5 @@
+-- md5 over the size and the first and last 64 KiB of the file, see utils::content_hash.
+-- Cheap enough to take on every import and enough to recognise a file after a move.
+ALTER TABLE tracks ADD COLUMN content_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_tracks_content_hash ON tracks(content_hash);
@@ -0,0 +1,113 @@
+-- Full-text index for search_library. Each table's rowid is the id of the row it
+-- indexes, and triggers keep it in step. remove_diacritics lets "beyonce" match
+-- "Beyoncé", the prefix indexes keep "bey*" queries cheap.
+CREATE VIRTUAL TABLE IF NOT EXISTS tracks_fts USING fts5(
+    title, artist, album,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS albums_fts USING fts5(
+    title, artist,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS artists_fts USING fts5(
+    name,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS playlists_fts USING fts5(
+    name, description,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+-- tracks
+CREATE TRIGGER IF NOT EXISTS tracks_fts_insert AFTER INSERT ON tracks BEGIN
+    INSERT INTO tracks_fts (rowid, title, artist, album)
+    VALUES (
+        NEW.id,
+        NEW.title,
+        (SELECT name FROM artists WHERE id = NEW.artist_id),
+        (SELECT title FROM albums WHERE id = NEW.album_id)
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS tracks_fts_update AFTER UPDATE OF title, artist_id, album_id ON tracks BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.id;
+    INSERT INTO tracks_fts (rowid, title, artist, album)
+    VALUES (
+        NEW.id,
+        NEW.title,
+        (SELECT name FROM artists WHERE id = NEW.artist_id),
+        (SELECT title FROM albums WHERE id = NEW.album_id)
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS tracks_fts_delete AFTER DELETE ON tracks BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.id;
+END;
+
+-- albums, whose artist name also shows up in their tracks' rows
+CREATE TRIGGER IF NOT EXISTS albums_fts_insert AFTER INSERT ON albums BEGIN
+    INSERT INTO albums_fts (rowid, title, artist)
+    VALUES (NEW.id, NEW.title, (SELECT name FROM artists WHERE id = NEW.artist_id));
+END;
+
+CREATE TRIGGER IF NOT EXISTS albums_fts_update AFTER UPDATE OF title, artist_id ON albums BEGIN
+    DELETE FROM albums_fts WHERE rowid = OLD.id;
+    INSERT INTO albums_fts (rowid, title, artist)
+    VALUES (NEW.id, NEW.title, (SELECT name FROM artists WHERE id = NEW.artist_id));
+    UPDATE tracks_fts SET album = NEW.title WHERE rowid IN (SELECT id FROM tracks WHERE album_id = NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS albums_fts_delete AFTER DELETE ON albums BEGIN
+    DELETE FROM albums_fts WHERE rowid = OLD.id;
+END;
+
+-- artists
+CREATE TRIGGER IF NOT EXISTS artists_fts_insert AFTER INSERT ON artists BEGIN
+    INSERT INTO artists_fts (rowid, name) VALUES (NEW.id, NEW.name);
+END;
+
+CREATE TRIGGER IF NOT EXISTS artists_fts_update AFTER UPDATE OF name ON artists BEGIN
+    DELETE FROM artists_fts WHERE rowid = OLD.id;
+    INSERT INTO artists_fts (rowid, name) VALUES (NEW.id, NEW.name);
+    UPDATE albums_fts SET artist = NEW.name WHERE rowid IN (SELECT id FROM albums WHERE artist_id = NEW.id);
+    UPDATE tracks_fts SET artist = NEW.name WHERE rowid IN (SELECT id FROM tracks WHERE artist_id = NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS artists_fts_delete AFTER DELETE ON artists BEGIN
+    DELETE FROM artists_fts WHERE rowid = OLD.id;
+END;
+
+-- playlists
+CREATE TRIGGER IF NOT EXISTS playlists_fts_insert AFTER INSERT ON playlists BEGIN
+    INSERT INTO playlists_fts (rowid, name, description) VALUES (NEW.id, NEW.name, NEW.description);
+END;
+
+CREATE TRIGGER IF NOT EXISTS playlists_fts_update AFTER UPDATE OF name, description ON playlists BEGIN
+    DELETE FROM playlists_fts WHERE rowid = OLD.id;
+    INSERT INTO playlists_fts (rowid, name, description) VALUES (NEW.id, NEW.name, NEW.description);
+END;
+
+CREATE TRIGGER IF NOT EXISTS playlists_fts_delete AFTER DELETE ON playlists BEGIN
+    DELETE FROM playlists_fts WHERE rowid = OLD.id;
+END;
+
+-- what's already in the library
+INSERT INTO tracks_fts (rowid, title, artist, album)
+SELECT t.id, t.title, a.name, al.title
+FROM tracks t
+LEFT JOIN artists a ON a.id = t.artist_id
+LEFT JOIN albums al ON al.id = t.album_id;
+
+INSERT INTO albums_fts (rowid, title, artist)
+SELECT al.id, al.title, a.name FROM albums al LEFT JOIN artists a ON a.id = al.artist_id;
+
+INSERT INTO artists_fts (rowid, name) SELECT id, name FROM artists;
+
+INSERT INTO playlists_fts (rowid, name, description) SELECT id, name, description FROM playlists;
@@ -0,0 +1,4 @@
+-- Rule set of a smart playlist as JSON (see SmartPlaylistRules), NULL for a plain
+-- playlist. Smart playlists keep their current contents in playlist_tracks like
+-- any other, refresh_smart_playlist rewrites them from the rules.
+ALTER TABLE playlists ADD COLUMN rules TEXT;
@@ -0,0 +1,65 @@
+-- Tags beyond title/artist/album. release_date is the tag's date as written
+-- ("2004", "2004-05-12"), year is its year on its own for sorting and filtering.
+ALTER TABLE tracks ADD COLUMN track_number INTEGER;
+ALTER TABLE tracks ADD COLUMN track_total INTEGER;
+ALTER TABLE tracks ADD COLUMN disc_number INTEGER;
+ALTER TABLE tracks ADD COLUMN disc_total INTEGER;
+ALTER TABLE tracks ADD COLUMN year INTEGER;
+ALTER TABLE tracks ADD COLUMN release_date TEXT;
+ALTER TABLE tracks ADD COLUMN genre TEXT;
+ALTER TABLE tracks ADD COLUMN album_artist TEXT;
+ALTER TABLE tracks ADD COLUMN composer TEXT;
+ALTER TABLE tracks ADD COLUMN bpm REAL;
+ALTER TABLE tracks ADD COLUMN comment TEXT;
+
+-- Which version of the tag reader last read the file, see tag_reader::TAG_VERSION.
+-- Rows behind it get their files re-read in the background.
+ALTER TABLE tracks ADD COLUMN tag_version INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_tracks_album_order ON tracks(album_id, disc_number, track_number);
+CREATE INDEX IF NOT EXISTS idx_tracks_genre ON tracks(genre COLLATE NOCASE);
+
+-- search covers genre too from now on
+DROP TRIGGER IF EXISTS tracks_fts_insert;
+DROP TRIGGER IF EXISTS tracks_fts_update;
+DROP TRIGGER IF EXISTS tracks_fts_delete;
+DROP TABLE IF EXISTS tracks_fts;
+
+CREATE VIRTUAL TABLE tracks_fts USING fts5(
+    title, artist, album, genre,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE TRIGGER tracks_fts_insert AFTER INSERT ON tracks BEGIN
+    INSERT INTO tracks_fts (rowid, title, artist, album, genre)
+    VALUES (
+        NEW.id,
+        NEW.title,
+        (SELECT name FROM artists WHERE id = NEW.artist_id),
+        (SELECT title FROM albums WHERE id = NEW.album_id),
+        NEW.genre
+    );
+END;
+
+CREATE TRIGGER tracks_fts_update AFTER UPDATE OF title, artist_id, album_id, genre ON tracks BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.id;
+    INSERT INTO tracks_fts (rowid, title, artist, album, genre)
+    VALUES (
+        NEW.id,
+        NEW.title,
+        (SELECT name FROM artists WHERE id = NEW.artist_id),
+        (SELECT title FROM albums WHERE id = NEW.album_id),
+        NEW.genre
+    );
+END;
+
+CREATE TRIGGER tracks_fts_delete AFTER DELETE ON tracks BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.id;
+END;
+
+INSERT INTO tracks_fts (rowid, title, artist, album, genre)
+SELECT t.id, t.title, a.name, al.title, t.genre
+FROM tracks t
+LEFT JOIN artists a ON a.id = t.artist_id
+LEFT JOIN albums al ON al.id = t.album_id;
@@ -0,0 +1,8 @@
+-- The compilation flag (TCMP / cpil) as tagged on the file, and on the album
+-- when any of its tracks has it or it was grouped under Various Artists.
+-- Albums are keyed by album artist from here on, the tag backfill moves
+-- existing tracks over.
+ALTER TABLE tracks ADD COLUMN compilation INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE albums ADD COLUMN compilation INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_albums_title_artist ON albums(title, artist_id);
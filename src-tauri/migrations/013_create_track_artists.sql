@@ -0,0 +1,17 @@
+-- Every artist on a track and what they did on it. tracks.artist_id stays the
+-- artist as tagged, it's also in here as the first primary artist.
+CREATE TABLE IF NOT EXISTS track_artists (
+    track_id  INTEGER NOT NULL,
+    artist_id INTEGER NOT NULL,
+    role      TEXT NOT NULL DEFAULT 'primary', -- primary, featured, remixer, composer
+    position  INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (track_id, artist_id, role),
+    FOREIGN KEY (track_id)  REFERENCES tracks(id)  ON DELETE CASCADE,
+    FOREIGN KEY (artist_id) REFERENCES artists(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_track_artists_artist ON track_artists(artist_id, role);
+
+-- the tag backfill fills in the rest
+INSERT OR IGNORE INTO track_artists (track_id, artist_id, role, position)
+SELECT id, artist_id, 'primary', 0 FROM tracks WHERE artist_id != 1;
@@ -0,0 +1,23 @@
+-- Cover art, stored once per distinct picture instead of as base64 on every
+-- track. Thumbnails are JPEG, NULL when the original is already that small or
+-- couldn't be decoded; the original is served in their place.
+CREATE TABLE IF NOT EXISTS artwork (
+    id     INTEGER PRIMARY KEY AUTOINCREMENT,
+    hash   TEXT NOT NULL UNIQUE, -- md5 of the original
+    mime   TEXT NOT NULL,
+    width  INTEGER,
+    height INTEGER,
+    data   BLOB NOT NULL,
+    small  BLOB, -- 64px
+    medium BLOB  -- 256px
+);
+
+ALTER TABLE tracks ADD COLUMN artwork_id INTEGER REFERENCES artwork(id) ON DELETE SET NULL;
+ALTER TABLE albums ADD COLUMN artwork_id INTEGER REFERENCES artwork(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_tracks_artwork ON tracks(artwork_id);
+CREATE INDEX IF NOT EXISTS idx_albums_artwork ON albums(artwork_id);
+
+-- thumbnail_base64 and thumbnail_mime are no longer written. What's in them is
+-- moved into artwork in the background on the next start, see
+-- utils::artwork::migrate_thumbnails, and then cleared.
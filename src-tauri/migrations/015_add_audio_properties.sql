@@ -0,0 +1,6 @@
+-- Stream properties, for telling the better of two copies of a track apart.
+-- bitrate is in kbps, all three are NULL where the format doesn't say. The
+-- tag backfill fills them in for existing rows.
+ALTER TABLE tracks ADD COLUMN bitrate INTEGER;
+ALTER TABLE tracks ADD COLUMN sample_rate INTEGER;
+ALTER TABLE tracks ADD COLUMN bit_depth INTEGER;
@@ -0,0 +1,10 @@
+-- Acoustic fingerprints (Chromaprint values as little-endian u32s), computed
+-- in the background. Kept out of tracks so the many `SELECT t.*` queries don't
+-- carry a few KB per row. A NULL fingerprint means the file couldn't be
+-- decoded, the row is there so it isn't tried again until the file changes.
+CREATE TABLE IF NOT EXISTS track_fingerprints (
+    track_id    INTEGER PRIMARY KEY,
+    fingerprint BLOB,
+    created_at  INTEGER NOT NULL DEFAULT (unixepoch()),
+    FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE
+);
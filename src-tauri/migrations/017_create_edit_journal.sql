@@ -0,0 +1,10 @@
+-- Tag edits made in the app, one row per edit or batch, so they can be undone.
+-- entries is a JSON array of {track_id, changes: [{field, old, new}]} with the
+-- values as the editor shows them.
+CREATE TABLE IF NOT EXISTS edit_journal (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    description TEXT NOT NULL,
+    entries     TEXT NOT NULL,
+    created_at  INTEGER NOT NULL DEFAULT (unixepoch()),
+    undone_at   INTEGER
+);
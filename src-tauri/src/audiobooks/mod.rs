@@ -0,0 +1,296 @@
+use tauri::State;
+
+use crate::{
+    db::UNKNOWN_ARTIST_ID,
+    models::{AppState as Database, Book, BookChapter},
+    player::{AudioPlayer, ChapterMark},
+};
+
+// Book rows with the aggregates the library view needs
+const BOOK_SELECT: &str = r#"
+    SELECT
+        b.*,
+        a.name                           AS artist_name,
+        COUNT(bt.track_id)               AS track_count,
+        COALESCE(SUM(t.duration_ms), 0)  AS duration_ms
+    FROM books b
+    LEFT JOIN artists     a  ON b.artist_id = a.id
+    LEFT JOIN book_tracks bt ON bt.book_id  = b.id
+    LEFT JOIN tracks      t  ON bt.track_id = t.id
+"#;
+
+#[allow(dead_code)]
+impl Database {
+    // book queries
+    pub async fn get_books(&self) -> Result<Vec<Book>, String> {
+        sqlx::query_as::<_, Book>(&format!(
+            "{} GROUP BY b.id ORDER BY b.title COLLATE NOCASE",
+            BOOK_SELECT
+        ))
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_book(&self, id: i64) -> Result<Book, String> {
+        sqlx::query_as::<_, Book>(&format!("{} WHERE b.id = ? GROUP BY b.id", BOOK_SELECT))
+            .bind(id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Groups the given files, in order, into one book. Files already in another
+    // book are moved over, since a file can only belong to one.
+    pub async fn create_book(
+        &self,
+        title: String,
+        artist_id: i64,
+        track_ids: Vec<i64>,
+    ) -> Result<i64, String> {
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let book_id: i64 =
+            sqlx::query_scalar("INSERT INTO books (title, artist_id) VALUES (?, ?) RETURNING id")
+                .bind(title.trim())
+                .bind(artist_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to create book: {}", e))?;
+
+        for (position, track_id) in track_ids.iter().enumerate() {
+            sqlx::query("DELETE FROM book_tracks WHERE track_id = ?")
+                .bind(track_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+
+            sqlx::query("INSERT INTO book_tracks (book_id, track_id, position) VALUES (?, ?, ?)")
+                .bind(book_id)
+                .bind(track_id)
+                .bind(position as i64)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to insert book track at position {}: {}", position, e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))?;
+
+        // rows imported before chapters were stored get them read now
+        for track_id in &track_ids {
+            if self.get_chapters(*track_id).await?.is_empty() {
+                let _ = self.rescan_chapters(*track_id).await;
+            }
+        }
+
+        Ok(book_id)
+    }
+
+    // Most multi-file books are ripped as one album, one file per part
+    pub async fn create_book_from_album(&self, album_id: i64) -> Result<i64, String> {
+        let (title, artist_id): (String, i64) =
+            sqlx::query_as("SELECT title, artist_id FROM albums WHERE id = ?")
+                .bind(album_id)
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+
+        let track_ids: Vec<i64> =
+            sqlx::query_scalar("SELECT id FROM tracks WHERE album_id = ? ORDER BY file_path")
+                .bind(album_id)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+
+        if track_ids.is_empty() {
+            return Err("Album has no tracks".to_string());
+        }
+
+        self.create_book(title, artist_id, track_ids).await
+    }
+
+    pub async fn delete_book(&self, id: i64) -> Result<(), String> {
+        // ON DELETE CASCADE handles book_tracks, the tracks themselves stay in the library
+        sqlx::query("DELETE FROM books WHERE id = ?")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to delete book: {}", e))?;
+
+        Ok(())
+    }
+
+    pub async fn get_book_chapters(&self, book_id: i64) -> Result<Vec<BookChapter>, String> {
+        sqlx::query_as::<_, BookChapter>(
+            r#"
+            SELECT
+                t.id        AS track_id,
+                bt.position AS track_position,
+                t.file_path,
+                c.position,
+                c.title,
+                c.start_ms,
+                c.end_ms
+            FROM book_tracks bt
+            JOIN tracks   t ON bt.track_id = t.id
+            JOIN chapters c ON c.track_id  = t.id
+            WHERE bt.book_id = ?
+
+            UNION ALL
+
+            -- files without embedded chapters count as one chapter each
+            SELECT
+                t.id,
+                bt.position,
+                t.file_path,
+                0,
+                t.title,
+                0,
+                t.duration_ms
+            FROM book_tracks bt
+            JOIN tracks t ON bt.track_id = t.id
+            WHERE bt.book_id = ?
+              AND NOT EXISTS (SELECT 1 FROM chapters c WHERE c.track_id = t.id)
+
+            ORDER BY track_position, position
+            "#,
+        )
+        .bind(book_id)
+        .bind(book_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn save_book_position(
+        &self,
+        book_id: i64,
+        track_id: i64,
+        position_ms: i64,
+    ) -> Result<(), String> {
+        sqlx::query(
+            "UPDATE books
+            SET resume_track_id = ?, resume_position_ms = ?, updated_at = unixepoch()
+            WHERE id = ?",
+        )
+        .bind(track_id)
+        .bind(position_ms.max(0))
+        .bind(book_id)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to save book position: {}", e))?;
+
+        Ok(())
+    }
+}
+
+fn to_chapter_marks(chapters: Vec<BookChapter>) -> Vec<ChapterMark> {
+    chapters
+        .into_iter()
+        .map(|c| ChapterMark {
+            track_id: c.track_id,
+            title: c.title,
+            file_path: c.file_path,
+            start_ms: c.start_ms,
+            end_ms: c.end_ms,
+        })
+        .collect()
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_books(state: State<'_, Database>) -> Result<Vec<Book>, String> {
+    state.get_books().await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_book(state: State<'_, Database>, id: i64) -> Result<Book, String> {
+    state.get_book(id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_book_chapters(
+    state: State<'_, Database>,
+    book_id: i64,
+) -> Result<Vec<BookChapter>, String> {
+    state.get_book_chapters(book_id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn create_book(
+    state: State<'_, Database>,
+    title: String,
+    artist_id: Option<i64>,
+    track_ids: Vec<i64>,
+) -> Result<i64, String> {
+    state.create_book(title, artist_id.unwrap_or(UNKNOWN_ARTIST_ID), track_ids).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn create_book_from_album(
+    state: State<'_, Database>,
+    album_id: i64,
+) -> Result<i64, String> {
+    state.create_book_from_album(album_id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn delete_book(state: State<'_, Database>, id: i64) -> Result<(), String> {
+    state.delete_book(id).await
+}
+
+// Loads the book's chapters into the player and picks up where the listener left off
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn play_book(
+    state: State<'_, Database>,
+    player: State<'_, AudioPlayer>,
+    book_id: i64,
+) -> Result<ChapterMark, String> {
+    let book = state.get_book(book_id).await?;
+    let marks = to_chapter_marks(state.get_book_chapters(book_id).await?);
+    if marks.is_empty() {
+        return Err("Book has no files".to_string());
+    }
+
+    let resume = book
+        .resume_track_id
+        .and_then(|id| marks.iter().find(|m| m.track_id == id))
+        .map(|m| (m.file_path.clone(), book.resume_position_ms))
+        .unwrap_or_else(|| (marks[0].file_path.clone(), 0));
+
+    player.leave_queue();
+    player.set_chapters(marks);
+    player.play(resume.0)?;
+    player.seek(resume.1 as f32 / 1000.0);
+
+    player
+        .current_chapter()
+        .ok_or_else(|| "Failed to resolve the current chapter".to_string())
+}
+
+// Called by the frontend on pause/close and periodically while a book is playing
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn save_book_progress(
+    state: State<'_, Database>,
+    player: State<'_, AudioPlayer>,
+    book_id: i64,
+) -> Result<(), String> {
+    let chapter = player
+        .current_chapter()
+        .ok_or_else(|| "No book is playing".to_string())?;
+    let position_ms = (player.get_position_secs() as f64 * 1000.0) as i64;
+
+    state.save_book_position(book_id, chapter.track_id, position_ms).await
+}
@@ -1,31 +1,84 @@
-use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+
+use sqlx::{pool::PoolConnection, Row, Sqlite, SqliteConnection};
 
 use crate::{
-    models::{Album, AppState as Database, Artist, ExtractedTrack, Track, Playlist, PlaylistPreview },
-    utils::current_date_as_int,
+    models::{Album, AlbumDetail, AlbumOverview, AppState as Database, Artist, ArtistDetail, Chapter, ReleaseGroup, ReleaseType, ArtistRole, Artwork, ArtworkImage, ArtworkSize, CreditedTrack, DuplicateCopy, EditJournalSummary, ExtractedChapter, ExtractedTrack, KnownFile, JournalEntry, LibraryFile, Track, Playlist, PlaylistPreview, QueueItem, SearchResults, SmartField, SmartMatch, SmartOp, SmartPlaylistRules, SmartRule, SmartSort, FilterOp, TrackCredit, TrackFilter, TrackListQuery, TrackPage, TrackSort, SourceConfig, SyncSummary },
+    player::MediaSource,
+    utils::{artwork::{make_thumbnails, prepare_base64_artwork}, chapter_reader::read_chapters, current_date_as_int, tag_reader::TAG_VERSION},
 };
 
-const UNKNOWN_ARTIST_ID: i64 = 1;
-const UNKNOWN_ALBUM_ID: i64 = 1;
+pub const UNKNOWN_ARTIST_ID: i64 = 1;
+pub const UNKNOWN_ALBUM_ID: i64 = 1;
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+pub const LOCAL_SOURCE_ID: i64 = 1;
+
+// What a source currently holds: every path it has, plus full metadata for the
+// paths the database didn't know about yet
+pub struct SourceListing {
+    pub paths: Vec<String>,
+    pub new_tracks: Vec<ExtractedTrack>,
+}
+
+// Where library rows come from. Local files are read straight off disk, remote
+// servers are listed over their API and their tracks streamed when played.
+pub trait LibrarySource {
+    fn source_id(&self) -> i64;
+
+    fn is_remote(&self) -> bool {
+        self.source_id() != LOCAL_SOURCE_ID
+    }
+
+    // `known` are the paths already in the database, only new ones need metadata
+    fn list(&self, known: &HashSet<String>) -> impl Future<Output = Result<SourceListing, String>> + Send;
+
+    // what the player should open for one of this source's file paths
+    fn resolve(&self, file_path: &str) -> Result<MediaSource, String>;
+}
 
 #[allow(dead_code)]
 impl Database {
+    async fn connection(&self) -> Result<PoolConnection<Sqlite>, String> {
+        self.db.acquire().await.map_err(|e| format!("Database error: {}", e))
+    }
+
     // artist queries
     pub async fn get_artists(&self) -> Result<Vec<Artist>, String> {
-        sqlx::query_as::<_, Artist>("SELECT id, name FROM artists ORDER BY name")
+        sqlx::query_as::<_, Artist>("SELECT id, name, source_id FROM artists ORDER BY name")
             .fetch_all(&self.db)
             .await
             .map_err(|e| format!("Database error: {}", e))
     }
 
     pub async fn get_artist(&self, name: String) -> Result<Artist, String> {
-        sqlx::query_as::<_, Artist>("SELECT id, name FROM artists WHERE name = ?")
+        sqlx::query_as::<_, Artist>("SELECT id, name, source_id FROM artists WHERE name = ?")
             .bind(name)
             .fetch_one(&self.db)
             .await
             .map_err(|e| format!("Database error: {}", e))
     }
 
+    // every track the artist is on, not just the ones filed under them
+    pub async fn get_artist_tracks(&self, artist_id: i64) -> Result<Vec<CreditedTrack>, String> {
+        sqlx::query_as::<_, CreditedTrack>(
+            // an artist with several roles on a track shows up once, under the first one
+            "SELECT t.*, a.name AS artist_name, al.title AS album_name, ta.role, MIN(ta.position) AS position
+            FROM track_artists ta
+            JOIN tracks t ON t.id = ta.track_id
+            LEFT JOIN artists a  ON a.id  = t.artist_id
+            LEFT JOIN albums  al ON al.id = t.album_id
+            WHERE ta.artist_id = ?
+            GROUP BY t.id
+            ORDER BY al.title COLLATE NOCASE, COALESCE(t.disc_number, 1), t.track_number, t.title COLLATE NOCASE",
+        )
+        .bind(artist_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
     pub async fn add_artist(&self, name: String) -> Result<i64, String> {
         let name = name.trim().to_string();
 
@@ -67,41 +120,97 @@ impl Database {
     }
 
     pub async fn find_or_create_artist(&self, name: &str) -> Result<i64, String> {
-        let trimmed = name.trim();
-        if trimmed.is_empty() {
-            return Ok(UNKNOWN_ARTIST_ID);
-        }
-
-        // Try to find existing artist
-        let maybe_id = sqlx::query_scalar!(
-            // Add 'as "id!"' to force non-nullable i64
-            "SELECT id as \"id!\" FROM artists WHERE name = ? COLLATE NOCASE",
-            trimmed
-        )
-        .fetch_optional(&self.db)
-        .await
-        .map_err(|e| format!("Artist lookup failed: {}", e))?;
+        let mut conn = self.connection().await?;
+        find_or_create_artist_in(&mut conn, name).await
+    }
 
-        if let Some(id) = maybe_id {
-            return Ok(id);
+    pub async fn get_artist_detail(&self, id: i64) -> Result<ArtistDetail, String> {
+        let artist = sqlx::query_as::<_, Artist>("SELECT id, name, source_id FROM artists WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .ok_or_else(|| format!("Artist {} not found", id))?;
+
+        let mut releases: Vec<ReleaseGroup> = Vec::new();
+        for album in self.get_album_overviews("al.artist_id = ?1", id).await? {
+            match releases.iter_mut().find(|group| group.release_type == album.release_type) {
+                Some(group) => group.albums.push(album),
+                None => releases.push(ReleaseGroup {
+                    release_type: album.release_type,
+                    albums: vec![album],
+                }),
+            }
         }
-
-        // Create new
-        let id = sqlx::query_scalar!(
-            "INSERT INTO artists (name) VALUES (?) RETURNING id",
-            trimmed
+        releases.sort_by_key(|group| group.release_type);
+
+        let appearances = self
+            .get_album_overviews(
+                "al.compilation = 1 AND al.artist_id != ?1 AND al.id IN (
+                    SELECT t.album_id FROM track_artists ta JOIN tracks t ON t.id = ta.track_id
+                    WHERE ta.artist_id = ?1 AND ta.role IN ('primary', 'featured')
+                )",
+                id,
+            )
+            .await?;
+
+        let (track_count, play_count, listening_time_ms) = sqlx::query_as::<_, (i64, i64, i64)>(
+            "SELECT
+                COUNT(*),
+                COALESCE(SUM((SELECT COUNT(*) FROM play_history WHERE track_id = t.id)), 0),
+                COALESCE(SUM((SELECT COUNT(*) FROM play_history WHERE track_id = t.id) * t.duration_ms), 0)
+            FROM tracks t
+            WHERE t.id IN (
+                SELECT track_id FROM track_artists WHERE artist_id = ? AND role IN ('primary', 'featured')
+            )",
         )
+        .bind(id)
         .fetch_one(&self.db)
         .await
-        .map_err(|e| format!("Artist creation failed: {}", e))?;
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(ArtistDetail {
+            artist,
+            releases,
+            appearances,
+            track_count,
+            play_count,
+            listening_time_ms,
+        })
+    }
 
-        Ok(id)
+    // Albums with tracks matching `condition` on `al`, which binds the artist
+    // id as ?1. Albums without a picture of their own show their first track's.
+    async fn get_album_overviews(&self, condition: &str, artist_id: i64) -> Result<Vec<AlbumOverview>, String> {
+        let sql = format!(
+            "SELECT al.id, al.title, al.artist_id, al.cover_path, al.source_id, al.compilation,
+                COALESCE(al.artwork_id, MIN(t.artwork_id)) AS artwork_id,
+                MIN(t.year) AS year,
+                COUNT(*) AS track_count,
+                SUM(t.duration_ms) AS duration_ms
+            FROM albums al
+            JOIN tracks t ON t.album_id = al.id
+            WHERE {}
+            GROUP BY al.id
+            ORDER BY year DESC, al.title COLLATE NOCASE",
+            condition
+        );
+
+        let mut albums = sqlx::query_as::<_, AlbumOverview>(&sql)
+            .bind(artist_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        for album in &mut albums {
+            album.release_type = ReleaseType::classify(album.album.compilation, album.track_count, album.duration_ms);
+        }
+        Ok(albums)
     }
 
     // album queries
     pub async fn get_albums(&self) -> Result<Vec<Album>, String> {
         sqlx::query_as::<_, Album>(
-            "SELECT id, title, artist_id, cover_path FROM albums ORDER BY title",
+            "SELECT id, title, artist_id, cover_path, source_id, compilation, artwork_id FROM albums ORDER BY title",
         )
         .fetch_all(&self.db)
         .await
@@ -110,7 +219,7 @@ impl Database {
 
     pub async fn get_album(&self, title: String) -> Result<Album, String> {
         sqlx::query_as::<_, Album>(
-            "SELECT id, title, artist_id, cover_path FROM albums WHERE title = ?",
+            "SELECT id, title, artist_id, cover_path, source_id, compilation, artwork_id FROM albums WHERE title = ?",
         )
         .bind(title)
         .fetch_one(&self.db)
@@ -118,6 +227,58 @@ impl Database {
         .map_err(|e| format!("Database error: {}", e))
     }
 
+    pub async fn get_album_detail(&self, id: i64) -> Result<AlbumDetail, String> {
+        let mut album = sqlx::query_as::<_, Album>(
+            "SELECT id, title, artist_id, cover_path, source_id, compilation, artwork_id FROM albums WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| format!("Album {} not found", id))?;
+
+        let artist_name = sqlx::query_scalar::<_, String>("SELECT name FROM artists WHERE id = ?")
+            .bind(album.artist_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .unwrap_or_default();
+
+        let tracks = sqlx::query_as::<_, Track>(
+            "SELECT t.*, a.name AS artist_name, al.title AS album_name
+            FROM tracks t
+            LEFT JOIN artists a  ON a.id  = t.artist_id
+            LEFT JOIN albums  al ON al.id = t.album_id
+            WHERE t.album_id = ?
+            ORDER BY COALESCE(t.disc_number, 1), t.track_number, t.title COLLATE NOCASE",
+        )
+        .bind(id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        let track_count = tracks.len() as i64;
+        let duration_ms = tracks.iter().map(|track| track.duration_ms).sum();
+        let year = tracks.iter().filter_map(|track| track.tags.year).min();
+        let mut formats: Vec<String> = tracks.iter().map(|track| track.file_format.to_lowercase()).collect();
+        formats.sort();
+        formats.dedup();
+        if album.artwork_id.is_none() {
+            album.artwork_id = tracks.iter().find_map(|track| track.artwork_id);
+        }
+
+        Ok(AlbumDetail {
+            release_type: ReleaseType::classify(album.compilation, track_count, duration_ms),
+            album,
+            artist_name,
+            tracks,
+            track_count,
+            duration_ms,
+            year,
+            formats,
+        })
+    }
+
     pub async fn add_album(
         &self,
         title: String,
@@ -169,36 +330,18 @@ impl Database {
     }
 
     pub async fn find_or_create_album(&self, title: &str, artist_id: i64) -> Result<i64, String> {
-        let trimmed = title.trim();
-        if trimmed.is_empty() {
-            return Ok(UNKNOWN_ALBUM_ID);
-        }
-
-        // Find existing album by title + artist
-        if let Some(id) = sqlx::query_scalar!(
-            "SELECT id FROM albums WHERE title = ? AND artist_id = ?",
-            trimmed,
-            artist_id
-        )
-        .fetch_optional(&self.db)
-        .await
-        .map_err(|e| format!("Album lookup failed: {}", e))?
-        {
-            return Ok(id);
-        }
-
-        // Create new
-        let id = sqlx::query_scalar!(
-            "INSERT INTO albums (title, artist_id) VALUES (?, ?) RETURNING id",
-            trimmed,
-            artist_id
-        )
-        .fetch_one(&self.db)
-        .await
-        .map_err(|e| format!("Album creation failed: {}", e))?
-        .ok_or_else(|| "Failed to get inserted album ID".to_string())?;
+        self.find_or_create_source_album(title, artist_id, LOCAL_SOURCE_ID).await
+    }
 
-        Ok(id)
+    // a remote copy of an album is its own row, so local and remote files never mix
+    pub async fn find_or_create_source_album(
+        &self,
+        title: &str,
+        artist_id: i64,
+        source_id: i64,
+    ) -> Result<i64, String> {
+        let mut conn = self.connection().await?;
+        find_or_create_album_in(&mut conn, title, artist_id, source_id).await
     }
 
     // track queries
@@ -207,7 +350,7 @@ impl Database {
 
     //
     pub async fn get_tracks(&self) -> Result<Vec<Track>, String> {
-        sqlx::query_as::<_, Track>("SELECT id, file_path, title, artist_id, album_id, duration_ms, file_format, file_size, date_added, thumbnail_base64, thumbnail_mime FROM tracks ORDER BY title")
+        sqlx::query_as::<_, Track>("SELECT id, file_path, title, artist_id, album_id, duration_ms, file_format, file_size, date_added, artwork_id, source_id, missing_since, track_number, track_total, disc_number, disc_total, year, release_date, genre, album_artist, composer, bpm, comment, compilation, bitrate, sample_rate, bit_depth FROM tracks ORDER BY title")
             .fetch_all(&self.db)
             .await
             .map_err(|e| format!("Database error: {}", e))
@@ -231,232 +374,2188 @@ impl Database {
         .map_err(|e| format!("Database error: {}", e))
     }
 
-    // opting to have struct as argument here because of the number of properties
-    pub async fn add_track(&self, track: ExtractedTrack) -> Result<i64, String> {
-        let file_path = track.file_path.clone();
-
-        // check if exists
-        if let Some(existing_id) = self.track_exists(&file_path).await? {
-            return Ok(-existing_id); // negative value indicates dupe
+    // A page of the library for lists too long to load at once. Paging is by
+    // key rather than offset: the sort keys of the `after` track are looked up
+    // and the page starts past them, with the id breaking ties.
+    pub async fn list_tracks(&self, query: &TrackListQuery) -> Result<TrackPage, String> {
+        let mut clauses = Vec::new();
+        let mut values = Vec::new();
+        if let Some(artist_id) = query.artist_id {
+            // like find_tracks, featured artists count too
+            clauses.push(
+                "EXISTS (SELECT 1 FROM track_artists ta WHERE ta.track_id = t.id AND ta.artist_id = ?
+                AND ta.role IN ('primary', 'featured'))",
+            );
+            values.push(RuleValue::Int(artist_id));
         }
+        if let Some(album_id) = query.album_id {
+            clauses.push("t.album_id = ?");
+            values.push(RuleValue::Int(album_id));
+        }
+        if let Some(format) = &query.format {
+            clauses.push("lower(t.file_format) = lower(?)");
+            values.push(RuleValue::Text(format.clone()));
+        }
+        if let Some(from) = query.added_from {
+            clauses.push("t.date_added >= ?");
+            values.push(RuleValue::Int(from));
+        }
+        if let Some(to) = query.added_to {
+            clauses.push("t.date_added <= ?");
+            values.push(RuleValue::Int(to));
+        }
+        let filter = if clauses.is_empty() { "1".to_string() } else { clauses.join(" AND ") };
+
+        let total_sql = format!("SELECT COUNT(*) FROM tracks t WHERE {}", filter);
+        let mut total_query = sqlx::query_scalar::<_, i64>(&total_sql);
+        for value in &values {
+            total_query = match value {
+                RuleValue::Text(text) => total_query.bind(text),
+                RuleValue::Int(number) => total_query.bind(*number),
+            };
+        }
+        let total = total_query
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
 
-        let artist_id = self.find_or_create_artist(&track.artist).await?;
-        let album_id = self.find_or_create_album(&track.album, artist_id).await?;
-
-        // insert
-        let id = sqlx::query("INSERT INTO tracks (file_path, title,
-         artist_id, album_id, duration_ms, file_format, file_size, date_added, thumbnail_base64, thumbnail_mime)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id")
-        .bind(&file_path)
-        .bind(track.title)
-        .bind(artist_id)
-        .bind(album_id)
-        .bind(track.duration_ms)
-        .bind(track.file_format)
-        .bind(track.file_size)
-        .bind(track.date_added.unwrap_or_else(|| current_date_as_int()))
-        .bind(track.thumbnail_base64.as_deref())
-        .bind(track.thumbnail_mime.as_deref())
-        .fetch_one(&self.db) // Use fetch_one with RETURNING id
-        .await
-        .map_err(|e| format!("Database error: {}", e))?
-        .get::<i64, _>(0); // Retrieve the returned ID
+        // keys can't be NULL, a NULL never compares greater or less than the cursor
+        let keys: &[&str] = match query.sort {
+            TrackSort::Title => &["t.title COLLATE NOCASE"],
+            TrackSort::Artist => &[
+                "COALESCE(a.name, '') COLLATE NOCASE",
+                "COALESCE(al.title, '') COLLATE NOCASE",
+                "COALESCE(t.disc_number, 0)",
+                "COALESCE(t.track_number, 0)",
+            ],
+            TrackSort::Album => &[
+                "COALESCE(al.title, '') COLLATE NOCASE",
+                "COALESCE(t.album_id, 0)",
+                "COALESCE(t.disc_number, 0)",
+                "COALESCE(t.track_number, 0)",
+            ],
+            TrackSort::DateAdded => &["t.date_added"],
+            TrackSort::Duration => &["t.duration_ms"],
+            TrackSort::PlayCount => &[PLAY_COUNT_SQL],
+        };
+        let (direction, past) = if query.descending { ("DESC", "<") } else { ("ASC", ">") };
+        let order = keys
+            .iter()
+            .chain(["t.id"].iter())
+            .map(|key| format!("{} {}", key, direction))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let keys = keys.join(", ");
+
+        let mut page_filter = filter;
+        if let Some(after) = query.after {
+            let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tracks WHERE id = ?")
+                .bind(after)
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            if exists == 0 {
+                return Err(format!("Track {} not found", after));
+            }
+
+            page_filter = format!(
+                "{0} AND ({1}, t.id) {2} (
+                    SELECT {1}, t.id FROM tracks t
+                    LEFT JOIN artists a  ON t.artist_id = a.id
+                    LEFT JOIN albums  al ON t.album_id  = al.id
+                    WHERE t.id = ?
+                )",
+                page_filter, keys, past
+            );
+            values.push(RuleValue::Int(after));
+        }
 
-        Ok(id)
-    }
+        let limit = query.limit.unwrap_or(200).clamp(1, 1000);
+        values.push(RuleValue::Int(limit));
 
-    pub async fn remove_track(&self, track_id: i64) -> Result<(), String> {
-        sqlx::query("DELETE FROM tracks WHERE id = ?")
-            .bind(track_id)
-            .execute(&self.db)
+        let sql = format!(
+            r#"
+            SELECT
+                t.*,
+                a.name   AS artist_name,
+                al.title AS album_name
+            FROM tracks t
+            LEFT JOIN artists a  ON t.artist_id = a.id
+            LEFT JOIN albums  al ON t.album_id  = al.id
+            WHERE {}
+            ORDER BY {}
+            LIMIT ?
+            "#,
+            page_filter, order
+        );
+
+        let mut page_query = sqlx::query_as::<_, Track>(&sql);
+        for value in values {
+            page_query = match value {
+                RuleValue::Text(text) => page_query.bind(text),
+                RuleValue::Int(number) => page_query.bind(number),
+            };
+        }
+        let tracks = page_query
+            .fetch_all(&self.db)
             .await
             .map_err(|e| format!("Database error: {}", e))?;
 
-        Ok(())
+        let next = if tracks.len() as i64 == limit { tracks.last().map(|track| track.id) } else { None };
+        Ok(TrackPage { tracks, total, next })
     }
 
-    pub async fn track_exists(&self, file_path: &str) -> Result<Option<i64>, String> {
-        // We use query_scalar to fetch a single value (the ID)
-        let maybe_id = sqlx::query_scalar::<_, i64>("SELECT id FROM tracks WHERE file_path = ?")
-            .bind(file_path)
-            .fetch_optional(&self.db)
-            .await
-            .map_err(|e| format!("Failed to check track existence: {}", e))?;
+    // opting to have struct as argument here because of the number of properties
+    pub async fn add_track(&self, track: ExtractedTrack) -> Result<i64, String> {
+        let mut conn = self.connection().await?;
+        insert_track(&mut conn, track).await
+    }
 
-        Ok(maybe_id)
+    // Same as add_track for many files at once, in a single transaction
+    pub async fn add_tracks(&self, tracks: Vec<ExtractedTrack>) -> Result<Vec<i64>, String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        let mut ids = Vec::with_capacity(tracks.len());
+        let mut paths = Vec::new();
+        for track in tracks {
+            if track.source_id.unwrap_or(LOCAL_SOURCE_ID) == LOCAL_SOURCE_ID {
+                paths.push(track.file_path.clone());
+            }
+            ids.push(insert_track(&mut tx, track).await?);
+        }
+        let vacated = group_compilations(&mut tx, &paths).await?;
+        drop_empty_albums(&mut tx, &vacated).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))?;
+        Ok(ids)
     }
 
-    // ── inside impl Database ──────────────────────────────────────────────────────
+    // every local track by path, for spotting changed files
+    pub async fn get_local_file_stats(&self) -> Result<HashMap<String, KnownFile>, String> {
+        let rows = sqlx::query_as::<_, KnownFile>(
+            "SELECT id, file_path, file_mtime, file_bytes, missing_since FROM tracks WHERE source_id = ?",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
 
-    // playlist queries
-    pub async fn get_playlist(&self, id: i64) -> Result<Playlist, String> {
-        sqlx::query_as::<_, Playlist>(
-            "SELECT id, name, description, cover_path, cover_color, is_system, created_at, updated_at
-            FROM playlists WHERE id = ?",
+        Ok(rows.into_iter().map(|file| (file.file_path.clone(), file)).collect())
+    }
+
+    pub async fn get_known_file(&self, file_path: &str) -> Result<Option<KnownFile>, String> {
+        sqlx::query_as::<_, KnownFile>(
+            "SELECT id, file_path, file_mtime, file_bytes, missing_since FROM tracks WHERE file_path = ? AND source_id = ?",
         )
-        .bind(id)
-        .fetch_one(&self.db)
+        .bind(file_path)
+        .bind(LOCAL_SOURCE_ID)
+        .fetch_optional(&self.db)
         .await
         .map_err(|e| format!("Database error: {}", e))
     }
 
-        // This is hella long because it fetches 4 separate thumbnails for the playlist preview, but it saves a lot of complexity on the frontend
-        pub async fn get_playlists_with_previews(&self) -> Result<Vec<PlaylistPreview>, String> {
-        sqlx::query_as::<_, PlaylistPreview>(
-            r#"
-            SELECT
-                p.id,
-                p.name,
-                p.description,
-                p.cover_path,
-                (SELECT COUNT(*) FROM playlist_tracks WHERE playlist_id = p.id) AS track_count,
-                (SELECT t.thumbnail_base64 FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 0) AS thumb1_base64,
-                (SELECT t.thumbnail_mime FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 0) AS thumb1_mime,
-                (SELECT t.thumbnail_base64 FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 1) AS thumb2_base64,
-                (SELECT t.thumbnail_mime FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 1) AS thumb2_mime,
-                (SELECT t.thumbnail_base64 FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 2) AS thumb3_base64,
-                (SELECT t.thumbnail_mime FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 2) AS thumb3_mime,
-                (SELECT t.thumbnail_base64 FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 3) AS thumb4_base64,
-                (SELECT t.thumbnail_mime FROM playlist_tracks pt JOIN tracks t ON pt.track_id = t.id
-                WHERE pt.playlist_id = p.id AND t.thumbnail_base64 IS NOT NULL
-                ORDER BY pt.position LIMIT 1 OFFSET 3) AS thumb4_mime
-            FROM playlists p
-            WHERE p.is_system = 0
-            ORDER BY p.name COLLATE NOCASE
-            "#,
+    // (id, path) of local files last read by an older tag reader
+    pub async fn get_stale_tag_files(&self) -> Result<Vec<(i64, String)>, String> {
+        sqlx::query_as::<_, (i64, String)>(
+            "SELECT id, file_path FROM tracks
+            WHERE source_id = ? AND tag_version < ? AND missing_since IS NULL
+            ORDER BY id",
         )
+        .bind(LOCAL_SOURCE_ID)
+        .bind(TAG_VERSION)
         .fetch_all(&self.db)
         .await
         .map_err(|e| format!("Database error: {}", e))
     }
 
-    pub async fn save_playlist(
-        &self,
-        name: String,
-        description: Option<String>,
-        cover_path: Option<String>,
-        track_ids: Vec<i64>,
-    ) -> Result<i64, String> {
-        let playlist_id = sqlx::query_scalar!(
-        "INSERT INTO playlists (name, description, cover_path) VALUES (?, ?, ?) RETURNING id",
-        name,
-        description,
-        cover_path
-    )
-    .fetch_one(&self.db)
-    .await
-    .map_err(|e| format!("Failed to create playlist: {}", e))?;
-
-        for (position, track_id) in track_ids.iter().enumerate() {
-            let pos = position as i64;
-            sqlx::query!(
-                "INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (?, ?, ?)",
-                playlist_id,
-                track_id,
-                pos
-            )
+    // the next backfill re-reads every local file
+    pub async fn mark_tags_stale(&self) -> Result<(), String> {
+        sqlx::query("UPDATE tracks SET tag_version = 0 WHERE source_id = ?")
+            .bind(LOCAL_SOURCE_ID)
             .execute(&self.db)
             .await
-            .map_err(|e| format!("Failed to insert playlist track at position {}: {}", pos, e))?;
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(())
+    }
+
+    // for files the current tag reader couldn't read either, so they aren't retried every start
+    pub async fn set_tags_current(&self, ids: &[i64]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for id in ids {
+            sqlx::query("UPDATE tracks SET tag_version = ? WHERE id = ?")
+                .bind(TAG_VERSION)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
         }
 
-        Ok(playlist_id)
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
     }
 
-    // Returns tracks in position order, with artist/album names joined in
-    // (same shape as get_tracks_with_names so the frontend can reuse the same type)
-    pub async fn get_playlist_tracks(&self, playlist_id: i64) -> Result<Vec<Track>, String> {
+    // records (id, mtime, size) of files that were just seen on disk, without
+    // touching the tags
+    pub async fn set_file_stats(&self, stats: &[(i64, Option<i64>, i64)]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for (id, mtime, bytes) in stats {
+            sqlx::query("UPDATE tracks SET file_mtime = ?, file_bytes = ?, missing_since = NULL WHERE id = ?")
+                .bind(mtime)
+                .bind(bytes)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Re-imports the tags of files that changed on disk, keeping the track ids
+    // so playlists and history stay intact
+    pub async fn update_tracks_from_files(&self, tracks: Vec<(i64, ExtractedTrack)>) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        let mut paths = Vec::with_capacity(tracks.len());
+        let mut vacated = HashSet::new();
+        for (id, track) in tracks {
+            paths.push(track.file_path.clone());
+            vacated.extend(update_track_in(&mut tx, id, track).await?);
+            clear_fingerprint(&mut tx, id).await?;
+        }
+        vacated.extend(group_compilations(&mut tx, &paths).await?);
+        drop_empty_albums(&mut tx, &vacated).await?;
+        prune_artwork(&mut tx).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Takes in the tags update_track_metadata just wrote. The audio is the same,
+    // so unlike a changed file the track keeps its fingerprint.
+    pub async fn apply_track_edit(&self, id: i64, track: ExtractedTrack) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        let paths = [track.file_path.clone()];
+        let mut vacated: HashSet<i64> = update_track_in(&mut tx, id, track).await?.into_iter().collect();
+        vacated.extend(group_compilations(&mut tx, &paths).await?);
+        drop_empty_albums(&mut tx, &vacated).await?;
+        prune_artwork(&mut tx).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_track_by_path(&self, file_path: &str) -> Result<Option<Track>, String> {
         sqlx::query_as::<_, Track>(
             r#"
             SELECT
                 t.*,
                 a.name   AS artist_name,
                 al.title AS album_name
-            FROM playlist_tracks pt
-            JOIN   tracks  t  ON pt.track_id  = t.id
-            LEFT JOIN artists  a  ON t.artist_id  = a.id
-            LEFT JOIN albums   al ON t.album_id   = al.id
-            WHERE pt.playlist_id = ?
-            ORDER BY pt.position
+            FROM tracks t
+            LEFT JOIN artists a  ON t.artist_id = a.id
+            LEFT JOIN albums  al ON t.album_id  = al.id
+            WHERE t.file_path = ?
             "#,
         )
-        .bind(playlist_id)
-        .fetch_all(&self.db)
+        .bind(file_path)
+        .fetch_optional(&self.db)
         .await
         .map_err(|e| format!("Database error: {}", e))
     }
 
-    // Delete + re-insert is the cleanest way to handle reordering without
-    // fighting the UNIQUE(playlist_id, position) constraint
-    pub async fn update_playlist(
-        &self,
-        id: i64,
-        name: String,
-        description: Option<String>,
-        cover_path: Option<String>,
-        track_ids: Vec<i64>,
-    ) -> Result<(), String> {
-        sqlx::query!(
-            "UPDATE playlists
-            SET name = ?, description = ?, cover_path = ?, updated_at = unixepoch()
-            WHERE id = ?",
-            name,
-            description,
-            cover_path,
-            id
+    pub async fn get_track(&self, id: i64) -> Result<Option<Track>, String> {
+        sqlx::query_as::<_, Track>(
+            r#"
+            SELECT
+                t.*,
+                a.name   AS artist_name,
+                al.title AS album_name
+            FROM tracks t
+            LEFT JOIN artists a  ON t.artist_id = a.id
+            LEFT JOIN albums  al ON t.album_id  = al.id
+            WHERE t.id = ?
+            "#,
         )
-        .execute(&self.db)
+        .bind(id)
+        .fetch_optional(&self.db)
         .await
-        .map_err(|e| format!("Failed to update playlist: {}", e))?;
-
-        sqlx::query!("DELETE FROM playlist_tracks WHERE playlist_id = ?", id)
-            .execute(&self.db)
-            .await
-            .map_err(|e| format!("Failed to clear old tracks: {}", e))?;
+        .map_err(|e| format!("Database error: {}", e))
+    }
 
-        for (position, track_id) in track_ids.iter().enumerate() {
-            let pos = position as i64;
-            sqlx::query!(
-                "INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (?, ?, ?)",
-                id,
-                track_id,
-                pos
-            )
-            .execute(&self.db)
-            .await
-            .map_err(|e| format!("Failed to insert track at position {}: {}", pos, e))?;
+    // All filters must match. Field names come from the caller, so they're mapped
+    // onto a fixed set of columns rather than interpolated.
+    pub async fn find_tracks(&self, filters: &[TrackFilter], ignore_case: bool) -> Result<Vec<Track>, String> {
+        let mut clauses = Vec::with_capacity(filters.len());
+        for filter in filters {
+            let field = filter.field.to_lowercase();
+            let column = match field.as_str() {
+                // any of the track's artists, checked against ca.name below
+                "artist" => "ca.name",
+                "albumartist" => "COALESCE(t.album_artist, a.name)",
+                "album" => "al.title",
+                "title" => "t.title",
+                "genre" => "t.genre",
+                "composer" => "t.composer",
+                "date" => "t.release_date",
+                "file" => "t.file_path",
+                "any" => "(t.title || ' ' || a.name || ' ' || al.title)",
+                other => return Err(format!("Unsupported filter field: {}", other)),
+            };
+            let column = if ignore_case { format!("lower({})", column) } else { column.to_string() };
+            let value = if ignore_case { "lower(?)" } else { "?" };
+
+            let clause = match filter.op {
+                FilterOp::Equals | FilterOp::NotEquals => format!("{} = {}", column, value),
+                FilterOp::Contains => format!("instr({}, {}) > 0", column, value),
+                FilterOp::StartsWith => format!("instr({}, {}) = 1", column, value),
+            };
+            let clause = if field == "artist" {
+                format!(
+                    "EXISTS (SELECT 1 FROM track_artists ta JOIN artists ca ON ca.id = ta.artist_id
+                    WHERE ta.track_id = t.id AND ta.role IN ('primary', 'featured') AND {})",
+                    clause
+                )
+            } else {
+                clause
+            };
+            clauses.push(match filter.op {
+                FilterOp::NotEquals => format!("NOT {}", clause),
+                _ => clause,
+            });
         }
 
-        Ok(())
-    }
-
-    pub async fn delete_playlist(&self, id: i64) -> Result<(), String> {
-        // ON DELETE CASCADE handles playlist_tracks automatically
-        sqlx::query!("DELETE FROM playlists WHERE id = ?", id)
-            .execute(&self.db)
-            .await
-            .map_err(|e| format!("Failed to delete playlist: {}", e))?;
+        let where_clause = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
 
+        let sql = format!(
+            r#"
+            SELECT
+                t.*,
+                a.name   AS artist_name,
+                al.title AS album_name
+            FROM tracks t
+            LEFT JOIN artists a  ON t.artist_id = a.id
+            LEFT JOIN albums  al ON t.album_id  = al.id
+            {}
+            ORDER BY a.name COLLATE NOCASE, al.title COLLATE NOCASE, t.title COLLATE NOCASE
+            "#,
+            where_clause
+        );
+
+        let mut query = sqlx::query_as::<_, Track>(&sql);
+        for filter in filters {
+            query = query.bind(&filter.value);
+        }
+
+        query
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Keeps the order of `track_ids`, silently skipping ids that no longer exist
+    pub async fn get_queue_items(&self, track_ids: &[i64]) -> Result<Vec<QueueItem>, String> {
+        let mut items = Vec::with_capacity(track_ids.len());
+        for track_id in track_ids {
+            let item = sqlx::query_as::<_, QueueItem>(
+                "SELECT t.id AS track_id, t.file_path, t.title, a.name AS artist_name
+                FROM tracks t
+                LEFT JOIN artists a ON t.artist_id = a.id
+                WHERE t.id = ?",
+            )
+            .bind(track_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+            items.extend(item);
+        }
+        Ok(items)
+    }
+
+    pub async fn remove_track(&self, track_id: i64) -> Result<(), String> {
+        sqlx::query("DELETE FROM tracks WHERE id = ?")
+            .bind(track_id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(())
+    }
+
+    pub async fn track_exists(&self, file_path: &str) -> Result<Option<i64>, String> {
+        // We use query_scalar to fetch a single value (the ID)
+        let maybe_id = sqlx::query_scalar::<_, i64>("SELECT id FROM tracks WHERE file_path = ?")
+            .bind(file_path)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Failed to check track existence: {}", e))?;
+
+        Ok(maybe_id)
+    }
+
+    // artwork queries
+    // A size that wasn't worth a thumbnail is served from the next one up
+    pub async fn get_artwork(&self, id: i64, size: ArtworkSize) -> Result<Option<(Vec<u8>, String)>, String> {
+        let column = match size {
+            ArtworkSize::Small => "COALESCE(small, medium)",
+            ArtworkSize::Medium => "medium",
+            ArtworkSize::Full => "NULL",
+        };
+        let sql = format!(
+            "SELECT COALESCE({0}, data), CASE WHEN {0} IS NULL THEN mime ELSE 'image/jpeg' END FROM artwork WHERE id = ?",
+            column
+        );
+
+        sqlx::query_as(&sql)
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // rows still holding a base64 picture from before the artwork table
+    pub async fn get_legacy_thumbnails(&self, limit: i64) -> Result<Vec<(i64, String, Option<String>)>, String> {
+        sqlx::query_as(
+            "SELECT id, thumbnail_base64, thumbnail_mime FROM tracks
+            WHERE thumbnail_base64 IS NOT NULL ORDER BY id LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // clears the base64 either way, a picture that couldn't be read is dropped
+    pub async fn set_track_artwork(&self, artwork: Vec<(i64, Option<Artwork>)>) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for (id, artwork) in artwork {
+            let artwork_id = match &artwork {
+                Some(artwork) => Some(store_artwork(&mut tx, artwork).await?),
+                None => None,
+            };
+            sqlx::query(
+                "UPDATE tracks SET artwork_id = COALESCE(?, artwork_id), thumbnail_base64 = NULL, thumbnail_mime = NULL
+                WHERE id = ?",
+            )
+            .bind(artwork_id)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // albums without a cover take their first track's picture
+    pub async fn fill_album_artwork(&self) -> Result<(), String> {
+        sqlx::query(
+            "UPDATE albums SET artwork_id = (
+                SELECT artwork_id FROM tracks
+                WHERE album_id = albums.id AND artwork_id IS NOT NULL
+                ORDER BY COALESCE(disc_number, 1), track_number, file_path LIMIT 1
+            )
+            WHERE artwork_id IS NULL AND id != ?",
+        )
+        .bind(UNKNOWN_ALBUM_ID)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+        Ok(())
+    }
+
+    // search queries
+
+    // Ranked matches for what the user typed, see fts_query. Titles weigh
+    // more than artists, and artists more than albums.
+    pub async fn search_library(&self, query: &str, limit: i64) -> Result<SearchResults, String> {
+        let Some(query) = fts_query(query) else {
+            return Ok(SearchResults { tracks: Vec::new(), albums: Vec::new(), artists: Vec::new(), playlists: Vec::new() });
+        };
+
+        let tracks = sqlx::query_as::<_, Track>(
+            "SELECT t.*, a.name AS artist_name, al.title AS album_name
+            FROM tracks_fts
+            JOIN tracks t ON t.id = tracks_fts.rowid
+            LEFT JOIN artists a ON t.artist_id = a.id
+            LEFT JOIN albums al ON t.album_id = al.id
+            WHERE tracks_fts MATCH ?
+            ORDER BY bm25(tracks_fts, 10.0, 5.0, 3.0, 1.0)
+            LIMIT ?",
+        )
+        .bind(&query)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Search failed: {}", e))?;
+
+        let albums = sqlx::query_as::<_, Album>(
+            "SELECT al.* FROM albums_fts
+            JOIN albums al ON al.id = albums_fts.rowid
+            WHERE albums_fts MATCH ?
+            ORDER BY bm25(albums_fts, 10.0, 5.0)
+            LIMIT ?",
+        )
+        .bind(&query)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Search failed: {}", e))?;
+
+        let artists = sqlx::query_as::<_, Artist>(
+            "SELECT a.* FROM artists_fts
+            JOIN artists a ON a.id = artists_fts.rowid
+            WHERE artists_fts MATCH ?
+            ORDER BY bm25(artists_fts)
+            LIMIT ?",
+        )
+        .bind(&query)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Search failed: {}", e))?;
+
+        let playlists = sqlx::query_as::<_, Playlist>(
+            "SELECT p.id, p.name, p.description, p.cover_path, p.cover_color, p.is_system, p.created_at, p.updated_at,
+            p.rules IS NOT NULL AS is_smart
+            FROM playlists_fts
+            JOIN playlists p ON p.id = playlists_fts.rowid
+            WHERE playlists_fts MATCH ?
+            ORDER BY bm25(playlists_fts, 10.0, 1.0)
+            LIMIT ?",
+        )
+        .bind(&query)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Search failed: {}", e))?;
+
+        Ok(SearchResults { tracks, albums, artists, playlists })
+    }
+
+    // chapter queries
+    pub async fn get_chapters(&self, track_id: i64) -> Result<Vec<Chapter>, String> {
+        sqlx::query_as::<_, Chapter>("SELECT * FROM chapters WHERE track_id = ? ORDER BY position")
+            .bind(track_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Replaces whatever chapters the track had before
+    pub async fn save_chapters(&self, track_id: i64, chapters: &[ExtractedChapter]) -> Result<(), String> {
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        write_chapters(&mut tx, track_id, chapters).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Re-reads chapters from the file, for rows imported before chapters were stored
+    pub async fn rescan_chapters(&self, track_id: i64) -> Result<Vec<Chapter>, String> {
+        let (file_path, duration_ms): (String, i64) =
+            sqlx::query_as("SELECT file_path, duration_ms FROM tracks WHERE id = ?")
+                .bind(track_id)
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+
+        let chapters = read_chapters(std::path::Path::new(&file_path), duration_ms)?;
+        self.save_chapters(track_id, &chapters).await?;
+        self.get_chapters(track_id).await
+    }
+
+    // play history
+    pub async fn record_play(&self, track_id: i64, played_at: Option<i64>, source: &str) -> Result<(), String> {
+        sqlx::query("INSERT INTO play_history (track_id, played_at, source) VALUES (?, COALESCE(?, unixepoch()), ?)")
+            .bind(track_id)
+            .bind(played_at)
+            .bind(source)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to record play: {}", e))?;
+
+        Ok(())
+    }
+
+    // library source queries
+    pub async fn get_sources(&self) -> Result<Vec<SourceConfig>, String> {
+        sqlx::query_as::<_, SourceConfig>("SELECT * FROM library_sources ORDER BY id")
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_source(&self, id: i64) -> Result<SourceConfig, String> {
+        sqlx::query_as::<_, SourceConfig>("SELECT * FROM library_sources WHERE id = ?")
+            .bind(id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn add_source(
+        &self,
+        kind: &str,
+        name: &str,
+        base_url: &str,
+        username: &str,
+        password: &str,
+        cache_enabled: bool,
+    ) -> Result<i64, String> {
+        sqlx::query_scalar(
+            "INSERT INTO library_sources (kind, name, base_url, username, password, cache_enabled)
+            VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(kind)
+        .bind(name.trim())
+        .bind(base_url.trim().trim_end_matches('/'))
+        .bind(username)
+        .bind(password)
+        .bind(cache_enabled)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| format!("Failed to add library source: {}", e))
+    }
+
+    pub async fn set_source_cache_enabled(&self, id: i64, enabled: bool) -> Result<(), String> {
+        sqlx::query("UPDATE library_sources SET cache_enabled = ? WHERE id = ?")
+            .bind(enabled)
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(())
+    }
+
+    // Drops everything the source brought in. Artists that local files (or books)
+    // still use are kept and handed over to the local source.
+    pub async fn delete_source(&self, id: i64) -> Result<(), String> {
+        if id == LOCAL_SOURCE_ID {
+            return Err("The local library source can't be removed".to_string());
+        }
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let statements = [
+            "DELETE FROM tracks WHERE source_id = ?",
+            "DELETE FROM albums WHERE source_id = ? AND id != 1",
+            "DELETE FROM artists WHERE source_id = ? AND id != 1
+                AND NOT EXISTS (SELECT 1 FROM albums WHERE artist_id = artists.id)
+                AND NOT EXISTS (SELECT 1 FROM tracks WHERE artist_id = artists.id)
+                AND NOT EXISTS (SELECT 1 FROM track_artists WHERE artist_id = artists.id)
+                AND NOT EXISTS (SELECT 1 FROM books  WHERE artist_id = artists.id)",
+            "UPDATE artists SET source_id = 1 WHERE source_id = ?",
+            "DELETE FROM library_sources WHERE id = ?",
+        ];
+        for statement in statements {
+            sqlx::query(statement)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to remove library source: {}", e))?;
+        }
+        prune_artwork(&mut tx).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_source_track_paths(&self, source_id: i64) -> Result<HashSet<String>, String> {
+        let paths: Vec<String> = sqlx::query_scalar("SELECT file_path FROM tracks WHERE source_id = ?")
+            .bind(source_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(paths.into_iter().collect())
+    }
+
+    // Adds whatever is new on the source. Remote rows that disappeared from the
+    // server are removed too; a local file that vanished may just be on an
+    // unplugged drive, so local rows are left alone.
+    pub async fn sync_source<S: LibrarySource + Sync>(&self, source: &S) -> Result<SyncSummary, String> {
+        let source_id = source.source_id();
+        let known = self.get_source_track_paths(source_id).await?;
+        let listing = source.list(&known).await?;
+
+        let mut added = 0;
+        for mut track in listing.new_tracks {
+            track.source_id = Some(source_id);
+            if self.add_track(track).await? > 0 {
+                added += 1;
+            }
+        }
+
+        let mut removed = 0;
+        if source.is_remote() {
+            let present: HashSet<&str> = listing.paths.iter().map(String::as_str).collect();
+            for path in known.iter().filter(|p| !present.contains(p.as_str())) {
+                sqlx::query("DELETE FROM tracks WHERE file_path = ? AND source_id = ?")
+                    .bind(path)
+                    .bind(source_id)
+                    .execute(&self.db)
+                    .await
+                    .map_err(|e| format!("Failed to remove track: {}", e))?;
+                removed += 1;
+            }
+
+            sqlx::query(
+                "DELETE FROM albums WHERE source_id = ? AND id != 1
+                AND NOT EXISTS (SELECT 1 FROM tracks WHERE album_id = albums.id)",
+            )
+            .bind(source_id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+            prune_artwork(&mut *self.connection().await?).await?;
+        }
+
+        sqlx::query("UPDATE library_sources SET last_synced = unixepoch() WHERE id = ?")
+            .bind(source_id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(SyncSummary { source_id, added, removed })
+    }
+
+    // ── inside impl Database ──────────────────────────────────────────────────────
+
+    // playlist queries
+    pub async fn get_playlist(&self, id: i64) -> Result<Playlist, String> {
+        sqlx::query_as::<_, Playlist>(
+            "SELECT id, name, description, cover_path, cover_color, is_system, created_at, updated_at,
+            rules IS NOT NULL AS is_smart
+            FROM playlists WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_playlist_by_name(&self, name: &str) -> Result<Option<Playlist>, String> {
+        sqlx::query_as::<_, Playlist>(
+            "SELECT id, name, description, cover_path, cover_color, is_system, created_at, updated_at,
+            rules IS NOT NULL AS is_smart
+            FROM playlists WHERE name = ? COLLATE NOCASE AND is_system = 0",
+        )
+        .bind(name)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // The collage takes the first four distinct covers of each playlist, fetched
+    // for all playlists at once rather than per playlist
+    pub async fn get_playlists_with_previews(&self) -> Result<Vec<PlaylistPreview>, String> {
+        let mut previews = sqlx::query_as::<_, PlaylistPreview>(
+            r#"
+            SELECT
+                p.id,
+                p.name,
+                p.description,
+                p.cover_path,
+                p.rules IS NOT NULL AS is_smart,
+                (SELECT COUNT(*) FROM playlist_tracks WHERE playlist_id = p.id) AS track_count
+            FROM playlists p
+            WHERE p.is_system = 0
+            ORDER BY p.name COLLATE NOCASE
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        let covers: Vec<(i64, i64)> = sqlx::query_as(
+            r#"
+            SELECT playlist_id, artwork_id FROM (
+                SELECT playlist_id, artwork_id, ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY first) AS n
+                FROM (
+                    SELECT pt.playlist_id, t.artwork_id, MIN(pt.position) AS first
+                    FROM playlist_tracks pt
+                    JOIN tracks t ON t.id = pt.track_id
+                    WHERE t.artwork_id IS NOT NULL
+                    GROUP BY pt.playlist_id, t.artwork_id
+                )
+            )
+            WHERE n <= 4
+            ORDER BY playlist_id, n
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        let mut by_playlist: HashMap<i64, Vec<i64>> = HashMap::new();
+        for (playlist_id, artwork_id) in covers {
+            by_playlist.entry(playlist_id).or_default().push(artwork_id);
+        }
+        for preview in &mut previews {
+            preview.artwork_ids = by_playlist.remove(&preview.id).unwrap_or_default();
+        }
+        Ok(previews)
+    }
+
+    pub async fn save_playlist(
+        &self,
+        name: String,
+        description: Option<String>,
+        cover_path: Option<String>,
+        track_ids: Vec<i64>,
+    ) -> Result<i64, String> {
+        let playlist_id = sqlx::query_scalar!(
+        "INSERT INTO playlists (name, description, cover_path) VALUES (?, ?, ?) RETURNING id",
+        name,
+        description,
+        cover_path
+    )
+    .fetch_one(&self.db)
+    .await
+    .map_err(|e| format!("Failed to create playlist: {}", e))?;
+
+        for (position, track_id) in track_ids.iter().enumerate() {
+            let pos = position as i64;
+            sqlx::query!(
+                "INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (?, ?, ?)",
+                playlist_id,
+                track_id,
+                pos
+            )
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to insert playlist track at position {}: {}", pos, e))?;
+        }
+
+        Ok(playlist_id)
+    }
+
+    // Returns tracks in position order, with artist/album names joined in
+    // (same shape as get_tracks_with_names so the frontend can reuse the same type).
+    // Smart playlists are read as last refreshed, a random one keeps its order
+    // until the library changes or refresh_smart_playlist is called.
+    pub async fn get_playlist_tracks(&self, playlist_id: i64) -> Result<Vec<Track>, String> {
+        sqlx::query_as::<_, Track>(
+            r#"
+            SELECT
+                t.*,
+                a.name   AS artist_name,
+                al.title AS album_name
+            FROM playlist_tracks pt
+            JOIN   tracks  t  ON pt.track_id  = t.id
+            LEFT JOIN artists  a  ON t.artist_id  = a.id
+            LEFT JOIN albums   al ON t.album_id   = al.id
+            WHERE pt.playlist_id = ?
+            ORDER BY pt.position
+            "#,
+        )
+        .bind(playlist_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Delete + re-insert is the cleanest way to handle reordering without
+    // fighting the UNIQUE(playlist_id, position) constraint
+    pub async fn update_playlist(
+        &self,
+        id: i64,
+        name: String,
+        description: Option<String>,
+        cover_path: Option<String>,
+        track_ids: Vec<i64>,
+    ) -> Result<(), String> {
+        sqlx::query!(
+            "UPDATE playlists
+            SET name = ?, description = ?, cover_path = ?, updated_at = unixepoch()
+            WHERE id = ?",
+            name,
+            description,
+            cover_path,
+            id
+        )
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to update playlist: {}", e))?;
+
+        sqlx::query!("DELETE FROM playlist_tracks WHERE playlist_id = ?", id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to clear old tracks: {}", e))?;
+
+        for (position, track_id) in track_ids.iter().enumerate() {
+            let pos = position as i64;
+            sqlx::query!(
+                "INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (?, ?, ?)",
+                id,
+                track_id,
+                pos
+            )
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to insert track at position {}: {}", pos, e))?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_playlist(&self, id: i64) -> Result<(), String> {
+        // ON DELETE CASCADE handles playlist_tracks automatically
+        sqlx::query!("DELETE FROM playlists WHERE id = ?", id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to delete playlist: {}", e))?;
+
+        Ok(())
+    }
+
+    // smart playlist queries
+    pub async fn save_smart_playlist(
+        &self,
+        name: String,
+        description: Option<String>,
+        rules: &SmartPlaylistRules,
+    ) -> Result<i64, String> {
+        // rules that don't compile are refused before they're stored
+        compile_smart_rules(rules)?;
+        let rules_json = serde_json::to_string(rules).map_err(|e| format!("Invalid rules: {}", e))?;
+
+        let playlist_id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO playlists (name, description, rules) VALUES (?, ?, ?) RETURNING id",
+        )
+        .bind(name)
+        .bind(description)
+        .bind(rules_json)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| format!("Failed to create playlist: {}", e))?;
+
+        self.refresh_smart_playlist(playlist_id).await?;
+        Ok(playlist_id)
+    }
+
+    pub async fn update_smart_playlist(
+        &self,
+        id: i64,
+        name: String,
+        description: Option<String>,
+        rules: &SmartPlaylistRules,
+    ) -> Result<(), String> {
+        compile_smart_rules(rules)?;
+        let rules_json = serde_json::to_string(rules).map_err(|e| format!("Invalid rules: {}", e))?;
+
+        sqlx::query(
+            "UPDATE playlists SET name = ?, description = ?, rules = ?, updated_at = unixepoch()
+            WHERE id = ? AND rules IS NOT NULL",
+        )
+        .bind(name)
+        .bind(description)
+        .bind(rules_json)
+        .bind(id)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to update playlist: {}", e))?;
+
+        self.refresh_smart_playlist(id).await
+    }
+
+    // None for plain playlists
+    pub async fn get_smart_playlist_rules(&self, id: i64) -> Result<Option<SmartPlaylistRules>, String> {
+        let rules = sqlx::query_scalar::<_, Option<String>>("SELECT rules FROM playlists WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .flatten();
+
+        rules
+            .map(|json| serde_json::from_str(&json).map_err(|e| format!("Invalid rules for playlist {}: {}", id, e)))
+            .transpose()
+    }
+
+    // Rewrites a smart playlist's tracks from its rules, plain playlists are left alone
+    pub async fn refresh_smart_playlist(&self, id: i64) -> Result<(), String> {
+        let Some(rules) = self.get_smart_playlist_rules(id).await? else {
+            return Ok(());
+        };
+        let (sql, values) = compile_smart_rules(&rules)?;
+
+        let mut query = sqlx::query_scalar::<_, i64>(&sql);
+        for value in values {
+            query = match value {
+                RuleValue::Text(text) => query.bind(text),
+                RuleValue::Int(number) => query.bind(number),
+            };
+        }
+        let track_ids = query
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Failed to evaluate smart playlist: {}", e))?;
+
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+        sqlx::query("DELETE FROM playlist_tracks WHERE playlist_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to clear old tracks: {}", e))?;
+
+        for (position, track_id) in track_ids.iter().enumerate() {
+            sqlx::query("INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (?, ?, ?)")
+                .bind(id)
+                .bind(track_id)
+                .bind(position as i64)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to insert track at position {}: {}", position, e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // after the library changed
+    pub async fn refresh_smart_playlists(&self) -> Result<(), String> {
+        let ids = sqlx::query_scalar::<_, i64>("SELECT id FROM playlists WHERE rules IS NOT NULL")
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        for id in ids {
+            self.refresh_smart_playlist(id).await?;
+        }
+        Ok(())
+    }
+
+    // maintenance functions
+
+    // Flags the track at `path`, or every track under it if it was a folder, as
+    // missing. Rows are kept so they can be relinked later.
+    pub async fn mark_missing(&self, path: &str) -> Result<u64, String> {
+        let prefix = folder_prefix(path);
+        let result = sqlx::query(
+            "UPDATE tracks SET missing_since = unixepoch()
+            WHERE source_id = ? AND missing_since IS NULL AND (file_path = ? OR substr(file_path, 1, ?) = ?)",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .bind(path)
+        .bind(prefix.chars().count() as i64)
+        .bind(&prefix)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Points a track at its file's new path, keeping its id. A row already made
+    // for the new path, by a scan or the watcher, is folded into the old one so
+    // its plays and playlist entries aren't lost.
+    pub async fn relink_track(&self, id: i64, new_path: &str) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        let duplicate = sqlx::query_scalar::<_, i64>("SELECT id FROM tracks WHERE file_path = ? AND id != ?")
+            .bind(new_path)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        if let Some(duplicate) = duplicate {
+            merge_track_into(&mut tx, id, duplicate).await?;
+        }
+
+        sqlx::query("UPDATE tracks SET file_path = ?, missing_since = NULL WHERE id = ?")
+            .bind(new_path)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        clear_fingerprint(&mut tx, id).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // every local track whose file is where the library thinks, for the organizer
+    pub async fn get_local_tracks(&self) -> Result<Vec<Track>, String> {
+        sqlx::query_as::<_, Track>(
+            "SELECT t.*, a.name AS artist_name, al.title AS album_name
+            FROM tracks t
+            LEFT JOIN artists a ON a.id = t.artist_id
+            LEFT JOIN albums al ON al.id = t.album_id
+            WHERE t.source_id = ? AND t.missing_since IS NULL
+            ORDER BY t.file_path",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // newest row so far, rows above it were added later
+    pub async fn get_last_track_id(&self) -> Result<i64, String> {
+        sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM tracks")
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Points tracks at the paths the organizer moved their files to, all or
+    // none. The files are the same, so unlike relink_track fingerprints stay.
+    // `last_id` is the newest row from before the files moved.
+    pub async fn move_track_files(&self, moves: &[(i64, String)], last_id: i64) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for (id, new_path) in moves {
+            let holder = sqlx::query_as::<_, (i64, bool)>(
+                "SELECT t.id, t.id > ? AND (t.content_hash IS NULL OR m.content_hash IS NULL OR t.content_hash = m.content_hash)
+                FROM tracks t, tracks m
+                WHERE t.file_path = ? AND t.id != ? AND m.id = ?",
+            )
+            .bind(last_id)
+            .bind(new_path)
+            .bind(id)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+            match holder {
+                // the watcher saw this file arrive first
+                Some((duplicate, true)) => merge_track_into(&mut tx, *id, duplicate).await?,
+                Some((other, false)) => return Err(format!("{} already belongs to track {}", new_path, other)),
+                None => {}
+            }
+
+            sqlx::query("UPDATE tracks SET file_path = ?, missing_since = NULL WHERE id = ?")
+                .bind(new_path)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Folds the duplicates into `keep`, which takes over their playlist entries,
+    // plays and bookmarks. The files themselves are left where they are.
+    pub async fn merge_tracks(&self, keep: i64, duplicates: &[i64]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tracks WHERE id = ?")
+            .bind(keep)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        if exists == 0 {
+            return Err(format!("Track {} not found", keep));
+        }
+
+        let mut vacated = HashSet::new();
+        for &duplicate in duplicates.iter().filter(|&&id| id != keep) {
+            let album_id: Option<i64> = sqlx::query_scalar("SELECT album_id FROM tracks WHERE id = ?")
+                .bind(duplicate)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            vacated.extend(album_id);
+            merge_track_into(&mut tx, keep, duplicate).await?;
+        }
+        drop_empty_albums(&mut tx, &vacated).await?;
+        prune_artwork(&mut tx).await?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // every track, with the hash of its file, for find_duplicates
+    pub async fn get_duplicate_candidates(&self) -> Result<Vec<DuplicateCopy>, String> {
+        sqlx::query_as::<_, DuplicateCopy>(
+            "SELECT t.*, a.name AS artist_name, al.title AS album_name
+            FROM tracks t
+            LEFT JOIN artists a ON a.id = t.artist_id
+            LEFT JOIN albums al ON al.id = t.album_id
+            ORDER BY t.id",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // every local track with what it takes to recognise its file somewhere else
+    pub async fn get_library_files(&self) -> Result<Vec<LibraryFile>, String> {
+        sqlx::query_as::<_, LibraryFile>(
+            "SELECT t.id, t.file_path, t.title, a.name AS artist_name, al.title AS album_name, t.duration_ms,
+            t.file_bytes, t.content_hash, t.missing_since, f.fingerprint
+            FROM tracks t
+            LEFT JOIN artists a ON a.id = t.artist_id
+            LEFT JOIN albums al ON al.id = t.album_id
+            LEFT JOIN track_fingerprints f ON f.track_id = t.id
+            WHERE t.source_id = ?
+            ORDER BY t.id",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn set_missing(&self, ids: &[i64]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for id in ids {
+            sqlx::query("UPDATE tracks SET missing_since = COALESCE(missing_since, unixepoch()) WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // (id, path) of local files that haven't been fingerprinted yet, by id from `after`
+    pub async fn get_unfingerprinted_files(&self, after: i64, limit: i64) -> Result<Vec<(i64, String)>, String> {
+        sqlx::query_as::<_, (i64, String)>(
+            "SELECT t.id, t.file_path FROM tracks t
+            WHERE t.source_id = ? AND t.missing_since IS NULL AND t.id > ?
+            AND NOT EXISTS (SELECT 1 FROM track_fingerprints f WHERE f.track_id = t.id)
+            ORDER BY t.id
+            LIMIT ?",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn count_unfingerprinted_files(&self) -> Result<i64, String> {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM tracks t
+            WHERE t.source_id = ? AND t.missing_since IS NULL
+            AND NOT EXISTS (SELECT 1 FROM track_fingerprints f WHERE f.track_id = t.id)",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // None records a file that couldn't be decoded
+    pub async fn set_fingerprints(&self, fingerprints: &[(i64, Option<Vec<u8>>)]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for (id, fingerprint) in fingerprints {
+            // the track may have been deleted while its file was decoded
+            sqlx::query(
+                "INSERT OR REPLACE INTO track_fingerprints (track_id, fingerprint)
+                SELECT id, ? FROM tracks WHERE id = ?",
+            )
+            .bind(fingerprint)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_fingerprint(&self, track_id: i64) -> Result<Option<Vec<u8>>, String> {
+        sqlx::query_scalar::<_, Option<Vec<u8>>>("SELECT fingerprint FROM track_fingerprints WHERE track_id = ?")
+            .bind(track_id)
+            .fetch_optional(&self.db)
+            .await
+            .map(Option::flatten)
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // every (track id, fingerprint) there is
+    pub async fn get_fingerprints(&self) -> Result<Vec<(i64, Vec<u8>)>, String> {
+        sqlx::query_as::<_, (i64, Vec<u8>)>(
+            "SELECT track_id, fingerprint FROM track_fingerprints WHERE fingerprint IS NOT NULL ORDER BY track_id",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn set_content_hashes(&self, hashes: &[(i64, String)]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for (id, hash) in hashes {
+            sqlx::query("UPDATE tracks SET content_hash = ? WHERE id = ?")
+                .bind(hash)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn clear_missing(&self, ids: &[i64]) -> Result<(), String> {
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        for id in ids {
+            sqlx::query("UPDATE tracks SET missing_since = NULL WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // A folder was renamed, moves every track under it along. A row the watcher
+    // already made for a new path is folded into the moved track, the way
+    // relink_track does it.
+    pub async fn move_folder(&self, from: &str, to: &str) -> Result<u64, String> {
+        let prefix = folder_prefix(from);
+        let mut tx = self.db.begin().await.map_err(|e| format!("Database error: {}", e))?;
+
+        let tracks = sqlx::query_as::<_, (i64, String)>(
+            "SELECT id, file_path FROM tracks WHERE source_id = ? AND substr(file_path, 1, ?) = ?",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .bind(prefix.chars().count() as i64)
+        .bind(&prefix)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        for (id, path) in &tracks {
+            let new_path = format!("{}{}", folder_prefix(to), &path[prefix.len()..]);
+            let duplicate = sqlx::query_scalar::<_, i64>("SELECT id FROM tracks WHERE file_path = ? AND id != ?")
+                .bind(&new_path)
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            if let Some(duplicate) = duplicate {
+                merge_track_into(&mut tx, *id, duplicate).await?;
+            }
+
+            sqlx::query("UPDATE tracks SET file_path = ?, missing_since = NULL WHERE id = ?")
+                .bind(&new_path)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))?;
+        Ok(tracks.len() as u64)
+    }
+
+    // The missing track a new file most likely is, going by identical size and
+    // mtime as a move keeps both. None unless exactly one track fits.
+    pub async fn find_moved_track(&self, file_bytes: i64, file_mtime: Option<i64>) -> Result<Option<i64>, String> {
+        let ids: Vec<i64> = sqlx::query_scalar(
+            "SELECT id FROM tracks WHERE source_id = ? AND missing_since IS NOT NULL
+            AND file_bytes = ? AND file_mtime IS ? LIMIT 2",
+        )
+        .bind(LOCAL_SOURCE_ID)
+        .bind(file_bytes)
+        .bind(file_mtime)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(if ids.len() == 1 { ids.first().copied() } else { None })
+    }
+
+    // edit journal queries
+    pub async fn add_edit_journal(&self, description: &str, entries: &[JournalEntry]) -> Result<i64, String> {
+        let entries = serde_json::to_string(entries).map_err(|e| e.to_string())?;
+        sqlx::query_scalar::<_, i64>("INSERT INTO edit_journal (description, entries) VALUES (?, ?) RETURNING id")
+            .bind(description)
+            .bind(entries)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // newest first
+    pub async fn get_edit_journal(&self, limit: i64) -> Result<Vec<EditJournalSummary>, String> {
+        sqlx::query_as::<_, EditJournalSummary>(
+            "SELECT id, description, created_at, undone_at, json_array_length(entries) AS track_count
+            FROM edit_journal
+            ORDER BY id DESC
+            LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // (id, entries, undone_at) of the given edit, or of the newest one not undone yet
+    pub async fn get_journal_entries(&self, id: Option<i64>) -> Result<Option<(i64, Vec<JournalEntry>, Option<i64>)>, String> {
+        let row = sqlx::query_as::<_, (i64, String, Option<i64>)>(
+            "SELECT id, entries, undone_at FROM edit_journal
+            WHERE (?1 IS NULL AND undone_at IS NULL) OR id = ?1
+            ORDER BY id DESC
+            LIMIT 1",
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        let Some((id, entries, undone_at)) = row else {
+            return Ok(None);
+        };
+        let entries = serde_json::from_str(&entries).map_err(|e| format!("Invalid journal entry {}: {}", id, e))?;
+        Ok(Some((id, entries, undone_at)))
+    }
+
+    pub async fn set_journal_undone(&self, id: i64) -> Result<(), String> {
+        sqlx::query("UPDATE edit_journal SET undone_at = unixepoch() WHERE id = ?")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
         Ok(())
     }
+}
+
+// Hands everything that points at `from` over to `into`, then drops `from`.
+// Where both are already in the same playlist or book the entry of `into` stays.
+async fn merge_track_into(conn: &mut SqliteConnection, into: i64, from: i64) -> Result<(), String> {
+    // where both copies are listed the duplicate's entry is dropped below, so
+    // these get renumbered once it's gone
+    let playlists: Vec<i64> = sqlx::query_scalar("SELECT playlist_id FROM playlist_tracks WHERE track_id = ?")
+        .bind(from)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    let books: Vec<i64> = sqlx::query_scalar("SELECT book_id FROM book_tracks WHERE track_id = ?")
+        .bind(from)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    for statement in [
+        "UPDATE OR IGNORE playlist_tracks SET track_id = ? WHERE track_id = ?",
+        "UPDATE OR IGNORE book_tracks SET track_id = ? WHERE track_id = ?",
+        "UPDATE books SET resume_track_id = ? WHERE resume_track_id = ?",
+        "UPDATE play_history SET track_id = ? WHERE track_id = ?",
+    ] {
+        sqlx::query(statement)
+            .bind(into)
+            .bind(from)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+    }
+
+    sqlx::query("DELETE FROM tracks WHERE id = ?")
+        .bind(from)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    for id in playlists {
+        renumber_positions(conn, "playlist_tracks", "playlist_id", id).await?;
+    }
+    for id in books {
+        renumber_positions(conn, "book_tracks", "book_id", id).await?;
+    }
+    Ok(())
+}
+
+// Closes the gaps a removed entry leaves in a playlist's or book's order. The
+// positions are flipped negative first so UNIQUE(.., position) holds throughout.
+async fn renumber_positions(conn: &mut SqliteConnection, table: &str, owner: &str, id: i64) -> Result<(), String> {
+    sqlx::query(&format!("UPDATE {table} SET position = -1 - position WHERE {owner} = ?"))
+        .bind(id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query(&format!(
+        "UPDATE {table} SET position = ranked.n
+        FROM (SELECT track_id, ROW_NUMBER() OVER (ORDER BY position DESC) - 1 AS n FROM {table} WHERE {owner} = ?) AS ranked
+        WHERE {table}.{owner} = ? AND {table}.track_id = ranked.track_id"
+    ))
+    .bind(id)
+    .bind(id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+    Ok(())
+}
+
+// Writes what was read from a file over the track's row, returning the album
+// it was on before
+async fn update_track_in(conn: &mut SqliteConnection, id: i64, track: ExtractedTrack) -> Result<Option<i64>, String> {
+    let old_album_id: Option<i64> = sqlx::query_scalar("SELECT album_id FROM tracks WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let artist_id = find_or_create_artist_in(conn, &track.artist).await?;
+    let album_id = find_or_create_track_album_in(conn, &track, artist_id, LOCAL_SOURCE_ID).await?;
+    let artwork_id = store_track_artwork(conn, &track, album_id).await?;
+
+    sqlx::query(
+        "UPDATE tracks SET title = ?, artist_id = ?, album_id = ?, duration_ms = ?, file_format = ?,
+        file_size = ?, artwork_id = ?, thumbnail_base64 = NULL, thumbnail_mime = NULL, file_mtime = ?, file_bytes = ?,
+        content_hash = ?, track_number = ?, track_total = ?, disc_number = ?, disc_total = ?, year = ?,
+        release_date = ?, genre = ?, album_artist = ?, composer = ?, bpm = ?, comment = ?, compilation = ?, bitrate = ?, sample_rate = ?,
+        bit_depth = ?, tag_version = ?, missing_since = NULL WHERE id = ?",
+    )
+    .bind(track.title)
+    .bind(artist_id)
+    .bind(album_id)
+    .bind(track.duration_ms)
+    .bind(track.file_format)
+    .bind(track.file_size)
+    .bind(artwork_id)
+    .bind(track.file_mtime)
+    .bind(track.file_bytes)
+    .bind(track.content_hash)
+    .bind(track.tags.track_number)
+    .bind(track.tags.track_total)
+    .bind(track.tags.disc_number)
+    .bind(track.tags.disc_total)
+    .bind(track.tags.year)
+    .bind(track.tags.release_date)
+    .bind(track.tags.genre)
+    .bind(track.tags.album_artist)
+    .bind(track.tags.composer)
+    .bind(track.tags.bpm)
+    .bind(track.tags.comment)
+    .bind(track.tags.compilation)
+    .bind(track.tags.bitrate)
+    .bind(track.tags.sample_rate)
+    .bind(track.tags.bit_depth)
+    .bind(TAG_VERSION)
+    .bind(id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    write_credits(conn, id, artist_id, &track.credits, LOCAL_SOURCE_ID).await?;
+    write_chapters(conn, id, &track.chapters).await?;
+    Ok(old_album_id)
+}
+
+// the file changed, the background job fingerprints it again
+async fn clear_fingerprint(conn: &mut SqliteConnection, track_id: i64) -> Result<(), String> {
+    sqlx::query("DELETE FROM track_fingerprints WHERE track_id = ?")
+        .bind(track_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(())
+}
+
+// A value bound into a compiled smart playlist query
+enum RuleValue {
+    Text(String),
+    Int(i64),
+}
+
+// Turns smart playlist rules into a query for the matching track ids, plus the
+// values to bind in order. Fields, operators and sorts map onto fixed SQL, only
+// values from the rules are bound. Missing files never match.
+fn compile_smart_rules(rules: &SmartPlaylistRules) -> Result<(String, Vec<RuleValue>), String> {
+    let mut values = Vec::new();
+    let condition = compile_rule(&rules.rule, &mut values)?;
+
+    let order = match rules.sort {
+        None => "a.name COLLATE NOCASE, al.title COLLATE NOCASE, t.title COLLATE NOCASE".to_string(),
+        Some(sort) => {
+            let column = match sort {
+                SmartSort::Title => "t.title COLLATE NOCASE",
+                SmartSort::Artist => "a.name COLLATE NOCASE",
+                SmartSort::Album => "al.title COLLATE NOCASE",
+                SmartSort::Duration => "t.duration_ms",
+                SmartSort::Year => "t.year",
+                SmartSort::DateAdded => "t.date_added",
+                SmartSort::PlayCount => PLAY_COUNT_SQL,
+                SmartSort::LastPlayed => LAST_PLAYED_SQL,
+                SmartSort::Random => "random()",
+            };
+            format!("{} {}", column, if rules.descending { "DESC" } else { "ASC" })
+        }
+    };
+
+    // LIMIT -1 is no limit in SQLite
+    let limit = match rules.limit {
+        Some(limit) if limit > 0 => limit,
+        Some(_) => return Err("A smart playlist limit must be positive".to_string()),
+        None => -1,
+    };
+    values.push(RuleValue::Int(limit));
+
+    let sql = format!(
+        "SELECT t.id FROM tracks t
+        LEFT JOIN artists a  ON t.artist_id = a.id
+        LEFT JOIN albums  al ON t.album_id  = al.id
+        WHERE t.missing_since IS NULL AND {}
+        ORDER BY {}, t.id
+        LIMIT ?",
+        condition, order
+    );
+    Ok((sql, values))
+}
+
+const PLAY_COUNT_SQL: &str = "(SELECT COUNT(*) FROM play_history WHERE track_id = t.id)";
+const LAST_PLAYED_SQL: &str = "(SELECT MAX(played_at) FROM play_history WHERE track_id = t.id)";
+
+fn compile_rule(rule: &SmartRule, values: &mut Vec<RuleValue>) -> Result<String, String> {
+    let (field, op, value) = match rule {
+        SmartRule::Group { match_kind, rules } => {
+            let (joiner, empty) = match match_kind {
+                SmartMatch::All => (" AND ", "1"),
+                SmartMatch::Any => (" OR ", "0"),
+            };
+            if rules.is_empty() {
+                return Ok(empty.to_string());
+            }
+            let parts = rules
+                .iter()
+                .map(|rule| compile_rule(rule, values))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(format!("({})", parts.join(joiner)));
+        }
+        SmartRule::Condition { field, op, value } => (*field, *op, value),
+    };
+    let unsupported = || format!("{:?} can't be used with {:?}", op, field);
+    // numbers may come in as text from a form
+    let number = || {
+        value
+            .as_i64()
+            .or_else(|| value.as_str().and_then(|text| text.trim().parse().ok()))
+            .ok_or_else(|| format!("{:?} needs a number", field))
+    };
+
+    match field {
+        SmartField::Title
+        | SmartField::Artist
+        | SmartField::Album
+        | SmartField::AlbumArtist
+        | SmartField::Genre
+        | SmartField::Composer
+        | SmartField::Format
+        | SmartField::Path => {
+            let column = match field {
+                SmartField::Title => "t.title",
+                SmartField::Artist => "a.name",
+                SmartField::Album => "al.title",
+                SmartField::AlbumArtist => "COALESCE(t.album_artist, a.name)",
+                SmartField::Genre => "t.genre",
+                SmartField::Composer => "t.composer",
+                SmartField::Format => "t.file_format",
+                _ => "t.file_path",
+            };
+            let column = format!("lower(COALESCE({}, ''))", column);
+            let clause = match op {
+                SmartOp::Is => format!("{} = lower(?)", column),
+                SmartOp::IsNot => format!("{} != lower(?)", column),
+                SmartOp::Contains => format!("instr({}, lower(?)) > 0", column),
+                SmartOp::NotContains => format!("instr({}, lower(?)) = 0", column),
+                SmartOp::StartsWith => format!("instr({}, lower(?)) = 1", column),
+                _ => return Err(unsupported()),
+            };
+            let text = match value {
+                serde_json::Value::String(text) => text.clone(),
+                serde_json::Value::Number(number) => number.to_string(),
+                _ => return Err(format!("{:?} needs text", field)),
+            };
+            values.push(RuleValue::Text(text));
+            Ok(clause)
+        }
+        SmartField::Duration | SmartField::Year | SmartField::Bpm | SmartField::PlayCount => {
+            let column = match field {
+                SmartField::Duration => "(t.duration_ms / 1000)",
+                SmartField::Year => "t.year",
+                SmartField::Bpm => "t.bpm",
+                _ => PLAY_COUNT_SQL,
+            };
+            let comparison = match op {
+                SmartOp::Is => "=",
+                SmartOp::IsNot => "!=",
+                SmartOp::Lt => "<",
+                SmartOp::Lte => "<=",
+                SmartOp::Gt => ">",
+                SmartOp::Gte => ">=",
+                _ => return Err(unsupported()),
+            };
+            values.push(RuleValue::Int(number()?));
+            Ok(format!("{} {} ?", column, comparison))
+        }
+        SmartField::DateAdded | SmartField::LastPlayed => {
+            let days = number()?.clamp(0, 365 * 100);
+            // date_added is a YYYYMMDD number, play history unix seconds
+            let (column, cutoff) = match field {
+                SmartField::DateAdded => {
+                    let date = chrono::Local::now() - chrono::Duration::days(days);
+                    ("t.date_added", date.format("%Y%m%d").to_string().parse::<i64>().unwrap_or(0))
+                }
+                _ => (LAST_PLAYED_SQL, chrono::Utc::now().timestamp() - days * 86_400),
+            };
+            let clause = match op {
+                SmartOp::WithinDays => format!("{} >= ?", column),
+                // never played counts as not within any number of days
+                SmartOp::NotWithinDays => format!("({0} IS NULL OR {0} < ?)", column),
+                _ => return Err(unsupported()),
+            };
+            values.push(RuleValue::Int(cutoff));
+            Ok(clause)
+        }
+    }
+}
+
+// Turns what the user typed into an FTS5 query where every word has to match,
+// each as a prefix. Words are split the way the index tokenizer splits them and
+// quoted, so nothing the user types is read as FTS syntax.
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+// What the path of everything under a folder starts with. Compared with
+// substr rather than LIKE, which ignores case and would take in /music/Rock
+// along with /music/rock.
+fn folder_prefix(folder: &str) -> String {
+    format!("{}{}", folder.trim_end_matches(std::path::MAIN_SEPARATOR), std::path::MAIN_SEPARATOR)
+}
+
+// The write path behind add_track, on a plain connection so batches can share
+// one transaction
+async fn find_or_create_artist_in(conn: &mut SqliteConnection, name: &str) -> Result<i64, String> {
+    let trimmed = name.trim();
+    if trimmed.is_empty() {
+        return Ok(UNKNOWN_ARTIST_ID);
+    }
 
-    // maintenance functions
+    // Try to find existing artist
+    let maybe_id = sqlx::query_scalar!(
+        // Add 'as "id!"' to force non-nullable i64
+        "SELECT id as \"id!\" FROM artists WHERE name = ? COLLATE NOCASE",
+        trimmed
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| format!("Artist lookup failed: {}", e))?;
+
+    if let Some(id) = maybe_id {
+        return Ok(id);
+    }
+
+    // Create new
+    let id = sqlx::query_scalar!(
+        "INSERT INTO artists (name) VALUES (?) RETURNING id",
+        trimmed
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Artist creation failed: {}", e))?;
+
+    Ok(id)
+}
+
+async fn find_or_create_album_in(
+    conn: &mut SqliteConnection,
+    title: &str,
+    artist_id: i64,
+    source_id: i64,
+) -> Result<i64, String> {
+    let trimmed = title.trim();
+    if trimmed.is_empty() {
+        return Ok(UNKNOWN_ALBUM_ID);
+    }
+
+    // Find existing album by title + artist
+    if let Some(id) = sqlx::query_scalar::<_, i64>(
+        "SELECT id FROM albums WHERE title = ? AND artist_id = ? AND source_id = ?",
+    )
+    .bind(trimmed)
+    .bind(artist_id)
+    .bind(source_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| format!("Album lookup failed: {}", e))?
+    {
+        return Ok(id);
+    }
+
+    // Create new
+    let id = sqlx::query_scalar!(
+        "INSERT INTO albums (title, artist_id, source_id) VALUES (?, ?, ?) RETURNING id",
+        trimmed,
+        artist_id,
+        source_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Album creation failed: {}", e))?
+    .ok_or_else(|| "Failed to get inserted album ID".to_string())?;
+
+    Ok(id)
+}
+
+// Albums are keyed by album artist, so a compilation stays one album however
+// many artists are on it
+async fn find_or_create_track_album_in(
+    conn: &mut SqliteConnection,
+    track: &ExtractedTrack,
+    artist_id: i64,
+    source_id: i64,
+) -> Result<i64, String> {
+    let album_artist_id = match track.tags.album_artist.as_deref() {
+        Some(name) => find_or_create_artist_in(conn, name).await?,
+        None if track.tags.compilation => find_or_create_artist_in(conn, VARIOUS_ARTISTS).await?,
+        None => artist_id,
+    };
+    let album_id = find_or_create_album_in(conn, &track.album, album_artist_id, source_id).await?;
+
+    if track.tags.compilation {
+        mark_compilation(conn, album_id).await?;
+    }
+    Ok(album_id)
+}
+
+async fn mark_compilation(conn: &mut SqliteConnection, album_id: i64) -> Result<(), String> {
+    sqlx::query("UPDATE albums SET compilation = 1 WHERE id = ? AND id != ?")
+        .bind(album_id)
+        .bind(UNKNOWN_ALBUM_ID)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct FolderTrack {
+    id: i64,
+    file_path: String,
+    artist_id: i64,
+    album_id: i64,
+    album_title: String,
+}
+
+// Files without an album artist that sit in one folder under one album title
+// but have different artists are a compilation nobody tagged as one, they go
+// on a Various Artists album. Returns the albums tracks were moved off.
+async fn group_compilations(conn: &mut SqliteConnection, paths: &[String]) -> Result<HashSet<i64>, String> {
+    let folders: HashSet<&Path> = paths.iter().filter_map(|path| Path::new(path).parent()).collect();
+    let mut vacated = HashSet::new();
+
+    for folder in folders {
+        let prefix = folder_prefix(&folder.to_string_lossy());
+        let rows = sqlx::query_as::<_, FolderTrack>(
+            "SELECT t.id, t.file_path, t.artist_id, t.album_id, al.title AS album_title
+            FROM tracks t
+            JOIN albums al ON al.id = t.album_id
+            WHERE substr(t.file_path, 1, ?) = ? AND t.source_id = ? AND t.album_id != ?
+              AND t.album_artist IS NULL AND t.compilation = 0",
+        )
+        .bind(prefix.chars().count() as i64)
+        .bind(&prefix)
+        .bind(LOCAL_SOURCE_ID)
+        .bind(UNKNOWN_ALBUM_ID)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        // subfolders are albums of their own
+        let mut albums: HashMap<String, Vec<FolderTrack>> = HashMap::new();
+        for row in rows.into_iter().filter(|row| Path::new(&row.file_path).parent() == Some(folder)) {
+            albums.entry(row.album_title.to_lowercase()).or_default().push(row);
+        }
+
+        for tracks in albums.values() {
+            let artists: HashSet<i64> = tracks.iter().map(|track| track.artist_id).collect();
+            let album_id = if artists.len() > 1 {
+                let various = find_or_create_artist_in(conn, VARIOUS_ARTISTS).await?;
+                let id = find_or_create_album_in(conn, &tracks[0].album_title, various, LOCAL_SOURCE_ID).await?;
+                mark_compilation(conn, id).await?;
+                id
+            } else {
+                // back on the artist's own album once it's no longer mixed
+                find_or_create_album_in(conn, &tracks[0].album_title, tracks[0].artist_id, LOCAL_SOURCE_ID).await?
+            };
+
+            for track in tracks.iter().filter(|track| track.album_id != album_id) {
+                sqlx::query("UPDATE tracks SET album_id = ? WHERE id = ?")
+                    .bind(album_id)
+                    .bind(track.id)
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(|e| format!("Database error: {}", e))?;
+                vacated.insert(track.album_id);
+            }
+        }
+    }
+    Ok(vacated)
+}
+
+// regrouping leaves the albums tracks used to be on behind
+async fn drop_empty_albums(conn: &mut SqliteConnection, ids: &HashSet<i64>) -> Result<(), String> {
+    for id in ids {
+        sqlx::query(
+            "DELETE FROM albums WHERE id = ? AND id != ?
+            AND NOT EXISTS (SELECT 1 FROM tracks WHERE album_id = albums.id)",
+        )
+        .bind(id)
+        .bind(UNKNOWN_ALBUM_ID)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    }
+    Ok(())
+}
+
+async fn insert_track(conn: &mut SqliteConnection, track: ExtractedTrack) -> Result<i64, String> {
+    let file_path = track.file_path.clone();
+
+    // check if exists
+    let existing_id = sqlx::query_scalar::<_, i64>("SELECT id FROM tracks WHERE file_path = ?")
+        .bind(&file_path)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to check track existence: {}", e))?;
+    if let Some(existing_id) = existing_id {
+        return Ok(-existing_id); // negative value indicates dupe
+    }
+
+    let source_id = track.source_id.unwrap_or(LOCAL_SOURCE_ID);
+    let artist_id = find_or_create_artist_in(conn, &track.artist).await?;
+    let album_id = find_or_create_track_album_in(conn, &track, artist_id, source_id).await?;
+    let artwork_id = store_track_artwork(conn, &track, album_id).await?;
+
+    // insert
+    let id = sqlx::query("INSERT INTO tracks (file_path, title,
+     artist_id, album_id, duration_ms, file_format, file_size, date_added, artwork_id, source_id,
+     file_mtime, file_bytes, content_hash,
+     track_number, track_total, disc_number, disc_total, year, release_date, genre, album_artist, composer, bpm, comment,
+     compilation, bitrate, sample_rate, bit_depth, tag_version)
+     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id")
+    .bind(&file_path)
+    .bind(track.title)
+    .bind(artist_id)
+    .bind(album_id)
+    .bind(track.duration_ms)
+    .bind(track.file_format)
+    .bind(track.file_size)
+    .bind(track.date_added.unwrap_or_else(|| current_date_as_int()))
+    .bind(artwork_id)
+    .bind(source_id)
+    .bind(track.file_mtime)
+    .bind(track.file_bytes)
+    .bind(track.content_hash)
+    .bind(track.tags.track_number)
+    .bind(track.tags.track_total)
+    .bind(track.tags.disc_number)
+    .bind(track.tags.disc_total)
+    .bind(track.tags.year)
+    .bind(track.tags.release_date)
+    .bind(track.tags.genre)
+    .bind(track.tags.album_artist)
+    .bind(track.tags.composer)
+    .bind(track.tags.bpm)
+    .bind(track.tags.comment)
+    .bind(track.tags.compilation)
+    .bind(track.tags.bitrate)
+    .bind(track.tags.sample_rate)
+    .bind(track.tags.bit_depth)
+    .bind(TAG_VERSION)
+    .fetch_one(&mut *conn) // Use fetch_one with RETURNING id
+    .await
+    .map_err(|e| format!("Database error: {}", e))?
+    .get::<i64, _>(0); // Retrieve the returned ID
+
+    claim_artist(conn, artist_id, source_id).await?;
+    write_credits(conn, id, artist_id, &track.credits, source_id).await?;
+
+    if !track.chapters.is_empty() {
+        write_chapters(conn, id, &track.chapters).await?;
+    }
+
+    Ok(id)
+}
+
+// Stores the track's picture unless it's already there, and makes it the
+// album's cover if the album has none yet
+async fn store_track_artwork(conn: &mut SqliteConnection, track: &ExtractedTrack, album_id: i64) -> Result<Option<i64>, String> {
+    // add_track from the upload form only has the base64 the preview showed
+    let uploaded = match (&track.artwork, &track.thumbnail_base64) {
+        (None, Some(data)) => prepare_base64_artwork(data, track.thumbnail_mime.clone()),
+        _ => None,
+    };
+    let Some(artwork) = track.artwork.as_ref().or(uploaded.as_ref()) else {
+        return Ok(None);
+    };
+
+    let id = store_artwork(conn, artwork).await?;
+    sqlx::query("UPDATE albums SET artwork_id = ? WHERE id = ? AND id != ? AND artwork_id IS NULL")
+        .bind(id)
+        .bind(album_id)
+        .bind(UNKNOWN_ALBUM_ID)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(Some(id))
+}
+
+async fn store_artwork(conn: &mut SqliteConnection, artwork: &Artwork) -> Result<i64, String> {
+    let existing = sqlx::query_scalar("SELECT id FROM artwork WHERE hash = ?")
+        .bind(&artwork.hash)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+
+    // only a picture we haven't seen is decoded
+    let data = artwork.data.clone();
+    let thumbnails = tauri::async_runtime::spawn_blocking(move || make_thumbnails(&data))
+        .await
+        .map_err(|e| format!("Failed to make thumbnails: {}", e))?;
+
+    sqlx::query_scalar(
+        "INSERT INTO artwork (hash, mime, width, height, data, small, medium)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        RETURNING id",
+    )
+    .bind(&artwork.hash)
+    .bind(&artwork.mime)
+    .bind(thumbnails.width)
+    .bind(thumbnails.height)
+    .bind(&artwork.data)
+    .bind(&thumbnails.small)
+    .bind(&thumbnails.medium)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to save artwork: {}", e))
+}
+
+// pictures nothing shows anymore
+async fn prune_artwork(conn: &mut SqliteConnection) -> Result<(), String> {
+    sqlx::query(
+        "DELETE FROM artwork
+        WHERE NOT EXISTS (SELECT 1 FROM tracks WHERE artwork_id = artwork.id)
+          AND NOT EXISTS (SELECT 1 FROM albums WHERE artwork_id = artwork.id)",
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+    Ok(())
+}
+
+// An artist counts as local once it has a local track, otherwise it
+// belongs to the remote source all of its tracks come from
+async fn claim_artist(conn: &mut SqliteConnection, artist_id: i64, source_id: i64) -> Result<(), String> {
+    sqlx::query(
+        "UPDATE artists SET source_id = ?
+        WHERE id = ? AND id != ?
+          AND (? = ? OR NOT EXISTS (
+              SELECT 1 FROM track_artists ta JOIN tracks t ON t.id = ta.track_id
+              WHERE ta.artist_id = artists.id AND t.source_id != ?))",
+    )
+    .bind(source_id)
+    .bind(artist_id)
+    .bind(UNKNOWN_ARTIST_ID)
+    .bind(source_id)
+    .bind(LOCAL_SOURCE_ID)
+    .bind(source_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+    Ok(())
+}
+
+// The artist the track is filed under comes first, then everyone else on it
+async fn write_credits(
+    conn: &mut SqliteConnection,
+    track_id: i64,
+    artist_id: i64,
+    credits: &[TrackCredit],
+    source_id: i64,
+) -> Result<(), String> {
+    sqlx::query("DELETE FROM track_artists WHERE track_id = ?")
+        .bind(track_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear old credits: {}", e))?;
+
+    let mut artists = Vec::with_capacity(credits.len() + 1);
+    if artist_id != UNKNOWN_ARTIST_ID {
+        artists.push((artist_id, ArtistRole::Primary));
+    }
+    for credit in credits {
+        let id = find_or_create_artist_in(conn, &credit.name).await?;
+        if id != UNKNOWN_ARTIST_ID {
+            claim_artist(conn, id, source_id).await?;
+            artists.push((id, credit.role));
+        }
+    }
 
-    // pub async fn sync_database();
+    for (position, (artist_id, role)) in artists.into_iter().enumerate() {
+        sqlx::query("INSERT OR IGNORE INTO track_artists (track_id, artist_id, role, position) VALUES (?, ?, ?, ?)")
+            .bind(track_id)
+            .bind(artist_id)
+            .bind(role.as_str())
+            .bind(position as i64)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to save credits: {}", e))?;
+    }
+    Ok(())
+}
+
+async fn write_chapters(conn: &mut SqliteConnection, track_id: i64, chapters: &[ExtractedChapter]) -> Result<(), String> {
+    sqlx::query("DELETE FROM chapters WHERE track_id = ?")
+        .bind(track_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear old chapters: {}", e))?;
+
+    for (position, chapter) in chapters.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO chapters (track_id, position, title, start_ms, end_ms) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(track_id)
+        .bind(position as i64)
+        .bind(&chapter.title)
+        .bind(chapter.start_ms)
+        .bind(chapter.end_ms)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to insert chapter at position {}: {}", position, e))?;
+    }
+    Ok(())
 }
 
 // Tauri Commands
@@ -472,6 +2571,39 @@ pub async fn get_artist(state: tauri::State<'_, Database>, name: String) -> Resu
     state.get_artist(name).await
 }
 
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_artist_tracks(state: tauri::State<'_, Database>, artist_id: i64) -> Result<Vec<CreditedTrack>, String> {
+    state.get_artist_tracks(artist_id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_artist_detail(state: tauri::State<'_, Database>, id: i64) -> Result<ArtistDetail, String> {
+    state.get_artist_detail(id).await
+}
+
+// medium unless asked otherwise, the size a grid or list shows
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_artwork(
+    state: tauri::State<'_, Database>,
+    artwork_id: i64,
+    size: Option<ArtworkSize>,
+) -> Result<ArtworkImage, String> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let (data, mime) = state
+        .get_artwork(artwork_id, size.unwrap_or_default())
+        .await?
+        .ok_or_else(|| format!("Artwork {} not found", artwork_id))?;
+
+    Ok(ArtworkImage {
+        mime,
+        base64: general_purpose::STANDARD.encode(data),
+    })
+}
+
 #[allow(dead_code)]
 #[tauri::command]
 pub async fn add_artist(state: tauri::State<'_, Database>, name: String) -> Result<i64, String> {
@@ -514,6 +2646,12 @@ pub async fn get_album(state: tauri::State<'_, Database>, title: String) -> Resu
     state.get_album(title).await
 }
 
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_album_detail(state: tauri::State<'_, Database>, id: i64) -> Result<AlbumDetail, String> {
+    state.get_album_detail(id).await
+}
+
 #[allow(dead_code)]
 #[tauri::command]
 pub async fn add_album(
@@ -565,6 +2703,27 @@ pub async fn get_tracks_with_names(
     state.get_tracks_with_names().await
 }
 
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn list_tracks(
+    state: tauri::State<'_, Database>,
+    query: TrackListQuery,
+) -> Result<TrackPage, String> {
+    state.list_tracks(&query).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn search_library(
+    state: tauri::State<'_, Database>,
+    query: String,
+    limit: Option<i64>,
+) -> Result<SearchResults, String> {
+    // per kind of result
+    let limit = limit.unwrap_or(20).clamp(1, 200);
+    state.search_library(&query, limit).await
+}
+
 #[allow(dead_code)]
 #[tauri::command]
 pub async fn add_track(
@@ -625,6 +2784,49 @@ pub async fn update_playlist(
     state.update_playlist(id, name, description, cover_path, track_ids).await
 }
 
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn save_smart_playlist(
+    state: tauri::State<'_, Database>,
+    name: String,
+    description: Option<String>,
+    rules: SmartPlaylistRules,
+) -> Result<i64, String> {
+    state.save_smart_playlist(name, description, &rules).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn update_smart_playlist(
+    state: tauri::State<'_, Database>,
+    id: i64,
+    name: String,
+    description: Option<String>,
+    rules: SmartPlaylistRules,
+) -> Result<(), String> {
+    state.update_smart_playlist(id, name, description, &rules).await
+}
+
+// Re-evaluates the rules on request, e.g. for "played this week" or a new shuffle
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn refresh_smart_playlist(
+    state: tauri::State<'_, Database>,
+    id: i64,
+) -> Result<Vec<Track>, String> {
+    state.refresh_smart_playlist(id).await?;
+    state.get_playlist_tracks(id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_smart_playlist_rules(
+    state: tauri::State<'_, Database>,
+    id: i64,
+) -> Result<Option<SmartPlaylistRules>, String> {
+    state.get_smart_playlist_rules(id).await
+}
+
 #[allow(dead_code)]
 #[tauri::command]
 pub async fn delete_playlist(
@@ -643,3 +2845,531 @@ pub async fn remove_track(
     state.remove_track(track_id).await
 }
 
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_track_chapters(
+    state: tauri::State<'_, Database>,
+    track_id: i64,
+) -> Result<Vec<Chapter>, String> {
+    state.get_chapters(track_id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn rescan_track_chapters(
+    state: tauri::State<'_, Database>,
+    track_id: i64,
+) -> Result<Vec<Chapter>, String> {
+    state.rescan_chapters(track_id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn record_play(
+    state: tauri::State<'_, Database>,
+    track_id: i64,
+) -> Result<(), String> {
+    state.record_play(track_id, None, "app").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{memory_db, track};
+
+    fn titles(results: &SearchResults) -> Vec<&str> {
+        results.tracks.iter().map(|track| track.title.as_str()).collect()
+    }
+
+    #[test]
+    fn search_ignores_accents() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            db.add_track(track("/music/halo.mp3", "Halo", "Beyoncé", "I Am... Sasha Fierce")).await.unwrap();
+            db.add_track(track("/music/other.mp3", "Other", "Someone", "Else")).await.unwrap();
+
+            let results = db.search_library("beyonce", 20).await.unwrap();
+            assert_eq!(titles(&results), ["Halo"]);
+            assert_eq!(results.artists.len(), 1);
+            assert_eq!(results.artists[0].name, "Beyoncé");
+            assert_eq!(titles(&db.search_library("BEYONCÉ", 20).await.unwrap()), ["Halo"]);
+        });
+    }
+
+    #[test]
+    fn search_matches_prefixes_of_every_word() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            db.add_track(track("/music/1.mp3", "Wish You Were Here", "Pink Floyd", "Wish You Were Here")).await.unwrap();
+            db.add_track(track("/music/2.mp3", "Wishing Well", "Terence Trent D'Arby", "Introducing")).await.unwrap();
+
+            assert_eq!(titles(&db.search_library("wish", 20).await.unwrap()).len(), 2);
+            assert_eq!(titles(&db.search_library("wis floy", 20).await.unwrap()), ["Wish You Were Here"]);
+            let results = db.search_library("pink", 20).await.unwrap();
+            assert_eq!(results.albums.len(), 1);
+            assert_eq!(results.albums[0].title, "Wish You Were Here");
+            assert!(titles(&db.search_library("floydd", 20).await.unwrap()).is_empty());
+        });
+    }
+
+    #[test]
+    fn search_treats_query_syntax_as_words() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            db.add_track(track("/music/1.mp3", "Rock AND Roll", "Band", "Album")).await.unwrap();
+            db.add_track(track("/music/2.mp3", "Roll Over", "Other", "Album")).await.unwrap();
+
+            for query in ["\"rock", "rock AND roll", "roll NOT over", "rock*", "roll:", "(rock) OR", "-roll ^"] {
+                assert!(db.search_library(query, 20).await.is_ok(), "{}", query);
+            }
+            // AND is a word every term must match, not an operator
+            assert_eq!(titles(&db.search_library("and roll", 20).await.unwrap()), ["Rock AND Roll"]);
+            assert_eq!(titles(&db.search_library("roll NOT over", 20).await.unwrap()), Vec::<&str>::new());
+            assert_eq!(titles(&db.search_library("\"rock\" *", 20).await.unwrap()), ["Rock AND Roll"]);
+
+            let nothing = db.search_library("\"* ()", 20).await.unwrap();
+            assert!(nothing.tracks.is_empty() && nothing.artists.is_empty());
+        });
+    }
+
+    #[test]
+    fn fts_query_quotes_every_word() {
+        assert_eq!(fts_query("Beyoncé halo").as_deref(), Some("\"Beyoncé\"* \"halo\"*"));
+        assert_eq!(fts_query("rock AND \"roll\"*").as_deref(), Some("\"rock\"* \"AND\"* \"roll\"*"));
+        assert_eq!(fts_query("AC/DC").as_deref(), Some("\"AC\"* \"DC\"*"));
+        assert_eq!(fts_query("  \"*( ) "), None);
+    }
+
+    fn rules(json: serde_json::Value) -> SmartPlaylistRules {
+        serde_json::from_value(json).unwrap()
+    }
+
+    async fn playlist_titles(db: &Database, id: i64) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT t.title FROM playlist_tracks pt JOIN tracks t ON t.id = pt.track_id
+            WHERE pt.playlist_id = ? ORDER BY pt.position",
+        )
+        .bind(id)
+        .fetch_all(&db.db)
+        .await
+        .unwrap()
+    }
+
+    fn days_ago(days: i64) -> i64 {
+        (chrono::Local::now() - chrono::Duration::days(days)).format("%Y%m%d").to_string().parse().unwrap()
+    }
+
+    async fn add(db: &Database, title: &str, genre: &str, added_days_ago: i64, plays: usize) -> i64 {
+        let mut new = track(&format!("/music/{}.mp3", title), title, "Artist", "Album");
+        new.tags.genre = Some(genre.to_string());
+        new.date_added = Some(days_ago(added_days_ago));
+        let id = db.add_track(new).await.unwrap();
+        for _ in 0..plays {
+            db.record_play(id, None, "app").await.unwrap();
+        }
+        id
+    }
+
+    #[test]
+    fn smart_playlist_of_new_unplayed_jazz() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            add(&db, "Fresh", "Jazz", 2, 0).await;
+            add(&db, "Played a bit", "jazz", 10, 2).await;
+            add(&db, "Played a lot", "Jazz", 10, 3).await;
+            add(&db, "Old", "Jazz", 45, 0).await;
+            add(&db, "Fresh rock", "Rock", 2, 0).await;
+
+            let id = db
+                .save_smart_playlist(
+                    "New jazz".to_string(),
+                    None,
+                    &rules(serde_json::json!({
+                        "rule": {"match": "all", "rules": [
+                            {"field": "genre", "op": "is", "value": "Jazz"},
+                            {"field": "date_added", "op": "within_days", "value": 30},
+                            {"field": "play_count", "op": "lt", "value": "3"},
+                        ]},
+                    })),
+                )
+                .await
+                .unwrap();
+            assert_eq!(playlist_titles(&db, id).await, ["Fresh", "Played a bit"]);
+            assert!(db.get_playlist(id).await.unwrap().is_smart);
+        });
+    }
+
+    #[test]
+    fn smart_playlist_sorts_and_limits() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            add(&db, "Once", "Jazz", 1, 1).await;
+            add(&db, "Never", "Jazz", 1, 0).await;
+            add(&db, "Thrice", "Jazz", 1, 3).await;
+            add(&db, "Twice", "Jazz", 1, 2).await;
+
+            let most_played = |limit: Option<i64>| {
+                rules(serde_json::json!({
+                    "rule": {"match": "all", "rules": []},
+                    "sort": "play_count", "descending": true, "limit": limit,
+                }))
+            };
+            let id = db.save_smart_playlist("Top".to_string(), None, &most_played(Some(2))).await.unwrap();
+            assert_eq!(playlist_titles(&db, id).await, ["Thrice", "Twice"]);
+
+            let by_title = rules(serde_json::json!({"rule": {"match": "all", "rules": []}, "sort": "title"}));
+            db.update_smart_playlist(id, "All".to_string(), None, &by_title).await.unwrap();
+            assert_eq!(playlist_titles(&db, id).await, ["Never", "Once", "Thrice", "Twice"]);
+            // an empty "any" matches nothing
+            let any = rules(serde_json::json!({"rule": {"match": "any", "rules": []}}));
+            db.update_smart_playlist(id, "Any".to_string(), None, &any).await.unwrap();
+            assert!(playlist_titles(&db, id).await.is_empty());
+            assert!(db.save_smart_playlist("None".to_string(), None, &most_played(Some(0))).await.is_err());
+        });
+    }
+
+    #[test]
+    fn smart_rules_refuse_what_they_cannot_compile() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let condition = |field: &str, op: &str, value: serde_json::Value| {
+                serde_json::json!({"rule": {"field": field, "op": op, "value": value}})
+            };
+
+            for json in [
+                condition("mood", "is", "happy".into()),
+                condition("genre", "matches", "Jazz".into()),
+                condition("title; DROP TABLE tracks", "is", "x".into()),
+            ] {
+                assert!(serde_json::from_value::<SmartPlaylistRules>(json.clone()).is_err(), "{}", json);
+            }
+            for json in [
+                condition("genre", "lt", "Jazz".into()),
+                condition("year", "contains", 1999.into()),
+                condition("date_added", "gt", 30.into()),
+                condition("play_count", "lt", "a few".into()),
+                condition("title", "is", serde_json::json!(["a", "b"])),
+            ] {
+                assert!(compile_smart_rules(&rules(json.clone())).is_err(), "{}", json);
+                assert!(db.save_smart_playlist("Bad".to_string(), None, &rules(json)).await.is_err());
+            }
+            let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM playlists WHERE rules IS NOT NULL")
+                .fetch_one(&db.db)
+                .await
+                .unwrap();
+            assert_eq!(stored, 0);
+        });
+    }
+
+    #[test]
+    fn smart_playlists_follow_the_library() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let played = add(&db, "Played", "Jazz", 1, 0).await;
+            let id = db
+                .save_smart_playlist(
+                    "Unplayed".to_string(),
+                    None,
+                    &rules(serde_json::json!({"rule": {"field": "play_count", "op": "is", "value": 0}})),
+                )
+                .await
+                .unwrap();
+            assert_eq!(playlist_titles(&db, id).await, ["Played"]);
+
+            db.record_play(played, None, "app").await.unwrap();
+            add(&db, "New", "Jazz", 1, 0).await;
+            // nothing changes until the playlists are refreshed
+            assert_eq!(playlist_titles(&db, id).await, ["Played"]);
+            db.refresh_smart_playlists().await.unwrap();
+            assert_eq!(playlist_titles(&db, id).await, ["New"]);
+
+            db.mark_missing("/music/New.mp3").await.unwrap();
+            db.refresh_smart_playlists().await.unwrap();
+            assert!(playlist_titles(&db, id).await.is_empty());
+        });
+    }
+
+    // every page in turn, checking each one's total
+    async fn walk(db: &Database, query: &TrackListQuery, total: i64) -> Vec<i64> {
+        let mut query = query.clone();
+        let mut ids = Vec::new();
+        loop {
+            let page = db.list_tracks(&query).await.unwrap();
+            assert_eq!(page.total, total);
+            ids.extend(page.tracks.iter().map(|track| track.id));
+            match page.next {
+                Some(next) => query.after = Some(next),
+                None => return ids,
+            }
+        }
+    }
+
+    #[test]
+    fn track_pages_cover_the_library_once() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            // ties on every key, and tracks without disc or track numbers, album or artist
+            let library = [
+                ("a", "Song", "Artist", "Album", Some(1), Some(1), "mp3"),
+                ("b", "song", "Artist", "Album", Some(1), Some(2), "flac"),
+                ("c", "Song", "Artist", "Album", None, Some(2), "mp3"),
+                ("d", "Intro", "Artist", "Album", None, None, "mp3"),
+                ("e", "Intro", "Artist", "Album", None, None, "flac"),
+                ("f", "Outro", "Artist", "Other", Some(2), None, "mp3"),
+                ("g", "Untitled", "", "", None, None, "mp3"),
+                ("h", "Untitled", "", "", None, None, "mp3"),
+                ("i", "Song", "Band", "Album", Some(1), Some(1), "mp3"),
+                ("j", "Zed", "band", "album", None, Some(1), "ogg"),
+                ("k", "Ärger", "Äther", "Ölbum", None, None, "mp3"),
+            ];
+            let mut all = Vec::new();
+            for (n, (name, title, artist, album, disc, number, format)) in library.into_iter().enumerate() {
+                let mut new = track(&format!("/music/{}.{}", name, format), title, artist, album);
+                new.file_format = format.to_string();
+                new.tags.disc_number = disc;
+                new.tags.track_number = number;
+                new.duration_ms = [180_000, 240_000][n % 2];
+                new.date_added = Some([20260101, 20260102, 20260102][n % 3]);
+                let id = db.add_track(new).await.unwrap();
+                for _ in 0..n % 3 {
+                    db.record_play(id, None, "app").await.unwrap();
+                }
+                all.push(id);
+            }
+
+            let sorts = [
+                TrackSort::Title,
+                TrackSort::Artist,
+                TrackSort::Album,
+                TrackSort::DateAdded,
+                TrackSort::Duration,
+                TrackSort::PlayCount,
+            ];
+            for sort in sorts {
+                for descending in [false, true] {
+                    for (format, total) in [(None, all.len()), (Some("mp3"), 8)] {
+                        let query = TrackListQuery {
+                            sort,
+                            descending,
+                            format: format.map(str::to_string),
+                            ..Default::default()
+                        };
+                        let whole = db.list_tracks(&TrackListQuery { limit: Some(1000), ..query.clone() }).await.unwrap();
+                        let whole: Vec<i64> = whole.tracks.iter().map(|track| track.id).collect();
+                        assert_eq!(whole.len(), total);
+                        if format.is_none() {
+                            let mut sorted = whole.clone();
+                            sorted.sort();
+                            assert_eq!(sorted, all);
+                        }
+
+                        for limit in [1, 2, 3, total as i64] {
+                            let paged = walk(&db, &TrackListQuery { limit: Some(limit), ..query.clone() }, total as i64).await;
+                            assert_eq!(paged, whole, "{:?} descending {} by {} {:?}", sort, descending, limit, format);
+                        }
+                    }
+                }
+            }
+
+            let unknown = TrackListQuery { after: Some(-1), ..Default::default() };
+            assert!(db.list_tracks(&unknown).await.is_err());
+        });
+    }
+
+    // the album a track ended up on, by its title and album artist, and whether it's a compilation
+    async fn album_of(db: &Database, track_id: i64) -> (String, String, bool) {
+        sqlx::query_as(
+            "SELECT al.title, a.name, al.compilation FROM tracks t
+            JOIN albums al ON al.id = t.album_id JOIN artists a ON a.id = al.artist_id
+            WHERE t.id = ?",
+        )
+        .bind(track_id)
+        .fetch_one(&db.db)
+        .await
+        .unwrap()
+    }
+
+    fn on(title: &str, artist: &str, compilation: bool) -> (String, String, bool) {
+        (title.to_string(), artist.to_string(), compilation)
+    }
+
+    #[test]
+    fn mixed_artists_in_a_folder_are_a_compilation() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let ids = db
+                .add_tracks(vec![
+                    track("/music/Now 42/01.mp3", "One", "First Artist", "Now 42"),
+                    track("/music/Now 42/02.mp3", "Two", "Second Artist", "now 42"),
+                    track("/music/Now 42/03.mp3", "Three", "First Artist", "Now 42"),
+                ])
+                .await
+                .unwrap();
+            for id in &ids {
+                assert_eq!(album_of(&db, *id).await, on("Now 42", VARIOUS_ARTISTS, true));
+            }
+            // the albums the tracks were filed under first are gone
+            let albums: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM albums WHERE title = 'Now 42' COLLATE NOCASE")
+                .fetch_one(&db.db)
+                .await
+                .unwrap();
+            assert_eq!(albums, 1);
+        });
+    }
+
+    #[test]
+    fn one_artist_or_an_album_artist_is_not_a_compilation() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let mut with_album_artist = vec![
+                track("/music/Tribute/01.mp3", "One", "First Artist", "Tribute"),
+                track("/music/Tribute/02.mp3", "Two", "Second Artist", "Tribute"),
+            ];
+            for new in &mut with_album_artist {
+                new.tags.album_artist = Some("Curator".to_string());
+            }
+            let tribute = db.add_tracks(with_album_artist).await.unwrap();
+            let solo = db
+                .add_tracks(vec![
+                    track("/music/Solo/01.mp3", "One", "Artist", "Solo"),
+                    track("/music/Solo/02.mp3", "Two", "Artist", "Solo"),
+                ])
+                .await
+                .unwrap();
+
+            for id in tribute {
+                assert_eq!(album_of(&db, id).await, on("Tribute", "Curator", false));
+            }
+            for id in solo {
+                assert_eq!(album_of(&db, id).await, on("Solo", "Artist", false));
+            }
+        });
+    }
+
+    #[test]
+    fn subfolders_are_albums_of_their_own() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let ids = db
+                .add_tracks(vec![
+                    track("/music/Box Set/01.mp3", "One", "First Artist", "Box Set"),
+                    track("/music/Box Set/CD2/01.mp3", "Two", "Second Artist", "Box Set"),
+                    track("/music/Box Set/CD3/01.mp3", "Three", "Third Artist", "Box Set"),
+                ])
+                .await
+                .unwrap();
+            assert_eq!(album_of(&db, ids[0]).await, on("Box Set", "First Artist", false));
+            assert_eq!(album_of(&db, ids[1]).await, on("Box Set", "Second Artist", false));
+            assert_eq!(album_of(&db, ids[2]).await, on("Box Set", "Third Artist", false));
+        });
+    }
+
+    #[test]
+    fn the_compilation_flag_wins() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let mut flagged = vec![
+                track("/music/Best Of/01.mp3", "One", "Artist", "Best Of"),
+                track("/music/Best Of/02.mp3", "Two", "Artist", "Best Of"),
+            ];
+            for new in &mut flagged {
+                new.tags.compilation = true;
+            }
+            let ids = db.add_tracks(flagged).await.unwrap();
+            for id in ids {
+                assert_eq!(album_of(&db, id).await, on("Best Of", VARIOUS_ARTISTS, true));
+            }
+        });
+    }
+
+    async fn paths(db: &Database) -> Vec<(String, bool)> {
+        sqlx::query_as("SELECT file_path, missing_since IS NOT NULL FROM tracks ORDER BY file_path")
+            .fetch_all(&db.db)
+            .await
+            .unwrap()
+    }
+
+    fn at(path: &str, missing: bool) -> (String, bool) {
+        (path.to_string(), missing)
+    }
+
+    #[test]
+    fn folders_match_with_their_case() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            db.add_tracks(vec![
+                track("/music/rock/a.mp3", "A", "Artist", "Album"),
+                track("/music/Rock/b.mp3", "B", "Artist", "Album"),
+                track("/music/rocks/c.mp3", "C", "Artist", "Album"),
+                track("/music/ro_k/d.mp3", "D", "Artist", "Album"),
+            ])
+            .await
+            .unwrap();
+
+            assert_eq!(db.mark_missing("/music/rock").await.unwrap(), 1);
+            assert_eq!(db.mark_missing("/music/ro_k/").await.unwrap(), 1);
+            assert_eq!(
+                paths(&db).await,
+                [
+                    at("/music/Rock/b.mp3", false),
+                    at("/music/ro_k/d.mp3", true),
+                    at("/music/rock/a.mp3", true),
+                    at("/music/rocks/c.mp3", false),
+                ]
+            );
+
+            assert_eq!(db.move_folder("/music/rock", "/music/Stone").await.unwrap(), 1);
+            assert_eq!(
+                paths(&db).await,
+                [
+                    at("/music/Rock/b.mp3", false),
+                    at("/music/Stone/a.mp3", false),
+                    at("/music/ro_k/d.mp3", true),
+                    at("/music/rocks/c.mp3", false),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn a_moved_folder_takes_over_rows_already_at_its_paths() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let moved = db.add_track(track("/music/old/a.mp3", "A", "Artist", "Album")).await.unwrap();
+            // the watcher saw the file arrive before the folder was known to have moved
+            let early = db.add_track(track("/music/new/a.mp3", "A", "Artist", "Album")).await.unwrap();
+            db.record_play(moved, None, "app").await.unwrap();
+            db.record_play(early, None, "app").await.unwrap();
+            let playlist: i64 = sqlx::query_scalar("INSERT INTO playlists (name) VALUES ('Mix') RETURNING id")
+                .fetch_one(&db.db)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (?, ?, 0)")
+                .bind(playlist)
+                .bind(early)
+                .execute(&db.db)
+                .await
+                .unwrap();
+
+            assert_eq!(db.move_folder("/music/old", "/music/new").await.unwrap(), 1);
+
+            assert_eq!(paths(&db).await, [at("/music/new/a.mp3", false)]);
+            assert_eq!(db.get_track(moved).await.unwrap().unwrap().file_path, "/music/new/a.mp3");
+            assert!(db.get_track(early).await.unwrap().is_none());
+            let plays: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM play_history WHERE track_id = ?")
+                .bind(moved)
+                .fetch_one(&db.db)
+                .await
+                .unwrap();
+            assert_eq!(plays, 2);
+            let listed: Vec<i64> = sqlx::query_scalar("SELECT track_id FROM playlist_tracks WHERE playlist_id = ?")
+                .bind(playlist)
+                .fetch_all(&db.db)
+                .await
+                .unwrap();
+            assert_eq!(listed, [moved]);
+            // and the search index lost the row that went
+            assert_eq!(db.search_library("A", 20).await.unwrap().tracks.len(), 1);
+        });
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+
+use crate::{
+    db::LOCAL_SOURCE_ID,
+    models::Track,
+    subsonic::{AlbumSummary, ArtistSummary, PlaylistSummary},
+    utils::{audio_content_type, escape_xml},
+};
+
+use super::ServerState;
+
+// UPnP error codes for the Browse action
+pub const ERROR_INVALID_ARGS: u32 = 402;
+pub const ERROR_NO_SUCH_OBJECT: u32 = 701;
+pub const ERROR_ACTION_FAILED: u32 = 501;
+
+// Streaming with byte seeks, see DLNA guidelines 7.4.1.3
+pub const DLNA_FEATURES: &str = "DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+
+#[derive(Debug)]
+pub struct BrowseError {
+    pub code: u32,
+    pub message: String,
+}
+
+impl BrowseError {
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl From<String> for BrowseError {
+    fn from(message: String) -> Self {
+        Self::new(ERROR_ACTION_FAILED, message)
+    }
+}
+
+// The tree renderers browse: the root holds Artists, Albums and Playlists,
+// artists hold their albums and albums and playlists hold tracks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectId {
+    Root,
+    Artists,
+    Albums,
+    Playlists,
+    Artist(i64),
+    Album(i64),
+    Playlist(i64),
+    Track(i64),
+}
+
+impl ObjectId {
+    pub fn parse(id: &str) -> Option<Self> {
+        match id {
+            "0" => return Some(Self::Root),
+            "artists" => return Some(Self::Artists),
+            "albums" => return Some(Self::Albums),
+            "playlists" => return Some(Self::Playlists),
+            _ => {}
+        }
+
+        let (kind, id) = id.split_once('-')?;
+        let id = id.parse().ok()?;
+        match kind {
+            "artist" => Some(Self::Artist(id)),
+            "album" => Some(Self::Album(id)),
+            "playlist" => Some(Self::Playlist(id)),
+            "track" => Some(Self::Track(id)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Root => write!(f, "0"),
+            Self::Artists => write!(f, "artists"),
+            Self::Albums => write!(f, "albums"),
+            Self::Playlists => write!(f, "playlists"),
+            Self::Artist(id) => write!(f, "artist-{}", id),
+            Self::Album(id) => write!(f, "album-{}", id),
+            Self::Playlist(id) => write!(f, "playlist-{}", id),
+            Self::Track(id) => write!(f, "track-{}", id),
+        }
+    }
+}
+
+pub struct BrowseResult {
+    pub didl: String,
+    pub returned: usize,
+    pub total: usize,
+}
+
+// BrowseDirectChildren, `count` of 0 means everything from `start`
+pub async fn browse_children(
+    state: &ServerState,
+    base_url: &str,
+    id: ObjectId,
+    start: usize,
+    count: usize,
+) -> Result<BrowseResult, BrowseError> {
+    let parent = id.to_string();
+    let objects: Vec<String> = match id {
+        ObjectId::Root => root_folders(state).await?.into_iter().map(|(_, folder)| folder).collect(),
+        ObjectId::Artists => state
+            .db
+            .subsonic_artists("", -1, 0)
+            .await?
+            .iter()
+            .map(|artist| artist_container(artist, &parent))
+            .collect(),
+        ObjectId::Albums => album_containers(state, base_url, None, &parent).await?,
+        ObjectId::Artist(artist_id) => album_containers(state, base_url, Some(artist_id), &parent).await?,
+        ObjectId::Playlists => state
+            .db
+            .subsonic_playlists(None)
+            .await?
+            .iter()
+            .map(|playlist| playlist_container(playlist, &parent))
+            .collect(),
+        ObjectId::Album(album_id) => track_items(&state.db.subsonic_album_tracks(album_id).await?, base_url, &parent),
+        ObjectId::Playlist(playlist_id) => {
+            track_items(&state.db.get_playlist_tracks(playlist_id).await?, base_url, &parent)
+        }
+        ObjectId::Track(_) => return Err(BrowseError::new(ERROR_INVALID_ARGS, "Tracks have no children")),
+    };
+
+    let total = objects.len();
+    let page: Vec<String> = objects
+        .into_iter()
+        .skip(start)
+        .take(if count == 0 { usize::MAX } else { count })
+        .collect();
+
+    Ok(BrowseResult { returned: page.len(), didl: didl(&page), total })
+}
+
+// BrowseMetadata, the object itself
+pub async fn browse_metadata(state: &ServerState, base_url: &str, id: ObjectId) -> Result<BrowseResult, BrowseError> {
+    let missing = || BrowseError::new(ERROR_NO_SUCH_OBJECT, format!("No such object: {}", id));
+
+    let object = match id {
+        ObjectId::Root => format!(
+            r#"<container id="0" parentID="-1" restricted="1" searchable="0" childCount="3"><dc:title>{}</dc:title><upnp:class>object.container.storageFolder</upnp:class></container>"#,
+            escape_xml(&state.friendly_name)
+        ),
+        ObjectId::Artists | ObjectId::Albums | ObjectId::Playlists => root_folders(state)
+            .await?
+            .into_iter()
+            .find(|(folder_id, _)| *folder_id == id)
+            .map(|(_, folder)| folder)
+            .ok_or_else(missing)?,
+        ObjectId::Artist(artist_id) => {
+            let artist = state.db.subsonic_artist(artist_id).await?.ok_or_else(missing)?;
+            artist_container(&artist, &ObjectId::Artists.to_string())
+        }
+        ObjectId::Album(album_id) => {
+            let album = state.db.subsonic_album(album_id).await?.ok_or_else(missing)?;
+            album_container(&album, base_url, &ObjectId::Albums.to_string())
+        }
+        ObjectId::Playlist(playlist_id) => {
+            let playlist = state.db.subsonic_playlists(Some(playlist_id)).await?;
+            playlist_container(playlist.first().ok_or_else(missing)?, &ObjectId::Playlists.to_string())
+        }
+        ObjectId::Track(track_id) => {
+            let track = state.db.get_track(track_id).await?.filter(is_shared).ok_or_else(missing)?;
+            track_item(&track, base_url, &ObjectId::Album(track.album_id).to_string())
+        }
+    };
+
+    Ok(BrowseResult { didl: didl(&[object]), returned: 1, total: 1 })
+}
+
+fn didl(objects: &[String]) -> String {
+    format!(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/" xmlns:dlna="urn:schemas-dlna-org:metadata-1-0/">{}</DIDL-Lite>"#,
+        objects.concat()
+    )
+}
+
+async fn root_folders(state: &ServerState) -> Result<Vec<(ObjectId, String)>, BrowseError> {
+    let artists = state.db.subsonic_artists("", -1, 0).await?.len();
+    let albums = state.db.subsonic_albums(None, "", -1, 0).await?.len();
+    let playlists = state.db.subsonic_playlists(None).await?.len();
+
+    Ok(vec![
+        (ObjectId::Artists, folder(ObjectId::Artists, "Artists", artists)),
+        (ObjectId::Albums, folder(ObjectId::Albums, "Albums", albums)),
+        (ObjectId::Playlists, folder(ObjectId::Playlists, "Playlists", playlists)),
+    ])
+}
+
+fn folder(id: ObjectId, title: &str, child_count: usize) -> String {
+    format!(
+        r#"<container id="{}" parentID="0" restricted="1" searchable="0" childCount="{}"><dc:title>{}</dc:title><upnp:class>object.container.storageFolder</upnp:class></container>"#,
+        id, child_count, title
+    )
+}
+
+fn artist_container(artist: &ArtistSummary, parent: &str) -> String {
+    format!(
+        r#"<container id="{}" parentID="{}" restricted="1" searchable="0" childCount="{}"><dc:title>{}</dc:title><upnp:class>object.container.person.musicArtist</upnp:class></container>"#,
+        ObjectId::Artist(artist.id),
+        parent,
+        artist.album_count,
+        escape_xml(&artist.name)
+    )
+}
+
+async fn album_containers(
+    state: &ServerState,
+    base_url: &str,
+    artist_id: Option<i64>,
+    parent: &str,
+) -> Result<Vec<String>, BrowseError> {
+    Ok(state
+        .db
+        .subsonic_albums(artist_id, "", -1, 0)
+        .await?
+        .iter()
+        .map(|album| album_container(album, base_url, parent))
+        .collect())
+}
+
+fn album_container(album: &AlbumSummary, base_url: &str, parent: &str) -> String {
+    let id = ObjectId::Album(album.id);
+    let artist = album
+        .artist_name
+        .as_deref()
+        .map(|name| format!("<upnp:artist>{0}</upnp:artist><dc:creator>{0}</dc:creator>", escape_xml(name)))
+        .unwrap_or_default();
+    let art = if album.has_cover {
+        format!("<upnp:albumArtURI>{}/art/{}</upnp:albumArtURI>", base_url, id)
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<container id="{}" parentID="{}" restricted="1" searchable="0" childCount="{}"><dc:title>{}</dc:title><upnp:class>object.container.album.musicAlbum</upnp:class>{}{}</container>"#,
+        id,
+        parent,
+        album.song_count,
+        escape_xml(&album.title),
+        artist,
+        art
+    )
+}
+
+fn playlist_container(playlist: &PlaylistSummary, parent: &str) -> String {
+    format!(
+        r#"<container id="{}" parentID="{}" restricted="1" searchable="0" childCount="{}"><dc:title>{}</dc:title><upnp:class>object.container.playlistContainer</upnp:class></container>"#,
+        ObjectId::Playlist(playlist.id),
+        parent,
+        playlist.song_count,
+        escape_xml(&playlist.name)
+    )
+}
+
+// Only files on this machine can be served, tracks from remote sources are left out
+fn is_shared(track: &Track) -> bool {
+    track.source_id == LOCAL_SOURCE_ID
+}
+
+fn track_items(tracks: &[Track], base_url: &str, parent: &str) -> Vec<String> {
+    tracks
+        .iter()
+        .filter(|track| is_shared(track))
+        .map(|track| track_item(track, base_url, parent))
+        .collect()
+}
+
+fn track_item(track: &Track, base_url: &str, parent: &str) -> String {
+    let id = ObjectId::Track(track.id);
+    let mut fields = format!(
+        "<dc:title>{}</dc:title><upnp:class>object.item.audioItem.musicTrack</upnp:class>",
+        escape_xml(&track.title)
+    );
+    if let Some(artist) = &track.artist_name {
+        let artist = escape_xml(artist);
+        fields.push_str(&format!("<upnp:artist>{0}</upnp:artist><dc:creator>{0}</dc:creator>", artist));
+    }
+    if let Some(album) = &track.album_name {
+        fields.push_str(&format!("<upnp:album>{}</upnp:album>", escape_xml(album)));
+    }
+    if let Some(number) = track.tags.track_number {
+        fields.push_str(&format!("<upnp:originalTrackNumber>{}</upnp:originalTrackNumber>", number));
+    }
+    if let Some(genre) = &track.tags.genre {
+        fields.push_str(&format!("<upnp:genre>{}</upnp:genre>", escape_xml(genre)));
+    }
+    if let Some(year) = track.tags.year {
+        fields.push_str(&format!("<dc:date>{:04}-01-01</dc:date>", year));
+    }
+    fields.push_str(&format!("<upnp:albumArtURI>{}/art/{}</upnp:albumArtURI>", base_url, id));
+
+    let ms = track.duration_ms.max(0);
+    let duration = format!("{}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000);
+
+    format!(
+        r#"<item id="{}" parentID="{}" restricted="1">{}<res protocolInfo="http-get:*:{}:{}" duration="{}">{}/media/{}.{}</res></item>"#,
+        id,
+        parent,
+        fields,
+        audio_content_type(&track.file_format),
+        DLNA_FEATURES,
+        duration,
+        base_url,
+        track.id,
+        escape_xml(&track.file_format)
+    )
+}
@@ -0,0 +1,162 @@
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use tauri::{AppHandle, State};
+use tokio::sync::oneshot;
+
+use crate::{
+    models::AppState as Database,
+    user_config::{self, DlnaConfig},
+};
+
+mod content_directory;
+mod descriptions;
+mod routes;
+mod ssdp;
+
+// What the HTTP handlers need to describe the device and browse the library
+#[derive(Clone)]
+pub struct ServerState {
+    pub db: Database,
+    pub uuid: String,
+    pub friendly_name: String,
+    // renderers cache browse results per update id, a new one each run is enough
+    pub system_update_id: u32,
+}
+
+struct RunningServer {
+    port: u16,
+    shutdown: oneshot::Sender<()>,
+    ssdp_shutdown: oneshot::Sender<()>,
+}
+
+// UPnP MediaServer so TVs and AV receivers on the LAN can browse and play the
+// library. Devices find it through SSDP, then talk HTTP to the port in the config.
+#[derive(Default)]
+pub struct DlnaServer {
+    running: Mutex<Option<RunningServer>>,
+}
+
+#[allow(dead_code)]
+impl DlnaServer {
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().is_some()
+    }
+
+    pub async fn start(&self, db: Database, config: &DlnaConfig) -> Result<(), String> {
+        self.stop();
+
+        if config.uuid.is_empty() {
+            return Err("The DLNA server has no device id".to_string());
+        }
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("Failed to bind DLNA port {}: {}", config.port, e))?;
+        let socket = ssdp::bind()?;
+
+        let state = ServerState {
+            db,
+            uuid: config.uuid.clone(),
+            friendly_name: config.friendly_name.trim().to_string(),
+            system_update_id: chrono::Utc::now().timestamp() as u32,
+        };
+        let router = routes::router(state);
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        tauri::async_runtime::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                log::error!("DLNA server stopped: {}", e);
+            }
+        });
+
+        let (ssdp_shutdown, ssdp_shutdown_rx) = oneshot::channel();
+        let (uuid, port) = (config.uuid.clone(), config.port);
+        tauri::async_runtime::spawn(async move {
+            ssdp::run(socket, &uuid, port, ssdp_shutdown_rx).await;
+        });
+
+        *self.running.lock().unwrap() = Some(RunningServer { port: config.port, shutdown, ssdp_shutdown });
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(server) = self.running.lock().unwrap().take() {
+            // SSDP says goodbye first so devices drop us right away
+            let _ = server.ssdp_shutdown.send(());
+            let _ = server.shutdown.send(());
+        }
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.running.lock().unwrap().as_ref().map(|s| s.port)
+    }
+}
+
+// Random (version 4) uuid identifying this device on the network
+fn new_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// The saved uuid wins over whatever the caller sent, it should never change
+fn with_uuid(app: &AppHandle, mut config: DlnaConfig) -> DlnaConfig {
+    let saved = user_config::load_dlna_config(app).uuid;
+    config.uuid = if saved.is_empty() { new_uuid() } else { saved };
+    config
+}
+
+// Called once from setup(), only starts the server if the user enabled it before
+pub fn start_if_enabled(app: AppHandle, db: Database) {
+    let config = user_config::load_dlna_config(&app);
+    if !config.enabled {
+        return;
+    }
+    let config = with_uuid(&app, config);
+    if let Err(e) = user_config::save_dlna_config(&app, &config) {
+        log::warn!("{}", e);
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let server = tauri::Manager::state::<DlnaServer>(&app);
+        if let Err(e) = server.start(db, &config).await {
+            log::error!("{}", e);
+        }
+    });
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_dlna_config(app: AppHandle) -> DlnaConfig {
+    user_config::load_dlna_config(&app)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_dlna_config(
+    app: AppHandle,
+    state: State<'_, Database>,
+    server: State<'_, DlnaServer>,
+    config: DlnaConfig,
+) -> Result<DlnaConfig, String> {
+    let config = with_uuid(&app, config);
+    if config.enabled {
+        server.start(state.inner().clone(), &config).await?;
+    } else {
+        server.stop();
+    }
+
+    user_config::save_dlna_config(&app, &config)?;
+    Ok(config)
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{any, get, post},
+    Router,
+};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use super::content_directory::{
+    self, BrowseError, ObjectId, DLNA_FEATURES, ERROR_ACTION_FAILED, ERROR_INVALID_ARGS, ERROR_NO_SUCH_OBJECT,
+};
+use super::descriptions::{self, CONNECTION_MANAGER_SCPD, CONTENT_DIRECTORY_SCPD};
+use super::ssdp::{CONNECTION_MANAGER, CONTENT_DIRECTORY};
+use super::ServerState;
+use crate::{
+    db::LOCAL_SOURCE_ID,
+    models::ArtworkSize,
+    subsonic::ItemId,
+    utils::{audio_content_type, escape_xml, ranged_file_response},
+};
+
+const ERROR_INVALID_ACTION: u32 = 401;
+
+// formats renderers can expect from us, reported by GetProtocolInfo
+const SOURCE_FORMATS: &[&str] = &["mp3", "flac", "ogg", "m4a", "wav", "aiff", "wma"];
+
+pub fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/description.xml", get(description))
+        .route("/ContentDirectory.xml", get(|| async { xml(CONTENT_DIRECTORY_SCPD.to_string()) }))
+        .route("/ConnectionManager.xml", get(|| async { xml(CONNECTION_MANAGER_SCPD.to_string()) }))
+        .route("/control/ContentDirectory", post(content_directory))
+        .route("/control/ConnectionManager", post(connection_manager))
+        .route("/events/{service}", any(events))
+        .route("/media/{file}", get(media))
+        .route("/art/{id}", get(art))
+        .with_state(state)
+}
+
+fn xml(body: String) -> Response {
+    ([(header::CONTENT_TYPE, r#"text/xml; charset="utf-8""#)], body).into_response()
+}
+
+async fn description(State(state): State<ServerState>) -> Response {
+    xml(descriptions::device(&state.uuid, &state.friendly_name))
+}
+
+// The action name from `SOAPACTION: "urn:...:ContentDirectory:1#Browse"`
+fn soap_action(headers: &HeaderMap) -> String {
+    headers
+        .get("soapaction")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim_matches('"').rsplit_once('#'))
+        .map(|(_, action)| action.to_string())
+        .unwrap_or_default()
+}
+
+// In-arguments of a SOAP call, by element name without namespace prefix
+fn soap_args(body: &str) -> HashMap<String, String> {
+    let mut reader = Reader::from_str(body);
+    let mut args = HashMap::new();
+    let mut current: Option<String> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                current = Some(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+            }
+            Ok(Event::Text(e)) => {
+                if let (Some(name), Ok(value)) = (&current, e.unescape()) {
+                    args.insert(name.clone(), value.into_owned());
+                }
+            }
+            Ok(Event::End(_)) => current = None,
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    args
+}
+
+fn soap_response(service: &str, action: &str, args: &[(&str, String)]) -> Response {
+    let args: String = args
+        .iter()
+        .map(|(name, value)| format!("<{0}>{1}</{0}>", name, escape_xml(value)))
+        .collect();
+    xml(format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{action}Response xmlns:u="{service}">{args}</u:{action}Response></s:Body></s:Envelope>"#,
+    ))
+}
+
+fn soap_fault(code: u32, description: &str) -> Response {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"#,
+        code,
+        escape_xml(description)
+    );
+    (StatusCode::INTERNAL_SERVER_ERROR, xml(body)).into_response()
+}
+
+async fn content_directory(State(state): State<ServerState>, headers: HeaderMap, body: String) -> Response {
+    let action = soap_action(&headers);
+    match action.as_str() {
+        "Browse" => match browse(&state, &headers, &soap_args(&body)).await {
+            Ok(response) => response,
+            Err(e) => soap_fault(e.code, &e.message),
+        },
+        "GetSearchCapabilities" => soap_response(CONTENT_DIRECTORY, &action, &[("SearchCaps", String::new())]),
+        "GetSortCapabilities" => soap_response(CONTENT_DIRECTORY, &action, &[("SortCaps", String::new())]),
+        "GetSystemUpdateID" => soap_response(CONTENT_DIRECTORY, &action, &[("Id", state.system_update_id.to_string())]),
+        _ => soap_fault(ERROR_INVALID_ACTION, "Invalid Action"),
+    }
+}
+
+async fn browse(
+    state: &ServerState,
+    headers: &HeaderMap,
+    args: &HashMap<String, String>,
+) -> Result<Response, BrowseError> {
+    // links go back to whichever address the renderer reached us on
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| BrowseError::new(ERROR_ACTION_FAILED, "Missing Host header"))?;
+    let base_url = format!("http://{}", host);
+
+    let object_id = args.get("ObjectID").map(String::as_str).unwrap_or("0");
+    let id = ObjectId::parse(object_id)
+        .ok_or_else(|| BrowseError::new(ERROR_NO_SUCH_OBJECT, format!("No such object: {}", object_id)))?;
+    let index = |name: &str| args.get(name).and_then(|v| v.trim().parse::<usize>().ok()).unwrap_or(0);
+
+    let result = match args.get("BrowseFlag").map(String::as_str) {
+        Some("BrowseDirectChildren") => {
+            content_directory::browse_children(state, &base_url, id, index("StartingIndex"), index("RequestedCount"))
+                .await?
+        }
+        Some("BrowseMetadata") => content_directory::browse_metadata(state, &base_url, id).await?,
+        _ => return Err(BrowseError::new(ERROR_INVALID_ARGS, "Invalid BrowseFlag")),
+    };
+
+    Ok(soap_response(
+        CONTENT_DIRECTORY,
+        "Browse",
+        &[
+            ("Result", result.didl),
+            ("NumberReturned", result.returned.to_string()),
+            ("TotalMatches", result.total.to_string()),
+            ("UpdateID", state.system_update_id.to_string()),
+        ],
+    ))
+}
+
+async fn connection_manager(headers: HeaderMap) -> Response {
+    let action = soap_action(&headers);
+    match action.as_str() {
+        "GetProtocolInfo" => {
+            let source = SOURCE_FORMATS
+                .iter()
+                .map(|format| format!("http-get:*:{}:*", audio_content_type(format)))
+                .collect::<Vec<_>>()
+                .join(",");
+            soap_response(CONNECTION_MANAGER, &action, &[("Source", source), ("Sink", String::new())])
+        }
+        "GetCurrentConnectionIDs" => soap_response(CONNECTION_MANAGER, &action, &[("ConnectionIDs", "0".to_string())]),
+        "GetCurrentConnectionInfo" => soap_response(
+            CONNECTION_MANAGER,
+            &action,
+            &[
+                ("RcsID", "-1".to_string()),
+                ("AVTransportID", "-1".to_string()),
+                ("ProtocolInfo", String::new()),
+                ("PeerConnectionManager", String::new()),
+                ("PeerConnectionID", "-1".to_string()),
+                ("Direction", "Output".to_string()),
+                ("Status", "OK".to_string()),
+            ],
+        ),
+        _ => soap_fault(ERROR_INVALID_ACTION, "Invalid Action"),
+    }
+}
+
+// Some control points refuse a device whose services can't be subscribed to.
+// Subscriptions are accepted but no events are sent, the library only changes
+// between runs as far as renderers are concerned.
+async fn events(method: Method) -> Response {
+    match method.as_str() {
+        "SUBSCRIBE" => {
+            let sid = format!("uuid:{}", super::new_uuid());
+            (StatusCode::OK, [("SID", sid), ("TIMEOUT", "Second-1800".to_string())]).into_response()
+        }
+        "UNSUBSCRIBE" => StatusCode::OK.into_response(),
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+// `/media/<track id>.<format>`, the extension is only there for picky renderers
+async fn media(State(state): State<ServerState>, Path(file): Path<String>, headers: HeaderMap) -> Response {
+    let not_found = || (StatusCode::NOT_FOUND, "Track not found").into_response();
+
+    let Some(id) = file.split('.').next().and_then(|id| id.parse::<i64>().ok()) else {
+        return not_found();
+    };
+    let track = match state.db.get_track(id).await {
+        Ok(Some(track)) if track.source_id == LOCAL_SOURCE_ID => track,
+        Ok(_) => return not_found(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    let Ok(file) = tokio::fs::File::open(&track.file_path).await else {
+        return not_found();
+    };
+
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    match ranged_file_response(file, audio_content_type(&track.file_format), range).await {
+        Ok(mut response) => {
+            let dlna_headers = response.headers_mut();
+            dlna_headers.insert("transfermode.dlna.org", HeaderValue::from_static("Streaming"));
+            dlna_headers.insert("contentfeatures.dlna.org", HeaderValue::from_static(DLNA_FEATURES));
+            response
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+// `/art/<object id>` for albums and tracks, the same artwork Subsonic clients get
+async fn art(State(state): State<ServerState>, Path(id): Path<String>) -> Response {
+    let item = match ObjectId::parse(&id) {
+        Some(ObjectId::Album(id)) => ItemId::Album(id),
+        Some(ObjectId::Track(id)) => ItemId::Track(id),
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    match state.db.subsonic_cover_art(item, ArtworkSize::Full).await {
+        Ok(Some((bytes, mime))) => ([(header::CONTENT_TYPE, mime)], bytes).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
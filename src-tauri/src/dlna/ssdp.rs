@@ -0,0 +1,148 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+const MAX_AGE: u64 = 1800;
+// re-announced well within max-age so devices never time us out
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(MAX_AGE / 2);
+
+pub const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+pub const CONTENT_DIRECTORY: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+pub const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+
+// Port 1900 is shared with every other UPnP program on the machine, so the
+// socket has to be bound with address reuse before tokio takes it over
+pub fn bind() -> Result<UdpSocket, String> {
+    let map_err = |e: std::io::Error| format!("Failed to open SSDP socket: {}", e);
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(map_err)?;
+    socket.set_reuse_address(true).map_err(map_err)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true).map_err(map_err)?;
+    socket.set_nonblocking(true).map_err(map_err)?;
+    socket
+        .bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, SSDP_PORT)).into())
+        .map_err(map_err)?;
+
+    let socket = UdpSocket::from_std(socket.into()).map_err(map_err)?;
+    socket
+        .join_multicast_v4(MULTICAST_ADDR, Ipv4Addr::UNSPECIFIED)
+        .map_err(map_err)?;
+    socket.set_multicast_ttl_v4(2).map_err(map_err)?;
+    Ok(socket)
+}
+
+// Announces the server until shutdown and answers M-SEARCH requests
+pub async fn run(socket: UdpSocket, uuid: &str, http_port: u16, mut shutdown: oneshot::Receiver<()>) {
+    let multicast = SocketAddr::from((MULTICAST_ADDR, SSDP_PORT));
+    let mut notify = tokio::time::interval(NOTIFY_INTERVAL);
+    let mut buf = [0u8; 2048];
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = notify.tick() => {
+                if let Some(ip) = local_ip_for(multicast) {
+                    for (nt, usn) in targets(uuid) {
+                        let message = notify_alive(&nt, &usn, &location(ip, http_port));
+                        let _ = socket.send_to(message.as_bytes(), multicast).await;
+                    }
+                }
+            }
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, peer)) = received else { continue };
+                let Some(search_target) = parse_search(&buf[..len]) else { continue };
+                let Some(ip) = local_ip_for(peer) else { continue };
+
+                for (st, usn) in targets(uuid) {
+                    if search_target == "ssdp:all" || search_target == st {
+                        let message = search_response(&st, &usn, &location(ip, http_port));
+                        let _ = socket.send_to(message.as_bytes(), peer).await;
+                    }
+                }
+            }
+        }
+    }
+
+    for (nt, usn) in targets(uuid) {
+        let _ = socket.send_to(notify_byebye(&nt, &usn).as_bytes(), multicast).await;
+    }
+}
+
+// (notification type, unique service name) for the device and each service
+fn targets(uuid: &str) -> Vec<(String, String)> {
+    let udn = format!("uuid:{}", uuid);
+    let mut targets = vec![
+        ("upnp:rootdevice".to_string(), format!("{}::upnp:rootdevice", udn)),
+        (udn.clone(), udn.clone()),
+    ];
+    for kind in [DEVICE_TYPE, CONTENT_DIRECTORY, CONNECTION_MANAGER] {
+        targets.push((kind.to_string(), format!("{}::{}", udn, kind)));
+    }
+    targets
+}
+
+// The ST header of an `ssdp:discover` M-SEARCH, anything else is ignored
+fn parse_search(packet: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(packet).ok()?;
+    let mut lines = text.lines();
+    if !lines.next()?.starts_with("M-SEARCH ") {
+        return None;
+    }
+
+    let mut is_discover = false;
+    let mut search_target = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        match name.trim().to_ascii_uppercase().as_str() {
+            "MAN" => is_discover = value.trim_matches('"') == "ssdp:discover",
+            "ST" => search_target = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    search_target.filter(|_| is_discover)
+}
+
+// Address of the interface that reaches `peer`, that's the one to advertise to it.
+// Connecting a UDP socket only picks the route, nothing is sent.
+fn local_ip_for(peer: SocketAddr) -> Option<IpAddr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(peer).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+fn location(ip: IpAddr, port: u16) -> String {
+    format!("http://{}:{}/description.xml", ip, port)
+}
+
+fn server_header() -> String {
+    format!("{}/1.0 UPnP/1.0 Tamaureus/{}", std::env::consts::OS, env!("CARGO_PKG_VERSION"))
+}
+
+fn notify_alive(nt: &str, usn: &str, location: &str) -> String {
+    format!(
+        "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nCACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nNT: {}\r\nNTS: ssdp:alive\r\nSERVER: {}\r\nUSN: {}\r\n\r\n",
+        MULTICAST_ADDR, SSDP_PORT, MAX_AGE, location, nt, server_header(), usn
+    )
+}
+
+fn notify_byebye(nt: &str, usn: &str) -> String {
+    format!(
+        "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nNT: {}\r\nNTS: ssdp:byebye\r\nUSN: {}\r\n\r\n",
+        MULTICAST_ADDR, SSDP_PORT, nt, usn
+    )
+}
+
+fn search_response(st: &str, usn: &str, location: &str) -> String {
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT");
+    format!(
+        "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nDATE: {}\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\n\r\n",
+        MAX_AGE, date, location, server_header(), st, usn
+    )
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use tauri::{AppHandle, State};
+
+use crate::{
+    db::{UNKNOWN_ALBUM_ID, UNKNOWN_ARTIST_ID},
+    editor::{current_value, edit_track, set_edit_value},
+    models::{AppState as Database, BatchEditResult, BatchOperation, EditField, JournalEntry, TextCase, TrackMetadataEdit},
+    user_config::{load_music_dir, load_naming_config},
+    utils::patterns::{compile_patterns, read_path, PathPattern},
+};
+
+// an operation ready to run, patterns are compiled once for the whole batch
+enum Step<'a> {
+    Set(EditField, Option<&'a str>),
+    Replace(EditField, Regex, &'a str, bool),
+    Case(EditField, TextCase),
+    Renumber(i64, bool),
+}
+
+// Runs the operations over every track, in the order given, then writes each
+// track's result with write_edits
+pub async fn batch_edit(
+    db: &Database,
+    track_ids: &[i64],
+    operations: &[BatchOperation],
+    dry_run: bool,
+) -> Result<BatchEditResult, String> {
+    let steps = compile(operations)?;
+    let mut edits = Vec::new();
+    for (position, track_id) in track_ids.iter().enumerate() {
+        edits.push((*track_id, batch_values(db, *track_id, &steps, position, track_ids.len()).await));
+    }
+    write_edits(db, edits, dry_run, |count| format!("Edited {} tracks", count)).await
+}
+
+// Sets tags from each track's path with the first of the patterns it matches.
+// With `only_missing` only tags without a value are set, an untagged file's
+// artist counts as missing even though the library shows "Unknown Artist".
+pub async fn tags_from_paths(
+    db: &Database,
+    track_ids: &[i64],
+    patterns: &[PathPattern],
+    music_dir: Option<&Path>,
+    only_missing: bool,
+    dry_run: bool,
+) -> Result<BatchEditResult, String> {
+    let mut edits = Vec::new();
+    for track_id in track_ids {
+        edits.push((*track_id, path_values(db, *track_id, patterns, music_dir, only_missing).await));
+    }
+    write_edits(db, edits, dry_run, |count| format!("Tagged {} tracks from their file names", count)).await
+}
+
+// Writes each track's edit like update_track_metadata would. A track that
+// fails doesn't stop the others. Everything that was written is journaled as
+// one edit, described by `describe` from the number of tracks.
+async fn write_edits(
+    db: &Database,
+    edits: Vec<(i64, Result<TrackMetadataEdit, String>)>,
+    dry_run: bool,
+    describe: impl Fn(usize) -> String,
+) -> Result<BatchEditResult, String> {
+    let mut result = BatchEditResult {
+        journal_id: None,
+        dry_run,
+        updates: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for (track_id, edit) in edits {
+        let update = match edit {
+            Ok(edit) => edit_track(db, track_id, edit, dry_run).await,
+            Err(e) => Err(e),
+        };
+        match update {
+            Ok(update) if !update.changes.is_empty() => result.updates.push(update),
+            Ok(_) => {}
+            Err(e) => result.errors.push(format!("Track {}: {}", track_id, e)),
+        }
+    }
+
+    if !dry_run && !result.updates.is_empty() {
+        let entries: Vec<JournalEntry> = result
+            .updates
+            .iter()
+            .map(|update| JournalEntry {
+                track_id: update.track_id,
+                changes: update.changes.clone(),
+            })
+            .collect();
+        result.journal_id = Some(db.add_edit_journal(&describe(entries.len()), &entries).await?);
+        db.refresh_smart_playlists().await?;
+    }
+    Ok(result)
+}
+
+fn compile(operations: &[BatchOperation]) -> Result<Vec<Step<'_>>, String> {
+    operations
+        .iter()
+        .map(|operation| {
+            Ok(match operation {
+                BatchOperation::Set { field, value } => Step::Set(*field, value.as_deref()),
+                BatchOperation::Replace {
+                    field,
+                    find,
+                    replace,
+                    regex,
+                    case_sensitive,
+                } => {
+                    if find.is_empty() {
+                        return Err("Nothing to find".to_string());
+                    }
+                    let pattern = if *regex { find.clone() } else { regex::escape(find) };
+                    let compiled = RegexBuilder::new(&pattern)
+                        .case_insensitive(!case_sensitive)
+                        .build()
+                        .map_err(|e| format!("Invalid pattern {}: {}", find, e))?;
+                    Step::Replace(*field, compiled, replace, *regex)
+                }
+                BatchOperation::Case { field, case } => Step::Case(*field, *case),
+                BatchOperation::Renumber { start, set_total } => Step::Renumber(start.unwrap_or(1), *set_total),
+            })
+        })
+        .collect()
+}
+
+// The edit the steps make to one track, the `position`th of `count`
+async fn batch_values(
+    db: &Database,
+    track_id: i64,
+    steps: &[Step<'_>],
+    position: usize,
+    count: usize,
+) -> Result<TrackMetadataEdit, String> {
+    let track = db
+        .get_track(track_id)
+        .await?
+        .ok_or_else(|| format!("Track {} not found", track_id))?;
+
+    // each step sees what the ones before it did
+    let mut values: HashMap<EditField, Option<String>> = HashMap::new();
+    let value = |values: &mut HashMap<EditField, Option<String>>, field: EditField| {
+        values
+            .entry(field)
+            .or_insert_with(|| current_value(&track, field))
+            .clone()
+    };
+    for step in steps {
+        match step {
+            Step::Set(field, text) => {
+                values.insert(*field, text.map(str::to_string));
+            }
+            Step::Replace(field, pattern, replace, expand) => {
+                if let Some(text) = value(&mut values, *field) {
+                    let replaced = if *expand {
+                        pattern.replace_all(&text, *replace)
+                    } else {
+                        pattern.replace_all(&text, NoExpand(replace))
+                    };
+                    values.insert(*field, Some(replaced.into_owned()));
+                }
+            }
+            Step::Case(field, case) => {
+                if let Some(text) = value(&mut values, *field) {
+                    values.insert(*field, Some(change_case(&text, *case)));
+                }
+            }
+            Step::Renumber(start, set_total) => {
+                values.insert(EditField::TrackNumber, Some((start + position as i64).to_string()));
+                if *set_total {
+                    values.insert(EditField::TrackTotal, Some(count.to_string()));
+                }
+            }
+        }
+    }
+
+    let mut edit = TrackMetadataEdit::default();
+    for (field, text) in values {
+        set_edit_value(&mut edit, field, text)?;
+    }
+    Ok(edit)
+}
+
+async fn path_values(
+    db: &Database,
+    track_id: i64,
+    patterns: &[PathPattern],
+    music_dir: Option<&Path>,
+    only_missing: bool,
+) -> Result<TrackMetadataEdit, String> {
+    let track = db
+        .get_track(track_id)
+        .await?
+        .ok_or_else(|| format!("Track {} not found", track_id))?;
+    let values = read_path(patterns, Path::new(&track.file_path), music_dir)
+        .ok_or_else(|| "Its path doesn't match the pattern".to_string())?;
+
+    let mut edit = TrackMetadataEdit::default();
+    for (field, value) in values {
+        let missing = match field {
+            EditField::Artist => track.artist_id == UNKNOWN_ARTIST_ID,
+            EditField::Album => track.album_id == UNKNOWN_ALBUM_ID,
+            _ => current_value(&track, field).is_none_or(|current| current.trim().is_empty()),
+        };
+        if missing || !only_missing {
+            set_edit_value(&mut edit, field, Some(value))?;
+        }
+    }
+    Ok(edit)
+}
+
+fn change_case(text: &str, case: TextCase) -> String {
+    match case {
+        TextCase::Upper => text.to_uppercase(),
+        TextCase::Lower => text.to_lowercase(),
+        // words start after whitespace, brackets and dashes, not after an apostrophe
+        TextCase::Title => {
+            let mut out = String::with_capacity(text.len());
+            let mut word_start = true;
+            for c in text.chars() {
+                if word_start {
+                    out.extend(c.to_uppercase());
+                } else {
+                    out.extend(c.to_lowercase());
+                }
+                word_start = c.is_whitespace() || matches!(c, '(' | '[' | '-' | '/' | '"');
+            }
+            out
+        }
+        TextCase::Sentence => {
+            let lower = text.to_lowercase();
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+    }
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn batch_edit_tracks(
+    state: State<'_, Database>,
+    track_ids: Vec<i64>,
+    operations: Vec<BatchOperation>,
+    dry_run: Option<bool>,
+) -> Result<BatchEditResult, String> {
+    batch_edit(&state, &track_ids, &operations, dry_run.unwrap_or(false)).await
+}
+
+// Without a pattern the configured read patterns are tried in turn, the way
+// files are read on import
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn tags_from_file_names(
+    app: AppHandle,
+    state: State<'_, Database>,
+    track_ids: Vec<i64>,
+    pattern: Option<String>,
+    only_missing: Option<bool>,
+    dry_run: Option<bool>,
+) -> Result<BatchEditResult, String> {
+    let patterns = match pattern {
+        Some(pattern) => vec![PathPattern::parse(&pattern)?],
+        None => compile_patterns(&load_naming_config(&app).read_patterns)?,
+    };
+    let music_dir = load_music_dir(app.clone()).map(|dir| PathBuf::from(dir.trim()));
+
+    tags_from_paths(
+        &state,
+        &track_ids,
+        &patterns,
+        music_dir.as_deref(),
+        only_missing.unwrap_or(true),
+        dry_run.unwrap_or(false),
+    )
+    .await
+}
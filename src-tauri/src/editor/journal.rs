@@ -0,0 +1,87 @@
+use tauri::State;
+
+use crate::{
+    editor::{current_value, edit_track, set_edit_value},
+    models::{AppState as Database, BatchEditResult, EditJournalSummary, TrackMetadataEdit},
+};
+
+// Puts back what an edit changed, in the files and the database. A field
+// that was changed again since keeps its newer value, and is reported. The
+// edit only counts as undone once every field is back, until then it can be
+// undone again and what was already put back is skipped.
+pub async fn undo_edit(db: &Database, journal_id: Option<i64>) -> Result<BatchEditResult, String> {
+    let (journal_id, entries, undone_at) = db.get_journal_entries(journal_id).await?.ok_or_else(|| match journal_id {
+        Some(id) => format!("Edit {} not found", id),
+        None => "Nothing to undo".to_string(),
+    })?;
+    if undone_at.is_some() {
+        return Err(format!("Edit {} was already undone", journal_id));
+    }
+
+    let mut result = BatchEditResult {
+        journal_id: Some(journal_id),
+        dry_run: false,
+        updates: Vec::new(),
+        errors: Vec::new(),
+    };
+    for entry in entries {
+        let Some(track) = db.get_track(entry.track_id).await? else {
+            result.errors.push(format!("Track {} no longer exists", entry.track_id));
+            continue;
+        };
+
+        let mut edit = TrackMetadataEdit::default();
+        for change in entry.changes {
+            let current = current_value(&track, change.field);
+            if current == change.old {
+                continue;
+            }
+            if current != change.new {
+                let field = serde_json::to_value(change.field).unwrap_or_default();
+                result.errors.push(format!(
+                    "Track {}: {} was changed again since, left as it is",
+                    entry.track_id,
+                    field.as_str().unwrap_or_default()
+                ));
+                continue;
+            }
+            if let Err(e) = set_edit_value(&mut edit, change.field, change.old) {
+                result.errors.push(format!("Track {}: {}", entry.track_id, e));
+            }
+        }
+
+        match edit_track(db, entry.track_id, edit, false).await {
+            Ok(update) if !update.changes.is_empty() => result.updates.push(update),
+            Ok(_) => {}
+            Err(e) => result.errors.push(format!("Track {}: {}", entry.track_id, e)),
+        }
+    }
+
+    if result.errors.is_empty() {
+        db.set_journal_undone(journal_id).await?;
+    }
+    if !result.updates.is_empty() {
+        db.refresh_smart_playlists().await?;
+    }
+    Ok(result)
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_edit_history(
+    state: State<'_, Database>,
+    limit: Option<i64>,
+) -> Result<Vec<EditJournalSummary>, String> {
+    state.get_edit_journal(limit.unwrap_or(50).clamp(1, 500)).await
+}
+
+// the newest edit that's still in place unless `journal_id` says which
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn undo_track_edit(
+    state: State<'_, Database>,
+    journal_id: Option<i64>,
+) -> Result<BatchEditResult, String> {
+    undo_edit(&state, journal_id).await
+}
@@ -0,0 +1,263 @@
+use std::path::Path;
+
+use tauri::State;
+
+use crate::{
+    db::{LOCAL_SOURCE_ID, UNKNOWN_ALBUM_ID, UNKNOWN_ARTIST_ID},
+    models::{AppState as Database, EditField, FieldChange, JournalEntry, MetadataUpdate, Track, TrackMetadataEdit},
+    utils::{tag_reader::extract_file_tags, tag_writer::write_tags},
+};
+
+pub mod batch;
+pub mod journal;
+
+// Writes the edit into the track's file, then reads the file back into its
+// row, so the database ends up with exactly what the file says. With `dry_run`
+// nothing is touched, the changes only come back as a diff. Smart playlists
+// are left to the caller, a batch refreshes them once.
+pub async fn edit_track(
+    db: &Database,
+    track_id: i64,
+    edit: TrackMetadataEdit,
+    dry_run: bool,
+) -> Result<MetadataUpdate, String> {
+    let track = db
+        .get_track(track_id)
+        .await?
+        .ok_or_else(|| format!("Track {} not found", track_id))?;
+    let (edit, changes) = changed_fields(&track, &edit)?;
+
+    if dry_run || changes.is_empty() {
+        return Ok(MetadataUpdate {
+            track_id,
+            dry_run,
+            changes,
+            track: (!dry_run).then_some(track),
+        });
+    }
+    if track.source_id != LOCAL_SOURCE_ID {
+        return Err("Only local files can be edited".to_string());
+    }
+    if !Path::new(&track.file_path).is_file() {
+        return Err(format!("File not found: {}", track.file_path));
+    }
+
+    let path = track.file_path.clone();
+    let extracted = tauri::async_runtime::spawn_blocking(move || {
+        write_tags(Path::new(&path), &edit)?;
+        extract_file_tags(&path)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    db.apply_track_edit(track_id, extracted).await?;
+
+    Ok(MetadataUpdate {
+        track_id,
+        dry_run,
+        changes,
+        track: db.get_track(track_id).await?,
+    })
+}
+
+// Keeps only the fields that would change, cleaned up, so tags like a
+// multi-value artist are only rewritten when they really change. Returns what
+// is left and the diff of it.
+fn changed_fields(track: &Track, edit: &TrackMetadataEdit) -> Result<(TrackMetadataEdit, Vec<FieldChange>), String> {
+    let mut changed = TrackMetadataEdit::default();
+    let mut changes = Vec::new();
+
+    for field in EditField::ALL {
+        let Some(value) = edit_value(edit, field) else {
+            continue;
+        };
+        set_edit_value(&mut changed, field, value)?;
+        // compared once cleaned up, "Rock " is no change from "Rock"
+        let new = edit_value(&changed, field).flatten();
+        let old = current_value(track, field);
+        if new == old {
+            clear_edit_value(&mut changed, field);
+        } else {
+            changes.push(FieldChange { field, old, new });
+        }
+    }
+    Ok((changed, changes))
+}
+
+// A field of the track as the editor shows it
+pub fn current_value(track: &Track, field: EditField) -> Option<String> {
+    let tags = &track.tags;
+    let number = |value: Option<i64>| value.map(|n| n.to_string());
+    match field {
+        EditField::Title => Some(track.title.clone()),
+        // the names untagged tracks are filed under aren't theirs
+        EditField::Artist => track.artist_name.clone().filter(|_| track.artist_id != UNKNOWN_ARTIST_ID),
+        EditField::Album => track.album_name.clone().filter(|_| track.album_id != UNKNOWN_ALBUM_ID),
+        EditField::AlbumArtist => tags.album_artist.clone(),
+        EditField::Genre => tags.genre.clone(),
+        EditField::Composer => tags.composer.clone(),
+        EditField::Comment => tags.comment.clone(),
+        EditField::Year => number(tags.year),
+        EditField::TrackNumber => number(tags.track_number),
+        EditField::TrackTotal => number(tags.track_total),
+        EditField::DiscNumber => number(tags.disc_number),
+        EditField::DiscTotal => number(tags.disc_total),
+        EditField::Bpm => tags.bpm.map(|bpm| bpm.to_string()),
+        EditField::Compilation => Some(tags.compilation.to_string()),
+    }
+}
+
+// What the edit sets a field to: None leaves it alone, Some(None) clears it
+fn edit_value(edit: &TrackMetadataEdit, field: EditField) -> Option<Option<String>> {
+    let number = |value: Option<Option<i64>>| value.map(|n| n.map(|n| n.to_string()));
+    match field {
+        EditField::Title => edit.title.clone().map(Some),
+        EditField::Artist => edit.artist.clone().map(|artist| Some(artist).filter(|artist| !artist.is_empty())),
+        EditField::Album => edit.album.clone().map(|album| Some(album).filter(|album| !album.is_empty())),
+        EditField::AlbumArtist => edit.album_artist.clone(),
+        EditField::Genre => edit.genre.clone(),
+        EditField::Composer => edit.composer.clone(),
+        EditField::Comment => edit.comment.clone(),
+        EditField::Year => number(edit.year),
+        EditField::TrackNumber => number(edit.track_number),
+        EditField::TrackTotal => number(edit.track_total),
+        EditField::DiscNumber => number(edit.disc_number),
+        EditField::DiscTotal => number(edit.disc_total),
+        EditField::Bpm => edit.bpm.map(|bpm| bpm.map(|bpm| bpm.to_string())),
+        EditField::Compilation => edit.compilation.map(|flag| Some(flag.to_string())),
+    }
+}
+
+// Sets a field from text, the way it's shown. Text is trimmed and an empty
+// value clears the field, except title, artist and album which need one.
+pub fn set_edit_value(edit: &mut TrackMetadataEdit, field: EditField, value: Option<String>) -> Result<(), String> {
+    let value = value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+    let required = |name: &str| value.clone().ok_or_else(|| format!("{} can't be empty", name));
+    let number = |name: &str| match value.as_deref() {
+        None => Ok(None),
+        Some(text) => text
+            .parse::<u32>()
+            .map(|n| Some(n as i64))
+            .map_err(|_| format!("{} isn't a valid {}", text, name)),
+    };
+
+    match field {
+        EditField::Title => edit.title = Some(required("Title")?),
+        // cleared, the track goes back to the unknown artist or album
+        EditField::Artist => edit.artist = Some(value.unwrap_or_default()),
+        EditField::Album => edit.album = Some(value.unwrap_or_default()),
+        EditField::AlbumArtist => edit.album_artist = Some(value),
+        EditField::Genre => edit.genre = Some(value),
+        EditField::Composer => edit.composer = Some(value),
+        EditField::Comment => edit.comment = Some(value),
+        EditField::Year => edit.year = Some(number("year")?),
+        EditField::TrackNumber => edit.track_number = Some(number("track number")?),
+        EditField::TrackTotal => edit.track_total = Some(number("track total")?),
+        EditField::DiscNumber => edit.disc_number = Some(number("disc number")?),
+        EditField::DiscTotal => edit.disc_total = Some(number("disc total")?),
+        EditField::Bpm => {
+            let bpm = match value.as_deref() {
+                None => None,
+                Some(text) => Some(
+                    text.parse::<f64>()
+                        .ok()
+                        .filter(|bpm| *bpm > 0.0 && bpm.is_finite())
+                        .ok_or_else(|| format!("{} isn't a valid BPM", text))?,
+                ),
+            };
+            edit.bpm = Some(bpm);
+        }
+        EditField::Compilation => {
+            let flag = match value.as_deref().map(str::to_lowercase).as_deref() {
+                None | Some("false" | "0" | "no") => false,
+                Some("true" | "1" | "yes") => true,
+                Some(text) => return Err(format!("{} isn't yes or no", text)),
+            };
+            edit.compilation = Some(flag);
+        }
+    }
+    Ok(())
+}
+
+fn clear_edit_value(edit: &mut TrackMetadataEdit, field: EditField) {
+    match field {
+        EditField::Title => edit.title = None,
+        EditField::Artist => edit.artist = None,
+        EditField::Album => edit.album = None,
+        EditField::AlbumArtist => edit.album_artist = None,
+        EditField::Genre => edit.genre = None,
+        EditField::Composer => edit.composer = None,
+        EditField::Comment => edit.comment = None,
+        EditField::Year => edit.year = None,
+        EditField::TrackNumber => edit.track_number = None,
+        EditField::TrackTotal => edit.track_total = None,
+        EditField::DiscNumber => edit.disc_number = None,
+        EditField::DiscTotal => edit.disc_total = None,
+        EditField::Bpm => edit.bpm = None,
+        EditField::Compilation => edit.compilation = None,
+    }
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn update_track_metadata(
+    state: State<'_, Database>,
+    track_id: i64,
+    fields: TrackMetadataEdit,
+    dry_run: Option<bool>,
+) -> Result<MetadataUpdate, String> {
+    let update = edit_track(&state, track_id, fields, dry_run.unwrap_or(false)).await?;
+
+    // recorded so it can be undone like a batch
+    if !update.dry_run && !update.changes.is_empty() {
+        let title = update.track.as_ref().map(|track| track.title.as_str()).unwrap_or_default();
+        let entry = JournalEntry {
+            track_id,
+            changes: update.changes.clone(),
+        };
+        state.add_edit_journal(&format!("Edited \"{}\"", title), &[entry]).await?;
+        state.refresh_smart_playlists().await?;
+    }
+    Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{memory_db, track};
+
+    // undoing an edit of an untagged track must clear the tag, not write the placeholder
+    #[test]
+    fn unknown_artist_and_album_have_no_value() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let ids = db
+                .add_tracks(vec![track("/music/a.mp3", "Song", "", ""), track("/music/b.mp3", "Song", "Artist", "Album")])
+                .await
+                .unwrap();
+            let untagged = db.get_track(ids[0]).await.unwrap().unwrap();
+            let tagged = db.get_track(ids[1]).await.unwrap().unwrap();
+
+            assert_eq!(current_value(&untagged, EditField::Artist), None);
+            assert_eq!(current_value(&untagged, EditField::Album), None);
+            assert_eq!(current_value(&tagged, EditField::Artist).as_deref(), Some("Artist"));
+            assert_eq!(current_value(&tagged, EditField::Album).as_deref(), Some("Album"));
+        });
+    }
+
+    #[test]
+    fn clearing_the_artist_is_no_change_for_an_untagged_track() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let ids = db.add_tracks(vec![track("/music/a.mp3", "Song", "", "")]).await.unwrap();
+            let untagged = db.get_track(ids[0]).await.unwrap().unwrap();
+
+            let mut edit = TrackMetadataEdit::default();
+            set_edit_value(&mut edit, EditField::Artist, None).unwrap();
+            let (_, changes) = changed_fields(&untagged, &edit).unwrap();
+
+            assert!(changes.is_empty());
+        });
+    }
+}
@@ -1,8 +1,21 @@
+mod audiobooks;
 mod db;
+mod dlna;
+mod editor;
 mod models;
+mod mpd;
 mod player;
+mod podcasts;
+mod protocol;
+mod remote;
+mod scanner;
+mod sources;
+mod subsonic;
+#[cfg(test)]
+mod test_support;
 mod user_config;
 mod utils;
+mod watcher;
 
 use crate::player::AudioPlayer;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
@@ -28,6 +41,11 @@ fn get_db_path(app: &tauri::AppHandle) -> String {
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_log::Builder::new().build())
+        // artwork and audio for the webview, by id, see protocol::SCHEME
+        .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, |ctx, request, responder| {
+            protocol::handle(ctx.app_handle().clone(), request, responder)
+        })
         .setup(|app| {
             
             let db_url = get_db_path(&app.handle());
@@ -48,12 +66,51 @@ pub fn run() {
             tauri::async_runtime::block_on(sqlx::migrate!().run(&pool))
                 .expect("Failed to run migrations");
 
-            app.manage(models::AppState { db: pool });
+            let db = models::AppState { db: pool };
+            podcasts::start_refresh_scheduler(app.handle().clone(), db.clone());
+
+            // Remote library sources, the player resolves their tracks through this
+            let cache_root = app.path().app_cache_dir()?.join("sources");
+            let library_sources = sources::LibrarySources::new(cache_root);
+            if let Err(e) = tauri::async_runtime::block_on(library_sources.load(&db)) {
+                log::error!("Failed to load library sources: {}", e);
+            }
+            app.manage(library_sources);
+            app.manage(scanner::LibraryScanner::default());
+            app.manage(db);
 
             // Manage audio player
             let audio_player = AudioPlayer::new(app.handle().clone());
             app.manage(audio_player);
 
+            // LAN remote control, only runs if the user opted in
+            app.manage(remote::RemoteServer::default());
+            remote::start_if_enabled(app.handle().clone(), app.state::<models::AppState>().inner().clone());
+
+            // MPD protocol server for existing clients, also opt-in
+            app.manage(mpd::MpdServer::default());
+            mpd::start_if_enabled(app.handle().clone(), app.state::<models::AppState>().inner().clone());
+
+            // Subsonic API for mobile clients, also opt-in
+            app.manage(subsonic::SubsonicServer::default());
+            subsonic::start_if_enabled(app.handle().clone(), app.state::<models::AppState>().inner().clone());
+
+            // DLNA media server for TVs and receivers, also opt-in
+            app.manage(dlna::DlnaServer::default());
+            dlna::start_if_enabled(app.handle().clone(), app.state::<models::AppState>().inner().clone());
+
+            // how artist tags are split, before anything reads a file
+            utils::credits::use_artist_config(user_config::load_artist_config(app.handle()));
+            // and the patterns that fill in tags files don't have
+            utils::patterns::use_naming_config(app.handle());
+
+            // keeps the library in sync with the music folder while the app runs
+            app.manage(watcher::LibraryWatcher::default());
+            watcher::watch_music_dir(app.handle());
+
+            // re-reads files imported before the tag reader learned new tags
+            scanner::start_tag_backfill(app.handle().clone());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -61,6 +118,8 @@ pub fn run() {
             // artist functions
             db::get_artists,
             db::get_artist,
+            db::get_artist_tracks,
+            db::get_artist_detail,
             db::add_artist,
             db::remove_artist,
             db::artist_exists,
@@ -68,6 +127,7 @@ pub fn run() {
             // album functions
             db::get_albums,
             db::get_album,
+            db::get_album_detail,
             db::add_album,
             db::remove_album,
             db::album_exists,
@@ -75,8 +135,18 @@ pub fn run() {
             // track functions
             db::get_tracks,
             db::get_tracks_with_names,
+            db::list_tracks,
             db::add_track,
             db::remove_track,
+            editor::update_track_metadata,
+            editor::batch::batch_edit_tracks,
+            editor::batch::tags_from_file_names,
+            editor::journal::get_edit_history,
+            editor::journal::undo_track_edit,
+            // artwork functions
+            db::get_artwork,
+            // search functions
+            db::search_library,
             // playlist functions
             db::get_playlist,
             db::get_playlists_with_previews,
@@ -84,10 +154,42 @@ pub fn run() {
             db::save_playlist,
             db::update_playlist,
             db::delete_playlist,
+            db::save_smart_playlist,
+            db::update_smart_playlist,
+            db::get_smart_playlist_rules,
+            db::refresh_smart_playlist,
+            // chapter functions
+            db::get_track_chapters,
+            db::rescan_track_chapters,
+            // library source functions
+            sources::get_library_sources,
+            sources::add_subsonic_source,
+            sources::sync_library_source,
+            sources::remove_library_source,
+            sources::set_source_cache_enabled,
+            sources::cache_remote_track,
+            // library scan functions
+            scanner::scan_library,
+            scanner::cancel_library_scan,
+            scanner::integrity::check_library_integrity,
+            scanner::integrity::relink_tracks,
+            scanner::duplicates::find_duplicate_tracks,
+            scanner::duplicates::merge_duplicate_tracks,
+            scanner::organizer::organize_library,
+            // play history functions
+            db::record_play,
             // user config functions
             user_config::save_music_dir,
             user_config::load_music_dir,
+            utils::credits::get_artist_config,
+            utils::credits::set_artist_config,
+            utils::patterns::get_naming_config,
+            utils::patterns::set_naming_config,
+            utils::patterns::preview_naming_pattern,
+            utils::acoustid::get_acoustid_config,
+            utils::acoustid::set_acoustid_config,
             // util functions
+            utils::acoustid::lookup_acoustid,
             utils::move_file_to_dir,
             utils::tag_reader::get_track_metadata,
             utils::get_user_song_dir,
@@ -100,7 +202,48 @@ pub fn run() {
             player::set_volume,
             player::get_playback_state,
             player::seek_track,
-            player::get_position
+            player::get_position,
+            player::next_chapter,
+            player::previous_chapter,
+            player::get_current_chapter,
+            player::get_queue,
+            player::enqueue_tracks,
+            player::clear_queue,
+            player::remove_from_queue,
+            player::play_queue_index,
+            player::next_track,
+            player::previous_track,
+            // podcast functions
+            podcasts::get_podcasts,
+            podcasts::get_podcast_episodes,
+            podcasts::subscribe_podcast,
+            podcasts::unsubscribe_podcast,
+            podcasts::refresh_podcasts,
+            podcasts::download_episode,
+            podcasts::save_episode_position,
+            podcasts::set_episode_played,
+            // audiobook functions
+            audiobooks::get_books,
+            audiobooks::get_book,
+            audiobooks::get_book_chapters,
+            audiobooks::create_book,
+            audiobooks::create_book_from_album,
+            audiobooks::delete_book,
+            audiobooks::play_book,
+            audiobooks::save_book_progress,
+            // remote control functions
+            remote::get_remote_config,
+            remote::set_remote_enabled,
+            remote::regenerate_remote_token,
+            // mpd server functions
+            mpd::get_mpd_config,
+            mpd::set_mpd_config,
+            // subsonic server functions
+            subsonic::get_subsonic_config,
+            subsonic::set_subsonic_config,
+            // dlna server functions
+            dlna::get_dlna_config,
+            dlna::set_dlna_config
         ])
         .run(tauri::generate_context!())
         .expect("error running tauri application");
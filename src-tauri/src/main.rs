@@ -1,11 +1,24 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod audiobooks;
 mod db;
+mod dlna;
+mod editor;
 mod models;
+mod mpd;
 mod user_config;
 mod player;
+mod podcasts;
+mod protocol;
+mod remote;
+mod scanner;
+mod sources;
+mod subsonic;
+#[cfg(test)]
+mod test_support;
 mod utils;
+mod watcher;
 
 fn main() {
     tamaureus_lib::run()
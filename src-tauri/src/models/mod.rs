@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 use sqlx::FromRow;
 
 #[derive(Debug, Clone, FromRow, serde::Serialize, serde::Deserialize)]
 pub struct Artist {
     pub id: i64,
     pub name: String,
+    pub source_id: i64,
 }
 
 #[derive(Debug, Clone, FromRow, serde::Serialize, serde::Deserialize)]
@@ -11,7 +14,10 @@ pub struct Album {
     pub id: i64,
     pub title: String,
     pub artist_id: i64,
-    pub cover_path: Option<String>
+    pub cover_path: Option<String>,
+    pub source_id: i64,
+    pub compilation: bool,
+    pub artwork_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, FromRow, serde::Serialize, serde::Deserialize)]
@@ -25,10 +31,12 @@ pub struct Track {
     pub file_format: String, // ex. "mp3"
     pub file_size: f64,
     pub date_added: Option<i64>, // ex. 20260112
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub thumbnail_base64: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub thumbnail_mime: Option<String>,
+    pub artwork_id: Option<i64>, // see get_artwork
+    pub source_id: i64, // 1 for local files, see library_sources
+    pub missing_since: Option<i64>, // set while the file can't be found
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub tags: TrackTags,
 
     pub artist_name: Option<String>,
     pub album_name:  Option<String>,
@@ -48,6 +56,631 @@ pub struct ExtractedTrack {
     pub thumbnail_base64: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub thumbnail_mime: Option<String>,
+    #[serde(default)]
+    pub chapters: Vec<ExtractedChapter>,
+    // None for local files
+    #[serde(default)]
+    pub source_id: Option<i64>,
+    // what the file looked like when it was read, see file_stats
+    #[serde(default)]
+    pub file_mtime: Option<i64>,
+    #[serde(default)]
+    pub file_bytes: Option<i64>,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    // the picture as stored, thumbnail_base64 is only filled in for previews
+    #[serde(skip)]
+    pub artwork: Option<Artwork>,
+    // everyone else on the track besides `artist`, see utils::credits
+    #[serde(default)]
+    pub credits: Vec<TrackCredit>,
+    #[serde(flatten)]
+    pub tags: TrackTags,
+}
+
+// Tags beyond title/artist/album, all optional since few files have every one.
+// `release_date` is the date as tagged ("2004" or "2004-05-12"), `year` its year.
+#[derive(Debug, Clone, Default, FromRow, serde::Serialize, serde::Deserialize)]
+pub struct TrackTags {
+    pub track_number: Option<i64>,
+    pub track_total: Option<i64>,
+    pub disc_number: Option<i64>,
+    pub disc_total: Option<i64>,
+    pub year: Option<i64>,
+    pub release_date: Option<String>,
+    pub genre: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub bpm: Option<f64>,
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub compilation: bool,
+    // from the audio stream rather than tags, bitrate in kbps
+    pub bitrate: Option<i64>,
+    pub sample_rate: Option<i64>,
+    pub bit_depth: Option<i64>,
+}
+
+// Cover art ready to store: the picture as embedded, deduplicated by its hash
+#[derive(Debug, Clone)]
+pub struct Artwork {
+    pub hash: String,
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+// What a newly stored picture gets, thumbnails only where it's bigger than them
+#[derive(Debug, Clone, Default)]
+pub struct Thumbnails {
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub small: Option<Vec<u8>>,
+    pub medium: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtworkSize {
+    Small, // 64px
+    #[default]
+    Medium, // 256px
+    Full,
+}
+
+impl ArtworkSize {
+    // the smallest stored size that still fills a square of this many pixels
+    pub fn for_pixels(size: u32) -> Self {
+        match size {
+            0..=64 => ArtworkSize::Small,
+            65..=256 => ArtworkSize::Medium,
+            _ => ArtworkSize::Full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArtworkImage {
+    pub mime: String,
+    pub base64: String,
+}
+
+// What an artist did on a track, stored as text in track_artists.role
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtistRole {
+    Primary,
+    Featured,
+    Remixer,
+    Composer,
+}
+
+impl ArtistRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArtistRole::Primary => "primary",
+            ArtistRole::Featured => "featured",
+            ArtistRole::Remixer => "remixer",
+            ArtistRole::Composer => "composer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TrackCredit {
+    pub name: String,
+    pub role: ArtistRole,
+}
+
+// A track on an artist's page and why it's there
+#[derive(Debug, Clone, FromRow, serde::Serialize, serde::Deserialize)]
+pub struct CreditedTrack {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub track: Track,
+    pub role: String,
+}
+
+// An album with its tracks in play order and totals over them. `artwork_id`
+// falls back to the first track with a picture.
+#[derive(Debug, serde::Serialize)]
+pub struct AlbumDetail {
+    #[serde(flatten)]
+    pub album: Album,
+    pub artist_name: String,
+    pub tracks: Vec<Track>,
+    pub track_count: i64,
+    pub duration_ms: i64,
+    pub year: Option<i64>,
+    pub formats: Vec<String>,
+    pub release_type: ReleaseType,
+}
+
+// An album on an artist's page, totals are over the whole album
+#[derive(Debug, Clone, FromRow, serde::Serialize)]
+pub struct AlbumOverview {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub album: Album,
+    pub year: Option<i64>,
+    pub track_count: i64,
+    pub duration_ms: i64,
+    #[sqlx(skip)]
+    pub release_type: ReleaseType,
+}
+
+// `appearances` are compilations by someone else the artist has tracks on.
+// `listening_time_ms` adds up every play of a track they're credited on.
+#[derive(Debug, serde::Serialize)]
+pub struct ArtistDetail {
+    #[serde(flatten)]
+    pub artist: Artist,
+    pub releases: Vec<ReleaseGroup>,
+    pub appearances: Vec<AlbumOverview>,
+    pub track_count: i64,
+    pub play_count: i64,
+    pub listening_time_ms: i64,
+}
+
+// newest first
+#[derive(Debug, serde::Serialize)]
+pub struct ReleaseGroup {
+    pub release_type: ReleaseType,
+    pub albums: Vec<AlbumOverview>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseType {
+    #[default]
+    Album,
+    Ep,
+    Single,
+    Compilation,
+}
+
+impl ReleaseType {
+    // Few files carry a release type tag, so it's told from the album's length
+    // the way stores do: up to 3 tracks is a single and up to 6 an EP, as long
+    // as either stays under half an hour.
+    pub fn classify(compilation: bool, track_count: i64, duration_ms: i64) -> Self {
+        const HALF_HOUR_MS: i64 = 30 * 60 * 1000;
+        match track_count {
+            _ if compilation => ReleaseType::Compilation,
+            ..=3 if duration_ms < HALF_HOUR_MS => ReleaseType::Single,
+            ..=6 if duration_ms < HALF_HOUR_MS => ReleaseType::Ep,
+            _ => ReleaseType::Album,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExtractedChapter {
+    pub title: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Chapter {
+    pub id: i64,
+    pub track_id: i64,
+    pub position: i64,
+    pub title: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Book {
+    pub id: i64,
+    pub title: String,
+    pub artist_id: i64,
+    pub resume_track_id: Option<i64>,
+    pub resume_position_ms: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+
+    pub artist_name: Option<String>,
+    pub track_count: i64,
+    pub duration_ms: i64,
+}
+
+// One chapter of a book, flattened across all of the book's files in play order.
+// Files without embedded chapters show up as a single chapter spanning the file.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct BookChapter {
+    pub track_id: i64,
+    pub track_position: i64,
+    pub file_path: String,
+    pub position: i64,
+    pub title: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+// A library source as stored. The password never leaves the backend.
+#[derive(Debug, Clone, FromRow, serde::Serialize, serde::Deserialize)]
+pub struct SourceConfig {
+    pub id: i64,
+    pub kind: String, // "local" or "subsonic"
+    pub name: String,
+    pub base_url: Option<String>,
+    pub username: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub password: Option<String>,
+    pub cache_enabled: bool,
+    pub last_synced: Option<i64>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SyncSummary {
+    pub source_id: i64,
+    pub added: i64,
+    pub removed: i64,
+}
+
+// What the database knows about a local file, to tell whether it changed on disk
+#[derive(Debug, Clone, FromRow)]
+pub struct KnownFile {
+    pub id: i64,
+    pub file_path: String,
+    pub file_mtime: Option<i64>,
+    pub file_bytes: Option<i64>,
+    pub missing_since: Option<i64>,
+}
+
+// A local track as the integrity check sees it
+#[derive(Debug, Clone, FromRow)]
+pub struct LibraryFile {
+    pub id: i64,
+    pub file_path: String,
+    pub title: String,
+    pub artist_name: Option<String>,
+    pub album_name: Option<String>,
+    pub duration_ms: i64,
+    pub file_bytes: Option<i64>,
+    pub content_hash: Option<String>,
+    pub missing_since: Option<i64>,
+    pub fingerprint: Option<Vec<u8>>,
+}
+
+// A file that is probably where a missing track went. `matched_by` is "content"
+// for an identical hash, "fingerprint" when it sounds the same and otherwise
+// "metadata" with the score from size, duration and tags. `replaces_track_id` is the row the file was imported as
+// meanwhile, relinking merges it into the missing track.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RelinkProposal {
+    pub track_id: i64,
+    pub title: String,
+    pub old_path: String,
+    pub new_path: String,
+    pub confidence: f64,
+    pub matched_by: String,
+    pub replaces_track_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct IntegrityReport {
+    pub checked: usize,
+    pub missing: usize,
+    pub proposals: Vec<RelinkProposal>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Relink {
+    pub track_id: i64,
+    pub new_path: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+}
+
+// A track the organizer moves, with the lyrics and cue sheets named after it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TrackMove {
+    pub track_id: i64,
+    pub from: String,
+    pub to: String,
+    pub sidecars: Vec<FileMove>,
+}
+
+// What organize_library did, or with `dry_run` would do. Folder pictures are
+// copied to every folder their tracks go to, `folder_files` lists the copies;
+// the originals go once nothing is left in their folder.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct OrganizeReport {
+    pub dry_run: bool,
+    pub moves: Vec<TrackMove>,
+    pub folder_files: Vec<FileMove>,
+    pub unchanged: usize,
+    pub errors: Vec<String>,
+}
+
+// How find_duplicate_tracks decides two tracks are the same recording: same
+// title and artist with durations at most `duration_tolerance_ms` apart, or
+// with `fingerprint` matching acoustic fingerprints within the same tolerance.
+// Identical files always count.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct DuplicateOptions {
+    pub duration_tolerance_ms: i64,
+    pub fingerprint: bool,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> Self {
+        DuplicateOptions {
+            duration_tolerance_ms: 2000,
+            fingerprint: true,
+        }
+    }
+}
+
+// Copies of one recording, best first. `matched_by` is "content" when every
+// copy is the same file, "fingerprint" when some of them only matched by
+// their audio, otherwise "metadata".
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DuplicateGroup {
+    pub matched_by: String,
+    pub copies: Vec<DuplicateCopy>,
+}
+
+#[derive(Debug, Clone, FromRow, serde::Serialize)]
+pub struct DuplicateCopy {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub track: Track,
+    #[serde(skip)]
+    pub content_hash: Option<String>,
+    #[sqlx(skip)]
+    pub lossless: bool,
+    #[sqlx(skip)]
+    #[serde(skip)]
+    pub fingerprint: Option<Vec<u32>>,
+}
+
+// Tag changes for update_track_metadata. A field that's left out stays as it
+// is, null clears it. Title can only be replaced, an empty artist or album
+// files the track under the unknown one.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TrackMetadataEdit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<Option<String>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub genre: Option<Option<String>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub composer: Option<Option<String>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub comment: Option<Option<String>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub year: Option<Option<i64>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<Option<i64>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub track_total: Option<Option<i64>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub disc_number: Option<Option<i64>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub disc_total: Option<Option<i64>>,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub bpm: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compilation: Option<bool>,
+}
+
+// tells `"genre": null` (clear it) apart from no genre key at all (keep it)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+// The tags the editor can change, named like the fields of TrackMetadataEdit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Composer,
+    Comment,
+    Year,
+    TrackNumber,
+    TrackTotal,
+    DiscNumber,
+    DiscTotal,
+    Bpm,
+    Compilation,
+}
+
+impl EditField {
+    pub const ALL: [EditField; 14] = [
+        EditField::Title,
+        EditField::Artist,
+        EditField::Album,
+        EditField::AlbumArtist,
+        EditField::Genre,
+        EditField::Composer,
+        EditField::Comment,
+        EditField::Year,
+        EditField::TrackNumber,
+        EditField::TrackTotal,
+        EditField::DiscNumber,
+        EditField::DiscTotal,
+        EditField::Bpm,
+        EditField::Compilation,
+    ];
+}
+
+// One line of an edit's diff, values as they'd be shown
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FieldChange {
+    pub field: EditField,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// A step of batch_edit_tracks, applied to every selected track in turn.
+// `replace` matches `find` literally unless `regex` is set, then `replace`
+// can refer to groups as $1. `renumber` numbers the tracks in the order they
+// were selected, from `start` (1 by default).
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Set {
+        field: EditField,
+        value: Option<String>,
+    },
+    Replace {
+        field: EditField,
+        find: String,
+        replace: String,
+        #[serde(default)]
+        regex: bool,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    Case {
+        field: EditField,
+        case: TextCase,
+    },
+    Renumber {
+        start: Option<i64>,
+        #[serde(default)]
+        set_total: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextCase {
+    Upper,
+    Lower,
+    // every word capitalised
+    Title,
+    // only the first letter
+    Sentence,
+}
+
+// What a batch edit or an undo did per track. `journal_id` is the edit_journal
+// row it was recorded as, or the one that was undone; None on a dry run.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BatchEditResult {
+    pub journal_id: Option<i64>,
+    pub dry_run: bool,
+    pub updates: Vec<MetadataUpdate>,
+    pub errors: Vec<String>,
+}
+
+// What preview_naming_pattern makes of a track: the tags its path gives,
+// None when the pattern doesn't match it, and the path its tags give
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NamingPreview {
+    pub track_id: i64,
+    pub file_path: String,
+    pub tags: Option<HashMap<EditField, String>>,
+    pub rendered: String,
+}
+
+// The changes one edit made to one track, as kept in edit_journal
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub track_id: i64,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, FromRow, serde::Serialize)]
+pub struct EditJournalSummary {
+    pub id: i64,
+    pub description: String,
+    pub created_at: i64,
+    pub undone_at: Option<i64>,
+    pub track_count: i64,
+}
+
+// What update_track_metadata did, or with `dry_run` would do. `track` is the
+// row as it is afterwards, None on a dry run.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MetadataUpdate {
+    pub track_id: i64,
+    pub dry_run: bool,
+    pub changes: Vec<FieldChange>,
+    pub track: Option<Track>,
+}
+
+// A lookup_acoustid result, shaped like AcoustID's own response so it
+// deserializes straight from it. Ids of recordings, artists and release groups
+// are MusicBrainz ids.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AcoustidMatch {
+    pub id: String,
+    pub score: f64,
+    #[serde(default)]
+    pub recordings: Vec<AcoustidRecording>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AcoustidRecording {
+    pub id: String,
+    pub title: Option<String>,
+    // seconds
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub artists: Vec<AcoustidArtist>,
+    #[serde(default, alias = "releasegroups")]
+    pub release_groups: Vec<AcoustidReleaseGroup>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AcoustidArtist {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AcoustidReleaseGroup {
+    pub id: String,
+    pub title: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+// Payload of `library_changed`, what the file watcher just applied
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct LibraryChanges {
+    pub added: usize,
+    pub updated: usize,
+    pub missing: usize,
+    pub moved: usize,
+}
+
+// Payload of `library_scan_progress`, also what scan_library returns at the end.
+// `queued` files are the new or changed ones that need their tags read.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ScanProgress {
+    pub found: usize,
+    pub queued: usize,
+    pub processed: usize,
+    pub failed: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub done: bool,
+    pub cancelled: bool,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
@@ -60,6 +693,95 @@ pub struct Playlist {
     pub is_system: bool,
     pub created_at: i64,
     pub updated_at: i64,
+    pub is_smart: bool,
+}
+
+// What a smart playlist holds, stored as JSON in `playlists.rules`, e.g.
+// {"rule": {"match": "all", "rules": [{"field": "play_count", "op": "lt", "value": 3},
+//  {"field": "date_added", "op": "within_days", "value": 30}]}, "sort": "random", "limit": 50}
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SmartPlaylistRules {
+    pub rule: SmartRule,
+    #[serde(default)]
+    pub sort: Option<SmartSort>,
+    #[serde(default)]
+    pub descending: bool,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum SmartRule {
+    Group {
+        #[serde(rename = "match")]
+        match_kind: SmartMatch,
+        rules: Vec<SmartRule>,
+    },
+    Condition {
+        field: SmartField,
+        op: SmartOp,
+        value: serde_json::Value,
+    },
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartMatch {
+    All,
+    Any,
+}
+
+// `duration` is in seconds
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Composer,
+    Format,
+    Path,
+    Duration,
+    Year,
+    Bpm,
+    DateAdded,
+    PlayCount,
+    LastPlayed,
+}
+
+// Text fields take is, is_not, contains, not_contains and starts_with, numbers
+// the comparisons, dates within_days and not_within_days
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartOp {
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    StartsWith,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    WithinDays,
+    NotWithinDays,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartSort {
+    Title,
+    Artist,
+    Album,
+    Duration,
+    Year,
+    DateAdded,
+    PlayCount,
+    LastPlayed,
+    Random,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
@@ -68,15 +790,115 @@ pub struct PlaylistPreview {
     pub name: String,
     pub description: Option<String>,
     pub cover_path: Option<String>,
+    pub is_smart: bool,
     pub track_count: i64,
-    pub thumb1_base64: Option<String>,
-    pub thumb1_mime: Option<String>,
-    pub thumb2_base64: Option<String>,
-    pub thumb2_mime: Option<String>,
-    pub thumb3_base64: Option<String>,
-    pub thumb3_mime: Option<String>,
-    pub thumb4_base64: Option<String>,
-    pub thumb4_mime: Option<String>,
+    // the first four distinct covers, for the collage
+    #[sqlx(skip)]
+    pub artwork_ids: Vec<i64>,
+}
+
+// What search_library found, each list ranked best match first
+#[derive(Debug, serde::Serialize)]
+pub struct SearchResults {
+    pub tracks: Vec<Track>,
+    pub albums: Vec<Album>,
+    pub artists: Vec<Artist>,
+    pub playlists: Vec<Playlist>,
+}
+
+// One page of list_tracks. `after` is the id of the last track already shown,
+// the page continues right after it in the chosen order. Dates are YYYYMMDD
+// like date_added and both ends are included.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct TrackListQuery {
+    pub sort: TrackSort,
+    pub descending: bool,
+    pub artist_id: Option<i64>,
+    pub album_id: Option<i64>,
+    pub format: Option<String>,
+    pub added_from: Option<i64>,
+    pub added_to: Option<i64>,
+    pub after: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+// `album` orders by album, then disc and track number
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackSort {
+    #[default]
+    Title,
+    Artist,
+    Album,
+    DateAdded,
+    Duration,
+    PlayCount,
+}
+
+// `total` counts every track matching the filters, `next` is the `after` for
+// the following page, None on the last one
+#[derive(Debug, serde::Serialize)]
+pub struct TrackPage {
+    pub tracks: Vec<Track>,
+    pub total: i64,
+    pub next: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Podcast {
+    pub id: i64,
+    pub feed_url: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub last_refreshed: Option<i64>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Episode {
+    pub id: i64,
+    pub podcast_id: i64,
+    pub guid: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub audio_url: String,
+    pub published_at: Option<i64>,
+    pub duration_ms: i64,
+    pub file_path: Option<String>,
+    pub position_ms: i64,
+    pub played: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct QueueItem {
+    // stays the same while the item is queued, assigned by the player on enqueue
+    #[sqlx(default)]
+    #[serde(default)]
+    pub id: i64,
+    pub track_id: i64,
+    pub file_path: String,
+    pub title: String,
+    pub artist_name: Option<String>,
+}
+
+// Field match used by protocol servers (MPD find/search, Subsonic search).
+// `field` is one of artist, album, title, file or any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    Equals,
+    NotEquals,
+    Contains,
+    StartsWith,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackFilter {
+    pub field: String,
+    pub op: FilterOp,
+    pub value: String,
 }
 
 #[derive(Clone)]
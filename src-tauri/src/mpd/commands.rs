@@ -0,0 +1,603 @@
+use std::fmt::Write;
+
+use tauri::{Manager, Runtime};
+
+use super::{Ack, MpdContext, ACK_ERROR_ARG, ACK_ERROR_NO_EXIST, ACK_ERROR_UNKNOWN};
+use crate::{
+    models::{FilterOp, Track, TrackFilter},
+    player::AudioPlayer,
+};
+
+const SUPPORTED_COMMANDS: &[&str] = &[
+    "add", "addid", "clear", "close", "command_list_begin", "command_list_end",
+    "command_list_ok_begin", "commands", "consume", "currentsong", "decoders", "delete",
+    "deleteid", "find", "getvol", "idle", "list", "listplaylist", "listplaylistinfo",
+    "listplaylists", "load", "lsinfo", "next", "noidle", "notcommands", "outputs", "pause",
+    "ping", "play", "playid", "playlistadd", "playlistclear", "playlistdelete", "playlistid",
+    "playlistinfo", "playlistmove", "previous", "random", "rename", "repeat",
+    "replay_gain_status", "rm", "save", "search", "seek", "seekcur", "setvol", "single",
+    "stats", "status", "stop", "tagtypes", "urlhandlers",
+];
+
+const TAG_TYPES: &[&str] = &["Artist", "Album", "AlbumArtist", "Title", "Track", "Disc", "Date", "Genre", "Composer", "Comment"];
+
+// Starting a track waits until its media is open, a round trip for a remote
+// one, so player calls that can do that run off the async workers
+async fn with_player<R, T, F>(context: &MpdContext<R>, f: F) -> Result<T, Ack>
+where
+    R: Runtime,
+    T: Send + 'static,
+    F: FnOnce(&AudioPlayer<R>) -> Result<T, String> + Send + 'static,
+{
+    let app = context.app.clone();
+    tauri::async_runtime::spawn_blocking(move || f(&app.state::<AudioPlayer<R>>()))
+        .await
+        .map_err(|e| Ack::new(ACK_ERROR_UNKNOWN, e.to_string()))?
+        .map_err(Ack::from)
+}
+
+pub async fn execute<R: Runtime>(
+    context: &MpdContext<R>,
+    name: &str,
+    args: &[String],
+    out: &mut String,
+) -> Result<(), Ack> {
+    let player = context.app.state::<AudioPlayer<R>>();
+
+    match name {
+        // noidle outside of idle is a no-op
+        "ping" | "clearerror" | "noidle" => {}
+        "commands" => {
+            for command in SUPPORTED_COMMANDS {
+                let _ = writeln!(out, "command: {}", command);
+            }
+        }
+        "notcommands" | "urlhandlers" | "decoders" => {}
+        "tagtypes" => {
+            for tag in TAG_TYPES {
+                let _ = writeln!(out, "tagtype: {}", tag);
+            }
+        }
+        "outputs" => out.push_str("outputid: 0\noutputname: Tamaureus\nplugin: rodio\noutputenabled: 1\n"),
+        "replay_gain_status" => out.push_str("replay_gain_mode: off\n"),
+        // playback modes aren't supported, so only "off" is accepted
+        "repeat" | "random" | "single" | "consume" => {
+            if arg(args, 0)? != "0" {
+                return Err(Ack::new(ACK_ERROR_ARG, format!("{} is not supported", name)));
+            }
+        }
+
+        // status
+        "status" => {
+            let state = player.get_playback_state();
+            let queue = player.get_queue();
+            let _ = writeln!(out, "volume: {}", (state.volume * 100.0).round() as i64);
+            out.push_str("repeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\n");
+            let _ = writeln!(out, "playlist: {}", context.queue_version.load(std::sync::atomic::Ordering::SeqCst));
+            let _ = writeln!(out, "playlistlength: {}", queue.items.len());
+
+            let play_state = if state.is_empty {
+                "stop"
+            } else if state.is_paused {
+                "pause"
+            } else {
+                "play"
+            };
+            let _ = writeln!(out, "state: {}", play_state);
+
+            if let Some(current) = queue.current {
+                let id = queue.items.get(current).map_or(0, |item| item.id);
+                let _ = writeln!(out, "song: {}\nsongid: {}", current, id);
+            }
+            if !state.is_empty {
+                let elapsed = player.get_position_secs() as f64;
+                let duration = match player.current_path() {
+                    Some(path) => context
+                        .db
+                        .get_track_by_path(&path)
+                        .await?
+                        .map(|t| t.duration_ms as f64 / 1000.0)
+                        .unwrap_or(0.0),
+                    None => 0.0,
+                };
+                let _ = writeln!(out, "time: {}:{}", elapsed as i64, duration.round() as i64);
+                let _ = writeln!(out, "elapsed: {:.3}\nduration: {:.3}", elapsed, duration);
+            }
+        }
+        "currentsong" => {
+            let queue = player.get_queue();
+            if let Some(path) = player.current_path() {
+                if let Some(track) = context.db.get_track_by_path(&path).await? {
+                    let queued = queue.current.and_then(|pos| queue.items.get(pos).map(|item| (pos, item.id)));
+                    write_song(context, out, &track, queued);
+                }
+            }
+        }
+        "stats" => {
+            let (artists, albums, songs, playtime): (i64, i64, i64, i64) = sqlx::query_as(
+                "SELECT
+                    (SELECT COUNT(*) FROM artists),
+                    (SELECT COUNT(*) FROM albums),
+                    (SELECT COUNT(*) FROM tracks),
+                    (SELECT COALESCE(SUM(duration_ms), 0) / 1000 FROM tracks)",
+            )
+            .fetch_one(&context.db.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+            let _ = writeln!(
+                out,
+                "artists: {}\nalbums: {}\nsongs: {}\nuptime: 0\nplaytime: 0\ndb_playtime: {}",
+                artists, albums, songs, playtime
+            );
+        }
+
+        // playback
+        "play" => {
+            let pos = args.first().map(|pos| parse_usize(pos)).transpose()?;
+            with_player(context, move |player| match pos {
+                Some(pos) => player.play_queue_index(pos).map(|_| ()),
+                None if !player.get_playback_state().is_empty => {
+                    player.resume();
+                    Ok(())
+                }
+                None => {
+                    let queue = player.get_queue();
+                    if queue.items.is_empty() {
+                        return Ok(());
+                    }
+                    player.play_queue_index(queue.current.unwrap_or(0)).map(|_| ())
+                }
+            })
+            .await?;
+        }
+        "playid" => {
+            let pos = queue_position(&player, arg(args, 0)?)?;
+            with_player(context, move |player| player.play_queue_index(pos)).await?;
+        }
+        "pause" => {
+            let pause = match args.first().map(String::as_str) {
+                Some("1") => true,
+                Some("0") => false,
+                _ => !player.get_playback_state().is_paused,
+            };
+            if pause {
+                player.pause();
+            } else {
+                player.resume();
+            }
+        }
+        "stop" => player.stop(),
+        // running off either end of the queue just stops, like MPD
+        "next" => {
+            with_player(context, |player| {
+                let _ = player.next_track();
+                Ok(())
+            })
+            .await?;
+        }
+        "previous" => {
+            with_player(context, |player| {
+                let _ = player.previous_track();
+                Ok(())
+            })
+            .await?;
+        }
+        "seekcur" => {
+            let seconds = parse_f32(arg(args, 0)?)?;
+            with_player(context, move |player| {
+                player.seek(seconds);
+                Ok(())
+            })
+            .await?;
+        }
+        "seek" => {
+            let pos = parse_usize(arg(args, 0)?)?;
+            let seconds = parse_f32(arg(args, 1)?)?;
+            with_player(context, move |player| {
+                if player.get_queue().current != Some(pos) {
+                    player.play_queue_index(pos)?;
+                }
+                player.seek(seconds);
+                Ok(())
+            })
+            .await?;
+        }
+        "setvol" => player.set_volume(parse_f32(arg(args, 0)?)? / 100.0),
+        "getvol" => {
+            let volume = player.get_playback_state().volume;
+            let _ = writeln!(out, "volume: {}", (volume * 100.0).round() as i64);
+        }
+
+        // queue
+        "playlistinfo" | "playlistid" => {
+            let queue = player.get_queue();
+            let only = match args.first() {
+                Some(a) if name == "playlistid" => Some(queue_position(&player, a)?),
+                Some(a) => Some(parse_usize(a)?),
+                None => None,
+            };
+            for (pos, item) in queue.items.iter().enumerate() {
+                if only.is_some_and(|o| o != pos) {
+                    continue;
+                }
+                if let Some(track) = context.db.get_track_by_path(&item.file_path).await? {
+                    write_song(context, out, &track, Some((pos, item.id)));
+                }
+            }
+        }
+        "add" | "addid" => {
+            let track = find_by_uri(context, arg(args, 0)?).await?;
+            let items = context.db.get_queue_items(&[track.id]).await?;
+            let ids = player.enqueue(items);
+            if name == "addid" {
+                let _ = writeln!(out, "Id: {}", ids.first().copied().unwrap_or_default());
+            }
+        }
+        "clear" => player.clear_queue(),
+        "delete" => player.remove_from_queue(parse_usize(arg(args, 0)?)?)?,
+        "deleteid" => player.remove_from_queue(queue_position(&player, arg(args, 0)?)?)?,
+
+        // library
+        "find" | "search" => {
+            let ignore_case = name == "search";
+            let default_op = if ignore_case { FilterOp::Contains } else { FilterOp::Equals };
+            let filters = parse_filters(context, args, default_op)?;
+            for track in context.db.find_tracks(&filters, ignore_case).await? {
+                write_song(context, out, &track, None);
+            }
+        }
+        "list" => {
+            let tag = arg(args, 0)?.to_lowercase();
+            let rest = &args[1..];
+            // legacy form: `list album "<artist>"`
+            let filters = if tag == "album" && rest.len() == 1 && !rest[0].starts_with('(') {
+                vec![TrackFilter { field: "artist".to_string(), op: FilterOp::Equals, value: rest[0].clone() }]
+            } else {
+                parse_filters(context, rest, FilterOp::Equals)?
+            };
+
+            let (key, pick): (&str, fn(&Track) -> Option<String>) = match tag.as_str() {
+                "artist" => ("Artist", |t| t.artist_name.clone()),
+                "albumartist" => ("AlbumArtist", |t| t.tags.album_artist.clone().or_else(|| t.artist_name.clone())),
+                "album" => ("Album", |t| t.album_name.clone()),
+                "title" => ("Title", |t| Some(t.title.clone())),
+                "genre" => ("Genre", |t| t.tags.genre.clone()),
+                "composer" => ("Composer", |t| t.tags.composer.clone()),
+                "date" => ("Date", |t| t.tags.release_date.clone()),
+                _ => return Err(Ack::new(ACK_ERROR_ARG, format!("Unsupported tag type: {}", tag))),
+            };
+
+            let mut values: Vec<String> = context
+                .db
+                .find_tracks(&filters, false)
+                .await?
+                .iter()
+                .filter_map(pick)
+                .collect();
+            values.sort_by_key(|v| v.to_lowercase());
+            values.dedup();
+            for value in values {
+                let _ = writeln!(out, "{}: {}", key, value);
+            }
+        }
+        "lsinfo" => match args.first().filter(|uri| !uri.is_empty() && *uri != "/") {
+            Some(uri) => {
+                let track = find_by_uri(context, uri).await?;
+                write_song(context, out, &track, None);
+            }
+            None => write_playlist_entries(context, out).await?,
+        },
+
+        // stored playlists
+        "listplaylists" => write_playlist_entries(context, out).await?,
+        "listplaylist" | "listplaylistinfo" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            for track in context.db.get_playlist_tracks(playlist_id).await? {
+                if name == "listplaylist" {
+                    let _ = writeln!(out, "file: {}", context.path_to_uri(&track.file_path));
+                } else {
+                    write_song(context, out, &track, None);
+                }
+            }
+        }
+        "load" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            let track_ids = playlist_track_ids(context, playlist_id).await?;
+            player.enqueue(context.db.get_queue_items(&track_ids).await?);
+        }
+        "save" => {
+            let playlist_name = arg(args, 0)?;
+            if context.db.get_playlist_by_name(playlist_name).await?.is_some() {
+                return Err(Ack::new(ACK_ERROR_ARG, "Playlist already exists"));
+            }
+            let track_ids = player.get_queue().items.iter().map(|i| i.track_id).collect();
+            context.db.save_playlist(playlist_name.clone(), None, None, track_ids).await?;
+            stored_playlist_changed(context);
+        }
+        "rm" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            context.db.delete_playlist(playlist_id).await?;
+            stored_playlist_changed(context);
+        }
+        "rename" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            let new_name = arg(args, 1)?.clone();
+            edit_playlist(context, playlist_id, Some(new_name), |_| Ok(())).await?;
+        }
+        "playlistadd" => {
+            let playlist_name = arg(args, 0)?;
+            let track = find_by_uri(context, arg(args, 1)?).await?;
+            match context.db.get_playlist_by_name(playlist_name).await? {
+                Some(playlist) => {
+                    edit_playlist(context, playlist.id, None, |ids| {
+                        if !ids.contains(&track.id) {
+                            ids.push(track.id);
+                        }
+                        Ok(())
+                    })
+                    .await?
+                }
+                // MPD creates the playlist on first add
+                None => {
+                    context.db.save_playlist(playlist_name.clone(), None, None, vec![track.id]).await?;
+                    stored_playlist_changed(context);
+                }
+            }
+        }
+        "playlistdelete" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            let pos = parse_usize(arg(args, 1)?)?;
+            edit_playlist(context, playlist_id, None, |ids| {
+                if pos >= ids.len() {
+                    return Err(Ack::new(ACK_ERROR_ARG, "Bad song index"));
+                }
+                ids.remove(pos);
+                Ok(())
+            })
+            .await?;
+        }
+        "playlistclear" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            edit_playlist(context, playlist_id, None, |ids| {
+                ids.clear();
+                Ok(())
+            })
+            .await?;
+        }
+        "playlistmove" => {
+            let playlist_id = find_playlist(context, arg(args, 0)?).await?;
+            let from = parse_usize(arg(args, 1)?)?;
+            let to = parse_usize(arg(args, 2)?)?;
+            edit_playlist(context, playlist_id, None, |ids| {
+                if from >= ids.len() || to >= ids.len() {
+                    return Err(Ack::new(ACK_ERROR_ARG, "Bad song index"));
+                }
+                let id = ids.remove(from);
+                ids.insert(to, id);
+                Ok(())
+            })
+            .await?;
+        }
+
+        _ => return Err(Ack::new(ACK_ERROR_UNKNOWN, format!("unknown command \"{}\"", name))),
+    }
+
+    Ok(())
+}
+
+fn arg(args: &[String], index: usize) -> Result<&String, Ack> {
+    args.get(index)
+        .ok_or_else(|| Ack::new(ACK_ERROR_ARG, "wrong number of arguments"))
+}
+
+fn parse_usize(value: &str) -> Result<usize, Ack> {
+    value
+        .parse()
+        .map_err(|_| Ack::new(ACK_ERROR_ARG, format!("Integer expected: {}", value)))
+}
+
+// MPD song ids are the queue items' own ids, they don't shift when the queue does
+fn queue_position<R: Runtime>(player: &AudioPlayer<R>, id: &str) -> Result<usize, Ack> {
+    let id = parse_usize(id)? as i64;
+    player
+        .queue_index_of(id)
+        .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such song"))
+}
+
+fn parse_f32(value: &str) -> Result<f32, Ack> {
+    value
+        .parse()
+        .map_err(|_| Ack::new(ACK_ERROR_ARG, format!("Number expected: {}", value)))
+}
+
+fn write_song<R: Runtime>(context: &MpdContext<R>, out: &mut String, track: &Track, queued: Option<(usize, i64)>) {
+    let _ = writeln!(out, "file: {}", context.path_to_uri(&track.file_path));
+    let _ = writeln!(out, "Title: {}", track.title);
+    if let Some(artist) = &track.artist_name {
+        let _ = writeln!(out, "Artist: {}", artist);
+    }
+    if let Some(album) = &track.album_name {
+        let _ = writeln!(out, "Album: {}", album);
+    }
+    let tags = &track.tags;
+    let optional = [
+        ("AlbumArtist", tags.album_artist.clone()),
+        ("Track", tags.track_number.map(|n| n.to_string())),
+        ("Disc", tags.disc_number.map(|n| n.to_string())),
+        ("Date", tags.release_date.clone()),
+        ("Genre", tags.genre.clone()),
+        ("Composer", tags.composer.clone()),
+        ("Comment", tags.comment.clone()),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            let _ = writeln!(out, "{}: {}", key, value);
+        }
+    }
+    let seconds = track.duration_ms as f64 / 1000.0;
+    let _ = writeln!(out, "Time: {}\nduration: {:.3}", seconds.round() as i64, seconds);
+    if let Some((pos, id)) = queued {
+        let _ = writeln!(out, "Pos: {}\nId: {}", pos, id);
+    }
+}
+
+async fn write_playlist_entries<R: Runtime>(context: &MpdContext<R>, out: &mut String) -> Result<(), Ack> {
+    let playlists: Vec<(String, i64)> =
+        sqlx::query_as("SELECT name, updated_at FROM playlists WHERE is_system = 0 ORDER BY name COLLATE NOCASE")
+            .fetch_all(&context.db.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+    for (name, updated_at) in playlists {
+        let modified = chrono::DateTime::from_timestamp(updated_at, 0)
+            .map(|d| d.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_default();
+        let _ = writeln!(out, "playlist: {}\nLast-Modified: {}", name, modified);
+    }
+    Ok(())
+}
+
+async fn find_by_uri<R: Runtime>(context: &MpdContext<R>, uri: &str) -> Result<Track, Ack> {
+    context
+        .db
+        .get_track_by_path(&context.uri_to_path(uri))
+        .await?
+        .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such song"))
+}
+
+async fn find_playlist<R: Runtime>(context: &MpdContext<R>, name: &str) -> Result<i64, Ack> {
+    context
+        .db
+        .get_playlist_by_name(name)
+        .await?
+        .map(|p| p.id)
+        .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such playlist"))
+}
+
+async fn playlist_track_ids<R: Runtime>(context: &MpdContext<R>, playlist_id: i64) -> Result<Vec<i64>, Ack> {
+    Ok(context
+        .db
+        .get_playlist_tracks(playlist_id)
+        .await?
+        .iter()
+        .map(|t| t.id)
+        .collect())
+}
+
+// Stored playlists are edited by rewriting the track list through update_playlist,
+// the same way the frontend does it
+async fn edit_playlist<R: Runtime>(
+    context: &MpdContext<R>,
+    playlist_id: i64,
+    new_name: Option<String>,
+    edit: impl FnOnce(&mut Vec<i64>) -> Result<(), Ack>,
+) -> Result<(), Ack> {
+    let playlist = context.db.get_playlist(playlist_id).await?;
+    let mut track_ids = playlist_track_ids(context, playlist_id).await?;
+    edit(&mut track_ids)?;
+
+    context
+        .db
+        .update_playlist(
+            playlist_id,
+            new_name.unwrap_or(playlist.name),
+            playlist.description,
+            playlist.cover_path,
+            track_ids,
+        )
+        .await?;
+    stored_playlist_changed(context);
+    Ok(())
+}
+
+fn stored_playlist_changed<R: Runtime>(context: &MpdContext<R>) {
+    let _ = context.events.send("stored_playlist");
+}
+
+// Accepts both the legacy `TAG VALUE [TAG VALUE...]` pairs and the filter
+// expressions newer clients send, e.g. `((artist == 'X') AND (album == 'Y'))`
+fn parse_filters<R: Runtime>(context: &MpdContext<R>, args: &[String], default_op: FilterOp) -> Result<Vec<TrackFilter>, Ack> {
+    let mut filters = Vec::new();
+    let mut rest = args;
+
+    if let Some(expression) = rest.first().filter(|a| a.starts_with('(')) {
+        filters = parse_filter_expression(expression)?;
+        rest = &rest[1..];
+    }
+
+    for pair in rest.chunks(2) {
+        let [tag, value] = pair else {
+            return Err(Ack::new(ACK_ERROR_ARG, "Incorrect number of filter arguments"));
+        };
+        // result shaping options, not filters
+        if matches!(tag.to_lowercase().as_str(), "sort" | "window" | "group" | "base") {
+            continue;
+        }
+        filters.push(TrackFilter {
+            field: tag.to_lowercase(),
+            op: default_op,
+            value: value.clone(),
+        });
+    }
+
+    for filter in filters.iter_mut() {
+        if filter.field == "file" {
+            filter.value = context.uri_to_path(&filter.value);
+        }
+    }
+
+    Ok(filters)
+}
+
+fn parse_filter_expression(expression: &str) -> Result<Vec<TrackFilter>, Ack> {
+    // split into words and quoted strings, parens and AND only add structure we don't need
+    let mut tokens: Vec<(bool, String)> = Vec::new(); // (is_quoted, text)
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | ')' => {}
+            c if c.is_whitespace() => {}
+            '\'' | '"' => {
+                let mut value = String::new();
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => value.extend(chars.next()),
+                        q if q == c => break,
+                        other => value.push(other),
+                    }
+                }
+                tokens.push((true, value));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.peek().copied().filter(|n| !n.is_whitespace() && *n != '(' && *n != ')') {
+                    word.push(next);
+                    chars.next();
+                }
+                if word != "AND" {
+                    tokens.push((false, word));
+                }
+            }
+        }
+    }
+
+    tokens
+        .chunks(3)
+        .map(|chunk| match chunk {
+            [(false, tag), (false, op), (true, value)] => {
+                let op = match op.as_str() {
+                    "==" => FilterOp::Equals,
+                    "!=" => FilterOp::NotEquals,
+                    "contains" => FilterOp::Contains,
+                    "=~" | "!~" => {
+                        return Err(Ack::new(ACK_ERROR_ARG, "Regular expression filters are not supported"))
+                    }
+                    "starts_with" => FilterOp::StartsWith,
+                    other => return Err(Ack::new(ACK_ERROR_ARG, format!("Unsupported filter operator: {}", other))),
+                };
+                Ok(TrackFilter { field: tag.to_lowercase(), op, value: value.clone() })
+            }
+            _ => Err(Ack::new(ACK_ERROR_ARG, "Invalid filter expression")),
+        })
+        .collect()
+}
@@ -0,0 +1,561 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, EventId, Listener, Runtime, State, Wry};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, oneshot};
+
+use crate::{
+    models::AppState as Database,
+    user_config::{self, load_music_dir, MpdConfig},
+};
+
+mod commands;
+
+const GREETING: &str = "OK MPD 0.23.5\n";
+
+// Player events mapped onto the MPD idle subsystems they touch
+const SUBSYSTEM_EVENTS: &[(&str, &[&str])] = &[
+    ("playback_state", &["player", "mixer"]),
+    ("track_finished", &["player"]),
+    ("queue_changed", &["playlist", "player"]),
+];
+
+// MPD ack error codes
+pub const ACK_ERROR_ARG: u32 = 2;
+pub const ACK_ERROR_UNKNOWN: u32 = 5;
+pub const ACK_ERROR_NO_EXIST: u32 = 50;
+pub const ACK_ERROR_SYSTEM: u32 = 52;
+
+#[derive(Debug)]
+pub struct Ack {
+    pub code: u32,
+    pub message: String,
+}
+
+impl Ack {
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+// database and player errors are all reported as "system" errors
+impl From<String> for Ack {
+    fn from(message: String) -> Self {
+        Self::new(ACK_ERROR_SYSTEM, message)
+    }
+}
+
+// Shared by every client connection
+pub struct MpdContext<R: Runtime = Wry> {
+    pub db: Database,
+    pub app: AppHandle<R>,
+    pub music_dir: Option<PathBuf>,
+    pub events: broadcast::Sender<&'static str>,
+    // bumped on every queue change, reported as `playlist:` in status
+    pub queue_version: AtomicU32,
+}
+
+impl<R: Runtime> MpdContext<R> {
+    pub fn new(app: AppHandle<R>, db: Database, music_dir: Option<PathBuf>) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            db,
+            app,
+            music_dir,
+            events,
+            queue_version: AtomicU32::new(1),
+        }
+    }
+
+    // MPD uris are relative to the music directory; anything outside it stays absolute
+    pub fn path_to_uri(&self, file_path: &str) -> String {
+        self.music_dir
+            .as_ref()
+            .and_then(|dir| std::path::Path::new(file_path).strip_prefix(dir).ok())
+            .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_else(|| file_path.to_string())
+    }
+
+    pub fn uri_to_path(&self, uri: &str) -> String {
+        match &self.music_dir {
+            Some(dir) if !std::path::Path::new(uri).is_absolute() => {
+                dir.join(uri).to_string_lossy().into_owned()
+            }
+            _ => uri.to_string(),
+        }
+    }
+}
+
+struct RunningServer {
+    shutdown: oneshot::Sender<()>,
+    listeners: Vec<EventId>,
+}
+
+#[derive(Default)]
+pub struct MpdServer {
+    running: Mutex<Option<RunningServer>>,
+}
+
+#[allow(dead_code)]
+impl MpdServer {
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().is_some()
+    }
+
+    pub async fn start(&self, app: AppHandle, db: Database, config: &MpdConfig) -> Result<(), String> {
+        self.stop(&app);
+
+        let listener = TcpListener::bind((config.bind_address.as_str(), config.port))
+            .await
+            .map_err(|e| format!("Failed to bind MPD port {}: {}", config.port, e))?;
+
+        let context = Arc::new(MpdContext::new(
+            app.clone(),
+            db,
+            load_music_dir(app.clone()).map(|dir| PathBuf::from(dir.trim())),
+        ));
+        let listeners = forward_events(&context);
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        tauri::async_runtime::spawn(serve(listener, context, shutdown_rx));
+
+        *self.running.lock().unwrap() = Some(RunningServer { shutdown, listeners });
+        Ok(())
+    }
+
+    pub fn stop(&self, app: &AppHandle) {
+        if let Some(server) = self.running.lock().unwrap().take() {
+            for id in server.listeners {
+                app.unlisten(id);
+            }
+            let _ = server.shutdown.send(());
+        }
+    }
+}
+
+// Turns player events into idle notifications, returns the listeners to remove on stop
+fn forward_events<R: Runtime>(context: &Arc<MpdContext<R>>) -> Vec<EventId> {
+    SUBSYSTEM_EVENTS
+        .iter()
+        .map(|(event, subsystems)| {
+            let subsystems = *subsystems;
+            let handler_context = context.clone();
+            context.app.listen_any(*event, move |_| {
+                if subsystems.contains(&"playlist") {
+                    handler_context.queue_version.fetch_add(1, Ordering::SeqCst);
+                }
+                for subsystem in subsystems {
+                    let _ = handler_context.events.send(subsystem);
+                }
+            })
+        })
+        .collect()
+}
+
+async fn serve<R: Runtime>(listener: TcpListener, context: Arc<MpdContext<R>>, mut shutdown: oneshot::Receiver<()>) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let context = context.clone();
+                    tauri::async_runtime::spawn(async move {
+                        let _ = handle_client(stream, context).await;
+                    });
+                }
+                Err(e) => log::warn!("MPD accept failed: {}", e),
+            },
+            _ = &mut shutdown => break,
+        }
+    }
+}
+
+async fn handle_client<R: Runtime>(stream: TcpStream, context: Arc<MpdContext<R>>) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut events = context.events.subscribe();
+    // subsystems that changed since this client last went idle
+    let mut pending: HashSet<&'static str> = HashSet::new();
+
+    writer.write_all(GREETING.as_bytes()).await?;
+
+    // Some(list_ok) while inside command_list_begin / command_list_ok_begin
+    let mut command_list: Option<(bool, Vec<String>)> = None;
+
+    while let Some(line) = lines.next_line().await? {
+        while let Ok(subsystem) = events.try_recv() {
+            pending.insert(subsystem);
+        }
+
+        let line = line.trim_end().to_string();
+
+        if let Some((list_ok, commands)) = command_list.as_mut() {
+            if line == "command_list_end" {
+                let list_ok = *list_ok;
+                let commands = std::mem::take(commands);
+                command_list = None;
+                let response = run_command_list(&context, &commands, list_ok).await;
+                writer.write_all(response.as_bytes()).await?;
+            } else {
+                commands.push(line);
+            }
+            continue;
+        }
+
+        match line.as_str() {
+            "command_list_begin" => command_list = Some((false, Vec::new())),
+            "command_list_ok_begin" => command_list = Some((true, Vec::new())),
+            "close" => break,
+            _ if line.split_whitespace().next() == Some("idle") => {
+                let wanted: Vec<String> = tokenize(&line).unwrap_or_default().into_iter().skip(1).collect();
+                let matches = |s: &str| wanted.is_empty() || wanted.iter().any(|w| w == s);
+
+                let mut changed: Vec<&str> = pending.iter().copied().filter(|s| matches(s)).collect();
+                if changed.is_empty() {
+                    // block until something relevant changes or the client sends noidle
+                    loop {
+                        tokio::select! {
+                            event = events.recv() => match event {
+                                Ok(subsystem) if matches(subsystem) => {
+                                    changed.push(subsystem);
+                                    break;
+                                }
+                                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                                Err(_) => return Ok(()),
+                            },
+                            next = lines.next_line() => match next? {
+                                Some(cmd) if cmd.trim() == "noidle" => break,
+                                // anything else while idle is a protocol error, MPD drops the client
+                                _ => return Ok(()),
+                            },
+                        }
+                    }
+                }
+
+                changed.sort_unstable();
+                changed.dedup();
+                let mut response = String::new();
+                for subsystem in changed {
+                    pending.remove(subsystem);
+                    response.push_str(&format!("changed: {}\n", subsystem));
+                }
+                response.push_str("OK\n");
+                writer.write_all(response.as_bytes()).await?;
+            }
+            _ => {
+                let response = match run_line(&context, &line).await {
+                    Ok(output) => output + "OK\n",
+                    Err((name, ack)) => format_ack(&ack, 0, &name),
+                };
+                writer.write_all(response.as_bytes()).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_line<R: Runtime>(context: &MpdContext<R>, line: &str) -> Result<String, (String, Ack)> {
+    let args = tokenize(line).map_err(|e| (String::new(), Ack::new(ACK_ERROR_ARG, e)))?;
+    let Some((name, args)) = args.split_first() else {
+        return Err((String::new(), Ack::new(ACK_ERROR_UNKNOWN, "No command given")));
+    };
+
+    let mut output = String::new();
+    commands::execute(context, name, args, &mut output)
+        .await
+        .map(|_| output)
+        .map_err(|ack| (name.clone(), ack))
+}
+
+async fn run_command_list<R: Runtime>(context: &MpdContext<R>, commands: &[String], list_ok: bool) -> String {
+    let mut response = String::new();
+    for (index, line) in commands.iter().enumerate() {
+        match run_line(context, line).await {
+            Ok(output) => {
+                response.push_str(&output);
+                if list_ok {
+                    response.push_str("list_OK\n");
+                }
+            }
+            // the first failure aborts the rest of the list
+            Err((name, ack)) => {
+                response.push_str(&format_ack(&ack, index, &name));
+                return response;
+            }
+        }
+    }
+    response.push_str("OK\n");
+    response
+}
+
+fn format_ack(ack: &Ack, index: usize, command: &str) -> String {
+    format!("ACK [{}@{}] {{{}}} {}\n", ack.code, index, command, ack.message)
+}
+
+// Splits a request line into arguments; double quotes group, backslash escapes
+pub fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+
+        let mut arg = String::new();
+        if first == '"' {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => arg.extend(chars.next()),
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    c => arg.push(c),
+                }
+            }
+            if !closed {
+                return Err("Missing closing '\"'".to_string());
+            }
+        } else {
+            while let Some(c) = chars.peek().copied().filter(|c| !c.is_whitespace()) {
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+// Called once from setup(), only starts the server if the user enabled it before
+pub fn start_if_enabled(app: AppHandle, db: Database) {
+    let config = user_config::load_mpd_config(&app);
+    if !config.enabled {
+        return;
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let server = tauri::Manager::state::<MpdServer>(&app);
+        if let Err(e) = server.start(app.clone(), db, &config).await {
+            log::error!("{}", e);
+        }
+    });
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_mpd_config(app: AppHandle) -> MpdConfig {
+    user_config::load_mpd_config(&app)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_mpd_config(
+    app: AppHandle,
+    state: State<'_, Database>,
+    server: State<'_, MpdServer>,
+    config: MpdConfig,
+) -> Result<MpdConfig, String> {
+    if config.enabled {
+        server.start(app.clone(), state.inner().clone(), &config).await?;
+    } else {
+        server.stop(&app);
+    }
+
+    user_config::save_mpd_config(&app, &config)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpStream};
+
+    use tauri::test::{mock_app, MockRuntime};
+    use tauri::{App, Manager};
+
+    use super::*;
+    use crate::player::AudioPlayer;
+    use crate::test_support::{memory_db, track};
+
+    struct TestServer {
+        addr: SocketAddr,
+        _app: App<MockRuntime>,
+        // the server stops once this is dropped
+        _shutdown: oneshot::Sender<()>,
+    }
+
+    fn start_server() -> TestServer {
+        let app = mock_app();
+        let handle = app.handle().clone();
+        handle.manage(AudioPlayer::new(handle.clone()));
+
+        tauri::async_runtime::block_on(async move {
+            let db = memory_db().await;
+            db.add_track(track("/music/Artist/One.mp3", "One", "Artist", "Album")).await.unwrap();
+            db.add_track(track("/music/Artist/Two.mp3", "Two", "Artist", "Album")).await.unwrap();
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let context = Arc::new(MpdContext::new(handle, db, Some(PathBuf::from("/music"))));
+            forward_events(&context);
+            let (shutdown, shutdown_rx) = oneshot::channel();
+            tauri::async_runtime::spawn(serve(listener, context, shutdown_rx));
+
+            TestServer { addr, _app: app, _shutdown: shutdown }
+        })
+    }
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(server: &TestServer) -> Self {
+            let writer = TcpStream::connect(server.addr).unwrap();
+            writer.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+            let mut client = Client { reader: BufReader::new(writer.try_clone().unwrap()), writer };
+            assert_eq!(client.read_line(), GREETING.trim_end());
+            client
+        }
+
+        fn read_line(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }
+
+        fn send(&mut self, line: &str) {
+            self.writer.write_all(format!("{}\n", line).as_bytes()).unwrap();
+        }
+
+        // every line up to and including the closing OK or ACK
+        fn response(&mut self) -> Vec<String> {
+            let mut lines = Vec::new();
+            loop {
+                let line = self.read_line();
+                let done = line == "OK" || line.starts_with("ACK ");
+                lines.push(line);
+                if done {
+                    return lines;
+                }
+            }
+        }
+
+        fn command(&mut self, line: &str) -> Vec<String> {
+            self.send(line);
+            self.response()
+        }
+    }
+
+    fn value<'a>(response: &'a [String], key: &str) -> Option<&'a str> {
+        response.iter().find_map(|line| line.strip_prefix(&format!("{}: ", key)))
+    }
+
+    fn values<'a>(response: &'a [String], key: &str) -> Vec<&'a str> {
+        response.iter().filter_map(|line| line.strip_prefix(&format!("{}: ", key))).collect()
+    }
+
+    #[test]
+    fn status_and_currentsong_on_an_empty_queue() {
+        let server = start_server();
+        let mut client = Client::connect(&server);
+
+        let status = client.command("status");
+        assert_eq!(status.last().unwrap(), "OK");
+        assert_eq!(value(&status, "playlistlength"), Some("0"));
+        assert_eq!(value(&status, "state"), Some("stop"));
+        assert_eq!(value(&status, "songid"), None);
+
+        assert_eq!(client.command("currentsong"), vec!["OK"]);
+    }
+
+    #[test]
+    fn song_ids_stay_with_their_queue_items() {
+        let server = start_server();
+        let mut client = Client::connect(&server);
+
+        assert_eq!(client.command("add \"Artist/One.mp3\""), vec!["OK"]);
+        let added = client.command("addid \"Artist/Two.mp3\"");
+        let two_id = value(&added, "Id").unwrap().to_string();
+
+        let queue = client.command("playlistinfo");
+        assert_eq!(values(&queue, "file"), vec!["Artist/One.mp3", "Artist/Two.mp3"]);
+        assert_eq!(values(&queue, "Pos"), vec!["0", "1"]);
+        let ids: Vec<String> = values(&queue, "Id").into_iter().map(str::to_string).collect();
+        assert_eq!(ids[1], two_id);
+
+        // removing the first item moves Two to position 0 but keeps its id
+        assert_eq!(client.command(&format!("deleteid {}", ids[0])), vec!["OK"]);
+        let queue = client.command("playlistinfo");
+        assert_eq!(values(&queue, "Title"), vec!["Two"]);
+        assert_eq!(value(&queue, "Pos"), Some("0"));
+        assert_eq!(value(&queue, "Id"), Some(two_id.as_str()));
+
+        let song = client.command(&format!("playlistid {}", two_id));
+        assert_eq!(value(&song, "Title"), Some("Two"));
+
+        let gone = client.command(&format!("playlistid {}", ids[0]));
+        assert_eq!(gone, vec!["ACK [50@0] {playlistid} No such song"]);
+        let gone = client.command(&format!("deleteid {}", ids[0]));
+        assert_eq!(gone, vec!["ACK [50@0] {deleteid} No such song"]);
+
+        let status = client.command("status");
+        assert_eq!(value(&status, "playlistlength"), Some("1"));
+
+        let missing = client.command("add \"Artist/Three.mp3\"");
+        assert_eq!(missing, vec!["ACK [50@0] {add} No such song"]);
+    }
+
+    #[test]
+    fn idle_wakes_up_on_queue_changes() {
+        let server = start_server();
+        let mut idler = Client::connect(&server);
+        let mut client = Client::connect(&server);
+
+        let before = client.command("status");
+        let version: u32 = value(&before, "playlist").unwrap().parse().unwrap();
+
+        idler.send("idle playlist");
+        assert_eq!(client.command("add \"Artist/One.mp3\""), vec!["OK"]);
+        assert_eq!(idler.response(), vec!["changed: playlist", "OK"]);
+
+        let after = idler.command("status");
+        assert!(value(&after, "playlist").unwrap().parse::<u32>().unwrap() > version);
+
+        // noidle ends an idle that nothing woke up
+        idler.send("idle stored_playlist");
+        idler.send("noidle");
+        assert_eq!(idler.response(), vec!["OK"]);
+    }
+
+    #[test]
+    fn only_idle_itself_idles() {
+        let server = start_server();
+        let mut client = Client::connect(&server);
+
+        assert_eq!(client.command("idlefoo"), vec!["ACK [5@0] {idlefoo} unknown command \"idlefoo\""]);
+        assert_eq!(client.command("ping"), vec!["OK"]);
+    }
+
+    #[test]
+    fn regex_filters_are_refused() {
+        let server = start_server();
+        let mut client = Client::connect(&server);
+
+        let response = client.command("find \"(Title =~ 'O.e')\"");
+        assert_eq!(response, vec!["ACK [2@0] {find} Regular expression filters are not supported"]);
+    }
+}
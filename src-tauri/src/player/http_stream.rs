@@ -0,0 +1,338 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Default)]
+struct Buffer {
+    data: Vec<u8>,
+    done: bool,
+    error: Option<String>,
+}
+
+// A remote file as Read + Seek for the decoder. The whole file downloads in the
+// background and reads past what has arrived block until it does, so seeking far
+// ahead waits for the download. With a cache path the file is also written there,
+// and only renamed into place once it arrived completely.
+pub struct HttpStream {
+    shared: Arc<(Mutex<Buffer>, Condvar)>,
+    cancelled: Arc<AtomicBool>,
+    pos: u64,
+    len: Option<u64>,
+}
+
+impl HttpStream {
+    pub fn open(url: &str, cache_path: Option<PathBuf>) -> Result<Self, String> {
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(None)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        let mut response = client
+            .get(url)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to start stream: {}", e))?;
+
+        // Subsonic servers report errors as a 200 with an XML or JSON body
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        if content_type.starts_with("text/xml") || content_type.starts_with("application/json") {
+            let body = response.text().unwrap_or_default();
+            return Err(format!("Server refused to stream the track: {}", body));
+        }
+
+        let len = response.content_length();
+        let shared = Arc::new((Mutex::new(Buffer::default()), Condvar::new()));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let (thread_shared, thread_cancelled) = (shared.clone(), cancelled.clone());
+        thread::spawn(move || {
+            let (lock, ready) = &*thread_shared;
+            let mut cache = cache_path.as_deref().and_then(CacheWriter::create);
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+
+            let result = loop {
+                if thread_cancelled.load(Ordering::SeqCst) {
+                    break Err("Stream cancelled".to_string());
+                }
+                match response.read(&mut chunk) {
+                    Ok(0) => break Ok(()),
+                    Ok(n) => {
+                        // the cache is best effort, playback carries on without it
+                        if cache.as_mut().is_some_and(|c| c.file.write_all(&chunk[..n]).is_err()) {
+                            cache = None;
+                        }
+                        lock.lock().unwrap().data.extend_from_slice(&chunk[..n]);
+                        ready.notify_all();
+                    }
+                    Err(e) => break Err(format!("Stream interrupted: {}", e)),
+                }
+            };
+
+            let mut buffer = lock.lock().unwrap();
+            let complete = result.is_ok() && len.is_none_or(|len| buffer.data.len() as u64 == len);
+            if let Some(cache) = cache {
+                cache.finish(complete);
+            }
+            buffer.error = result.err();
+            buffer.done = true;
+            ready.notify_all();
+        });
+
+        Ok(Self { shared, cancelled, pos: 0, len })
+    }
+
+    pub fn len(&self) -> Option<u64> {
+        self.len
+    }
+}
+
+impl Drop for HttpStream {
+    // stops the download when the track is skipped
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (lock, ready) = &*self.shared;
+        let mut buffer = lock.lock().unwrap();
+        while buffer.data.len() as u64 <= self.pos && !buffer.done {
+            buffer = ready.wait(buffer).unwrap();
+        }
+
+        let start = self.pos as usize;
+        if start >= buffer.data.len() {
+            return match &buffer.error {
+                Some(e) => Err(io::Error::other(e.clone())),
+                None => Ok(0),
+            };
+        }
+
+        let n = buf.len().min(buffer.data.len() - start);
+        buf[..n].copy_from_slice(&buffer.data[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::Current(delta) => self.pos as i128 + delta as i128,
+            SeekFrom::End(delta) => {
+                let end = match self.len {
+                    Some(len) => len,
+                    // without a Content-Length the end is only known once everything arrived
+                    None => {
+                        let (lock, ready) = &*self.shared;
+                        let mut buffer = lock.lock().unwrap();
+                        while !buffer.done {
+                            buffer = ready.wait(buffer).unwrap();
+                        }
+                        buffer.data.len() as u64
+                    }
+                };
+                end as i128 + delta as i128
+            }
+        };
+
+        if target < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek before start of stream"));
+        }
+        self.pos = target as u64;
+        Ok(self.pos)
+    }
+}
+
+// Writes to `<name>.part` and renames on success, so a half-downloaded file is
+// never mistaken for a cached one
+struct CacheWriter {
+    file: File,
+    part_path: PathBuf,
+    final_path: PathBuf,
+}
+
+impl CacheWriter {
+    fn create(path: &Path) -> Option<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).ok()?;
+        }
+        let part_path = path.with_extension("part");
+        let file = File::create(&part_path).ok()?;
+        Some(Self { file, part_path, final_path: path.to_path_buf() })
+    }
+
+    fn finish(mut self, complete: bool) {
+        if complete && self.file.flush().is_ok() && std::fs::rename(&self.part_path, &self.final_path).is_ok() {
+            return;
+        }
+        let _ = std::fs::remove_file(&self.part_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+    use crate::db::LibrarySource;
+    use crate::models::SourceConfig;
+    use crate::player::MediaSource;
+    use crate::sources::{remote_path, SubsonicSource};
+    use crate::test_support::{serve, temp_dir, Response};
+
+    // a few chunks long, and no two nearby bytes alike
+    fn audio_bytes() -> Vec<u8> {
+        (0..300_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn read_at(stream: &mut HttpStream, pos: SeekFrom, len: usize) -> Vec<u8> {
+        stream.seek(pos).unwrap();
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn reads_any_range_of_the_file() {
+        let body = audio_bytes();
+        let served = body.clone();
+        let base = serve(move |_| Response::ok(served.clone()));
+
+        let mut stream = HttpStream::open(&format!("{}/song.mp3", base), None).unwrap();
+        assert_eq!(stream.len(), Some(body.len() as u64));
+
+        assert_eq!(read_at(&mut stream, SeekFrom::Start(0), 16), body[..16]);
+        assert_eq!(read_at(&mut stream, SeekFrom::Start(200_000), 70_000), body[200_000..270_000]);
+        assert_eq!(read_at(&mut stream, SeekFrom::Current(-150_000), 10), body[120_000..120_010]);
+        assert_eq!(read_at(&mut stream, SeekFrom::End(-5), 5), body[body.len() - 5..]);
+
+        // past the end reads nothing instead of blocking
+        stream.seek(SeekFrom::End(10)).unwrap();
+        assert_eq!(stream.read(&mut [0u8; 8]).unwrap(), 0);
+        assert!(stream.seek(SeekFrom::Current(-(body.len() as i64) - 100)).is_err());
+    }
+
+    #[test]
+    fn seeks_from_the_end_without_a_content_length() {
+        let body = audio_bytes();
+        let served = body.clone();
+        let base = serve(move |_| Response::ok(served.clone()).without_length());
+
+        let mut stream = HttpStream::open(&format!("{}/song.mp3", base), None).unwrap();
+        assert_eq!(stream.len(), None);
+
+        // waits for the whole download to find the end
+        assert_eq!(stream.seek(SeekFrom::End(-100)).unwrap(), body.len() as u64 - 100);
+        let mut tail = Vec::new();
+        stream.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, body[body.len() - 100..]);
+    }
+
+    #[test]
+    fn refuses_subsonic_error_bodies() {
+        let base = serve(|_| {
+            Response::ok(r#"<subsonic-response status="failed"/>"#).header("Content-Type", "text/xml")
+        });
+        let error = HttpStream::open(&format!("{}/rest/stream", base), None).err().unwrap();
+        assert!(error.contains("Server refused"), "{}", error);
+    }
+
+    #[test]
+    fn cached_streams_play_offline() {
+        let body = audio_bytes();
+        let served = body.clone();
+        let online = Arc::new(AtomicBool::new(true));
+        let server_online = online.clone();
+        let base = serve(move |request| {
+            if !server_online.load(Ordering::SeqCst) {
+                return Response::status(503);
+            }
+            match request.path.split('?').next() {
+                Some("/rest/stream") => Response::ok(served.clone()).header("Content-Type", "audio/mpeg"),
+                _ => Response::status(404),
+            }
+        });
+
+        let cache_root = temp_dir("stream-cache");
+        let source = SubsonicSource::new(
+            &SourceConfig {
+                id: 7,
+                kind: "subsonic".to_string(),
+                name: "Test".to_string(),
+                base_url: Some(base),
+                username: Some("user".to_string()),
+                password: Some("secret".to_string()),
+                cache_enabled: true,
+                last_synced: None,
+                created_at: 0,
+            },
+            &cache_root,
+        );
+        let path = remote_path(7, "song-1");
+
+        // first play streams, and keeps a copy once the whole file arrived
+        let MediaSource::Stream { url, cache_path } = source.resolve(&path).unwrap() else {
+            panic!("expected a stream before anything is cached");
+        };
+        let cache_path = cache_path.unwrap();
+        let mut stream = HttpStream::open(&url, Some(cache_path.clone())).unwrap();
+        let mut streamed = Vec::new();
+        stream.read_to_end(&mut streamed).unwrap();
+        drop(stream);
+        assert_eq!(streamed, body);
+        assert_eq!(std::fs::read(&cache_path).unwrap(), body);
+        assert!(!cache_path.with_extension("part").exists());
+
+        // with the server gone the cached file is played instead
+        online.store(false, Ordering::SeqCst);
+        match source.resolve(&path).unwrap() {
+            MediaSource::File(file) => assert_eq!(Path::new(&file), cache_path),
+            other => panic!("expected the cached file, got {:?}", other),
+        }
+        assert!(HttpStream::open(&url, None).is_err());
+
+        let _ = std::fs::remove_dir_all(&cache_root);
+    }
+
+    #[test]
+    fn incomplete_downloads_are_not_cached() {
+        let body = audio_bytes();
+        let served = body[..100_000].to_vec();
+        // claims the full length but hangs up early
+        let full_len = body.len();
+        let base = serve(move |_| {
+            Response::ok(served.clone())
+                .header("Content-Length", full_len)
+                .without_length()
+        });
+
+        let cache_root = temp_dir("stream-partial");
+        let cache_path = cache_root.join("song-1");
+        let mut stream = HttpStream::open(&format!("{}/song.mp3", base), Some(cache_path.clone())).unwrap();
+        let mut streamed = Vec::new();
+        assert!(stream.read_to_end(&mut streamed).is_err());
+        assert_eq!(streamed, body[..100_000]);
+        drop(stream);
+
+        assert!(!cache_path.exists());
+        assert!(!cache_path.with_extension("part").exists());
+        let _ = std::fs::remove_dir_all(&cache_root);
+    }
+}
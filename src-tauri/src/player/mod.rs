@@ -1,17 +1,47 @@
 use rodio::{Sink, Source};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read, Seek};
+use std::path::PathBuf;
 use std::sync::mpsc::{channel, Sender};
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
-use tauri::{AppHandle, Emitter, State};
+use tauri::{AppHandle, Emitter, Listener, Manager, Runtime, State, Wry};
+
+use crate::models::{AppState as Database, QueueItem};
+use crate::sources::LibrarySources;
+
+mod http_stream;
+
+use http_stream::HttpStream;
 
 // Global atomic to track playback "generations"
 static SEEK_VERSION: AtomicU32 = AtomicU32::new(0);
 
+// Bumped by every play(). Sources are opened off the audio thread, so a slow stream
+// can finish opening after a later track; the audio thread drops it in that case.
+static PLAY_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// previous_chapter restarts the current chapter unless we're this close to its start
+const CHAPTER_RESTART_THRESHOLD_MS: i64 = 3000;
+
+// What to open for a library path. Remote tracks stream over HTTP unless a cached
+// copy exists, in which case sources resolve them to a plain file.
+#[derive(Debug, Clone)]
+pub enum MediaSource {
+    File(String),
+    Stream {
+        url: String,
+        cache_path: Option<PathBuf>, // written once the whole file has arrived
+    },
+}
+
+type BoxedSource = Box<dyn Source + Send>;
+
 pub enum AudioCommand {
-    Play(String, Sender<Result<f64, String>>),
+    // an opened source, with the play() generation it was opened for
+    Play(BoxedSource, u64, Sender<Result<f64, String>>),
     Pause,
     Resume,
     Stop,
@@ -28,12 +58,95 @@ pub struct PlaybackState {
     pub volume: f32,
 }
 
-pub struct AudioPlayer {
+// A chapter as the player sees it: a span of some file. Chapters of a multi-file
+// audiobook are loaded in play order, so "next" may cross into the next file.
+#[derive(Clone, serde::Serialize)]
+pub struct ChapterMark {
+    pub track_id: i64,
+    pub title: String,
+    pub file_path: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+// Backend play queue. `current` is None while playing something outside the queue,
+// which also stops the queue from auto-advancing when that track finishes.
+#[derive(Clone, Default, serde::Serialize)]
+pub struct PlayQueue {
+    pub items: Vec<QueueItem>,
+    pub current: Option<usize>,
+    // last id handed to an item, ids are never reused, not even after a clear
+    #[serde(skip)]
+    last_id: i64,
+}
+
+// Generic over the runtime only so tests can drive it with tauri's mock runtime
+pub struct AudioPlayer<R: Runtime = Wry> {
     pub tx: Sender<AudioCommand>,
+    app_handle: AppHandle<R>,
+    current_path: Mutex<Option<String>>,
+    chapters: Mutex<Vec<ChapterMark>>,
+    queue: Mutex<PlayQueue>,
+}
+
+fn build_decoder<R>(reader: R, byte_len: Option<u64>) -> Result<rodio::Decoder<R>, String>
+where
+    R: Read + Seek + Send + Sync + 'static,
+{
+    let mut builder = rodio::Decoder::builder()
+        .with_data(reader);
+
+    // Very important for seeking support
+    if let Some(len) = byte_len {
+        builder = builder.with_byte_len(len);
+    }
+    builder = builder.with_seekable(true); // ← enables seeking
+
+    builder.build()
+        .map_err(|e| format!("Decoder build failed: {}", e))
+}
+
+// Opening a stream waits on the network and probing reads the file, so this runs on
+// a worker thread and only the ready decoder goes to the audio thread
+fn open_media(media: MediaSource) -> Result<BoxedSource, String> {
+    match media {
+        MediaSource::File(path) => {
+            let file = File::open(&path)
+                .map_err(|e| format!("Failed to open file: {}", e))?;
+
+            let reader = BufReader::new(file);
+            let file_len = reader.get_ref().metadata().ok().map(|m| m.len()); // optional but helpful
+
+            Ok(Box::new(build_decoder(reader, file_len)?))
+        }
+        MediaSource::Stream { url, cache_path } => {
+            let stream = HttpStream::open(&url, cache_path)?;
+            let stream_len = stream.len();
+
+            Ok(Box::new(build_decoder(stream, stream_len)?))
+        }
+    }
+}
+
+// Replaces whatever is playing, returns the new track's duration in seconds
+fn start_source<S: Source + Send + 'static>(sink: &Sink, source: S) -> f64 {
+    let duration = source
+        .total_duration()
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0);
+
+    sink.stop();
+    sink.append(source);
+    sink.play();
+
+    // Reset version on new track
+    SEEK_VERSION.store(0, Ordering::SeqCst);
+
+    duration
 }
 
 #[allow(dead_code)]
-fn start_audio_thread(app_handle: AppHandle) -> Sender<AudioCommand> {
+fn start_audio_thread<R: Runtime>(app_handle: AppHandle<R>) -> Sender<AudioCommand> {
     let (tx, rx) = channel::<AudioCommand>();
 
     thread::spawn(move || {
@@ -41,50 +154,42 @@ fn start_audio_thread(app_handle: AppHandle) -> Sender<AudioCommand> {
             .expect("Failed to open default audio output stream");
         let sink = Sink::connect_new(&stream.mixer());
 
+        // set while a track is loaded, so we can tell "finished" apart from "stopped"
+        let mut has_track = false;
+
         loop {
+            let mut state_changed = false;
+
             while let Ok(cmd) = rx.try_recv() {
                 match cmd {
-                    AudioCommand::Play(path, reply) => {
-                        let result: Result<f64, String> = (|| {
-                            let file = File::open(&path)
-                                .map_err(|e| format!("Failed to open file: {}", e))?;
-
-                            let reader = BufReader::new(file);
-                            let file_len = reader.get_ref().metadata().ok().map(|m| m.len()); // optional but helpful
-
-                            let mut builder = rodio::Decoder::builder()
-                                .with_data(reader);
+                    AudioCommand::Play(source, generation, reply) => {
+                        if generation != PLAY_GENERATION.load(Ordering::SeqCst) {
+                            let _ = reply.send(Err("Replaced by another track".to_string()));
+                            continue;
+                        }
 
-                            // Very important for seeking support
-                            if let Some(len) = file_len {
-                                builder = builder.with_byte_len(len);
-                            }
-                            builder = builder.with_seekable(true); // ← enables seeking
-
-                            let source = builder.build()
-                                .map_err(|e| format!("Decoder build failed: {}", e))?;
-
-                            let duration = source
-                                .total_duration()
-                                .map(|d| d.as_secs_f64())
-                                .unwrap_or(0.0);
-
-                            sink.stop();
-                            sink.append(source);
-                            sink.play();
-
-                            // Reset version on new track
-                            SEEK_VERSION.store(0, Ordering::SeqCst);
-
-                            Ok(duration)
-                        })();
-
-                        let _ = reply.send(result);
+                        let duration = start_source(&sink, source);
+                        has_track = true;
+                        state_changed = true;
+                        let _ = reply.send(Ok(duration));
+                    }
+                    AudioCommand::Pause => {
+                        sink.pause();
+                        state_changed = true;
+                    }
+                    AudioCommand::Resume => {
+                        sink.play();
+                        state_changed = true;
+                    }
+                    AudioCommand::Stop => {
+                        sink.stop();
+                        has_track = false;
+                        state_changed = true;
+                    }
+                    AudioCommand::SetVolume(v) => {
+                        sink.set_volume(v.clamp(0.0, 1.0));
+                        state_changed = true;
                     }
-                    AudioCommand::Pause => sink.pause(),
-                    AudioCommand::Resume => sink.play(),
-                    AudioCommand::Stop => sink.stop(),
-                    AudioCommand::SetVolume(v) => sink.set_volume(v.clamp(0.0, 1.0)),
                     AudioCommand::Seek(seconds) => {
                         // Increment version to invalidate old position messages
                         SEEK_VERSION.fetch_add(1, Ordering::SeqCst);
@@ -104,6 +209,22 @@ fn start_audio_thread(app_handle: AppHandle) -> Sender<AudioCommand> {
                 }
             }
 
+            if state_changed {
+                let _ = app_handle.emit(
+                    "playback_state",
+                    PlaybackState {
+                        is_paused: sink.is_paused(),
+                        is_empty: sink.empty(),
+                        volume: sink.volume(),
+                    },
+                );
+            }
+
+            if has_track && sink.empty() {
+                has_track = false;
+                let _ = app_handle.emit("track_finished", ());
+            }
+
             // Emit streaming data
             if !sink.is_paused() && !sink.empty() {
                 let position = sink.get_pos().as_secs_f32();
@@ -120,21 +241,61 @@ fn start_audio_thread(app_handle: AppHandle) -> Sender<AudioCommand> {
 }
 
 #[allow(dead_code)]
-impl AudioPlayer {
-    pub fn new(app_handle: AppHandle) -> Self {
-        let tx = start_audio_thread(app_handle);
-        Self { tx }
+impl<R: Runtime> AudioPlayer<R> {
+    pub fn new(app_handle: AppHandle<R>) -> Self {
+        let tx = start_audio_thread(app_handle.clone());
+
+        // track_finished is emitted from the audio thread, and play() waits on that
+        // thread, so advancing has to happen somewhere else
+        let handle = app_handle.clone();
+        app_handle.listen_any("track_finished", move |_| {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                let player = handle.state::<AudioPlayer<R>>();
+                if player.get_queue().current.is_some() {
+                    let _ = player.next_track();
+                }
+            });
+        });
+
+        Self {
+            tx,
+            app_handle,
+            current_path: Mutex::new(None),
+            chapters: Mutex::new(Vec::new()),
+            queue: Mutex::new(PlayQueue::default()),
+        }
     }
 
+    // `path` is a library file path, remote tracks are resolved through their source
     pub fn play(&self, path: String) -> Result<f64, String> {
+        let media = match self.app_handle.try_state::<LibrarySources>() {
+            Some(sources) => sources.resolve(&path)?,
+            None => MediaSource::File(path.clone()),
+        };
+
+        let generation = PLAY_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
         let (reply_tx, reply_rx) = channel();
-        let _ = self.tx.send(AudioCommand::Play(path, reply_tx));
-        reply_rx.recv().unwrap_or_else(|_| Err("Thread disconnected".into()))
+        let tx = self.tx.clone();
+        thread::spawn(move || match open_media(media) {
+            Ok(source) => {
+                let _ = tx.send(AudioCommand::Play(source, generation, reply_tx));
+            }
+            Err(e) => {
+                let _ = reply_tx.send(Err(e));
+            }
+        });
+        let duration = reply_rx.recv().unwrap_or_else(|_| Err("Thread disconnected".into()))?;
+        *self.current_path.lock().unwrap() = Some(path);
+        Ok(duration)
     }
 
     pub fn pause(&self) { let _ = self.tx.send(AudioCommand::Pause); }
     pub fn resume(&self) { let _ = self.tx.send(AudioCommand::Resume); }
-    pub fn stop(&self) { let _ = self.tx.send(AudioCommand::Stop); }
+    pub fn stop(&self) {
+        let _ = self.tx.send(AudioCommand::Stop);
+        *self.current_path.lock().unwrap() = None;
+    }
     pub fn set_volume(&self, volume: f32) { let _ = self.tx.send(AudioCommand::SetVolume(volume)); }
     pub fn seek(&self, seconds: f32) { let _ = self.tx.send(AudioCommand::Seek(seconds)); }
     
@@ -149,11 +310,207 @@ impl AudioPlayer {
         let _ = self.tx.send(AudioCommand::GetState(reply_tx));
         reply_rx.recv().unwrap_or(PlaybackState { is_paused: true, is_empty: true, volume: 0.0 })
     }
+
+    pub fn current_path(&self) -> Option<String> {
+        self.current_path.lock().unwrap().clone()
+    }
+
+    // chapter navigation
+    pub fn set_chapters(&self, chapters: Vec<ChapterMark>) {
+        *self.chapters.lock().unwrap() = chapters;
+    }
+
+    pub fn clear_chapters(&self) {
+        self.chapters.lock().unwrap().clear();
+    }
+
+    // Index of the chapter under the playhead, None when the current file has no chapters loaded
+    pub fn current_chapter_index(&self) -> Option<usize> {
+        let path = self.current_path()?;
+        let position_ms = (self.get_position_secs() as f64 * 1000.0) as i64;
+        let chapters = self.chapters.lock().unwrap();
+
+        chapters
+            .iter()
+            .rposition(|c| c.file_path == path && c.start_ms <= position_ms)
+            .or_else(|| chapters.iter().position(|c| c.file_path == path))
+    }
+
+    pub fn current_chapter(&self) -> Option<ChapterMark> {
+        let index = self.current_chapter_index()?;
+        self.chapters.lock().unwrap().get(index).cloned()
+    }
+
+    // Only reloads the file when the chapter lives in a different one
+    pub fn jump_to_chapter(&self, index: usize) -> Result<ChapterMark, String> {
+        let chapter = self
+            .chapters
+            .lock()
+            .unwrap()
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("No chapter at index {}", index))?;
+
+        if self.current_path().as_deref() != Some(chapter.file_path.as_str()) {
+            self.play(chapter.file_path.clone())?;
+        }
+        self.seek(chapter.start_ms as f32 / 1000.0);
+
+        Ok(chapter)
+    }
+
+    pub fn next_chapter(&self) -> Result<ChapterMark, String> {
+        let index = self
+            .current_chapter_index()
+            .ok_or_else(|| "Current track has no chapters".to_string())?;
+        if index + 1 >= self.chapters.lock().unwrap().len() {
+            return Err("Already at the last chapter".to_string());
+        }
+        self.jump_to_chapter(index + 1)
+    }
+
+    // Like a CD player: restart the chapter, or go back one if we're already at its start
+    pub fn previous_chapter(&self) -> Result<ChapterMark, String> {
+        let index = self
+            .current_chapter_index()
+            .ok_or_else(|| "Current track has no chapters".to_string())?;
+        let start_ms = self.chapters.lock().unwrap()[index].start_ms;
+        let position_ms = (self.get_position_secs() as f64 * 1000.0) as i64;
+
+        if position_ms - start_ms > CHAPTER_RESTART_THRESHOLD_MS || index == 0 {
+            self.jump_to_chapter(index)
+        } else {
+            self.jump_to_chapter(index - 1)
+        }
+    }
+
+    // queue
+    pub fn get_queue(&self) -> PlayQueue {
+        self.queue.lock().unwrap().clone()
+    }
+
+    fn queue_changed(&self) {
+        let _ = self.app_handle.emit("queue_changed", self.get_queue());
+    }
+
+    // Returns the ids given to the new items
+    pub fn enqueue(&self, items: Vec<QueueItem>) -> Vec<i64> {
+        let ids = {
+            let mut queue = self.queue.lock().unwrap();
+            let mut ids = Vec::with_capacity(items.len());
+            for mut item in items {
+                queue.last_id += 1;
+                item.id = queue.last_id;
+                ids.push(item.id);
+                queue.items.push(item);
+            }
+            ids
+        };
+        self.queue_changed();
+        ids
+    }
+
+    pub fn queue_index_of(&self, id: i64) -> Option<usize> {
+        self.queue.lock().unwrap().items.iter().position(|item| item.id == id)
+    }
+
+    pub fn leave_queue(&self) {
+        self.queue.lock().unwrap().current = None;
+        self.queue_changed();
+    }
+
+    pub fn clear_queue(&self) {
+        {
+            let mut queue = self.queue.lock().unwrap();
+            queue.items.clear();
+            queue.current = None;
+        }
+        self.queue_changed();
+    }
+
+    pub fn remove_from_queue(&self, index: usize) -> Result<(), String> {
+        {
+            let mut queue = self.queue.lock().unwrap();
+            if index >= queue.items.len() {
+                return Err(format!("No queue item at index {}", index));
+            }
+            queue.items.remove(index);
+
+            // keep pointing at the same item, or drop out of the queue if it was removed
+            queue.current = match queue.current {
+                Some(current) if current == index => None,
+                Some(current) if current > index => Some(current - 1),
+                other => other,
+            };
+        }
+        self.queue_changed();
+        Ok(())
+    }
+
+    pub fn play_queue_index(&self, index: usize) -> Result<f64, String> {
+        let item = self
+            .queue
+            .lock()
+            .unwrap()
+            .items
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("No queue item at index {}", index))?;
+
+        self.clear_chapters();
+        let duration = self.play(item.file_path)?;
+        self.queue.lock().unwrap().current = Some(index);
+        self.queue_changed();
+        Ok(duration)
+    }
+
+    // Stops at the end of the queue instead of wrapping around
+    pub fn next_track(&self) -> Result<f64, String> {
+        let next = self.queue.lock().unwrap().current.map_or(0, |i| i + 1);
+        if next >= self.queue.lock().unwrap().items.len() {
+            self.stop();
+            self.queue.lock().unwrap().current = None;
+            self.queue_changed();
+            return Err("Reached the end of the queue".to_string());
+        }
+        self.play_queue_index(next)
+    }
+
+    pub fn previous_track(&self) -> Result<f64, String> {
+        let previous = self
+            .queue
+            .lock()
+            .unwrap()
+            .current
+            .map_or(0, |i| i.saturating_sub(1));
+        self.play_queue_index(previous)
+    }
+}
+
+// Opening a remote track is an HTTP round trip, so commands that start one
+// wait for it on a blocking thread rather than the main one
+async fn with_player<T, F>(app: AppHandle, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&AudioPlayer) -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || f(&app.state::<AudioPlayer>()))
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 // Tauri commands
 #[allow(dead_code)]
-#[tauri::command] pub fn play_track(path: String, player: State<'_, AudioPlayer>) -> Result<f64, String> { player.play(path) }
+#[tauri::command]
+pub async fn play_track(app: AppHandle, path: String) -> Result<f64, String> {
+    with_player(app, move |player| {
+        // a plain track replaces whatever audiobook was loaded, and leaves the queue
+        player.clear_chapters();
+        player.leave_queue();
+        player.play(path)
+    })
+    .await
+}
 
 #[allow(dead_code)]
 #[tauri::command] pub fn pause(player: State<'_, AudioPlayer>) { player.pause(); }
@@ -174,4 +531,52 @@ impl AudioPlayer {
 #[tauri::command] pub fn get_position(player: State<'_, AudioPlayer>) -> f32 { player.get_position_secs() }
 
 #[allow(dead_code)]
-#[tauri::command] pub fn get_playback_state(player: State<'_, AudioPlayer>) -> PlaybackState { player.get_playback_state() }
\ No newline at end of file
+#[tauri::command] pub fn get_playback_state(player: State<'_, AudioPlayer>) -> PlaybackState { player.get_playback_state() }
+
+#[allow(dead_code)]
+#[tauri::command] pub fn next_chapter(player: State<'_, AudioPlayer>) -> Result<ChapterMark, String> { player.next_chapter() }
+
+#[allow(dead_code)]
+#[tauri::command] pub fn previous_chapter(player: State<'_, AudioPlayer>) -> Result<ChapterMark, String> { player.previous_chapter() }
+
+#[allow(dead_code)]
+#[tauri::command] pub fn get_current_chapter(player: State<'_, AudioPlayer>) -> Option<ChapterMark> { player.current_chapter() }
+
+#[allow(dead_code)]
+#[tauri::command] pub fn get_queue(player: State<'_, AudioPlayer>) -> PlayQueue { player.get_queue() }
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn enqueue_tracks(
+    state: State<'_, Database>,
+    player: State<'_, AudioPlayer>,
+    track_ids: Vec<i64>,
+) -> Result<PlayQueue, String> {
+    let items = state.get_queue_items(&track_ids).await?;
+    player.enqueue(items);
+    Ok(player.get_queue())
+}
+
+#[allow(dead_code)]
+#[tauri::command] pub fn clear_queue(player: State<'_, AudioPlayer>) { player.clear_queue(); }
+
+#[allow(dead_code)]
+#[tauri::command] pub fn remove_from_queue(index: usize, player: State<'_, AudioPlayer>) -> Result<(), String> { player.remove_from_queue(index) }
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn play_queue_index(app: AppHandle, index: usize) -> Result<f64, String> {
+    with_player(app, move |player| player.play_queue_index(index)).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn next_track(app: AppHandle) -> Result<f64, String> {
+    with_player(app, |player| player.next_track()).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn previous_track(app: AppHandle) -> Result<f64, String> {
+    with_player(app, |player| player.previous_track()).await
+}
@@ -0,0 +1,202 @@
+use chrono::DateTime;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+// Parsed shape of a feed before it touches the database. Works for both RSS 2.0
+// (channel/item + enclosure) and Atom (feed/entry + link rel="enclosure").
+#[derive(Debug, Default)]
+pub struct ParsedFeed {
+    pub title: String,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub episodes: Vec<ParsedEpisode>,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedEpisode {
+    pub guid: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub audio_url: String,
+    pub published_at: Option<i64>,
+    pub duration_ms: i64,
+}
+
+pub fn parse_feed(xml: &str) -> Result<ParsedFeed, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut feed = ParsedFeed::default();
+    let mut stack: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut current: Option<ParsedEpisode> = None;
+    let mut saw_root = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = tag_name(&e);
+                if name == "rss" || name == "feed" {
+                    saw_root = true;
+                }
+                if name == "item" || name == "entry" {
+                    current = Some(ParsedEpisode::default());
+                }
+                handle_attributes(&e, &name, &mut feed, current.as_mut());
+                stack.push(name);
+                text.clear();
+            }
+            // <enclosure .../>, <link .../> and <itunes:image .../> are usually self-closing
+            Ok(Event::Empty(e)) => {
+                let name = tag_name(&e);
+                handle_attributes(&e, &name, &mut feed, current.as_mut());
+            }
+            Ok(Event::Text(e)) => {
+                let value = e.unescape().map_err(|e| format!("Invalid feed text: {}", e))?;
+                text.push_str(&value);
+            }
+            Ok(Event::CData(e)) => {
+                text.push_str(&String::from_utf8_lossy(&e.into_inner()));
+            }
+            Ok(Event::End(_)) => {
+                let name = stack.pop().unwrap_or_default();
+                let value = std::mem::take(&mut text).trim().to_string();
+
+                if name == "item" || name == "entry" {
+                    if let Some(mut episode) = current.take() {
+                        // feeds without an enclosure are blog posts, not episodes
+                        if !episode.audio_url.is_empty() {
+                            if episode.guid.is_empty() {
+                                episode.guid = episode.audio_url.clone();
+                            }
+                            feed.episodes.push(episode);
+                        }
+                    }
+                    continue;
+                }
+
+                if value.is_empty() {
+                    continue;
+                }
+
+                match current.as_mut() {
+                    Some(episode) => match name.as_str() {
+                        "title" => episode.title = value,
+                        "guid" | "id" => episode.guid = value,
+                        "description" | "summary" | "itunes:summary" | "content:encoded"
+                            if episode.description.is_none() =>
+                        {
+                            episode.description = Some(value)
+                        }
+                        "pubDate" | "published" | "updated" if episode.published_at.is_none() => {
+                            episode.published_at = parse_date(&value)
+                        }
+                        "itunes:duration" => episode.duration_ms = parse_duration_ms(&value),
+                        _ => {}
+                    },
+                    None => {
+                        let parent = stack.last().map(String::as_str);
+
+                        // Atom nests the author: <feed><author><name>..</name></author>
+                        if name == "name" && parent == Some("author") {
+                            if feed.author.is_none() {
+                                feed.author = Some(value);
+                            }
+                            continue;
+                        }
+
+                        // only take channel level fields, not <image><title> and friends
+                        if !matches!(parent, Some("channel") | Some("feed")) {
+                            continue;
+                        }
+                        match name.as_str() {
+                            "title" => feed.title = value,
+                            "description" | "subtitle" | "itunes:summary" if feed.description.is_none() => {
+                                feed.description = Some(value)
+                            }
+                            "itunes:author" | "author" | "managingEditor" if feed.author.is_none() => {
+                                feed.author = Some(value)
+                            }
+                            "logo" | "icon" if feed.image_url.is_none() => feed.image_url = Some(value),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(format!(
+                    "Invalid feed XML at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    if !saw_root {
+        return Err("Not an RSS or Atom feed".to_string());
+    }
+    if feed.title.is_empty() {
+        feed.title = "Untitled Podcast".to_string();
+    }
+
+    Ok(feed)
+}
+
+fn tag_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.name().as_ref()).into_owned()
+}
+
+fn handle_attributes(
+    e: &BytesStart,
+    name: &str,
+    feed: &mut ParsedFeed,
+    episode: Option<&mut ParsedEpisode>,
+) {
+    let attr = |key: &str| -> Option<String> {
+        e.attributes()
+            .flatten()
+            .find(|a| a.key.as_ref() == key.as_bytes())
+            .and_then(|a| a.unescape_value().ok())
+            .map(|v| v.into_owned())
+    };
+
+    match (name, episode) {
+        ("enclosure", Some(episode)) => {
+            if let Some(url) = attr("url") {
+                episode.audio_url = url;
+            }
+        }
+        ("link", Some(episode)) if attr("rel").as_deref() == Some("enclosure") => {
+            if let Some(href) = attr("href") {
+                episode.audio_url = href;
+            }
+        }
+        ("itunes:image", None) => {
+            if let Some(href) = attr("href") {
+                feed.image_url = Some(href);
+            }
+        }
+        _ => {}
+    }
+}
+
+// RSS uses RFC 2822, Atom uses RFC 3339
+fn parse_date(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .map(|d| d.timestamp())
+        .ok()
+}
+
+// itunes:duration is either plain seconds or [HH:]MM:SS
+fn parse_duration_ms(value: &str) -> i64 {
+    let seconds = value
+        .split(':')
+        .try_fold(0i64, |acc, part| part.trim().parse::<f64>().map(|p| acc * 60 + p as i64))
+        .unwrap_or(0);
+    seconds.max(0) * 1000
+}
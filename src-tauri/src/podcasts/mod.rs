@@ -0,0 +1,499 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tauri::{AppHandle, Emitter};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    models::{AppState as Database, Episode, Podcast},
+    user_config::load_music_dir,
+    utils::sanitize_file_name,
+};
+
+pub mod feed;
+
+use feed::ParsedFeed;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60 * 6);
+
+// a position this close to the end counts as finished
+const PLAYED_THRESHOLD_MS: i64 = 30_000;
+
+#[derive(Clone, serde::Serialize)]
+pub struct DownloadProgress {
+    pub episode_id: i64,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+#[allow(dead_code)]
+impl Database {
+    // podcast queries
+    pub async fn get_podcasts(&self) -> Result<Vec<Podcast>, String> {
+        sqlx::query_as::<_, Podcast>("SELECT * FROM podcasts ORDER BY title COLLATE NOCASE")
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_podcast(&self, id: i64) -> Result<Podcast, String> {
+        sqlx::query_as::<_, Podcast>("SELECT * FROM podcasts WHERE id = ?")
+            .bind(id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Inserts the podcast or refreshes its channel fields, then merges the episodes.
+    // Returns (podcast_id, number of episodes that weren't known before)
+    pub async fn upsert_podcast(&self, feed_url: &str, feed: &ParsedFeed) -> Result<(i64, i64), String> {
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let podcast_id: i64 = sqlx::query_scalar(
+            "INSERT INTO podcasts (feed_url, title, author, description, image_url, last_refreshed)
+            VALUES (?, ?, ?, ?, ?, unixepoch())
+            ON CONFLICT(feed_url) DO UPDATE SET
+                title = excluded.title,
+                author = excluded.author,
+                description = excluded.description,
+                image_url = excluded.image_url,
+                last_refreshed = excluded.last_refreshed
+            RETURNING id",
+        )
+        .bind(feed_url)
+        .bind(&feed.title)
+        .bind(&feed.author)
+        .bind(&feed.description)
+        .bind(&feed.image_url)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to save podcast: {}", e))?;
+
+        let (before,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM episodes WHERE podcast_id = ?")
+            .bind(podcast_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        // playback state (position, played, file_path) is never overwritten by a refresh
+        for episode in &feed.episodes {
+            sqlx::query(
+                "INSERT INTO episodes (podcast_id, guid, title, description, audio_url, published_at, duration_ms)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(podcast_id, guid) DO UPDATE SET
+                    title = excluded.title,
+                    description = excluded.description,
+                    audio_url = excluded.audio_url,
+                    published_at = excluded.published_at,
+                    duration_ms = excluded.duration_ms",
+            )
+            .bind(podcast_id)
+            .bind(&episode.guid)
+            .bind(&episode.title)
+            .bind(&episode.description)
+            .bind(&episode.audio_url)
+            .bind(episode.published_at)
+            .bind(episode.duration_ms)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to save episode {}: {}", episode.guid, e))?;
+        }
+
+        let (after,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM episodes WHERE podcast_id = ?")
+            .bind(podcast_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        tx.commit().await.map_err(|e| format!("Database error: {}", e))?;
+
+        Ok((podcast_id, after - before))
+    }
+
+    pub async fn delete_podcast(&self, id: i64) -> Result<(), String> {
+        // ON DELETE CASCADE handles episodes, downloaded files are left on disk
+        sqlx::query("DELETE FROM podcasts WHERE id = ?")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to delete podcast: {}", e))?;
+
+        Ok(())
+    }
+
+    // episode queries
+    pub async fn get_episodes(&self, podcast_id: i64) -> Result<Vec<Episode>, String> {
+        sqlx::query_as::<_, Episode>(
+            "SELECT * FROM episodes WHERE podcast_id = ? ORDER BY published_at DESC, id DESC",
+        )
+        .bind(podcast_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_episode(&self, id: i64) -> Result<Episode, String> {
+        sqlx::query_as::<_, Episode>("SELECT * FROM episodes WHERE id = ?")
+            .bind(id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn set_episode_file_path(&self, id: i64, file_path: &str) -> Result<(), String> {
+        sqlx::query("UPDATE episodes SET file_path = ? WHERE id = ?")
+            .bind(file_path)
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(())
+    }
+
+    // Saves the resume point, and flags the episode as played once it's near the end
+    pub async fn save_episode_position(&self, id: i64, position_ms: i64) -> Result<(), String> {
+        sqlx::query(
+            "UPDATE episodes
+            SET position_ms = ?,
+                played = played OR (duration_ms > 0 AND ? >= duration_ms - ?)
+            WHERE id = ?",
+        )
+        .bind(position_ms.max(0))
+        .bind(position_ms)
+        .bind(PLAYED_THRESHOLD_MS)
+        .bind(id)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(())
+    }
+
+    // Marking as played or unplayed both restart the episode from the top
+    pub async fn set_episode_played(&self, id: i64, played: bool) -> Result<(), String> {
+        sqlx::query("UPDATE episodes SET played = ?, position_ms = 0 WHERE id = ?")
+            .bind(played)
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(())
+    }
+}
+
+pub async fn fetch_feed(feed_url: &str) -> Result<ParsedFeed, String> {
+    let body = reqwest::get(feed_url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to fetch feed: {}", e))?
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read feed: {}", e))?;
+
+    feed::parse_feed(&body)
+}
+
+pub async fn refresh_podcast(db: &Database, podcast_id: i64) -> Result<i64, String> {
+    let podcast = db.get_podcast(podcast_id).await?;
+    let feed = fetch_feed(&podcast.feed_url).await?;
+    let (_, new_episodes) = db.upsert_podcast(&podcast.feed_url, &feed).await?;
+    Ok(new_episodes)
+}
+
+// One failing feed shouldn't stop the others from refreshing
+pub async fn refresh_all(db: &Database) -> Result<i64, String> {
+    let mut new_episodes = 0;
+    for podcast in db.get_podcasts().await? {
+        match refresh_podcast(db, podcast.id).await {
+            Ok(count) => new_episodes += count,
+            Err(e) => log::warn!("Failed to refresh podcast {}: {}", podcast.feed_url, e),
+        }
+    }
+    Ok(new_episodes)
+}
+
+// Background refresh loop, started once from setup()
+pub fn start_refresh_scheduler(app: AppHandle, db: Database) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+            if let Ok(new_episodes) = refresh_all(&db).await {
+                let _ = app.emit("podcasts_refreshed", new_episodes);
+            }
+        }
+    });
+}
+
+// music_dir/Podcasts/<podcast>/<episode> [<id>].<ext>, the id keeps episodes that
+// share a title (trailers, "Bonus", reruns) from overwriting each other
+fn episode_download_path(music_dir: &Path, podcast: &Podcast, episode: &Episode) -> PathBuf {
+    let extension = reqwest::Url::parse(&episode.audio_url)
+        .ok()
+        .and_then(|url| {
+            Path::new(url.path())
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_lowercase())
+        })
+        .unwrap_or_else(|| "mp3".to_string());
+
+    music_dir
+        .join("Podcasts")
+        .join(sanitize_file_name(&podcast.title))
+        .join(format!("{} [{}].{}", sanitize_file_name(&episode.title), episode.id, extension))
+}
+
+pub async fn download_episode_to(
+    db: &Database,
+    episode_id: i64,
+    music_dir: &Path,
+    on_progress: impl Fn(DownloadProgress),
+) -> Result<String, String> {
+    let episode = db.get_episode(episode_id).await?;
+    if let Some(existing) = episode.file_path.as_deref() {
+        if Path::new(existing).exists() {
+            return Ok(existing.to_string());
+        }
+    }
+
+    let podcast = db.get_podcast(episode.podcast_id).await?;
+    let dest_path = episode_download_path(music_dir, &podcast, &episode);
+    if let Some(parent) = dest_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create podcast dir: {}", e))?;
+    }
+
+    let mut response = reqwest::get(&episode.audio_url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to download episode: {}", e))?;
+    let total = response.content_length();
+
+    // write to a .part file so a cancelled download never looks complete
+    let part_path = dest_path.with_extension("part");
+    let mut file = tokio::fs::File::create(&part_path)
+        .await
+        .map_err(|e| format!("Failed to create file: {}", e))?;
+
+    let mut downloaded: u64 = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download interrupted: {}", e))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        downloaded += chunk.len() as u64;
+        on_progress(DownloadProgress { episode_id, downloaded, total });
+    }
+    file.flush().await.map_err(|e| format!("Failed to write file: {}", e))?;
+    drop(file);
+
+    tokio::fs::rename(&part_path, &dest_path)
+        .await
+        .map_err(|e| format!("Failed to move file: {}", e))?;
+
+    let dest = dest_path.to_string_lossy().into_owned();
+    db.set_episode_file_path(episode_id, &dest).await?;
+    Ok(dest)
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_podcasts(state: tauri::State<'_, Database>) -> Result<Vec<Podcast>, String> {
+    state.get_podcasts().await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_podcast_episodes(
+    state: tauri::State<'_, Database>,
+    podcast_id: i64,
+) -> Result<Vec<Episode>, String> {
+    state.get_episodes(podcast_id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn subscribe_podcast(
+    state: tauri::State<'_, Database>,
+    feed_url: String,
+) -> Result<Podcast, String> {
+    let feed_url = feed_url.trim().to_string();
+    let feed = fetch_feed(&feed_url).await?;
+    let (id, _) = state.upsert_podcast(&feed_url, &feed).await?;
+    state.get_podcast(id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn unsubscribe_podcast(state: tauri::State<'_, Database>, id: i64) -> Result<(), String> {
+    state.delete_podcast(id).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn refresh_podcasts(state: tauri::State<'_, Database>) -> Result<i64, String> {
+    refresh_all(&state).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn download_episode(
+    app: AppHandle,
+    state: tauri::State<'_, Database>,
+    episode_id: i64,
+) -> Result<String, String> {
+    let music_dir = load_music_dir(app.clone()).ok_or_else(|| "Music directory is not set".to_string())?;
+    download_episode_to(&state, episode_id, Path::new(music_dir.trim()), |progress| {
+        let _ = app.emit("podcast_download_progress", progress);
+    })
+    .await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn save_episode_position(
+    state: tauri::State<'_, Database>,
+    episode_id: i64,
+    position_ms: i64,
+) -> Result<(), String> {
+    state.save_episode_position(episode_id, position_ms).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_episode_played(
+    state: tauri::State<'_, Database>,
+    episode_id: i64,
+    played: bool,
+) -> Result<(), String> {
+    state.set_episode_played(episode_id, played).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_support::{memory_db, serve, temp_dir, Response};
+
+    const FEED: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/podcast_feed.xml"));
+
+    const EXTRA_ITEM: &str = r#"<item>
+      <guid>episode-3</guid>
+      <title>Third</title>
+      <enclosure url="{base}/audio/3.ogg" type="audio/ogg"/>
+    </item>"#;
+
+    // serves the fixture feed, with a third episode once `published` is set
+    fn feed_server(published: Arc<AtomicBool>) -> String {
+        let base = Arc::new(Mutex::new(String::new()));
+        let handler_base = base.clone();
+        let url = serve(move |request| {
+            let base = handler_base.lock().unwrap().clone();
+            match request.path.as_str() {
+                "/feed.xml" => {
+                    let extra = if published.load(Ordering::SeqCst) { EXTRA_ITEM } else { "" };
+                    Response::ok(FEED.replace("{extra}", extra).replace("{base}", &base))
+                }
+                "/audio/1.mp3?token=abc" => Response::ok(b"first!".to_vec()),
+                "/audio/2.mp3" => Response::ok(b"second".to_vec()),
+                _ => Response::status(404),
+            }
+        });
+        *base.lock().unwrap() = url.clone();
+        url
+    }
+
+    #[test]
+    fn refresh_merges_new_episodes_and_keeps_playback_state() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let published = Arc::new(AtomicBool::new(false));
+            let base = feed_server(published.clone());
+            let feed_url = format!("{}/feed.xml", base);
+
+            let feed = fetch_feed(&feed_url).await.unwrap();
+            let (podcast_id, new_episodes) = db.upsert_podcast(&feed_url, &feed).await.unwrap();
+            assert_eq!(new_episodes, 2);
+
+            let podcast = db.get_podcast(podcast_id).await.unwrap();
+            assert_eq!(podcast.title, "Test Podcast");
+            assert_eq!(podcast.author.as_deref(), Some("Test Author"));
+            assert_eq!(podcast.image_url, Some(format!("{}/cover.jpg", base)));
+
+            let episodes = db.get_episodes(podcast_id).await.unwrap();
+            assert_eq!(episodes.len(), 2);
+            assert_eq!(episodes[0].guid, "episode-2");
+            assert_eq!(episodes[0].duration_ms, 90_000);
+            assert_eq!(episodes[1].description.as_deref(), Some("<p>First one</p>"));
+            db.save_episode_position(episodes[1].id, 60_000).await.unwrap();
+
+            // nothing new yet
+            assert_eq!(refresh_podcast(&db, podcast_id).await.unwrap(), 0);
+
+            published.store(true, Ordering::SeqCst);
+            assert_eq!(refresh_all(&db).await.unwrap(), 1);
+
+            let episodes = db.get_episodes(podcast_id).await.unwrap();
+            assert_eq!(episodes.len(), 3);
+            let first = episodes.iter().find(|e| e.guid == "episode-1").unwrap();
+            assert_eq!(first.position_ms, 60_000);
+            let third = episodes.iter().find(|e| e.guid == "episode-3").unwrap();
+            assert_eq!(third.audio_url, format!("{}/audio/3.ogg", base));
+        });
+    }
+
+    #[test]
+    fn download_gives_episodes_with_the_same_title_their_own_file() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let base = feed_server(Arc::new(AtomicBool::new(false)));
+            let feed_url = format!("{}/feed.xml", base);
+            let music_dir = temp_dir("podcast-download");
+
+            let feed = fetch_feed(&feed_url).await.unwrap();
+            let (podcast_id, _) = db.upsert_podcast(&feed_url, &feed).await.unwrap();
+            let episodes = db.get_episodes(podcast_id).await.unwrap();
+
+            let mut paths = Vec::new();
+            for episode in &episodes {
+                let progress = Mutex::new(Vec::new());
+                let path = download_episode_to(&db, episode.id, &music_dir, |p| {
+                    progress.lock().unwrap().push((p.downloaded, p.total));
+                })
+                .await
+                .unwrap();
+
+                assert_eq!(progress.lock().unwrap().last(), Some(&(6, Some(6))));
+                assert_eq!(db.get_episode(episode.id).await.unwrap().file_path.as_deref(), Some(path.as_str()));
+                paths.push(path);
+            }
+
+            let expected_dir = music_dir.join("Podcasts").join("Test Podcast");
+            assert_eq!(paths[0], expected_dir.join(format!("Bonus [{}].mp3", episodes[0].id)).to_string_lossy());
+            assert_eq!(paths[1], expected_dir.join(format!("Bonus [{}].mp3", episodes[1].id)).to_string_lossy());
+            assert_eq!(std::fs::read(&paths[0]).unwrap(), b"second");
+            assert_eq!(std::fs::read(&paths[1]).unwrap(), b"first!");
+
+            // already on disk, so no second request
+            let again = download_episode_to(&db, episodes[0].id, &music_dir, |_| panic!("downloaded twice"))
+                .await
+                .unwrap();
+            assert_eq!(again, paths[0]);
+
+            let _ = std::fs::remove_dir_all(&music_dir);
+        });
+    }
+}
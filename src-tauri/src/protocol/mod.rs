@@ -0,0 +1,292 @@
+use tauri::{
+    http::{header, Request, Response, StatusCode},
+    AppHandle, Manager, UriSchemeResponder,
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{
+    db::LOCAL_SOURCE_ID,
+    models::{AppState as Database, ArtworkSize},
+    player::MediaSource,
+    sources::LibrarySources,
+    utils::{audio_content_type, parse_byte_range},
+};
+
+// `tamaureus://art/<artwork id>?size=256` and `tamaureus://audio/<track id>`.
+// Windows and Android reach the same handler as `http://tamaureus.localhost/...`.
+pub const SCHEME: &str = "tamaureus";
+
+// Responses are buffered, so a request gets at most this much back, with or
+// without a range. Media elements ask for the rest as they need it.
+const MAX_CHUNK: u64 = 4 * 1024 * 1024;
+
+type HttpResponse = Response<Vec<u8>>;
+
+pub fn handle(app: AppHandle, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    tauri::async_runtime::spawn(async move {
+        let response = match respond(&app, &request).await {
+            Ok(response) => response,
+            Err(e) => status(StatusCode::INTERNAL_SERVER_ERROR, e),
+        };
+        responder.respond(response);
+    });
+}
+
+async fn respond(app: &AppHandle, request: &Request<Vec<u8>>) -> Result<HttpResponse, String> {
+    let uri = request.uri();
+    // the route is the host on `tamaureus://art/1` and the first path segment otherwise
+    let mut segments: Vec<&str> = uri.path().split('/').filter(|s| !s.is_empty()).collect();
+    if let Some(host @ ("art" | "audio")) = uri.host() {
+        segments.insert(0, host);
+    }
+
+    let id = segments.get(1).and_then(|id| id.parse::<i64>().ok());
+    match (segments.first().copied(), id) {
+        (Some("art"), Some(id)) => art(app, id, query_param(uri.query(), "size")).await,
+        (Some("audio"), Some(id)) => {
+            let range = request.headers().get(header::RANGE).and_then(|v| v.to_str().ok());
+            audio(app, id, range).await
+        }
+        _ => Ok(status(StatusCode::NOT_FOUND, "Not found")),
+    }
+}
+
+// size is small, medium or full, or a pixel size like Subsonic's getCoverArt
+async fn art(app: &AppHandle, id: i64, size: Option<&str>) -> Result<HttpResponse, String> {
+    let size = match size {
+        Some("small") => ArtworkSize::Small,
+        Some("full") => ArtworkSize::Full,
+        Some(size) => size.parse().map_or(ArtworkSize::Medium, ArtworkSize::for_pixels),
+        None => ArtworkSize::Medium,
+    };
+
+    let db = app.state::<Database>();
+    let Some((data, mime)) = db.get_artwork(id, size).await? else {
+        return Ok(status(StatusCode::NOT_FOUND, "Artwork not found"));
+    };
+
+    builder(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime)
+        // an artwork id always points at the same picture
+        .header(header::CACHE_CONTROL, "max-age=31536000, immutable")
+        .body(data)
+        .map_err(|e| e.to_string())
+}
+
+// only tracks in the library can be read, never an arbitrary path
+async fn audio(app: &AppHandle, id: i64, range: Option<&str>) -> Result<HttpResponse, String> {
+    let db = app.state::<Database>();
+    let Some(track) = db.get_track(id).await? else {
+        return Ok(status(StatusCode::NOT_FOUND, "Track not found"));
+    };
+
+    let media = if track.source_id == LOCAL_SOURCE_ID {
+        MediaSource::File(track.file_path.clone())
+    } else {
+        app.state::<LibrarySources>().resolve(&track.file_path)?
+    };
+
+    let content_type = audio_content_type(&track.file_format);
+    let path = match media {
+        MediaSource::File(path) => path,
+        MediaSource::Stream { url, .. } => return proxy(&url, range, content_type).await,
+    };
+
+    let Ok(mut file) = tokio::fs::File::open(&path).await else {
+        return Ok(status(StatusCode::NOT_FOUND, "Track file not found"));
+    };
+    let len = file
+        .metadata()
+        .await
+        .map_err(|e| format!("Failed to read file metadata: {}", e))?
+        .len();
+
+    // no range is read as the whole file, of which the first chunk goes out
+    let (start, end) = match parse_byte_range(range.unwrap_or("bytes=0-"), len) {
+        Some((start, end)) => (start, end.min(start + MAX_CHUNK - 1)),
+        None => return Ok(not_satisfiable(Some(len))),
+    };
+
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|e| format!("Failed to seek file: {}", e))?;
+    let mut data = vec![0; (end - start + 1) as usize];
+    file.read_exact(&mut data)
+        .await
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    partial(data, start, Some(len), content_type)
+}
+
+// Remote tracks that aren't cached yet. The stream url carries the server's
+// credentials, so it's fetched here rather than handed to the webview.
+async fn proxy(url: &str, range: Option<&str>, content_type: &str) -> Result<HttpResponse, String> {
+    let mut response = reqwest::Client::new()
+        .get(url)
+        .header(reqwest::header::RANGE, range.unwrap_or("bytes=0-"))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach the server: {}", e))?;
+
+    match response.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => {
+            let (start, total) = response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_content_range)
+                .ok_or_else(|| "Server answered a range without Content-Range".to_string())?;
+            let data = read_body(&mut response, 0, MAX_CHUNK).await?;
+            partial(data, start, total, content_type)
+        }
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => Ok(not_satisfiable(None)),
+        code if code.is_success() => {
+            // the server ignored the range and is sending the whole file
+            let total = response.content_length();
+            let Some((start, end)) = parse_byte_range(range.unwrap_or("bytes=0-"), total.unwrap_or(u64::MAX)) else {
+                return Ok(not_satisfiable(total));
+            };
+            let data = read_body(&mut response, start, (end - start + 1).min(MAX_CHUNK)).await?;
+            if data.is_empty() {
+                return Ok(not_satisfiable(total));
+            }
+            partial(data, start, total, content_type)
+        }
+        code => Ok(status(StatusCode::BAD_GATEWAY, format!("Server returned {}", code))),
+    }
+}
+
+// Skips `skip` bytes of a body, then reads up to `limit` of the rest
+async fn read_body(response: &mut reqwest::Response, mut skip: u64, limit: u64) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    while (data.len() as u64) < limit {
+        let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read from the server: {}", e))?
+        else {
+            break;
+        };
+        let skipped = skip.min(chunk.len() as u64);
+        skip -= skipped;
+        let rest = &chunk[skipped as usize..];
+        let wanted = (limit - data.len() as u64).min(rest.len() as u64) as usize;
+        data.extend_from_slice(&rest[..wanted]);
+    }
+    Ok(data)
+}
+
+// "bytes start-end/total", total may be "*"
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.parse().ok()?;
+    Some((start, total.parse().ok()))
+}
+
+// `data` starting at `start`, of a file `total` bytes long if that's known
+fn partial(data: Vec<u8>, start: u64, total: Option<u64>, content_type: &str) -> Result<HttpResponse, String> {
+    let end = start + (data.len() as u64).saturating_sub(1);
+    let total = total.map_or("*".to_string(), |total| total.to_string());
+    builder(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, total))
+        .body(data)
+        .map_err(|e| e.to_string())
+}
+
+fn not_satisfiable(len: Option<u64>) -> HttpResponse {
+    let len = len.map_or("*".to_string(), |len| len.to_string());
+    builder(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+        .body(Vec::new())
+        .unwrap_or_default()
+}
+
+// the page is served from another origin, fetch() and canvas reads need this
+fn builder(code: StatusCode) -> tauri::http::response::Builder {
+    Response::builder()
+        .status(code)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+}
+
+fn status(code: StatusCode, message: impl Into<String>) -> HttpResponse {
+    builder(code)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(message.into().into_bytes())
+        .unwrap_or_default()
+}
+
+fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{serve, Response as TestResponse};
+
+    // just over one chunk, and no two nearby bytes alike
+    fn audio_bytes() -> Vec<u8> {
+        (0..MAX_CHUNK + 1000).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn content_range(response: &HttpResponse) -> &str {
+        response.headers()[header::CONTENT_RANGE].to_str().unwrap()
+    }
+
+    #[test]
+    fn proxy_caps_an_open_request_at_one_chunk() {
+        let body = audio_bytes();
+        let len = body.len();
+        let base = serve(move |request| TestResponse::ranged(request, &body));
+
+        let response = tauri::async_runtime::block_on(proxy(&format!("{}/stream", base), None, "audio/mpeg")).unwrap();
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body().len() as u64, MAX_CHUNK);
+        assert_eq!(content_range(&response), format!("bytes 0-{}/{}", MAX_CHUNK - 1, len));
+    }
+
+    #[test]
+    fn proxy_cuts_the_range_out_of_a_whole_file() {
+        let body = audio_bytes();
+        let expected = body[10..20].to_vec();
+        let len = body.len();
+        // a server that doesn't do ranges
+        let base = serve(move |_| TestResponse::ok(body.clone()));
+
+        let response =
+            tauri::async_runtime::block_on(proxy(&format!("{}/stream", base), Some("bytes=10-19"), "audio/mpeg")).unwrap();
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body(), &expected);
+        assert_eq!(content_range(&response), format!("bytes 10-19/{}", len));
+    }
+
+    #[test]
+    fn proxy_reports_an_unknown_length() {
+        let body = audio_bytes();
+        let expected = body[100..200].to_vec();
+        let base = serve(move |_| TestResponse::ok(body.clone()).without_length());
+
+        let response =
+            tauri::async_runtime::block_on(proxy(&format!("{}/stream", base), Some("bytes=100-199"), "audio/mpeg")).unwrap();
+
+        assert_eq!(response.body(), &expected);
+        assert_eq!(content_range(&response), "bytes 100-199/*");
+    }
+
+    #[test]
+    fn proxy_passes_on_server_errors() {
+        let base = serve(|_| TestResponse::status(404));
+
+        let response = tauri::async_runtime::block_on(proxy(&format!("{}/stream", base), None, "audio/mpeg")).unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,178 @@
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use tauri::{AppHandle, EventId, Listener, State};
+use tokio::sync::{broadcast, oneshot};
+
+use crate::{
+    models::AppState as Database,
+    user_config::{self, RemoteConfig},
+};
+
+mod routes;
+
+// Player events forwarded to WebSocket clients as {"event": ..., "payload": ...}
+const PUSH_EVENTS: &[&str] = &[
+    "audio_position",
+    "playback_state",
+    "track_finished",
+    "queue_changed",
+];
+
+#[derive(Clone)]
+pub struct ServerState {
+    pub db: Database,
+    pub app: AppHandle,
+    pub token: String,
+    pub events: broadcast::Sender<String>,
+}
+
+struct RunningServer {
+    port: u16,
+    shutdown: oneshot::Sender<()>,
+    listeners: Vec<EventId>,
+}
+
+// Managed state, holds the running server (if any) so it can be stopped again
+#[derive(Default)]
+pub struct RemoteServer {
+    running: Mutex<Option<RunningServer>>,
+}
+
+#[allow(dead_code)]
+impl RemoteServer {
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().is_some()
+    }
+
+    pub async fn start(&self, app: AppHandle, db: Database, config: &RemoteConfig) -> Result<(), String> {
+        self.stop(&app);
+
+        // opt-in and meant for phones on the same network, so listen on every interface
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("Failed to bind remote control port {}: {}", config.port, e))?;
+
+        let (events, _) = broadcast::channel(256);
+        let listeners = PUSH_EVENTS
+            .iter()
+            .map(|name| {
+                let events = events.clone();
+                app.listen_any(*name, move |event| {
+                    // no subscribers is fine, the message is just dropped
+                    let _ = events.send(format!(
+                        r#"{{"event":"{}","payload":{}}}"#,
+                        name,
+                        event.payload()
+                    ));
+                })
+            })
+            .collect();
+
+        let router = routes::router(ServerState {
+            db,
+            app,
+            token: config.token.clone(),
+            events,
+        });
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        tauri::async_runtime::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                log::error!("Remote control server stopped: {}", e);
+            }
+        });
+
+        *self.running.lock().unwrap() = Some(RunningServer {
+            port: config.port,
+            shutdown,
+            listeners,
+        });
+
+        Ok(())
+    }
+
+    pub fn stop(&self, app: &AppHandle) {
+        if let Some(server) = self.running.lock().unwrap().take() {
+            for id in server.listeners {
+                app.unlisten(id);
+            }
+            let _ = server.shutdown.send(());
+        }
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.running.lock().unwrap().as_ref().map(|s| s.port)
+    }
+}
+
+// Called once from setup(), only starts the server if the user enabled it before
+pub fn start_if_enabled(app: AppHandle, db: Database) {
+    let config = user_config::load_remote_config(&app);
+    if !config.enabled {
+        return;
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let server = tauri::Manager::state::<RemoteServer>(&app);
+        if let Err(e) = server.start(app.clone(), db, &config).await {
+            log::error!("{}", e);
+        }
+    });
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_remote_config(app: AppHandle) -> RemoteConfig {
+    user_config::load_remote_config(&app)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_remote_enabled(
+    app: AppHandle,
+    state: State<'_, Database>,
+    server: State<'_, RemoteServer>,
+    enabled: bool,
+    port: Option<u16>,
+) -> Result<RemoteConfig, String> {
+    let mut config = user_config::load_remote_config(&app);
+    config.enabled = enabled;
+    if let Some(port) = port {
+        config.port = port;
+    }
+
+    if enabled {
+        server.start(app.clone(), state.inner().clone(), &config).await?;
+    } else {
+        server.stop(&app);
+    }
+
+    user_config::save_remote_config(&app, &config)?;
+    Ok(config)
+}
+
+// Invalidates every paired device; the server restarts with the new token
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn regenerate_remote_token(
+    app: AppHandle,
+    state: State<'_, Database>,
+    server: State<'_, RemoteServer>,
+) -> Result<RemoteConfig, String> {
+    let mut config = user_config::load_remote_config(&app);
+    config.token = user_config::generate_token();
+    user_config::save_remote_config(&app, &config)?;
+
+    if server.is_running() {
+        server.start(app.clone(), state.inner().clone(), &config).await?;
+    }
+    Ok(config)
+}
@@ -0,0 +1,302 @@
+use axum::{
+    extract::{ws::Message, ws::WebSocket, Path, Query, Request, State, WebSocketUpgrade},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use tauri::Manager;
+
+use super::ServerState;
+use crate::{
+    models::{Playlist, PlaylistPreview, Track},
+    player::{AudioPlayer, PlayQueue, PlaybackState},
+    utils::constant_time_eq,
+};
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+fn bad_request(e: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, e)
+}
+
+// Starting playback waits on the audio thread (and opening a stream on the network),
+// so it runs on the blocking pool instead of tying up a runtime worker
+async fn with_player<T, F>(state: &ServerState, f: F) -> ApiResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AudioPlayer) -> Result<T, String> + Send + 'static,
+{
+    let app = state.app.clone();
+    tauri::async_runtime::spawn_blocking(move || f(&app.state::<AudioPlayer>()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .map_err(bad_request)
+}
+
+// Every route mirrors a Tauri command of the same name
+pub fn router(state: ServerState) -> Router {
+    Router::new()
+        // player
+        .route("/api/state", get(get_state))
+        .route("/api/play", post(play_track))
+        .route("/api/pause", post(pause))
+        .route("/api/resume", post(resume))
+        .route("/api/stop", post(stop_track))
+        .route("/api/seek", post(seek_track))
+        .route("/api/volume", post(set_volume))
+        // queue
+        .route("/api/queue", get(get_queue).post(enqueue_tracks).delete(clear_queue))
+        .route("/api/queue/{index}", delete(remove_from_queue).post(play_queue_index))
+        .route("/api/queue/next", post(next_track))
+        .route("/api/queue/previous", post(previous_track))
+        // library
+        .route("/api/tracks", get(get_tracks_with_names))
+        .route("/api/playlists", get(get_playlists_with_previews).post(save_playlist))
+        .route(
+            "/api/playlists/{id}",
+            get(get_playlist).put(update_playlist).delete(delete_playlist),
+        )
+        .route("/api/playlists/{id}/tracks", get(get_playlist_tracks))
+        // push updates
+        .route("/api/ws", get(websocket))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+// Accepts `Authorization: Bearer <token>`, or `?token=` since browsers can't set
+// headers on a WebSocket handshake
+async fn require_token(
+    State(state): State<ServerState>,
+    Query(query): Query<TokenQuery>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string);
+
+    match header_token.or(query.token) {
+        Some(token) if constant_time_eq(&token, &state.token) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, "Invalid or missing token").into_response(),
+    }
+}
+
+// player
+#[derive(serde::Serialize)]
+struct RemoteState {
+    playback: PlaybackState,
+    position: f32,
+    current_path: Option<String>,
+    queue: PlayQueue,
+}
+
+async fn get_state(State(state): State<ServerState>) -> Json<RemoteState> {
+    let player = state.app.state::<AudioPlayer>();
+    Json(RemoteState {
+        playback: player.get_playback_state(),
+        position: player.get_position_secs(),
+        current_path: player.current_path(),
+        queue: player.get_queue(),
+    })
+}
+
+// by id, so a remote can only ever play what's in the library
+#[derive(Deserialize)]
+struct PlayBody {
+    track_id: i64,
+}
+
+async fn play_track(State(state): State<ServerState>, Json(body): Json<PlayBody>) -> ApiResult<f64> {
+    let Some(track) = state.db.get_track(body.track_id).await.map_err(bad_request)? else {
+        return Err((StatusCode::NOT_FOUND, "Track not found".to_string()));
+    };
+
+    with_player(&state, move |player| {
+        player.clear_chapters();
+        player.leave_queue();
+        player.play(track.file_path)
+    })
+    .await
+}
+
+async fn pause(State(state): State<ServerState>) -> StatusCode {
+    state.app.state::<AudioPlayer>().pause();
+    StatusCode::NO_CONTENT
+}
+
+async fn resume(State(state): State<ServerState>) -> StatusCode {
+    state.app.state::<AudioPlayer>().resume();
+    StatusCode::NO_CONTENT
+}
+
+async fn stop_track(State(state): State<ServerState>) -> StatusCode {
+    state.app.state::<AudioPlayer>().stop();
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Deserialize)]
+struct SeekBody {
+    seconds: f32,
+}
+
+async fn seek_track(State(state): State<ServerState>, Json(body): Json<SeekBody>) -> StatusCode {
+    state.app.state::<AudioPlayer>().seek(body.seconds);
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Deserialize)]
+struct VolumeBody {
+    volume: f32,
+}
+
+async fn set_volume(State(state): State<ServerState>, Json(body): Json<VolumeBody>) -> StatusCode {
+    state.app.state::<AudioPlayer>().set_volume(body.volume);
+    StatusCode::NO_CONTENT
+}
+
+// queue
+async fn get_queue(State(state): State<ServerState>) -> Json<PlayQueue> {
+    Json(state.app.state::<AudioPlayer>().get_queue())
+}
+
+#[derive(Deserialize)]
+struct EnqueueBody {
+    track_ids: Vec<i64>,
+}
+
+async fn enqueue_tracks(
+    State(state): State<ServerState>,
+    Json(body): Json<EnqueueBody>,
+) -> ApiResult<PlayQueue> {
+    let items = state.db.get_queue_items(&body.track_ids).await.map_err(bad_request)?;
+    let player = state.app.state::<AudioPlayer>();
+    player.enqueue(items);
+    Ok(Json(player.get_queue()))
+}
+
+async fn clear_queue(State(state): State<ServerState>) -> StatusCode {
+    state.app.state::<AudioPlayer>().clear_queue();
+    StatusCode::NO_CONTENT
+}
+
+async fn remove_from_queue(
+    State(state): State<ServerState>,
+    Path(index): Path<usize>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .app
+        .state::<AudioPlayer>()
+        .remove_from_queue(index)
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(bad_request)
+}
+
+async fn play_queue_index(State(state): State<ServerState>, Path(index): Path<usize>) -> ApiResult<f64> {
+    with_player(&state, move |player| player.play_queue_index(index)).await
+}
+
+async fn next_track(State(state): State<ServerState>) -> ApiResult<f64> {
+    with_player(&state, |player| player.next_track()).await
+}
+
+async fn previous_track(State(state): State<ServerState>) -> ApiResult<f64> {
+    with_player(&state, |player| player.previous_track()).await
+}
+
+// library
+async fn get_tracks_with_names(State(state): State<ServerState>) -> ApiResult<Vec<Track>> {
+    state.db.get_tracks_with_names().await.map(Json).map_err(bad_request)
+}
+
+async fn get_playlists_with_previews(State(state): State<ServerState>) -> ApiResult<Vec<PlaylistPreview>> {
+    state.db.get_playlists_with_previews().await.map(Json).map_err(bad_request)
+}
+
+async fn get_playlist(State(state): State<ServerState>, Path(id): Path<i64>) -> ApiResult<Playlist> {
+    state.db.get_playlist(id).await.map(Json).map_err(bad_request)
+}
+
+async fn get_playlist_tracks(State(state): State<ServerState>, Path(id): Path<i64>) -> ApiResult<Vec<Track>> {
+    state.db.get_playlist_tracks(id).await.map(Json).map_err(bad_request)
+}
+
+#[derive(Deserialize)]
+struct PlaylistBody {
+    name: String,
+    description: Option<String>,
+    cover_path: Option<String>,
+    track_ids: Vec<i64>,
+}
+
+async fn save_playlist(State(state): State<ServerState>, Json(body): Json<PlaylistBody>) -> ApiResult<i64> {
+    state
+        .db
+        .save_playlist(body.name, body.description, body.cover_path, body.track_ids)
+        .await
+        .map(Json)
+        .map_err(bad_request)
+}
+
+async fn update_playlist(
+    State(state): State<ServerState>,
+    Path(id): Path<i64>,
+    Json(body): Json<PlaylistBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .db
+        .update_playlist(id, body.name, body.description, body.cover_path, body.track_ids)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(bad_request)
+}
+
+async fn delete_playlist(
+    State(state): State<ServerState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .db
+        .delete_playlist(id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(bad_request)
+}
+
+// push updates
+async fn websocket(State(state): State<ServerState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| forward_events(socket, state))
+}
+
+async fn forward_events(mut socket: WebSocket, state: ServerState) {
+    let mut events = state.events.subscribe();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(message) => {
+                    if socket.send(Message::Text(message.into())).await.is_err() {
+                        break;
+                    }
+                }
+                // a slow client just misses some position updates
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                _ => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+use tauri::State;
+
+use crate::{
+    db::LOCAL_SOURCE_ID,
+    models::{AppState as Database, DuplicateCopy, DuplicateGroup, DuplicateOptions},
+    utils::fingerprint::{fingerprint_from_blob, matching_pairs},
+};
+
+// lossless whatever the encoder settings, m4a can be either
+const LOSSLESS_FORMATS: &[&str] = &["flac", "wav", "aiff", "aif", "ape", "wv"];
+
+// Groups tracks that are probably the same recording. Matches chain, so a
+// copy can join a group through any one of its members.
+pub async fn find_duplicates(db: &Database, options: &DuplicateOptions) -> Result<Vec<DuplicateGroup>, String> {
+    let mut copies = db.get_duplicate_candidates().await?;
+    let mut fingerprints: HashMap<i64, Vec<u32>> = HashMap::new();
+    if options.fingerprint {
+        for (id, blob) in db.get_fingerprints().await? {
+            fingerprints.insert(id, fingerprint_from_blob(&blob));
+        }
+    }
+    for copy in &mut copies {
+        copy.lossless = is_lossless(&copy.track.file_format, copy.track.tags.bit_depth);
+        copy.fingerprint = fingerprints.remove(&copy.track.id);
+    }
+    let mut parents: Vec<usize> = (0..copies.len()).collect();
+
+    // sorted by duration each copy only needs comparing with the next one
+    let mut by_name: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (i, copy) in copies.iter().enumerate() {
+        let title = normalize(&copy.track.title);
+        if !title.is_empty() {
+            let artist = normalize(copy.track.artist_name.as_deref().unwrap_or_default());
+            by_name.entry((title, artist)).or_default().push(i);
+        }
+    }
+    for same_name in by_name.values_mut() {
+        same_name.sort_by_key(|i| copies[*i].track.duration_ms);
+        for pair in same_name.windows(2) {
+            let drift = copies[pair[1]].track.duration_ms - copies[pair[0]].track.duration_ms;
+            if drift <= options.duration_tolerance_ms {
+                union(&mut parents, pair[0], pair[1]);
+            }
+        }
+    }
+
+    // identical files, whatever their tags say
+    let mut by_hash: HashMap<&str, usize> = HashMap::new();
+    for (i, copy) in copies.iter().enumerate() {
+        if let Some(hash) = copy.content_hash.as_deref() {
+            let first = *by_hash.entry(hash).or_insert(i);
+            union(&mut parents, first, i);
+        }
+    }
+
+    // the same audio under other tags. Only the start is fingerprinted, so
+    // the durations have to agree too or an edit would match its full version.
+    let mut by_fingerprint = vec![false; copies.len()];
+    let fingerprinted: Vec<usize> = (0..copies.len()).filter(|i| copies[*i].fingerprint.is_some()).collect();
+    let values: Vec<&[u32]> = fingerprinted
+        .iter()
+        .filter_map(|i| copies[*i].fingerprint.as_deref())
+        .collect();
+    for (a, b, _) in matching_pairs(&values) {
+        let (a, b) = (fingerprinted[a], fingerprinted[b]);
+        let drift = (copies[a].track.duration_ms - copies[b].track.duration_ms).abs();
+        if drift <= options.duration_tolerance_ms && find(&mut parents, a) != find(&mut parents, b) {
+            by_fingerprint[a] = true;
+            by_fingerprint[b] = true;
+            union(&mut parents, a, b);
+        }
+    }
+
+    let mut grouped: HashMap<usize, (Vec<DuplicateCopy>, bool)> = HashMap::new();
+    for (i, copy) in copies.into_iter().enumerate() {
+        let group = grouped.entry(find(&mut parents, i)).or_default();
+        group.0.push(copy);
+        group.1 |= by_fingerprint[i];
+    }
+
+    let mut groups: Vec<DuplicateGroup> = grouped
+        .into_values()
+        .filter(|(copies, _)| copies.len() > 1)
+        .map(|(mut copies, by_fingerprint)| {
+            copies.sort_by(compare_quality);
+            let hash = copies[0].content_hash.as_deref();
+            let same_file = hash.is_some() && copies.iter().all(|copy| copy.content_hash.as_deref() == hash);
+            let matched_by = if same_file {
+                "content"
+            } else if by_fingerprint {
+                "fingerprint"
+            } else {
+                "metadata"
+            };
+            DuplicateGroup {
+                matched_by: matched_by.to_string(),
+                copies,
+            }
+        })
+        .collect();
+    groups.sort_by_cached_key(|group| normalize(&group.copies[0].track.title));
+    Ok(groups)
+}
+
+// Best first: a file that's there over a missing one and local over remote,
+// then lossless, bitrate, sample rate, bit depth and size. The oldest row wins
+// a tie, it's the one most likely to be in playlists already.
+fn compare_quality(a: &DuplicateCopy, b: &DuplicateCopy) -> Ordering {
+    let key = |copy: &DuplicateCopy| {
+        let tags = &copy.track.tags;
+        (
+            copy.track.missing_since.is_none(),
+            copy.track.source_id == LOCAL_SOURCE_ID,
+            copy.lossless,
+            tags.bitrate.unwrap_or(0),
+            tags.sample_rate.unwrap_or(0),
+            tags.bit_depth.unwrap_or(0),
+        )
+    };
+    key(b)
+        .cmp(&key(a))
+        .then(b.track.file_size.total_cmp(&a.track.file_size))
+        .then(a.track.id.cmp(&b.track.id))
+}
+
+fn is_lossless(format: &str, bit_depth: Option<i64>) -> bool {
+    let format = format.to_lowercase();
+    LOSSLESS_FORMATS.contains(&format.as_str())
+        // ALAC has a bit depth, AAC doesn't
+        || (matches!(format.as_str(), "m4a" | "mp4") && bit_depth.is_some())
+}
+
+// "Don't Stop (Live)" and "don't stop - live" are the same title
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn find_duplicate_tracks(
+    state: State<'_, Database>,
+    options: Option<DuplicateOptions>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    find_duplicates(&state, &options.unwrap_or_default()).await
+}
+
+// Keeps one copy and moves everything that pointed at the others onto it.
+// With `delete_files` the other local files go too, otherwise the next scan
+// brings them back as tracks of their own.
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn merge_duplicate_tracks(
+    state: State<'_, Database>,
+    keep: i64,
+    duplicates: Vec<i64>,
+    delete_files: Option<bool>,
+) -> Result<usize, String> {
+    let duplicates: Vec<i64> = duplicates.into_iter().filter(|id| *id != keep).collect();
+    if state.get_track(keep).await?.is_none() {
+        return Err(format!("Track {} not found", keep));
+    }
+
+    // paths are looked up first, the rows are gone after the merge
+    let mut files = Vec::new();
+    if delete_files.unwrap_or(false) {
+        for id in &duplicates {
+            if let Some(track) = state.get_track(*id).await? {
+                if track.source_id == LOCAL_SOURCE_ID {
+                    files.push(track.file_path);
+                }
+            }
+        }
+    }
+
+    // files only go once the merge has committed, a failed one leaves them all
+    state.merge_tracks(keep, &duplicates).await?;
+    for path in files {
+        match std::fs::remove_file(Path::new(&path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => log::warn!("Failed to delete {}: {}", path, e),
+            _ => {}
+        }
+    }
+    state.refresh_smart_playlists().await?;
+    Ok(duplicates.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{memory_db, track};
+
+    #[test]
+    fn merging_closes_playlist_gaps() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let ids = db
+                .add_tracks(vec![
+                    track("/music/a.mp3", "Song", "Artist", "Album"),
+                    track("/music/b.mp3", "Other", "Artist", "Album"),
+                    track("/music/a copy.mp3", "Song", "Artist", "Album"),
+                ])
+                .await
+                .unwrap();
+            let (keep, other, duplicate) = (ids[0], ids[1], ids[2]);
+            let playlist = db
+                .save_playlist("Mix".to_string(), None, None, vec![keep, duplicate, other])
+                .await
+                .unwrap();
+
+            db.merge_tracks(keep, &[duplicate]).await.unwrap();
+
+            let positions: Vec<(i64, i64)> =
+                sqlx::query_as("SELECT track_id, position FROM playlist_tracks WHERE playlist_id = ? ORDER BY position")
+                    .bind(playlist)
+                    .fetch_all(&db.db)
+                    .await
+                    .unwrap();
+            assert_eq!(positions, vec![(keep, 0), (other, 1)]);
+        });
+    }
+}
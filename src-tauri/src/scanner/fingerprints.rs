@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    models::{AppState as Database, ScanProgress},
+    utils::fingerprint::{compute_fingerprint, fingerprint_to_blob},
+};
+
+// files decoded between database writes and progress events
+const BATCH_SIZE: i64 = 50;
+
+// Only one pass runs at a time. It doesn't take the scanner's slot, it only
+// reads files and shouldn't hold up a scan.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Frees the slot however the pass ends, a panic included
+struct RunningPass;
+
+impl Drop for RunningPass {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+// Fingerprints the local files that don't have one yet, in the background.
+// Progress goes out as `library_fingerprint_progress`.
+pub fn start_fingerprinting(app: AppHandle) {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let running = RunningPass;
+    tauri::async_runtime::spawn(async move {
+        let _running = running;
+        let db = app.state::<Database>();
+        if let Err(e) = fingerprint_library(&app, &db).await {
+            log::error!("Fingerprinting failed: {}", e);
+        }
+    });
+}
+
+async fn fingerprint_library(app: &AppHandle, db: &Database) -> Result<ScanProgress, String> {
+    let mut progress = ScanProgress::default();
+    let queued = db.count_unfingerprinted_files().await? as usize;
+    if queued == 0 {
+        progress.done = true;
+        return Ok(progress);
+    }
+    progress.found = queued;
+    progress.queued = queued;
+    emit_progress(app, &progress);
+
+    // ids only go up, so a file that can't be read now isn't retried until the next pass
+    let mut after = 0;
+    loop {
+        let batch = db.get_unfingerprinted_files(after, BATCH_SIZE).await?;
+        let Some((last, _)) = batch.last() else {
+            break;
+        };
+        after = *last;
+        let read = batch.len();
+
+        let results = tauri::async_runtime::spawn_blocking(move || {
+            batch
+                .into_iter()
+                .filter_map(|(id, path)| {
+                    let path = Path::new(&path);
+                    match compute_fingerprint(path) {
+                        Ok(fingerprint) => Some((id, Some(fingerprint_to_blob(&fingerprint)))),
+                        // gone, the integrity check will flag it
+                        Err(_) if !path.is_file() => None,
+                        Err(e) => {
+                            log::warn!("Failed to fingerprint {}: {}", path.display(), e);
+                            Some((id, None))
+                        }
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| format!("Failed to fingerprint files: {}", e))?;
+
+        progress.processed += read;
+        progress.failed += results.iter().filter(|(_, fingerprint)| fingerprint.is_none()).count();
+        progress.updated += results.iter().filter(|(_, fingerprint)| fingerprint.is_some()).count();
+        db.set_fingerprints(&results).await?;
+        emit_progress(app, &progress);
+    }
+
+    progress.done = true;
+    emit_progress(app, &progress);
+    Ok(progress)
+}
+
+fn emit_progress(app: &AppHandle, progress: &ScanProgress) {
+    let _ = app.emit("library_fingerprint_progress", progress.clone());
+}
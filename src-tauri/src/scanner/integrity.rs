@@ -0,0 +1,334 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    models::{AppState as Database, ExtractedTrack, IntegrityReport, LibraryFile, Relink, RelinkProposal},
+    sources::LibrarySources,
+    user_config::load_music_dir,
+    utils::{
+        collect_audio_files, content_hash,
+        fingerprint::{compute_fingerprint, fingerprint_from_blob, matching_pairs},
+        tag_reader::extract_track_metadata,
+    },
+};
+
+// below this a proposal is more likely wrong than right
+const MIN_CONFIDENCE: f64 = 0.5;
+// content_hash only samples the size and both ends of a file, a retag in the
+// middle of it still hashes the same
+const MAX_HASH_CONFIDENCE: f64 = 0.99;
+// tags alone never count as much as identical content
+const MAX_METADATA_CONFIDENCE: f64 = 0.95;
+// nor do fingerprints, a remaster can sound the same
+const MAX_FINGERPRINT_CONFIDENCE: f64 = 0.99;
+const PROPOSALS_PER_TRACK: usize = 3;
+
+// A file a missing track may have moved to
+struct Candidate {
+    path: String,
+    // the row it was imported as in the meantime, if any
+    track_id: Option<i64>,
+    title: String,
+    artist: String,
+    album: String,
+    duration_ms: i64,
+    file_bytes: Option<i64>,
+    content_hash: Option<String>,
+    fingerprint: Option<Vec<u32>>,
+}
+
+impl Candidate {
+    fn from_row(file: &LibraryFile) -> Self {
+        Candidate {
+            path: file.file_path.clone(),
+            track_id: Some(file.id),
+            title: file.title.clone(),
+            artist: file.artist_name.clone().unwrap_or_default(),
+            album: file.album_name.clone().unwrap_or_default(),
+            duration_ms: file.duration_ms,
+            file_bytes: file.file_bytes,
+            content_hash: file.content_hash.clone(),
+            fingerprint: file.fingerprint.as_deref().map(fingerprint_from_blob),
+        }
+    }
+
+    fn from_file((track, fingerprint): (ExtractedTrack, Option<Vec<u32>>)) -> Self {
+        Candidate {
+            path: track.file_path,
+            track_id: None,
+            title: track.title,
+            artist: track.artist,
+            album: track.album,
+            duration_ms: track.duration_ms,
+            file_bytes: track.file_bytes,
+            content_hash: track.content_hash,
+            fingerprint,
+        }
+    }
+}
+
+// What one blocking pass over the disk found
+#[derive(Default)]
+struct DiskState {
+    // rows whose file is gone
+    gone: HashSet<i64>,
+    // rows flagged missing whose file is back
+    back: Vec<i64>,
+    // rows imported before hashes were kept
+    hashes: Vec<(i64, String)>,
+    // files under the library roots no row points at, fingerprinted if some
+    // missing track could be matched that way
+    unknown: Vec<(ExtractedTrack, Option<Vec<u32>>)>,
+}
+
+fn inspect_disk(files: &mut [LibraryFile], roots: &[PathBuf]) -> DiskState {
+    let mut disk = DiskState::default();
+
+    for file in files.iter_mut() {
+        let path = Path::new(&file.file_path);
+        if !path.is_file() {
+            disk.gone.insert(file.id);
+            continue;
+        }
+        if file.missing_since.take().is_some() {
+            disk.back.push(file.id);
+        }
+        if file.content_hash.is_none() {
+            file.content_hash = content_hash(path);
+            if let Some(hash) = &file.content_hash {
+                disk.hashes.push((file.id, hash.clone()));
+            }
+        }
+    }
+
+    let fingerprint = files
+        .iter()
+        .any(|file| file.fingerprint.is_some() && disk.gone.contains(&file.id));
+    let mut known: HashSet<String> = files.iter().map(|file| file.file_path.clone()).collect();
+    for path in roots.iter().flat_map(|root| collect_audio_files(root)) {
+        // roots may nest, each file is looked at once
+        if !known.insert(path.clone()) {
+            continue;
+        }
+        match extract_track_metadata(&path) {
+            Ok(track) => {
+                let fingerprint = fingerprint.then(|| compute_fingerprint(Path::new(&path)).ok()).flatten();
+                disk.unknown.push((track, fingerprint));
+            }
+            Err(e) => log::warn!("Failed to read {}: {}", path, e),
+        }
+    }
+    disk
+}
+
+// Flags tracks whose file is gone, then looks through the library roots for
+// where each of them went. Nothing is relinked here, see relink_tracks.
+pub async fn check_integrity(db: &Database, roots: Vec<PathBuf>) -> Result<IntegrityReport, String> {
+    let mut files = db.get_library_files().await?;
+    let (files, disk) = tauri::async_runtime::spawn_blocking(move || {
+        let disk = inspect_disk(&mut files, &roots);
+        (files, disk)
+    })
+    .await
+    .map_err(|e| format!("Failed to check library: {}", e))?;
+
+    let gone: Vec<i64> = disk.gone.iter().copied().collect();
+    db.set_missing(&gone).await?;
+    db.clear_missing(&disk.back).await?;
+    db.set_content_hashes(&disk.hashes).await?;
+
+    let checked = files.len();
+    let (missing, present): (Vec<_>, Vec<_>) = files.into_iter().partition(|file| disk.gone.contains(&file.id));
+    let candidates: Vec<Candidate> = present
+        .iter()
+        .map(Candidate::from_row)
+        .chain(disk.unknown.into_iter().map(Candidate::from_file))
+        .collect();
+
+    Ok(IntegrityReport {
+        checked,
+        missing: missing.len(),
+        proposals: propose(&missing, &candidates),
+    })
+}
+
+fn propose(missing: &[LibraryFile], candidates: &[Candidate]) -> Vec<RelinkProposal> {
+    // only candidates sharing a hash, a size or a title are worth scoring
+    let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        for key in match_keys(candidate.content_hash.as_deref(), candidate.file_bytes, &candidate.title) {
+            by_key.entry(key).or_default().push(i);
+        }
+    }
+
+    // and candidates that sound the same, whatever they're called now
+    let fingerprints: Vec<(usize, Vec<u32>)> = missing
+        .iter()
+        .enumerate()
+        .filter_map(|(i, track)| Some((i, fingerprint_from_blob(track.fingerprint.as_deref()?))))
+        .collect();
+    let candidate_fingerprints: Vec<(usize, &[u32])> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, candidate)| Some((i, candidate.fingerprint.as_deref()?)))
+        .collect();
+    let mut same_audio: HashMap<usize, HashMap<usize, f64>> = HashMap::new();
+    if !fingerprints.is_empty() {
+        let values: Vec<&[u32]> = fingerprints
+            .iter()
+            .map(|(_, fingerprint)| fingerprint.as_slice())
+            .chain(candidate_fingerprints.iter().map(|(_, fingerprint)| *fingerprint))
+            .collect();
+        for (a, b, similarity) in matching_pairs(&values) {
+            // pairs come lowest index first, missing tracks are at the front
+            if a < fingerprints.len() && b >= fingerprints.len() {
+                let candidate = candidate_fingerprints[b - fingerprints.len()].0;
+                same_audio.entry(fingerprints[a].0).or_default().insert(candidate, similarity);
+            }
+        }
+    }
+
+    let mut proposals = Vec::new();
+    for (n, track) in missing.iter().enumerate() {
+        let mut seen = HashSet::new();
+        let same_audio = same_audio.remove(&n).unwrap_or_default();
+        let mut scored: Vec<(f64, &str, &Candidate)> = match_keys(track.content_hash.as_deref(), track.file_bytes, &track.title)
+            .iter()
+            .filter_map(|key| by_key.get(key))
+            .flatten()
+            .chain(same_audio.keys())
+            .filter(|i| seen.insert(**i))
+            // a row imported before this one went missing is a track of its own
+            .filter(|i| candidates[**i].track_id.is_none_or(|id| id > track.id))
+            .map(|i| {
+                let candidate = &candidates[*i];
+                let (mut confidence, mut matched_by) = score(track, candidate);
+                if let Some(similarity) = same_audio.get(i) {
+                    let similarity = similarity.min(MAX_FINGERPRINT_CONFIDENCE);
+                    if similarity > confidence {
+                        (confidence, matched_by) = ((similarity * 100.0).round() / 100.0, "fingerprint");
+                    }
+                }
+                (confidence, matched_by, candidate)
+            })
+            .filter(|(confidence, _, _)| *confidence >= MIN_CONFIDENCE)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        proposals.extend(scored.into_iter().take(PROPOSALS_PER_TRACK).map(|(confidence, matched_by, candidate)| {
+            RelinkProposal {
+                track_id: track.id,
+                title: track.title.clone(),
+                old_path: track.file_path.clone(),
+                new_path: candidate.path.clone(),
+                confidence,
+                matched_by: matched_by.to_string(),
+                replaces_track_id: candidate.track_id,
+            }
+        }));
+    }
+    proposals
+}
+
+fn match_keys(hash: Option<&str>, bytes: Option<i64>, title: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let Some(hash) = hash {
+        keys.push(format!("hash:{}", hash));
+    }
+    if let Some(bytes) = bytes {
+        keys.push(format!("size:{}", bytes));
+    }
+    let title = normalize(title);
+    if !title.is_empty() {
+        keys.push(format!("title:{}", title));
+    }
+    keys
+}
+
+fn score(track: &LibraryFile, candidate: &Candidate) -> (f64, &'static str) {
+    if track.content_hash.is_some() && track.content_hash == candidate.content_hash {
+        return (MAX_HASH_CONFIDENCE, "content");
+    }
+
+    let mut score = 0.0;
+    if track.file_bytes.is_some() && track.file_bytes == candidate.file_bytes {
+        score += 0.4;
+    }
+    let drift = (track.duration_ms - candidate.duration_ms).abs();
+    if drift <= 1000 {
+        score += 0.2;
+    } else if drift <= 3000 {
+        score += 0.1;
+    }
+    if same_text(&track.title, &candidate.title) {
+        score += 0.2;
+    }
+    if same_text(track.artist_name.as_deref().unwrap_or_default(), &candidate.artist) {
+        score += 0.1;
+    }
+    if same_text(track.album_name.as_deref().unwrap_or_default(), &candidate.album) {
+        score += 0.05;
+    }
+    if Path::new(&track.file_path).file_name() == Path::new(&candidate.path).file_name() {
+        score += 0.05;
+    }
+
+    let score = (score * 100.0_f64).round() / 100.0;
+    (score.min(MAX_METADATA_CONFIDENCE), "metadata")
+}
+
+fn normalize(text: &str) -> String {
+    text.trim().to_lowercase()
+}
+
+fn same_text(a: &str, b: &str) -> bool {
+    let a = normalize(a);
+    !a.is_empty() && a == normalize(b)
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn check_library_integrity(app: AppHandle, state: State<'_, Database>) -> Result<IntegrityReport, String> {
+    // the music folder, then whatever the other library sources keep on disk
+    let mut roots: Vec<PathBuf> = load_music_dir(app.clone())
+        .map(|dir| PathBuf::from(dir.trim()))
+        .into_iter()
+        .collect();
+    if let Some(sources) = app.try_state::<LibrarySources>() {
+        roots.extend(sources.roots());
+    }
+    if roots.is_empty() {
+        return Err("No music folder has been set".to_string());
+    }
+
+    check_integrity(&state, roots).await
+}
+
+// Applies the proposals the user accepted. Ids, playlists and plays stay with
+// the track, only its path and tags change.
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn relink_tracks(state: State<'_, Database>, relinks: Vec<Relink>) -> Result<usize, String> {
+    if let Some(relink) = relinks.iter().find(|relink| !Path::new(&relink.new_path).is_file()) {
+        return Err(format!("File not found: {}", relink.new_path));
+    }
+
+    for relink in &relinks {
+        state.relink_track(relink.track_id, &relink.new_path).await?;
+
+        // a metadata match isn't the same file, take the tags it has now
+        let path = relink.new_path.clone();
+        let result = tauri::async_runtime::spawn_blocking(move || extract_track_metadata(&path))
+            .await
+            .map_err(|e| e.to_string())?;
+        match result {
+            Ok(track) => state.update_tracks_from_files(vec![(relink.track_id, track)]).await?,
+            Err(e) => log::warn!("Failed to read {}: {}", relink.new_path, e),
+        }
+    }
+    state.refresh_smart_playlists().await?;
+    Ok(relinks.len())
+}
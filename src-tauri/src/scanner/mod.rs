@@ -0,0 +1,291 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::mpsc;
+
+use crate::{
+    models::{AppState as Database, ExtractedTrack, ScanProgress},
+    user_config::load_music_dir,
+    utils::{artwork::migrate_thumbnails, collect_audio_files, file_stats, tag_reader::extract_track_metadata},
+};
+
+pub mod duplicates;
+pub mod fingerprints;
+pub mod integrity;
+pub mod organizer;
+
+// files written to the database per transaction
+const BATCH_SIZE: usize = 100;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_WORKERS: usize = 8;
+
+// Managed state for the one library scan that may run at a time
+#[derive(Default)]
+pub struct LibraryScanner {
+    cancel: Mutex<Option<Arc<AtomicBool>>>,
+}
+
+// Clears the running scan however it ends, and stops its workers if it failed
+struct RunningScan<'a> {
+    scanner: &'a LibraryScanner,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Drop for RunningScan<'_> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        *self.scanner.cancel.lock().unwrap() = None;
+    }
+}
+
+// a file that needs its tags read, with the id of its row if it has one
+struct QueuedFile {
+    path: String,
+    track_id: Option<i64>,
+}
+
+#[allow(dead_code)]
+impl LibraryScanner {
+    pub fn is_running(&self) -> bool {
+        self.cancel.lock().unwrap().is_some()
+    }
+
+    // true if there was a scan to cancel
+    pub fn cancel(&self) -> bool {
+        match self.cancel.lock().unwrap().as_ref() {
+            Some(cancel) => {
+                cancel.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn begin(&self) -> Result<RunningScan<'_>, String> {
+        let mut slot = self.cancel.lock().unwrap();
+        if slot.is_some() {
+            return Err("A library scan is already running".to_string());
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        *slot = Some(cancel.clone());
+        Ok(RunningScan { scanner: self, cancel })
+    }
+
+    // Walks the music folder, reads the tags of new and changed files on a few
+    // threads and writes them in batches. Files whose mtime and size match the
+    // database are skipped. Progress goes out as `library_scan_progress`.
+    pub async fn scan(&self, app: &AppHandle, db: &Database, music_dir: PathBuf) -> Result<ScanProgress, String> {
+        let running = self.begin()?;
+        let cancel = running.cancel.clone();
+        let mut progress = ScanProgress::default();
+
+        let files = tauri::async_runtime::spawn_blocking(move || {
+            collect_audio_files(&music_dir)
+                .into_iter()
+                .filter_map(|path| file_stats(Path::new(&path)).map(|(mtime, bytes)| (path, mtime, bytes)))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| format!("Failed to scan music folder: {}", e))?;
+        progress.found = files.len();
+
+        let known = db.get_local_file_stats().await?;
+        let mut queue = Vec::new();
+        let mut backfill = Vec::new();
+        let mut returned = Vec::new();
+        for (path, mtime, bytes) in files {
+            let Some(file) = known.get(&path) else {
+                queue.push(QueuedFile { path, track_id: None });
+                continue;
+            };
+            if file.file_mtime.is_none() || file.file_bytes.is_none() {
+                // imported before stats were kept, assume it's unchanged
+                backfill.push((file.id, mtime, bytes));
+            } else if file.file_mtime != mtime || file.file_bytes != Some(bytes) {
+                queue.push(QueuedFile { path, track_id: Some(file.id) });
+            } else if file.missing_since.is_some() {
+                // back where it was, the tags are still good
+                returned.push(file.id);
+            }
+        }
+        db.set_file_stats(&backfill).await?;
+        db.clear_missing(&returned).await?;
+        progress.queued = queue.len();
+        emit_progress(app, &progress);
+
+        read_queue(app, db, queue, cancel.clone(), &mut progress).await?;
+        db.refresh_smart_playlists().await?;
+
+        progress.cancelled = cancel.load(Ordering::SeqCst);
+        progress.done = true;
+        emit_progress(app, &progress);
+        Ok(progress)
+    }
+
+    // Re-reads the files of rows an older tag reader imported, see TAG_VERSION.
+    // Runs as a scan so the two never overlap, and reports progress the same way.
+    pub async fn backfill_tags(&self, app: &AppHandle, db: &Database) -> Result<ScanProgress, String> {
+        let running = self.begin()?;
+        let cancel = running.cancel.clone();
+        let mut progress = ScanProgress::default();
+
+        let queue: Vec<QueuedFile> = db
+            .get_stale_tag_files()
+            .await?
+            .into_iter()
+            .map(|(id, path)| QueuedFile { path, track_id: Some(id) })
+            .collect();
+        if queue.is_empty() {
+            progress.done = true;
+            return Ok(progress);
+        }
+        progress.found = queue.len();
+        progress.queued = queue.len();
+        emit_progress(app, &progress);
+
+        let failed = read_queue(app, db, queue, cancel.clone(), &mut progress).await?;
+        db.set_tags_current(&failed).await?;
+        db.refresh_smart_playlists().await?;
+
+        progress.cancelled = cancel.load(Ordering::SeqCst);
+        progress.done = true;
+        emit_progress(app, &progress);
+        Ok(progress)
+    }
+}
+
+// Reads the queued files and writes what came out in batches, returning the
+// ids of known tracks whose file couldn't be read
+async fn read_queue(
+    app: &AppHandle,
+    db: &Database,
+    queue: Vec<QueuedFile>,
+    cancel: Arc<AtomicBool>,
+    progress: &mut ScanProgress,
+) -> Result<Vec<i64>, String> {
+    let mut results = spawn_workers(queue, cancel);
+    let mut new_tracks = Vec::new();
+    let mut changed = Vec::new();
+    let mut failed = Vec::new();
+    let mut last_emit = Instant::now();
+
+    while let Some((file, result)) = results.recv().await {
+        progress.processed += 1;
+        match (result, file.track_id) {
+            (Ok(track), None) => new_tracks.push(track),
+            (Ok(track), Some(id)) => changed.push((id, track)),
+            (Err(e), track_id) => {
+                progress.failed += 1;
+                failed.extend(track_id);
+                log::warn!("Failed to read {}: {}", file.path, e);
+            }
+        }
+
+        if new_tracks.len() + changed.len() >= BATCH_SIZE {
+            write_batch(db, &mut new_tracks, &mut changed, progress).await?;
+        }
+        if last_emit.elapsed() >= PROGRESS_INTERVAL {
+            emit_progress(app, progress);
+            last_emit = Instant::now();
+        }
+    }
+    // what was read before a cancel is still worth keeping
+    write_batch(db, &mut new_tracks, &mut changed, progress).await?;
+    Ok(failed)
+}
+
+// Catches the library up with the current tag reader, and moves pictures left
+// in the old base64 columns into the artwork table first. Fingerprinting
+// whatever is new follows.
+pub fn start_tag_backfill(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let scanner = app.state::<LibraryScanner>();
+        let db = app.state::<Database>();
+        if let Err(e) = migrate_thumbnails(&db).await {
+            log::error!("Artwork migration failed: {}", e);
+        }
+        if let Err(e) = scanner.backfill_tags(&app, &db).await {
+            log::error!("Tag backfill failed: {}", e);
+        }
+        fingerprints::start_fingerprinting(app.clone());
+    });
+}
+
+// Tag reading is blocking IO, so it runs on blocking threads that pull from a
+// shared queue until it is empty or the scan is cancelled
+fn spawn_workers(
+    queue: Vec<QueuedFile>,
+    cancel: Arc<AtomicBool>,
+) -> mpsc::Receiver<(QueuedFile, Result<ExtractedTrack, String>)> {
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .clamp(1, MAX_WORKERS)
+        .min(queue.len().max(1));
+
+    let queue = Arc::new(Mutex::new(queue.into_iter()));
+    let (tx, rx) = mpsc::channel(BATCH_SIZE * 2);
+
+    for _ in 0..workers {
+        let (queue, cancel, tx) = (queue.clone(), cancel.clone(), tx.clone());
+        tauri::async_runtime::spawn_blocking(move || {
+            while !cancel.load(Ordering::SeqCst) {
+                let Some(file) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = extract_track_metadata(&file.path);
+                if tx.blocking_send((file, result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    rx
+}
+
+async fn write_batch(
+    db: &Database,
+    new_tracks: &mut Vec<ExtractedTrack>,
+    changed: &mut Vec<(i64, ExtractedTrack)>,
+    progress: &mut ScanProgress,
+) -> Result<(), String> {
+    if !new_tracks.is_empty() {
+        let ids = db.add_tracks(std::mem::take(new_tracks)).await?;
+        progress.added += ids.iter().filter(|id| **id > 0).count();
+    }
+    if !changed.is_empty() {
+        progress.updated += changed.len();
+        db.update_tracks_from_files(std::mem::take(changed)).await?;
+    }
+    Ok(())
+}
+
+fn emit_progress(app: &AppHandle, progress: &ScanProgress) {
+    let _ = app.emit("library_scan_progress", progress.clone());
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn scan_library(
+    app: AppHandle,
+    state: State<'_, Database>,
+    scanner: State<'_, LibraryScanner>,
+) -> Result<ScanProgress, String> {
+    let music_dir = load_music_dir(app.clone())
+        .map(|dir| PathBuf::from(dir.trim()))
+        .ok_or_else(|| "No music folder has been set".to_string())?;
+
+    let progress = scanner.scan(&app, &state, music_dir).await?;
+    fingerprints::start_fingerprinting(app);
+    Ok(progress)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub fn cancel_library_scan(scanner: State<'_, LibraryScanner>) -> bool {
+    scanner.cancel()
+}
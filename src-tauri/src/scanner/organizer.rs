@@ -0,0 +1,513 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tauri::{AppHandle, State};
+
+use crate::{
+    db::LOCAL_SOURCE_ID,
+    models::{AppState as Database, FileMove, OrganizeReport, Track, TrackMove},
+    user_config::{load_music_dir, load_naming_config},
+    utils::{
+        is_audio_file,
+        patterns::{naming_value, PathPattern},
+    },
+};
+
+// named after their track, they move and are renamed with it
+const SIDECAR_EXTENSIONS: &[&str] = &["lrc", "cue"];
+// pictures for the whole folder, lowercase
+const FOLDER_FILES: &[&str] = &["cover.jpg", "cover.png", "folder.jpg", "folder.png", "front.jpg"];
+// NAME_MAX on most file systems, in bytes
+const MAX_NAME_BYTES: usize = 255;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Moves tracks to the paths their tags give them under the music folder. Every
+// file is moved before the database is touched, and if it can't take the new
+// paths the files are put back, so the two never disagree. A track whose file
+// can't be moved stays where it is and doesn't stop the others.
+pub async fn organize(
+    db: &Database,
+    tracks: Vec<Track>,
+    pattern: PathPattern,
+    music_dir: PathBuf,
+    max_name_length: usize,
+    dry_run: bool,
+) -> Result<OrganizeReport, String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("The library is already being organized".to_string());
+    }
+    let result = organize_tracks(db, tracks, pattern, music_dir, max_name_length, dry_run).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn organize_tracks(
+    db: &Database,
+    tracks: Vec<Track>,
+    pattern: PathPattern,
+    music_dir: PathBuf,
+    max_name_length: usize,
+    dry_run: bool,
+) -> Result<OrganizeReport, String> {
+    // a path some row holds is taken even while its file is missing
+    let held: HashSet<String> = db
+        .get_source_track_paths(LOCAL_SOURCE_ID)
+        .await?
+        .into_iter()
+        .map(|path| path.to_lowercase())
+        .collect();
+    let (mut report, music_dir) = tauri::async_runtime::spawn_blocking(move || {
+        (plan(&tracks, &pattern, &music_dir, max_name_length, &held), music_dir)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    report.dry_run = dry_run;
+    if dry_run || report.moves.is_empty() {
+        return Ok(report);
+    }
+
+    let last_id = db.get_last_track_id().await?;
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        execute(&mut report);
+        report
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let moved: Vec<(i64, String)> = report
+        .moves
+        .iter()
+        .map(|track| (track.track_id, track.to.clone()))
+        .collect();
+    if let Err(e) = db.move_track_files(&moved, last_id).await {
+        tauri::async_runtime::spawn_blocking(move || undo(&report))
+            .await
+            .map_err(|e| e.to_string())?;
+        return Err(e);
+    }
+
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        clean_up(&report, &music_dir);
+        report
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+// Where every track goes, without touching anything
+fn plan(
+    tracks: &[Track],
+    pattern: &PathPattern,
+    music_dir: &Path,
+    max_name_length: usize,
+    held: &HashSet<String>,
+) -> OrganizeReport {
+    let max_name_length = max_name_length.min(MAX_NAME_BYTES);
+    let mut report = OrganizeReport::default();
+    // lowercase, some file systems don't tell case apart
+    let mut taken = HashSet::new();
+    // folder -> the folders its tracks go to
+    let mut destinations: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+
+    for track in tracks {
+        let from = PathBuf::from(&track.file_path);
+        let rendered = pattern.render(|field| naming_value(track, field));
+        let (folders, stem) = rendered.rsplit_once('/').unwrap_or(("", &rendered));
+        let mut folder = music_dir.to_path_buf();
+        for name in folders.split('/').filter(|name| !name.is_empty()) {
+            folder.push(limit_length(name, max_name_length));
+        }
+        let extension = from
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+
+        let to = free_path(&folder, stem, &extension, max_name_length, &from, &mut taken, held);
+        if to == from {
+            report.unchanged += 1;
+            continue;
+        }
+
+        let mut sidecars = Vec::new();
+        for sidecar in sidecars_of(&from) {
+            let target = to.with_extension(sidecar.extension().unwrap_or_default());
+            if target.exists() {
+                report.errors.push(format!(
+                    "{} is left behind, {} already exists",
+                    sidecar.display(),
+                    target.display()
+                ));
+                continue;
+            }
+            sidecars.push(FileMove {
+                from: sidecar.to_string_lossy().into_owned(),
+                to: target.to_string_lossy().into_owned(),
+            });
+        }
+
+        if let (Some(folder), Some(target)) = (from.parent(), to.parent()) {
+            if folder != target {
+                destinations.entry(folder.to_path_buf()).or_default().push(target.to_path_buf());
+            }
+        }
+        report.moves.push(TrackMove {
+            track_id: track.id,
+            from: track.file_path.clone(),
+            to: to.to_string_lossy().into_owned(),
+            sidecars,
+        });
+    }
+
+    for (folder, targets) in destinations {
+        for picture in folder_files(&folder) {
+            let Some(name) = picture.file_name() else {
+                continue;
+            };
+            let mut copied = HashSet::new();
+            for target in &targets {
+                let to = target.join(name);
+                if copied.insert(to.clone()) && !to.exists() {
+                    report.folder_files.push(FileMove {
+                        from: picture.to_string_lossy().into_owned(),
+                        to: to.to_string_lossy().into_owned(),
+                    });
+                }
+            }
+        }
+    }
+    report
+}
+
+// Cut to `max_bytes` on a character boundary, without leaving a trailing
+// space or dot. File systems count a name's length in bytes.
+fn limit_length(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_string();
+    }
+    let mut end = max_bytes;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].trim_end_matches(|c: char| c == '.' || c.is_whitespace()).to_string()
+}
+
+// "stem.ext" in `folder`, or "stem (2).ext" and so on when another file is
+// there, a row holds it or another track of this run was given it. The stem is
+// cut so the whole name, suffix and extension included, fits in `max_bytes`.
+// A track already at the path keeps it.
+fn free_path(
+    folder: &Path,
+    stem: &str,
+    extension: &str,
+    max_bytes: usize,
+    from: &Path,
+    taken: &mut HashSet<String>,
+    held: &HashSet<String>,
+) -> PathBuf {
+    let own = from.to_string_lossy().to_lowercase();
+
+    let mut n = 1;
+    loop {
+        let suffix = if n == 1 { String::new() } else { format!(" ({})", n) };
+        let room = max_bytes.saturating_sub(suffix.len() + extension.len());
+        let candidate = folder.join(format!("{}{}{}", limit_length(stem, room), suffix, extension));
+        let key = candidate.to_string_lossy().to_lowercase();
+        if !taken.contains(&key) && (key == own || (!held.contains(&key) && !candidate.exists())) {
+            taken.insert(key);
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+// lyrics and cue sheets with the same name as the file
+fn sidecars_of(path: &Path) -> Vec<PathBuf> {
+    let (Some(folder), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|file| {
+            file.file_stem() == Some(stem)
+                && file
+                    .extension()
+                    .is_some_and(|extension| SIDECAR_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()))
+                && file.is_file()
+        })
+        .collect()
+}
+
+fn folder_files(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|file| {
+            file.file_name()
+                .is_some_and(|name| FOLDER_FILES.contains(&name.to_string_lossy().to_lowercase().as_str()))
+                && file.is_file()
+        })
+        .collect()
+}
+
+// Makes the moves. `moves` is left with only the tracks that really moved,
+// and those with only the sidecars that did.
+fn execute(report: &mut OrganizeReport) {
+    let mut moved = Vec::new();
+    for mut track in std::mem::take(&mut report.moves) {
+        if let Err(e) = move_file(Path::new(&track.from), Path::new(&track.to)) {
+            report.errors.push(format!("Track {}: {}", track.track_id, e));
+            continue;
+        }
+        let audio_names = (file_name(&track.from), file_name(&track.to));
+        track.sidecars.retain(|sidecar| match move_sidecar(sidecar, &audio_names) {
+            Ok(()) => true,
+            Err(e) => {
+                report.errors.push(e);
+                false
+            }
+        });
+        moved.push(track);
+    }
+    report.moves = moved;
+
+    // only into folders a track made it to
+    let made: HashSet<PathBuf> = report
+        .moves
+        .iter()
+        .filter_map(|track| Path::new(&track.to).parent().map(Path::to_path_buf))
+        .collect();
+    let mut copied = Vec::new();
+    for picture in std::mem::take(&mut report.folder_files) {
+        if !Path::new(&picture.to).parent().is_some_and(|folder| made.contains(folder)) {
+            continue;
+        }
+        match std::fs::copy(&picture.from, &picture.to) {
+            Ok(_) => copied.push(picture),
+            Err(e) => report.errors.push(format!("Failed to copy {}: {}", picture.from, e)),
+        }
+    }
+    report.folder_files = copied;
+}
+
+// A cue sheet names the file it's for, that has to follow the rename
+fn move_sidecar(sidecar: &FileMove, (old_name, new_name): &(String, String)) -> Result<(), String> {
+    let (from, to) = (Path::new(&sidecar.from), Path::new(&sidecar.to));
+    let is_cue = from
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"));
+
+    // cue sheets that aren't UTF-8 are moved as they are
+    match std::fs::read_to_string(from) {
+        Ok(sheet) if is_cue && old_name != new_name && sheet.contains(old_name.as_str()) => {
+            std::fs::write(to, sheet.replace(old_name.as_str(), new_name))
+                .and_then(|_| std::fs::remove_file(from))
+                .map_err(|e| format!("Failed to move {}: {}", sidecar.from, e))
+        }
+        _ => move_file(from, to),
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+// a rename where it can be, a copy where it can't, like across drives
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if let Some(folder) = to.parent() {
+        std::fs::create_dir_all(folder).map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to).map_err(|e| format!("Failed to move {}: {}", from.display(), e))?;
+    std::fs::remove_file(from).map_err(|e| {
+        let _ = std::fs::remove_file(to);
+        format!("Failed to move {}: {}", from.display(), e)
+    })
+}
+
+// Puts everything back after the database refused the new paths, best effort
+fn undo(report: &OrganizeReport) {
+    for track in report.moves.iter().rev() {
+        for sidecar in &track.sidecars {
+            let _ = move_file(Path::new(&sidecar.to), Path::new(&sidecar.from));
+        }
+        let _ = move_file(Path::new(&track.to), Path::new(&track.from));
+    }
+    for picture in &report.folder_files {
+        let _ = std::fs::remove_file(&picture.to);
+    }
+}
+
+// Folders the tracks left: their pictures go once no audio is left in them,
+// then the folders themselves and their parents if that left them empty
+fn clean_up(report: &OrganizeReport, music_dir: &Path) {
+    let left: HashSet<&Path> = report
+        .moves
+        .iter()
+        .filter_map(|track| Path::new(&track.from).parent())
+        .collect();
+
+    for folder in left {
+        if !folder.starts_with(music_dir) || has_audio(folder) {
+            continue;
+        }
+        for picture in &report.folder_files {
+            let picture = Path::new(&picture.from);
+            if picture.parent() == Some(folder) {
+                let _ = std::fs::remove_file(picture);
+            }
+        }
+
+        let mut folder = folder;
+        while folder != music_dir && std::fs::remove_dir(folder).is_ok() {
+            match folder.parent() {
+                Some(parent) if parent.starts_with(music_dir) => folder = parent,
+                _ => break,
+            }
+        }
+    }
+}
+
+fn has_audio(folder: &Path) -> bool {
+    std::fs::read_dir(folder)
+        .map(|entries| entries.flatten().any(|entry| is_audio_file(&entry.path())))
+        .unwrap_or(true)
+}
+
+// Tauri Commands
+// Without track_ids every local track is organized, without a pattern the
+// configured file_pattern is used
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn organize_library(
+    app: AppHandle,
+    state: State<'_, Database>,
+    track_ids: Option<Vec<i64>>,
+    pattern: Option<String>,
+    dry_run: Option<bool>,
+) -> Result<OrganizeReport, String> {
+    let music_dir = load_music_dir(app.clone())
+        .map(|dir| PathBuf::from(dir.trim()))
+        .ok_or_else(|| "No music folder has been set".to_string())?;
+    let config = load_naming_config(&app);
+    let pattern = PathPattern::parse(pattern.as_deref().unwrap_or(&config.file_pattern))?;
+
+    let mut errors = Vec::new();
+    let tracks = match track_ids {
+        None => state.get_local_tracks().await?,
+        Some(ids) => {
+            let mut tracks = Vec::new();
+            for id in ids {
+                match state.get_track(id).await? {
+                    None => errors.push(format!("Track {} not found", id)),
+                    Some(track) if track.source_id != LOCAL_SOURCE_ID => {
+                        errors.push(format!("Track {}: only local files can be organized", id))
+                    }
+                    Some(track) if track.missing_since.is_some() => {
+                        errors.push(format!("Track {}: its file is missing", id))
+                    }
+                    Some(track) => tracks.push(track),
+                }
+            }
+            tracks
+        }
+    };
+
+    let mut report = organize(
+        &state,
+        tracks,
+        pattern,
+        music_dir,
+        config.max_name_length,
+        dry_run.unwrap_or(false),
+    )
+    .await?;
+    errors.append(&mut report.errors);
+    report.errors = errors;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{memory_db, track};
+
+    fn name_of(path: &Path) -> String {
+        path.file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn limits_names_in_bytes() {
+        // three bytes each, the cut mustn't split one
+        assert_eq!(limit_length("ééééé", 7), "ééé");
+        assert_eq!(limit_length("Mr. Brown", 4), "Mr");
+        assert_eq!(limit_length("short", 255), "short");
+    }
+
+    #[test]
+    fn rows_hold_their_paths_while_the_file_is_missing() {
+        let folder = Path::new("/nowhere/Artist");
+        let held = HashSet::from(["/nowhere/artist/song.mp3".to_string()]);
+
+        let to = free_path(folder, "Song", ".mp3", 255, Path::new("/elsewhere/a.mp3"), &mut HashSet::new(), &held);
+        assert_eq!(name_of(&to), "Song (2).mp3");
+
+        // but a track keeps its own
+        let to = free_path(folder, "Song", ".mp3", 255, Path::new("/nowhere/Artist/Song.mp3"), &mut HashSet::new(), &held);
+        assert_eq!(name_of(&to), "Song.mp3");
+    }
+
+    #[test]
+    fn suffix_and_extension_fit_in_the_limit() {
+        let folder = Path::new("/nowhere");
+        let stem = "x".repeat(300);
+        let mut taken = HashSet::new();
+
+        let first = free_path(folder, &stem, ".flac", 255, Path::new("/a.flac"), &mut taken, &HashSet::new());
+        let second = free_path(folder, &stem, ".flac", 255, Path::new("/b.flac"), &mut taken, &HashSet::new());
+
+        assert_eq!(name_of(&first).len(), 255);
+        assert_eq!(name_of(&second).len(), 255);
+        assert!(name_of(&second).ends_with("x (2).flac"));
+    }
+
+    #[test]
+    fn only_the_row_the_watcher_made_for_the_move_is_merged() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let ids = db
+                .add_tracks(vec![track("/music/a.mp3", "A", "Artist", "Album"), track("/music/b.mp3", "B", "Artist", "Album")])
+                .await
+                .unwrap();
+            let last_id = db.get_last_track_id().await.unwrap();
+
+            // a row that was there before the move isn't the moved file
+            let taken = db.move_track_files(&[(ids[0], "/music/b.mp3".to_string())], last_id).await;
+            assert!(taken.is_err());
+
+            let watched = db.add_tracks(vec![track("/music/Artist/A.mp3", "A", "Artist", "Album")]).await.unwrap();
+            db.move_track_files(&[(ids[0], "/music/Artist/A.mp3".to_string())], last_id)
+                .await
+                .unwrap();
+
+            assert!(db.get_track(watched[0]).await.unwrap().is_none());
+            assert_eq!(db.get_track(ids[0]).await.unwrap().unwrap().file_path, "/music/Artist/A.mp3");
+            assert_eq!(db.get_track(ids[1]).await.unwrap().unwrap().file_path, "/music/b.mp3");
+        });
+    }
+}
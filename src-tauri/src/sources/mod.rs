@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, Emitter, State};
+
+use crate::{
+    db::{LibrarySource, SourceListing, LOCAL_SOURCE_ID},
+    models::{AppState as Database, SourceConfig, SyncSummary},
+    player::MediaSource,
+    user_config::load_music_dir,
+    utils::{collect_audio_files, tag_reader::extract_track_metadata},
+};
+
+mod subsonic;
+
+pub use subsonic::SubsonicSource;
+
+const REMOTE_SCHEME: &str = "subsonic://";
+
+pub fn remote_path(source_id: i64, remote_id: &str) -> String {
+    format!("{}{}/{}", REMOTE_SCHEME, source_id, remote_id)
+}
+
+// (source id, id on the server) for a remote track's file path
+pub fn parse_remote_path(path: &str) -> Option<(i64, &str)> {
+    let (source_id, remote_id) = path.strip_prefix(REMOTE_SCHEME)?.split_once('/')?;
+    Some((source_id.parse().ok()?, remote_id))
+}
+
+// The music folder on disk
+pub struct LocalSource {
+    pub music_dir: Option<PathBuf>,
+}
+
+impl LibrarySource for LocalSource {
+    fn source_id(&self) -> i64 {
+        LOCAL_SOURCE_ID
+    }
+
+    async fn list(&self, known: &HashSet<String>) -> Result<SourceListing, String> {
+        let dir = self
+            .music_dir
+            .clone()
+            .ok_or_else(|| "No music folder has been set".to_string())?;
+        let known = known.clone();
+
+        // tag reading is blocking file IO
+        tauri::async_runtime::spawn_blocking(move || {
+            let paths = collect_audio_files(&dir);
+            let new_tracks = paths
+                .iter()
+                .filter(|path| !known.contains(*path))
+                .filter_map(|path| extract_track_metadata(path).ok())
+                .collect();
+            SourceListing { paths, new_tracks }
+        })
+        .await
+        .map_err(|e| format!("Failed to scan music folder: {}", e))
+    }
+
+    fn resolve(&self, file_path: &str) -> Result<MediaSource, String> {
+        Ok(MediaSource::File(file_path.to_string()))
+    }
+}
+
+// Managed state holding a client for every remote source, so the player can turn
+// a track's path into something playable without touching the database
+pub struct LibrarySources {
+    cache_root: PathBuf,
+    remotes: Mutex<HashMap<i64, Arc<SubsonicSource>>>,
+}
+
+#[allow(dead_code)]
+impl LibrarySources {
+    pub fn new(cache_root: PathBuf) -> Self {
+        Self {
+            cache_root,
+            remotes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn load(&self, db: &Database) -> Result<(), String> {
+        for config in db.get_sources().await? {
+            if config.kind == "subsonic" {
+                self.register(&config);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn register(&self, config: &SourceConfig) -> Arc<SubsonicSource> {
+        let source = Arc::new(SubsonicSource::new(config, &self.cache_root));
+        self.remotes.lock().unwrap().insert(config.id, source.clone());
+        source
+    }
+
+    pub fn unregister(&self, id: i64) -> Option<Arc<SubsonicSource>> {
+        self.remotes.lock().unwrap().remove(&id)
+    }
+
+    pub fn get(&self, id: i64) -> Option<Arc<SubsonicSource>> {
+        self.remotes.lock().unwrap().get(&id).cloned()
+    }
+
+    // Where the remote sources keep files on disk, their offline caches
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.remotes
+            .lock()
+            .unwrap()
+            .values()
+            .map(|source| source.cache_dir().to_path_buf())
+            .collect()
+    }
+
+    pub fn resolve(&self, path: &str) -> Result<MediaSource, String> {
+        match parse_remote_path(path) {
+            Some((source_id, _)) => self
+                .get(source_id)
+                .ok_or_else(|| format!("Library source {} is not available", source_id))?
+                .resolve(path),
+            None => Ok(MediaSource::File(path.to_string())),
+        }
+    }
+}
+
+async fn sync_and_notify<S: LibrarySource + Sync>(
+    app: &AppHandle,
+    db: &Database,
+    source: &S,
+) -> Result<SyncSummary, String> {
+    let summary = db.sync_source(source).await?;
+    let _ = app.emit("library_source_synced", summary.clone());
+    Ok(summary)
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn get_library_sources(state: State<'_, Database>) -> Result<Vec<SourceConfig>, String> {
+    state.get_sources().await
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NewSubsonicSource {
+    pub name: String,
+    pub base_url: String,
+    pub username: String,
+    pub password: String,
+    pub cache_enabled: bool,
+}
+
+// The server is checked before anything is saved; the first sync runs in the
+// background and reports through `library_source_synced`
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn add_subsonic_source(
+    app: AppHandle,
+    state: State<'_, Database>,
+    sources: State<'_, LibrarySources>,
+    source: NewSubsonicSource,
+) -> Result<SourceConfig, String> {
+    let cache_enabled = source.cache_enabled;
+    let mut config = SourceConfig {
+        id: 0,
+        kind: "subsonic".to_string(),
+        name: source.name,
+        base_url: Some(source.base_url),
+        username: Some(source.username),
+        password: Some(source.password),
+        cache_enabled,
+        last_synced: None,
+        created_at: 0,
+    };
+    SubsonicSource::new(&config, &sources.cache_root).ping().await?;
+
+    config.id = state
+        .add_source(
+            &config.kind,
+            &config.name,
+            config.base_url.as_deref().unwrap_or_default(),
+            config.username.as_deref().unwrap_or_default(),
+            config.password.as_deref().unwrap_or_default(),
+            cache_enabled,
+        )
+        .await?;
+    let config = state.get_source(config.id).await?;
+    let source = sources.register(&config);
+
+    let db = state.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = sync_and_notify(&app, &db, source.as_ref()).await {
+            log::warn!("Failed to sync library source {}: {}", config.id, e);
+        }
+    });
+
+    Ok(config)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn sync_library_source(
+    app: AppHandle,
+    state: State<'_, Database>,
+    sources: State<'_, LibrarySources>,
+    id: i64,
+) -> Result<SyncSummary, String> {
+    if id == LOCAL_SOURCE_ID {
+        let music_dir = load_music_dir(app.clone()).map(|dir| PathBuf::from(dir.trim()));
+        return sync_and_notify(&app, &state, &LocalSource { music_dir }).await;
+    }
+
+    let source = sources
+        .get(id)
+        .ok_or_else(|| format!("Library source {} is not available", id))?;
+    sync_and_notify(&app, &state, source.as_ref()).await
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn remove_library_source(
+    state: State<'_, Database>,
+    sources: State<'_, LibrarySources>,
+    id: i64,
+) -> Result<(), String> {
+    state.delete_source(id).await?;
+
+    if let Some(source) = sources.unregister(id) {
+        let _ = tokio::fs::remove_dir_all(source.cache_dir()).await;
+    }
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_source_cache_enabled(
+    state: State<'_, Database>,
+    sources: State<'_, LibrarySources>,
+    id: i64,
+    enabled: bool,
+) -> Result<(), String> {
+    state.set_source_cache_enabled(id, enabled).await?;
+
+    let config = state.get_source(id).await?;
+    if config.kind == "subsonic" {
+        sources.register(&config);
+    }
+    Ok(())
+}
+
+// Makes a remote track available offline, returns the cached file's path
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn cache_remote_track(
+    state: State<'_, Database>,
+    sources: State<'_, LibrarySources>,
+    track_id: i64,
+) -> Result<String, String> {
+    let track = state
+        .get_track(track_id)
+        .await?
+        .ok_or_else(|| format!("Track {} not found", track_id))?;
+    let (source_id, remote_id) = parse_remote_path(&track.file_path)
+        .ok_or_else(|| "Only remote tracks can be cached".to_string())?;
+
+    let source = sources
+        .get(source_id)
+        .ok_or_else(|| format!("Library source {} is not available", source_id))?;
+    let path = source.download(remote_id).await?;
+
+    Ok(path.to_string_lossy().into_owned())
+}
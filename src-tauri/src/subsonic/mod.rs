@@ -0,0 +1,417 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use sqlx::FromRow;
+use tauri::{AppHandle, State};
+use tokio::sync::oneshot;
+
+use crate::{
+    models::{AppState as Database, ArtworkSize, Track},
+    user_config::{self, SubsonicConfig},
+};
+
+mod response;
+mod routes;
+
+// Subsonic ids are opaque strings, so one namespace covers every kind of item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemId {
+    Artist(i64),
+    Album(i64),
+    Track(i64),
+    Playlist(i64),
+}
+
+impl ItemId {
+    pub fn parse(id: &str) -> Option<Self> {
+        let (kind, id) = id.split_once('-')?;
+        let id = id.parse().ok()?;
+        match kind {
+            "ar" => Some(Self::Artist(id)),
+            "al" => Some(Self::Album(id)),
+            "tr" => Some(Self::Track(id)),
+            "pl" => Some(Self::Playlist(id)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Artist(id) => write!(f, "ar-{}", id),
+            Self::Album(id) => write!(f, "al-{}", id),
+            Self::Track(id) => write!(f, "tr-{}", id),
+            Self::Playlist(id) => write!(f, "pl-{}", id),
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct ArtistSummary {
+    pub id: i64,
+    pub name: String,
+    pub album_count: i64,
+}
+
+#[derive(Debug, FromRow)]
+pub struct AlbumSummary {
+    pub id: i64,
+    pub title: String,
+    pub artist_id: i64,
+    pub artist_name: Option<String>,
+    pub song_count: i64,
+    pub duration_ms: i64,
+    pub created: Option<i64>, // date_added of the oldest track, ex. 20260112
+    pub has_cover: bool,
+}
+
+#[derive(Debug, FromRow)]
+pub struct PlaylistSummary {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub song_count: i64,
+    pub duration_ms: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// Only artists and albums that actually have tracks are listed
+const ARTIST_SELECT: &str = r#"
+    SELECT
+        ar.id,
+        ar.name,
+        COUNT(al.id) AS album_count
+    FROM artists ar
+    JOIN albums al ON al.artist_id = ar.id
+    WHERE EXISTS (SELECT 1 FROM tracks t WHERE t.album_id = al.id)
+"#;
+
+const ALBUM_SELECT: &str = r#"
+    SELECT
+        al.id,
+        al.title,
+        al.artist_id,
+        ar.name                                          AS artist_name,
+        COUNT(t.id)                                      AS song_count,
+        COALESCE(SUM(t.duration_ms), 0)                  AS duration_ms,
+        MIN(t.date_added)                                AS created,
+        (al.cover_path IS NOT NULL OR al.artwork_id IS NOT NULL
+            OR MAX(t.artwork_id IS NOT NULL))            AS has_cover
+    FROM albums al
+    JOIN tracks t        ON t.album_id   = al.id
+    LEFT JOIN artists ar ON al.artist_id = ar.id
+"#;
+
+const TRACK_SELECT: &str = r#"
+    SELECT
+        t.*,
+        a.name   AS artist_name,
+        al.title AS album_name
+    FROM tracks t
+    LEFT JOIN artists a  ON t.artist_id = a.id
+    LEFT JOIN albums  al ON t.album_id  = al.id
+"#;
+
+// Queries behind the Subsonic endpoints. An empty search query matches everything,
+// which is how clients sync the whole library, and a limit of -1 means no limit.
+#[allow(dead_code)]
+impl Database {
+    pub async fn subsonic_artists(&self, query: &str, limit: i64, offset: i64) -> Result<Vec<ArtistSummary>, String> {
+        sqlx::query_as::<_, ArtistSummary>(&format!(
+            "{} AND instr(lower(ar.name), lower(?)) > 0
+            GROUP BY ar.id ORDER BY ar.name COLLATE NOCASE LIMIT ? OFFSET ?",
+            ARTIST_SELECT
+        ))
+        .bind(query)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn subsonic_artist(&self, id: i64) -> Result<Option<ArtistSummary>, String> {
+        sqlx::query_as::<_, ArtistSummary>(&format!("{} AND ar.id = ? GROUP BY ar.id", ARTIST_SELECT))
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn subsonic_albums(
+        &self,
+        artist_id: Option<i64>,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AlbumSummary>, String> {
+        sqlx::query_as::<_, AlbumSummary>(&format!(
+            "{} WHERE (? IS NULL OR al.artist_id = ?) AND instr(lower(al.title), lower(?)) > 0
+            GROUP BY al.id ORDER BY al.title COLLATE NOCASE LIMIT ? OFFSET ?",
+            ALBUM_SELECT
+        ))
+        .bind(artist_id)
+        .bind(artist_id)
+        .bind(query)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn subsonic_album(&self, id: i64) -> Result<Option<AlbumSummary>, String> {
+        sqlx::query_as::<_, AlbumSummary>(&format!("{} WHERE al.id = ? GROUP BY al.id", ALBUM_SELECT))
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // there are no track numbers yet, file names usually sort in album order
+    pub async fn subsonic_album_tracks(&self, album_id: i64) -> Result<Vec<Track>, String> {
+        sqlx::query_as::<_, Track>(&format!(
+            "{} WHERE t.album_id = ? ORDER BY COALESCE(t.disc_number, 1), t.track_number, t.file_path",
+            TRACK_SELECT
+        ))
+            .bind(album_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn subsonic_search_tracks(&self, query: &str, limit: i64, offset: i64) -> Result<Vec<Track>, String> {
+        sqlx::query_as::<_, Track>(&format!(
+            "{} WHERE instr(lower(t.title || ' ' || COALESCE(a.name, '') || ' ' || COALESCE(al.title, '')), lower(?)) > 0
+            ORDER BY t.title COLLATE NOCASE LIMIT ? OFFSET ?",
+            TRACK_SELECT
+        ))
+        .bind(query)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // all playlists when id is None
+    pub async fn subsonic_playlists(&self, id: Option<i64>) -> Result<Vec<PlaylistSummary>, String> {
+        sqlx::query_as::<_, PlaylistSummary>(
+            r#"
+            SELECT
+                p.id,
+                p.name,
+                p.description,
+                COUNT(t.id)                     AS song_count,
+                COALESCE(SUM(t.duration_ms), 0) AS duration_ms,
+                p.created_at,
+                p.updated_at
+            FROM playlists p
+            LEFT JOIN playlist_tracks pt ON pt.playlist_id = p.id
+            LEFT JOIN tracks t           ON pt.track_id    = t.id
+            WHERE p.is_system = 0 AND (? IS NULL OR p.id = ?)
+            GROUP BY p.id
+            ORDER BY p.name COLLATE NOCASE
+            "#,
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // Artwork for an item as (bytes, mime). Albums prefer their cover file and fall
+    // back to the first embedded picture, tracks fall back to their album.
+    pub async fn subsonic_cover_art(&self, id: ItemId, size: ArtworkSize) -> Result<Option<(Vec<u8>, String)>, String> {
+        let artwork_id: Option<i64> = match id {
+            ItemId::Track(track_id) => {
+                let row: Option<(i64, Option<i64>)> =
+                    sqlx::query_as("SELECT album_id, artwork_id FROM tracks WHERE id = ?")
+                        .bind(track_id)
+                        .fetch_optional(&self.db)
+                        .await
+                        .map_err(|e| format!("Database error: {}", e))?;
+
+                match row {
+                    Some((_, Some(artwork_id))) => Some(artwork_id),
+                    Some((album_id, None)) => {
+                        return Box::pin(self.subsonic_cover_art(ItemId::Album(album_id), size)).await
+                    }
+                    None => None,
+                }
+            }
+            ItemId::Album(album_id) => {
+                let cover_path: Option<String> =
+                    sqlx::query_scalar("SELECT cover_path FROM albums WHERE id = ?")
+                        .bind(album_id)
+                        .fetch_optional(&self.db)
+                        .await
+                        .map_err(|e| format!("Database error: {}", e))?
+                        .flatten();
+
+                if let Some(path) = cover_path {
+                    if let Ok(bytes) = tokio::fs::read(&path).await {
+                        return Ok(Some((bytes, image_content_type(&path).to_string())));
+                    }
+                }
+
+                sqlx::query_scalar(
+                    "SELECT COALESCE(al.artwork_id, (
+                        SELECT artwork_id FROM tracks
+                        WHERE album_id = al.id AND artwork_id IS NOT NULL
+                        ORDER BY file_path LIMIT 1))
+                    FROM albums al WHERE al.id = ?",
+                )
+                .bind(album_id)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?
+                .flatten()
+            }
+            ItemId::Playlist(playlist_id) => sqlx::query_scalar(
+                "SELECT t.artwork_id FROM playlist_tracks pt
+                JOIN tracks t ON pt.track_id = t.id
+                WHERE pt.playlist_id = ? AND t.artwork_id IS NOT NULL
+                ORDER BY pt.position LIMIT 1",
+            )
+            .bind(playlist_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?,
+            ItemId::Artist(_) => None,
+        };
+
+        match artwork_id {
+            Some(artwork_id) => self.get_artwork(artwork_id, size).await,
+            None => Ok(None),
+        }
+    }
+}
+
+fn image_content_type(path: &str) -> &'static str {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        _ => "image/jpeg",
+    }
+}
+
+#[derive(Clone)]
+pub struct ServerState {
+    pub db: Database,
+    pub username: String,
+    pub password: String,
+}
+
+struct RunningServer {
+    port: u16,
+    shutdown: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+pub struct SubsonicServer {
+    running: Mutex<Option<RunningServer>>,
+}
+
+#[allow(dead_code)]
+impl SubsonicServer {
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().is_some()
+    }
+
+    pub async fn start(&self, db: Database, config: &SubsonicConfig) -> Result<(), String> {
+        self.stop();
+
+        if config.username.trim().is_empty() || config.password.is_empty() {
+            return Err("Set a username and password before enabling the Subsonic server".to_string());
+        }
+
+        // phones are the main clients, so listen on every interface like the remote control
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("Failed to bind Subsonic port {}: {}", config.port, e))?;
+
+        let router = routes::router(ServerState {
+            db,
+            username: config.username.trim().to_string(),
+            password: config.password.clone(),
+        });
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        tauri::async_runtime::spawn(async move {
+            let result = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                log::error!("Subsonic server stopped: {}", e);
+            }
+        });
+
+        *self.running.lock().unwrap() = Some(RunningServer { port: config.port, shutdown });
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(server) = self.running.lock().unwrap().take() {
+            let _ = server.shutdown.send(());
+        }
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.running.lock().unwrap().as_ref().map(|s| s.port)
+    }
+}
+
+// Called once from setup(), only starts the server if the user enabled it before
+pub fn start_if_enabled(app: AppHandle, db: Database) {
+    let config = user_config::load_subsonic_config(&app);
+    if !config.enabled {
+        return;
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let server = tauri::Manager::state::<SubsonicServer>(&app);
+        if let Err(e) = server.start(db, &config).await {
+            log::error!("{}", e);
+        }
+    });
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_subsonic_config(app: AppHandle) -> SubsonicConfig {
+    user_config::load_subsonic_config(&app)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_subsonic_config(
+    app: AppHandle,
+    state: State<'_, Database>,
+    server: State<'_, SubsonicServer>,
+    config: SubsonicConfig,
+) -> Result<SubsonicConfig, String> {
+    if config.enabled {
+        server.start(state.inner().clone(), &config).await?;
+    } else {
+        server.stop();
+    }
+
+    user_config::save_subsonic_config(&app, &config)?;
+    Ok(config)
+}
@@ -0,0 +1,150 @@
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+
+use crate::utils::escape_xml;
+
+pub const API_VERSION: &str = "1.16.1";
+
+// Subsonic error codes
+pub const ERROR_GENERIC: u32 = 0;
+pub const ERROR_MISSING_PARAMETER: u32 = 10;
+pub const ERROR_WRONG_CREDENTIALS: u32 = 40;
+pub const ERROR_AUTH_NOT_SUPPORTED: u32 = 42;
+pub const ERROR_NOT_FOUND: u32 = 70;
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub code: u32,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    pub fn not_found(what: &str) -> Self {
+        Self::new(ERROR_NOT_FOUND, format!("{} not found", what))
+    }
+}
+
+// database errors are reported as generic errors
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        Self::new(ERROR_GENERIC, message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Xml,
+    Json,
+}
+
+impl Format {
+    // `f` request parameter, XML unless the client asks otherwise
+    pub fn from_param(f: Option<&str>) -> Self {
+        match f {
+            Some("json") => Self::Json,
+            _ => Self::Xml,
+        }
+    }
+}
+
+pub fn ok(format: Format, body: Value) -> Response {
+    render(format, "ok", body)
+}
+
+pub fn error(format: Format, error: ApiError) -> Response {
+    let body = serde_json::json!({
+        "error": { "code": error.code, "message": error.message }
+    });
+    render(format, "failed", body)
+}
+
+// Errors are still HTTP 200, clients only look at the status attribute
+fn render(format: Format, status: &str, body: Value) -> Response {
+    let mut root = Map::new();
+    root.insert("status".into(), status.into());
+    root.insert("version".into(), API_VERSION.into());
+    root.insert("type".into(), "tamaureus".into());
+    root.insert("serverVersion".into(), env!("CARGO_PKG_VERSION").into());
+    root.insert("openSubsonic".into(), true.into());
+    if let Value::Object(fields) = body {
+        root.extend(fields);
+    }
+    let mut root = Value::Object(root);
+    strip_nulls(&mut root);
+
+    match format {
+        Format::Json => {
+            let json = serde_json::json!({ "subsonic-response": root });
+            ([(header::CONTENT_TYPE, "application/json")], json.to_string()).into_response()
+        }
+        Format::Xml => {
+            let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            write_element(&mut xml, "subsonic-response", &root, true);
+            ([(header::CONTENT_TYPE, "text/xml; charset=utf-8")], xml).into_response()
+        }
+    }
+}
+
+// Optional fields are built as null and left out of the response entirely
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.retain(|_, v| !v.is_null());
+            fields.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+// The JSON and XML forms of the API map onto each other: scalar fields become
+// attributes, objects become child elements and arrays repeat the element.
+fn write_element(out: &mut String, name: &str, value: &Value, root: bool) {
+    out.push('<');
+    out.push_str(name);
+    if root {
+        out.push_str(r#" xmlns="http://subsonic.org/restapi""#);
+    }
+
+    let Value::Object(fields) = value else {
+        // scalar array items, written as text content
+        out.push('>');
+        out.push_str(&escape_xml(&scalar_to_string(value)));
+        out.push_str(&format!("</{}>", name));
+        return;
+    };
+
+    let mut children = Vec::new();
+    for (key, field) in fields {
+        match field {
+            Value::Object(_) => children.push((key, field)),
+            Value::Array(items) => children.extend(items.iter().map(|item| (key, item))),
+            scalar => out.push_str(&format!(r#" {}="{}""#, key, escape_xml(&scalar_to_string(scalar)))),
+        }
+    }
+
+    if children.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+
+    out.push('>');
+    for (key, child) in children {
+        write_element(out, key, child, false);
+    }
+    out.push_str(&format!("</{}>", name));
+}
+
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
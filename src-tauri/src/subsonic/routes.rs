@@ -0,0 +1,487 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{rejection::FormRejection, Form, Path, Query, State},
+    http::{header, HeaderMap, Method},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde_json::{json, Value};
+
+use super::response::{
+    self, ApiError, Format, ERROR_AUTH_NOT_SUPPORTED, ERROR_GENERIC, ERROR_MISSING_PARAMETER,
+    ERROR_WRONG_CREDENTIALS,
+};
+use super::{AlbumSummary, ArtistSummary, ItemId, PlaylistSummary, ServerState};
+use crate::{
+    models::{ArtworkSize, Track},
+    utils::{audio_content_type, constant_time_eq, ranged_file_response},
+};
+
+// articles skipped when sorting artists into the index, see getArtists
+const IGNORED_ARTICLES: &[&str] = &["The", "A", "An"];
+
+pub fn router(state: ServerState) -> Router {
+    // clients call both /rest/ping and /rest/ping.view, and some POST their parameters
+    Router::new()
+        .route("/rest/{method}", get(dispatch).post(dispatch))
+        .with_state(state)
+}
+
+// Request parameters, which can repeat (scrobble takes several `id`s)
+struct Params(Vec<(String, String)>);
+
+impl Params {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    fn all(&self, key: &str) -> Vec<&str> {
+        self.0.iter().filter(|(k, _)| k == key).map(|(_, v)| v.as_str()).collect()
+    }
+
+    fn require(&self, key: &str) -> Result<&str, ApiError> {
+        self.get(key).ok_or_else(|| {
+            ApiError::new(ERROR_MISSING_PARAMETER, format!("Required parameter '{}' is missing", key))
+        })
+    }
+
+    fn int(&self, key: &str, default: i64) -> i64 {
+        self.get(key).and_then(|v| v.parse().ok()).unwrap_or(default)
+    }
+
+    fn item_id(&self, key: &str) -> Result<ItemId, ApiError> {
+        let id = self.require(key)?;
+        ItemId::parse(id).ok_or_else(|| ApiError::not_found(&format!("Item '{}'", id)))
+    }
+}
+
+async fn dispatch(
+    State(state): State<ServerState>,
+    Path(method): Path<String>,
+    request_method: Method,
+    headers: HeaderMap,
+    Query(query): Query<Vec<(String, String)>>,
+    form: Result<Form<Vec<(String, String)>>, FormRejection>,
+) -> Response {
+    // for GET the form extractor just reads the query string again
+    let mut params = query;
+    if request_method == Method::POST {
+        if let Ok(Form(body)) = form {
+            params.extend(body);
+        }
+    }
+    let params = Params(params);
+    let format = Format::from_param(params.get("f"));
+
+    if let Err(e) = authenticate(&state, &params) {
+        return response::error(format, e);
+    }
+
+    let result = match method.trim_end_matches(".view") {
+        // binary endpoints answer with the file itself on success
+        "stream" | "download" => match stream(&state, &params, &headers).await {
+            Ok(response) => return response,
+            Err(e) => Err(e),
+        },
+        "getCoverArt" => match get_cover_art(&state, &params).await {
+            Ok(response) => return response,
+            Err(e) => Err(e),
+        },
+        // system
+        "ping" => Ok(json!({})),
+        "getLicense" => Ok(json!({ "license": { "valid": true } })),
+        "getOpenSubsonicExtensions" => Ok(json!({ "openSubsonicExtensions": [] })),
+        "getMusicFolders" => Ok(json!({
+            "musicFolders": { "musicFolder": [{ "id": 1, "name": "Music" }] }
+        })),
+        // browsing
+        "getArtists" => get_artists(&state).await,
+        "getArtist" => get_artist(&state, &params).await,
+        "getAlbum" => get_album(&state, &params).await,
+        "getSong" => get_song(&state, &params).await,
+        "search3" => search3(&state, &params).await,
+        // playlists
+        "getPlaylists" => get_playlists(&state).await,
+        "getPlaylist" => get_playlist(&state, &params).await,
+        // annotation
+        "scrobble" => scrobble(&state, &params).await,
+        other => Err(ApiError::new(ERROR_GENERIC, format!("Unsupported method '{}'", other))),
+    };
+
+    match result {
+        Ok(body) => response::ok(format, body),
+        Err(e) => response::error(format, e),
+    }
+}
+
+// Token authentication only: t = md5(password + s). The plain `p` parameter would
+// put the password on the wire as-is, so it's refused.
+fn authenticate(state: &ServerState, params: &Params) -> Result<(), ApiError> {
+    let username = params.require("u")?;
+
+    let password_ok = match (params.get("t"), params.get("s")) {
+        (Some(token), Some(salt)) => {
+            let expected = format!("{:x}", md5::compute(format!("{}{}", state.password, salt)));
+            constant_time_eq(&token.to_lowercase(), &expected)
+        }
+        _ if params.get("p").is_some() => {
+            return Err(ApiError::new(
+                ERROR_AUTH_NOT_SUPPORTED,
+                "Password authentication is not supported, use token authentication",
+            ))
+        }
+        _ => {
+            return Err(ApiError::new(
+                ERROR_MISSING_PARAMETER,
+                "Required parameters 't' and 's' are missing",
+            ))
+        }
+    };
+
+    if username == state.username && password_ok {
+        Ok(())
+    } else {
+        Err(ApiError::new(ERROR_WRONG_CREDENTIALS, "Wrong username or password"))
+    }
+}
+
+// dates are stored as YYYYMMDD integers
+fn iso_date(date: Option<i64>) -> Option<String> {
+    let date = chrono::NaiveDate::parse_from_str(&date?.to_string(), "%Y%m%d").ok()?;
+    Some(format!("{}T00:00:00Z", date.format("%Y-%m-%d")))
+}
+
+fn iso_timestamp(secs: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(secs, 0)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+fn artist_json(artist: &ArtistSummary) -> Value {
+    json!({
+        "id": ItemId::Artist(artist.id).to_string(),
+        "name": artist.name,
+        "albumCount": artist.album_count,
+    })
+}
+
+fn album_json(album: &AlbumSummary) -> Value {
+    json!({
+        "id": ItemId::Album(album.id).to_string(),
+        "name": album.title,
+        "artist": album.artist_name,
+        "artistId": ItemId::Artist(album.artist_id).to_string(),
+        "coverArt": album.has_cover.then(|| ItemId::Album(album.id).to_string()),
+        "songCount": album.song_count,
+        "duration": album.duration_ms / 1000,
+        "created": iso_date(album.created),
+    })
+}
+
+fn song_json(track: &Track) -> Value {
+    let cover_art = if track.artwork_id.is_some() {
+        ItemId::Track(track.id)
+    } else {
+        ItemId::Album(track.album_id)
+    };
+
+    let mut song = json!({
+        "id": ItemId::Track(track.id).to_string(),
+        "parent": ItemId::Album(track.album_id).to_string(),
+        "isDir": false,
+        "title": track.title,
+        "album": track.album_name,
+        "artist": track.artist_name,
+        "albumId": ItemId::Album(track.album_id).to_string(),
+        "artistId": ItemId::Artist(track.artist_id).to_string(),
+        "coverArt": cover_art.to_string(),
+        "duration": track.duration_ms / 1000,
+        // file_size is stored in MB
+        "size": (track.file_size * 1_048_576.0) as i64,
+        "suffix": track.file_format,
+        "contentType": audio_content_type(&track.file_format),
+        "created": iso_date(track.date_added),
+        "type": "music",
+        "mediaType": "song",
+    });
+
+    // clients expect these to be left out rather than null
+    let tags = &track.tags;
+    let optional = [
+        ("track", tags.track_number.map(Value::from)),
+        ("discNumber", tags.disc_number.map(Value::from)),
+        ("year", tags.year.map(Value::from)),
+        ("genre", tags.genre.clone().map(Value::from)),
+        ("bpm", tags.bpm.map(|bpm| Value::from(bpm.round() as i64))),
+        ("comment", tags.comment.clone().map(Value::from)),
+        ("displayAlbumArtist", tags.album_artist.clone().map(Value::from)),
+        ("displayComposer", tags.composer.clone().map(Value::from)),
+        ("bitRate", tags.bitrate.map(Value::from)),
+        ("samplingRate", tags.sample_rate.map(Value::from)),
+        ("bitDepth", tags.bit_depth.map(Value::from)),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            song[key] = value;
+        }
+    }
+    song
+}
+
+fn playlist_json(playlist: &PlaylistSummary, owner: &str) -> Value {
+    json!({
+        "id": ItemId::Playlist(playlist.id).to_string(),
+        "name": playlist.name,
+        "comment": playlist.description,
+        "owner": owner,
+        "public": false,
+        "songCount": playlist.song_count,
+        "duration": playlist.duration_ms / 1000,
+        "created": iso_timestamp(playlist.created_at),
+        "changed": iso_timestamp(playlist.updated_at),
+        "coverArt": ItemId::Playlist(playlist.id).to_string(),
+    })
+}
+
+// index letter for an artist, leading articles are skipped
+fn index_key(name: &str) -> String {
+    let sort_name = IGNORED_ARTICLES
+        .iter()
+        .find_map(|article| {
+            name.get(..article.len() + 1)
+                .filter(|prefix| prefix.eq_ignore_ascii_case(&format!("{} ", article)))
+                .map(|_| &name[article.len() + 1..])
+        })
+        .unwrap_or(name);
+
+    match sort_name.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => "#".to_string(),
+    }
+}
+
+// browsing
+async fn get_artists(state: &ServerState) -> Result<Value, ApiError> {
+    let artists = state.db.subsonic_artists("", -1, 0).await?;
+
+    let mut index: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for artist in &artists {
+        index.entry(index_key(&artist.name)).or_default().push(artist_json(artist));
+    }
+
+    let index: Vec<Value> = index
+        .into_iter()
+        .map(|(name, artists)| json!({ "name": name, "artist": artists }))
+        .collect();
+
+    Ok(json!({
+        "artists": { "ignoredArticles": IGNORED_ARTICLES.join(" "), "index": index }
+    }))
+}
+
+async fn get_artist(state: &ServerState, params: &Params) -> Result<Value, ApiError> {
+    let ItemId::Artist(id) = params.item_id("id")? else {
+        return Err(ApiError::not_found("Artist"));
+    };
+    let artist = state.db.subsonic_artist(id).await?.ok_or_else(|| ApiError::not_found("Artist"))?;
+    let albums = state.db.subsonic_albums(Some(id), "", -1, 0).await?;
+
+    let mut body = artist_json(&artist);
+    body["album"] = albums.iter().map(album_json).collect();
+    Ok(json!({ "artist": body }))
+}
+
+async fn get_album(state: &ServerState, params: &Params) -> Result<Value, ApiError> {
+    let ItemId::Album(id) = params.item_id("id")? else {
+        return Err(ApiError::not_found("Album"));
+    };
+    let album = state.db.subsonic_album(id).await?.ok_or_else(|| ApiError::not_found("Album"))?;
+    let tracks = state.db.subsonic_album_tracks(id).await?;
+
+    let mut body = album_json(&album);
+    body["song"] = tracks.iter().map(song_json).collect();
+    Ok(json!({ "album": body }))
+}
+
+async fn get_song(state: &ServerState, params: &Params) -> Result<Value, ApiError> {
+    let ItemId::Track(id) = params.item_id("id")? else {
+        return Err(ApiError::not_found("Song"));
+    };
+    let track = state.db.get_track(id).await?.ok_or_else(|| ApiError::not_found("Song"))?;
+    Ok(json!({ "song": song_json(&track) }))
+}
+
+async fn search3(state: &ServerState, params: &Params) -> Result<Value, ApiError> {
+    // some clients send `""` to mean "everything"
+    let query = params.require("query")?.trim().trim_matches('"');
+
+    let artists = state
+        .db
+        .subsonic_artists(query, params.int("artistCount", 20), params.int("artistOffset", 0))
+        .await?;
+    let albums = state
+        .db
+        .subsonic_albums(None, query, params.int("albumCount", 20), params.int("albumOffset", 0))
+        .await?;
+    let songs = state
+        .db
+        .subsonic_search_tracks(query, params.int("songCount", 20), params.int("songOffset", 0))
+        .await?;
+
+    Ok(json!({
+        "searchResult3": {
+            "artist": artists.iter().map(artist_json).collect::<Vec<_>>(),
+            "album": albums.iter().map(album_json).collect::<Vec<_>>(),
+            "song": songs.iter().map(song_json).collect::<Vec<_>>(),
+        }
+    }))
+}
+
+// playlists
+async fn get_playlists(state: &ServerState) -> Result<Value, ApiError> {
+    let playlists = state.db.subsonic_playlists(None).await?;
+    let playlists: Vec<Value> = playlists.iter().map(|p| playlist_json(p, &state.username)).collect();
+    Ok(json!({ "playlists": { "playlist": playlists } }))
+}
+
+async fn get_playlist(state: &ServerState, params: &Params) -> Result<Value, ApiError> {
+    let ItemId::Playlist(id) = params.item_id("id")? else {
+        return Err(ApiError::not_found("Playlist"));
+    };
+    let playlist = state
+        .db
+        .subsonic_playlists(Some(id))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::not_found("Playlist"))?;
+    let tracks = state.db.get_playlist_tracks(id).await?;
+
+    let mut body = playlist_json(&playlist, &state.username);
+    body["entry"] = tracks.iter().map(song_json).collect();
+    Ok(json!({ "playlist": body }))
+}
+
+// annotation
+// `submission=false` is a "now playing" notification, only finished plays are recorded
+async fn scrobble(state: &ServerState, params: &Params) -> Result<Value, ApiError> {
+    let ids = params.all("id");
+    if ids.is_empty() {
+        params.require("id")?;
+    }
+    if params.get("submission") == Some("false") {
+        return Ok(json!({}));
+    }
+
+    // optional play times in milliseconds, one per id
+    let times = params.all("time");
+    for (index, id) in ids.iter().enumerate() {
+        let Some(ItemId::Track(track_id)) = ItemId::parse(id) else {
+            return Err(ApiError::not_found(&format!("Song '{}'", id)));
+        };
+        let played_at = times.get(index).and_then(|t| t.parse::<i64>().ok()).map(|ms| ms / 1000);
+        state.db.record_play(track_id, played_at, "subsonic").await?;
+    }
+
+    Ok(json!({}))
+}
+
+// Serves the original file, with Range support so clients can seek. There is no
+// transcoding, `format` and `maxBitRate` are ignored.
+async fn stream(state: &ServerState, params: &Params, headers: &HeaderMap) -> Result<Response, ApiError> {
+    let ItemId::Track(id) = params.item_id("id")? else {
+        return Err(ApiError::not_found("Song"));
+    };
+    let track = state.db.get_track(id).await?.ok_or_else(|| ApiError::not_found("Song"))?;
+
+    // clients aren't told where the library is, the path goes to the log
+    let file = tokio::fs::File::open(&track.file_path).await.map_err(|e| {
+        log::warn!("Subsonic stream of {} failed: {}", track.file_path, e);
+        ApiError::not_found("Song")
+    })?;
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+
+    Ok(ranged_file_response(file, audio_content_type(&track.file_format), range).await?)
+}
+
+// `size` picks the smallest stored thumbnail that's at least that big
+async fn get_cover_art(state: &ServerState, params: &Params) -> Result<Response, ApiError> {
+    let id = params.item_id("id")?;
+    let size = params
+        .get("size")
+        .and_then(|size| size.parse::<u32>().ok())
+        .map_or(ArtworkSize::Full, ArtworkSize::for_pixels);
+    let (bytes, mime) = state
+        .db
+        .subsonic_cover_art(id, size)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Cover art"))?;
+
+    Ok(([(header::CONTENT_TYPE, mime)], bytes).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::memory_db;
+
+    fn params(pairs: &[(&str, &str)]) -> Params {
+        Params(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    fn token(password: &str, salt: &str) -> String {
+        format!("{:x}", md5::compute(format!("{}{}", password, salt)))
+    }
+
+    #[test]
+    fn authenticates_by_token_only() {
+        tauri::async_runtime::block_on(async {
+            let state = ServerState {
+                db: memory_db().await,
+                username: "listener".to_string(),
+                password: "sesame".to_string(),
+            };
+            let code = |pairs: &[(&str, &str)]| authenticate(&state, &params(pairs)).err().map(|e| e.code);
+
+            let good = token("sesame", "c19b2d");
+            assert_eq!(code(&[("u", "listener"), ("t", &good), ("s", "c19b2d")]), None);
+            assert_eq!(code(&[("u", "listener"), ("t", &good.to_uppercase()), ("s", "c19b2d")]), None);
+
+            // the token of another salt, or of another password
+            assert_eq!(code(&[("u", "listener"), ("t", &good), ("s", "a4f7e1")]), Some(ERROR_WRONG_CREDENTIALS));
+            let wrong = token("open", "c19b2d");
+            assert_eq!(code(&[("u", "listener"), ("t", &wrong), ("s", "c19b2d")]), Some(ERROR_WRONG_CREDENTIALS));
+            // a good token for the password is no good for another user
+            assert_eq!(code(&[("u", "someone"), ("t", &good), ("s", "c19b2d")]), Some(ERROR_WRONG_CREDENTIALS));
+
+            // the password itself is refused, even when it's right
+            assert_eq!(code(&[("u", "listener"), ("p", "sesame")]), Some(ERROR_AUTH_NOT_SUPPORTED));
+            assert_eq!(code(&[("u", "listener"), ("p", "enc:736573616d65")]), Some(ERROR_AUTH_NOT_SUPPORTED));
+            assert_eq!(code(&[("u", "listener"), ("t", &good)]), Some(ERROR_MISSING_PARAMETER));
+            assert_eq!(code(&[("t", &good), ("s", "c19b2d")]), Some(ERROR_MISSING_PARAMETER));
+        });
+    }
+
+    #[test]
+    fn a_missing_file_does_not_show_its_path() {
+        tauri::async_runtime::block_on(async {
+            let db = memory_db().await;
+            let id = db
+                .add_track(crate::test_support::track("/home/someone/Music/gone.mp3", "Gone", "Artist", "Album"))
+                .await
+                .unwrap();
+            let state = ServerState {
+                db,
+                username: "listener".to_string(),
+                password: "sesame".to_string(),
+            };
+
+            let song = ItemId::Track(id).to_string();
+            let Err(e) = stream(&state, &params(&[("id", &song)]), &HeaderMap::new()).await else {
+                panic!("there's no file to stream");
+            };
+            assert_eq!((e.code, e.message.as_str()), (response::ERROR_NOT_FOUND, "Song not found"));
+        });
+    }
+}
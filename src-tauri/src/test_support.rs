@@ -0,0 +1,196 @@
+// Shared helpers for unit tests: an in-memory database with the real migrations,
+// and a tiny HTTP server standing in for feeds, streams and web services.
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+use sqlx::sqlite::SqlitePoolOptions;
+
+use crate::models::{AppState as Database, ExtractedTrack};
+
+// one connection, so every query sees the same in-memory database
+pub async fn memory_db() -> Database {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory DB");
+    sqlx::migrate!().run(&pool).await.expect("Failed to run migrations");
+    Database { db: pool }
+}
+
+// A plain three minute mp3 as the scanner would have read it
+pub fn track(file_path: &str, title: &str, artist: &str, album: &str) -> ExtractedTrack {
+    ExtractedTrack {
+        file_path: file_path.to_string(),
+        title: title.to_string(),
+        artist: artist.to_string(),
+        album: album.to_string(),
+        duration_ms: 180_000,
+        file_format: "mp3".to_string(),
+        file_size: 4.2,
+        date_added: None,
+        thumbnail_base64: None,
+        thumbnail_mime: None,
+        chapters: Vec::new(),
+        source_id: None,
+        file_mtime: None,
+        file_bytes: None,
+        content_hash: None,
+        artwork: None,
+        credits: Vec::new(),
+        tags: Default::default(),
+    }
+}
+
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("tamaureus-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    dir
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    // lower-cased names
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|v| v.as_str())
+    }
+
+    // "bytes=start-" or "bytes=start-end", end inclusive
+    pub fn range(&self) -> Option<(u64, Option<u64>)> {
+        let spec = self.header("range")?.strip_prefix("bytes=")?;
+        let (start, end) = spec.split_once('-')?;
+        Some((start.parse().ok()?, end.parse().ok()))
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // false sends the body with no Content-Length and closes the connection after it
+    pub content_length: bool,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Response { status: 200, headers: Vec::new(), body: body.into(), content_length: true }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Response { status, headers: Vec::new(), body: Vec::new(), content_length: true }
+    }
+
+    pub fn header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn without_length(mut self) -> Self {
+        self.content_length = false;
+        self
+    }
+
+    // Answers a Range request against the whole body with 206, or 200 without one
+    pub fn ranged(request: &Request, body: &[u8]) -> Self {
+        let Some((start, end)) = request.range() else {
+            return Response::ok(body.to_vec()).header("Accept-Ranges", "bytes");
+        };
+        let len = body.len() as u64;
+        if start >= len {
+            return Response::status(416).header("Content-Range", format!("bytes */{}", len));
+        }
+        let end = end.unwrap_or(len - 1).min(len - 1);
+        Response {
+            status: 206,
+            headers: vec![
+                ("Accept-Ranges".to_string(), "bytes".to_string()),
+                ("Content-Range".to_string(), format!("bytes {}-{}/{}", start, end, len)),
+            ],
+            body: body[start as usize..=end as usize].to_vec(),
+            content_length: true,
+        }
+    }
+}
+
+// Serves every request on its own thread until the test process exits.
+// Returns the base url, e.g. "http://127.0.0.1:41234"
+pub fn serve<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+    let addr = listener.local_addr().unwrap();
+    let handler = Arc::new(handler);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            std::thread::spawn(move || {
+                let _ = handle_connection(stream, handler.as_ref());
+            });
+        }
+    });
+
+    format!("http://{}", addr)
+}
+
+fn handle_connection<F>(stream: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(&Request) -> Response,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    // one request per connection keeps the framing trivial
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    let head_only = method == "HEAD";
+    let response = handler(&Request { method, path, headers, body });
+
+    let mut head = format!("HTTP/1.1 {} X\r\nConnection: close\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if response.content_length {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    head.push_str("\r\n");
+
+    writer.write_all(head.as_bytes())?;
+    // HEAD answers carry the length of the body they don't send
+    if !head_only {
+        writer.write_all(&response.body)?;
+    }
+    writer.flush()
+}
@@ -1,6 +1,180 @@
 use std::fs;
+use rand::distr::{Alphanumeric, SampleString};
 use tauri::{AppHandle, Manager}; // Required for .path() extension trait
 
+const REMOTE_CONFIG_FILE: &str = "remote_control.json";
+const DEFAULT_REMOTE_PORT: u16 = 7878;
+
+// Settings for the LAN remote-control server. Off by default; the token is
+// generated on first load and has to be sent with every request.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RemoteConfig {
+    pub enabled: bool,
+    pub port: u16,
+    pub token: String,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_REMOTE_PORT,
+            token: generate_token(),
+        }
+    }
+}
+
+const MPD_CONFIG_FILE: &str = "mpd.json";
+
+// Settings for the MPD protocol server. Localhost only unless the user changes
+// bind_address, since MPD has no authentication worth the name.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MpdConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl Default for MpdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 6600,
+        }
+    }
+}
+
+const SUBSONIC_CONFIG_FILE: &str = "subsonic.json";
+
+// Settings for the Subsonic API server. Clients authenticate with
+// md5(password + salt), so the password has to be kept in plain text here.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SubsonicConfig {
+    pub enabled: bool,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+}
+
+impl Default for SubsonicConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 4040,
+            username: "tamaureus".to_string(),
+            password: String::new(),
+        }
+    }
+}
+
+const DLNA_CONFIG_FILE: &str = "dlna.json";
+
+// Settings for the UPnP/DLNA media server. The uuid is generated the first time
+// the server starts and kept, so TVs remember the device between runs.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DlnaConfig {
+    pub enabled: bool,
+    pub port: u16,
+    pub friendly_name: String,
+    pub uuid: String,
+}
+
+impl Default for DlnaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8200,
+            friendly_name: "Tamaureus".to_string(),
+            uuid: String::new(),
+        }
+    }
+}
+
+const ARTIST_CONFIG_FILE: &str = "artists.json";
+
+// How artist tags are split into the artists on a track. Multi-value tags are
+// always used as they are; names are only split on these when split_names is on.
+// "&" isn't a separator by default, too many band names have one.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ArtistConfig {
+    pub split_names: bool,
+    // "A feat. B" and "Title (feat. B)"
+    pub featuring: Vec<String>,
+    // "A & B", "A; B"
+    pub separators: Vec<String>,
+    pub parse_titles: bool,
+}
+
+impl Default for ArtistConfig {
+    fn default() -> Self {
+        Self {
+            split_names: true,
+            featuring: vec!["feat.".to_string(), "ft.".to_string(), "featuring".to_string()],
+            separators: vec![";".to_string()],
+            parse_titles: true,
+        }
+    }
+}
+
+const ACOUSTID_CONFIG_FILE: &str = "acoustid.json";
+
+// Where fingerprints are looked up. Anything that answers like AcoustID's
+// /v2/lookup works, a local mirror included; `api_key` is the application key.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AcoustidConfig {
+    pub endpoint: String,
+    pub api_key: String,
+}
+
+impl Default for AcoustidConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://api.acoustid.org/v2/lookup".to_string(),
+            api_key: String::new(),
+        }
+    }
+}
+
+const NAMING_CONFIG_FILE: &str = "naming.json";
+
+// Patterns that tie paths under the music folder to tags, see utils::patterns.
+// Files imported without a title, artist or album get them from the first of
+// `read_patterns` their path matches, when tags_from_path is on.
+// `file_pattern` is the other way round, the path the organizer gives a track,
+// with every folder and file name cut to `max_name_length` bytes, extension
+// included, and never past the 255 most file systems allow.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NamingConfig {
+    pub tags_from_path: bool,
+    pub read_patterns: Vec<String>,
+    pub file_pattern: String,
+    pub max_name_length: usize,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        Self {
+            tags_from_path: true,
+            read_patterns: vec![
+                "%artist%/%album%/%track% - %title%".to_string(),
+                "%artist%/%album%/%track% %title%".to_string(),
+                "%artist%/%album%/%title%".to_string(),
+                "%artist% - %title%".to_string(),
+            ],
+            file_pattern: "%albumartist%/%year% - %album%/%track% - %title%".to_string(),
+            max_name_length: 120,
+        }
+    }
+}
+
+pub fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::rng(), 32)
+}
+
 #[allow(dead_code)]
 #[tauri::command]
 pub fn save_music_dir(app: AppHandle, path: String) -> Result<(), String> {
@@ -17,7 +191,12 @@ pub fn save_music_dir(app: AppHandle, path: String) -> Result<(), String> {
     let config_path = config_dir.join("settings.txt");
 
     // 3. Write the file
-    fs::write(config_path, path).map_err(|e| e.to_string())
+    fs::write(config_path, path).map_err(|e| e.to_string())?;
+
+    // 4. Follow the new folder, paths are read relative to it
+    crate::utils::patterns::use_naming_config(&app);
+    crate::watcher::watch_music_dir(&app);
+    Ok(())
 }
 
 
@@ -29,3 +208,99 @@ pub fn load_music_dir(app: AppHandle) -> Option<String> {
 
     fs::read_to_string(config_path).ok()
 }
+
+// JSON settings files live next to settings.txt, a missing or unreadable file means defaults
+fn load_json_config<T: serde::de::DeserializeOwned + Default>(app: &AppHandle, file_name: &str) -> T {
+    let config_path = match app.path().app_config_dir() {
+        Ok(dir) => dir.join(file_name),
+        Err(_) => return T::default(),
+    };
+
+    fs::read_to_string(config_path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_json_config<T: serde::Serialize>(app: &AppHandle, file_name: &str, config: &T) -> Result<(), String> {
+    let config_dir = app.path().app_config_dir()
+        .map_err(|_| "Could not find config directory".to_string())?;
+
+    if !config_dir.exists() {
+        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
+    }
+
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(config_dir.join(file_name), json).map_err(|e| e.to_string())
+}
+
+// The default carries a freshly generated token, so it's saved straight away,
+// otherwise every start would hand out a new one and unpair every device
+pub fn load_remote_config(app: &AppHandle) -> RemoteConfig {
+    let saved = app
+        .path()
+        .app_config_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(REMOTE_CONFIG_FILE)).ok())
+        .and_then(|json| serde_json::from_str(&json).ok());
+
+    saved.unwrap_or_else(|| {
+        let config = RemoteConfig::default();
+        if let Err(e) = save_remote_config(app, &config) {
+            log::warn!("Failed to save remote control config: {}", e);
+        }
+        config
+    })
+}
+
+pub fn save_remote_config(app: &AppHandle, config: &RemoteConfig) -> Result<(), String> {
+    save_json_config(app, REMOTE_CONFIG_FILE, config)
+}
+
+pub fn load_mpd_config(app: &AppHandle) -> MpdConfig {
+    load_json_config(app, MPD_CONFIG_FILE)
+}
+
+pub fn save_mpd_config(app: &AppHandle, config: &MpdConfig) -> Result<(), String> {
+    save_json_config(app, MPD_CONFIG_FILE, config)
+}
+
+pub fn load_subsonic_config(app: &AppHandle) -> SubsonicConfig {
+    load_json_config(app, SUBSONIC_CONFIG_FILE)
+}
+
+pub fn save_subsonic_config(app: &AppHandle, config: &SubsonicConfig) -> Result<(), String> {
+    save_json_config(app, SUBSONIC_CONFIG_FILE, config)
+}
+
+pub fn load_dlna_config(app: &AppHandle) -> DlnaConfig {
+    load_json_config(app, DLNA_CONFIG_FILE)
+}
+
+pub fn save_dlna_config(app: &AppHandle, config: &DlnaConfig) -> Result<(), String> {
+    save_json_config(app, DLNA_CONFIG_FILE, config)
+}
+
+pub fn load_artist_config(app: &AppHandle) -> ArtistConfig {
+    load_json_config(app, ARTIST_CONFIG_FILE)
+}
+
+pub fn save_artist_config(app: &AppHandle, config: &ArtistConfig) -> Result<(), String> {
+    save_json_config(app, ARTIST_CONFIG_FILE, config)
+}
+
+pub fn load_acoustid_config(app: &AppHandle) -> AcoustidConfig {
+    load_json_config(app, ACOUSTID_CONFIG_FILE)
+}
+
+pub fn save_acoustid_config(app: &AppHandle, config: &AcoustidConfig) -> Result<(), String> {
+    save_json_config(app, ACOUSTID_CONFIG_FILE, config)
+}
+
+pub fn load_naming_config(app: &AppHandle) -> NamingConfig {
+    load_json_config(app, NAMING_CONFIG_FILE)
+}
+
+pub fn save_naming_config(app: &AppHandle, config: &NamingConfig) -> Result<(), String> {
+    save_json_config(app, NAMING_CONFIG_FILE, config)
+}
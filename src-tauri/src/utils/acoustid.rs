@@ -0,0 +1,155 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::Value;
+use tauri::{AppHandle, State};
+
+use crate::{
+    db::LOCAL_SOURCE_ID,
+    models::{AcoustidMatch, AppState as Database},
+    user_config::{self, AcoustidConfig},
+    utils::fingerprint::{compute_fingerprint, encode_fingerprint, fingerprint_from_blob},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+// Asks the configured service which recordings sound like this fingerprint,
+// best match first
+pub async fn lookup(config: &AcoustidConfig, fingerprint: &[u32], duration_ms: i64) -> Result<Vec<AcoustidMatch>, String> {
+    let endpoint = config.endpoint.trim();
+    if endpoint.is_empty() {
+        return Err("No AcoustID endpoint has been set".to_string());
+    }
+
+    let duration = (duration_ms / 1000).to_string();
+    let fingerprint = encode_fingerprint(fingerprint);
+    let form = [
+        ("client", config.api_key.trim()),
+        ("duration", duration.as_str()),
+        ("fingerprint", fingerprint.as_str()),
+        ("meta", "recordings releasegroups"),
+        ("format", "json"),
+    ];
+
+    // errors come back as JSON too, with a 400
+    let text = reqwest::Client::new()
+        .post(endpoint)
+        .timeout(TIMEOUT)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", endpoint, e))?
+        .text()
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", endpoint, e))?;
+    let body: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid response from {}: {}", endpoint, e))?;
+
+    if body["status"] != "ok" {
+        return Err(format!(
+            "AcoustID error {}: {}",
+            body["error"]["code"],
+            body["error"]["message"].as_str().unwrap_or("unknown error")
+        ));
+    }
+    let mut matches: Vec<AcoustidMatch> = serde_json::from_value(body["results"].clone())
+        .map_err(|e| format!("Invalid response from {}: {}", endpoint, e))?;
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(matches)
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_acoustid_config(app: AppHandle) -> AcoustidConfig {
+    user_config::load_acoustid_config(&app)
+}
+
+#[allow(dead_code)]
+#[tauri::command]
+pub fn set_acoustid_config(app: AppHandle, config: AcoustidConfig) -> Result<AcoustidConfig, String> {
+    user_config::save_acoustid_config(&app, &config)?;
+    Ok(config)
+}
+
+// Uses the stored fingerprint, or fingerprints the file there and then if the
+// background job hasn't got to it yet
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn lookup_acoustid(
+    app: AppHandle,
+    state: State<'_, Database>,
+    track_id: i64,
+) -> Result<Vec<AcoustidMatch>, String> {
+    let track = state
+        .get_track(track_id)
+        .await?
+        .ok_or_else(|| format!("Track {} not found", track_id))?;
+
+    let fingerprint = match state.get_fingerprint(track_id).await? {
+        Some(blob) => fingerprint_from_blob(&blob),
+        None if track.source_id == LOCAL_SOURCE_ID => {
+            let path = track.file_path.clone();
+            tauri::async_runtime::spawn_blocking(move || compute_fingerprint(Path::new(&path)))
+                .await
+                .map_err(|e| e.to_string())??
+        }
+        None => return Err(format!("Track {} has no fingerprint", track_id)),
+    };
+
+    lookup(&user_config::load_acoustid_config(&app), &fingerprint, track.duration_ms).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_support::{serve, Response};
+
+    fn config(base: &str) -> AcoustidConfig {
+        AcoustidConfig {
+            endpoint: format!("{}/v2/lookup", base),
+            api_key: "key".to_string(),
+        }
+    }
+
+    #[test]
+    fn lookup_posts_the_fingerprint_and_sorts_matches() {
+        let sent = Arc::new(Mutex::new(String::new()));
+        let seen = sent.clone();
+        let base = serve(move |request| {
+            *seen.lock().unwrap() = String::from_utf8_lossy(&request.body).into_owned();
+            Response::ok(
+                r#"{"status": "ok", "results": [
+                    {"id": "weak", "score": 0.4},
+                    {"id": "strong", "score": 0.9, "recordings": [{"id": "rec", "title": "Song",
+                        "artists": [{"id": "art", "name": "Artist"}],
+                        "releasegroups": [{"id": "rg", "title": "Album", "type": "Album"}]}]}
+                ]}"#,
+            )
+        });
+
+        let matches = tauri::async_runtime::block_on(lookup(&config(&base), &[1, 7], 185_500)).unwrap();
+
+        assert_eq!(matches.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ["strong", "weak"]);
+        assert_eq!(matches[0].recordings[0].release_groups[0].title.as_deref(), Some("Album"));
+        let form = sent.lock().unwrap().clone();
+        assert!(form.contains("client=key"), "{}", form);
+        assert!(form.contains("duration=185"), "{}", form);
+        assert!(form.contains(&format!("fingerprint={}", encode_fingerprint(&[1, 7]))), "{}", form);
+    }
+
+    #[test]
+    fn lookup_reports_service_errors() {
+        // AcoustID answers errors with a 400 and a JSON body
+        let base = serve(|_| Response {
+            status: 400,
+            ..Response::ok(r#"{"status": "error", "error": {"code": 4, "message": "invalid API key"}}"#)
+        });
+
+        let error = tauri::async_runtime::block_on(lookup(&config(&base), &[1], 1000)).unwrap_err();
+
+        assert!(error.contains("invalid API key"), "{}", error);
+    }
+}
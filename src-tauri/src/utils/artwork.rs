@@ -0,0 +1,102 @@
+use std::io::Cursor;
+
+use base64::{engine::general_purpose, Engine as _};
+use image::{DynamicImage, ImageFormat};
+
+use crate::models::{AppState as Database, Artwork, Thumbnails};
+
+const SMALL: u32 = 64;
+const MEDIUM: u32 = 256;
+// what the tag reader used to store for tracks without a picture
+const DEFAULT_COVER: &[u8] = include_bytes!("../../../public/default-cover.png");
+// rows moved per transaction by migrate_thumbnails
+const MIGRATE_BATCH: i64 = 100;
+
+// Hashes a picture and settles its mime type. Thumbnails are left to
+// store_artwork, which only needs them for a picture it hasn't seen yet.
+pub fn prepare_artwork(data: Vec<u8>, mime: Option<String>) -> Option<Artwork> {
+    if data.is_empty() {
+        return None;
+    }
+
+    let hash = format!("{:x}", md5::compute(&data));
+    let mime = mime
+        .filter(|mime| mime.starts_with("image/"))
+        .or_else(|| image::guess_format(&data).ok().map(|format| format.to_mime_type().to_string()))
+        .unwrap_or_else(|| "image/jpeg".to_string());
+
+    Some(Artwork { hash, mime, data })
+}
+
+// Decodes a picture for its size and thumbnails. Slow enough that this belongs
+// on a blocking thread.
+pub fn make_thumbnails(data: &[u8]) -> Thumbnails {
+    // a picture we can't decode is still worth keeping, it just gets no thumbnails
+    let Ok(image) = image::load_from_memory(data) else {
+        return Thumbnails::default();
+    };
+    let thumbnail = |size: u32| {
+        if image.width() <= size && image.height() <= size {
+            return None;
+        }
+        encode_jpeg(&image.thumbnail(size, size))
+    };
+
+    Thumbnails {
+        width: Some(image.width() as i64),
+        height: Some(image.height() as i64),
+        small: thumbnail(SMALL),
+        medium: thumbnail(MEDIUM),
+    }
+}
+
+// for artwork that arrives over IPC, see add_track
+pub fn prepare_base64_artwork(data: &str, mime: Option<String>) -> Option<Artwork> {
+    let bytes = general_purpose::STANDARD.decode(data).ok()?;
+    prepare_artwork(bytes, mime)
+}
+
+fn encode_jpeg(image: &DynamicImage) -> Option<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    // JPEG has no alpha channel
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_to(&mut out, ImageFormat::Jpeg)
+        .ok()?;
+    Some(out.into_inner())
+}
+
+// Moves the base64 thumbnails tracks were imported with into the artwork
+// table, a batch at a time. The placeholder cover some rows got instead of a
+// picture is dropped rather than stored.
+pub async fn migrate_thumbnails(db: &Database) -> Result<usize, String> {
+    let mut moved = 0;
+    loop {
+        let rows = db.get_legacy_thumbnails(MIGRATE_BATCH).await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let prepared = tauri::async_runtime::spawn_blocking(move || {
+            rows.into_iter()
+                .map(|(id, data, mime)| {
+                    let artwork = general_purpose::STANDARD
+                        .decode(data)
+                        .ok()
+                        .filter(|bytes| bytes != DEFAULT_COVER)
+                        .and_then(|bytes| prepare_artwork(bytes, mime));
+                    (id, artwork)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| format!("Failed to read thumbnails: {}", e))?;
+
+        moved += prepared.len();
+        db.set_track_artwork(prepared).await?;
+    }
+
+    if moved > 0 {
+        db.fill_album_artwork().await?;
+    }
+    Ok(moved)
+}
@@ -0,0 +1,537 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::models::ExtractedChapter;
+
+// sizes and counts come straight from the file, so everything is clamped to what the
+// file can actually hold, and chapter lists to something no real book gets near
+const MAX_CHAPTERS: usize = 10_000;
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+const MAX_TEXT_SAMPLE_SIZE: u64 = 64 * 1024;
+
+// lofty doesn't surface chapters, so they are read straight from the container:
+//  - mp3: ID3v2 CHAP frames
+//  - m4b/m4a/mp4: Nero `chpl` atom, falling back to a QuickTime chapter text track
+// `duration_ms` closes off the last chapter, since neither format stores its end reliably.
+pub fn read_chapters(path: &Path, duration_ms: i64) -> Result<Vec<ExtractedChapter>, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+
+    let mut magic = [0u8; 8];
+    let read = file.read(&mut magic).map_err(|e| format!("Failed to read file: {}", e))?;
+    file.seek(SeekFrom::Start(0))
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    let mut chapters = if read >= 3 && &magic[..3] == b"ID3" {
+        read_id3_chapters(&mut file)?
+    } else if read >= 8 && &magic[4..8] == b"ftyp" {
+        read_mp4_chapters(&mut file)?
+    } else {
+        Vec::new()
+    };
+
+    chapters.sort_by_key(|c| c.start_ms);
+    close_chapter_ends(&mut chapters, duration_ms);
+    Ok(chapters)
+}
+
+fn close_chapter_ends(chapters: &mut [ExtractedChapter], duration_ms: i64) {
+    for i in 0..chapters.len() {
+        let next_start = chapters.get(i + 1).map(|c| c.start_ms).unwrap_or(duration_ms);
+        let chapter = &mut chapters[i];
+        if chapter.end_ms <= chapter.start_ms || chapter.end_ms > next_start {
+            chapter.end_ms = next_start.max(chapter.start_ms);
+        }
+        if chapter.title.trim().is_empty() {
+            chapter.title = format!("Chapter {}", i + 1);
+        }
+    }
+}
+
+// ── ID3v2 ────────────────────────────────────────────────────────────────────
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0usize, |acc, b| (acc << 7) | (*b as usize & 0x7f))
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_be_bytes(buf)
+}
+
+fn read_id3_chapters(file: &mut File) -> Result<Vec<ExtractedChapter>, String> {
+    let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header)
+        .map_err(|e| format!("Failed to read ID3 header: {}", e))?;
+
+    let major = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]).min(file_len.saturating_sub(10) as usize);
+
+    // CHAP only exists from v2.3 on, and whole-tag unsynchronisation is too rare to bother with
+    if !(3..=4).contains(&major) || flags & 0x80 != 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut tag = vec![0u8; size];
+    file.read_exact(&mut tag)
+        .map_err(|e| format!("Failed to read ID3 tag: {}", e))?;
+
+    let mut offset = 0;
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        // extended header: v2.4 size includes itself, v2.3 doesn't
+        offset = if major == 4 {
+            syncsafe(&tag[0..4])
+        } else {
+            be_u32(&tag[0..4]) as usize + 4
+        };
+    }
+
+    let mut chapters = Vec::new();
+    for (id, body) in id3_frames(&tag[offset.min(tag.len())..], major) {
+        if id == b"CHAP" {
+            if let Some(chapter) = parse_chap_frame(body, major) {
+                chapters.push(chapter);
+            }
+        }
+    }
+
+    Ok(chapters)
+}
+
+fn id3_frames(mut data: &[u8], major: u8) -> Vec<(&[u8], &[u8])> {
+    let mut frames = Vec::new();
+    while data.len() >= 10 && data[0] != 0 {
+        let size = if major == 4 {
+            syncsafe(&data[4..8])
+        } else {
+            be_u32(&data[4..8]) as usize
+        };
+        if 10 + size > data.len() {
+            break;
+        }
+        frames.push((&data[0..4], &data[10..10 + size]));
+        data = &data[10 + size..];
+    }
+    frames
+}
+
+// CHAP: element id \0, start ms, end ms, start offset, end offset, then embedded frames
+fn parse_chap_frame(body: &[u8], major: u8) -> Option<ExtractedChapter> {
+    let id_end = body.iter().position(|b| *b == 0)?;
+    let times = body.get(id_end + 1..id_end + 17)?;
+    let start_ms = be_u32(&times[0..4]) as i64;
+    let end_ms = be_u32(&times[4..8]) as i64;
+
+    let title = id3_frames(&body[id_end + 17..], major)
+        .into_iter()
+        .find(|(id, _)| *id == b"TIT2")
+        .map(|(_, text)| decode_id3_text(text))
+        .unwrap_or_default();
+
+    Some(ExtractedChapter { title, start_ms, end_ms })
+}
+
+fn decode_id3_text(data: &[u8]) -> String {
+    let Some((encoding, text)) = data.split_first() else {
+        return String::new();
+    };
+
+    let decoded = match encoding {
+        // latin-1 maps 1:1 onto the first 256 code points
+        0 => text.iter().map(|b| *b as char).collect(),
+        1 | 2 => decode_utf16(text, *encoding == 2),
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+
+    decoded.trim_end_matches('\0').trim().to_string()
+}
+
+fn decode_utf16(mut data: &[u8], mut big_endian: bool) -> String {
+    if data.len() >= 2 {
+        match (data[0], data[1]) {
+            (0xFF, 0xFE) => {
+                big_endian = false;
+                data = &data[2..];
+            }
+            (0xFE, 0xFF) => {
+                big_endian = true;
+                data = &data[2..];
+            }
+            _ => {}
+        }
+    }
+
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+// ── MP4 ──────────────────────────────────────────────────────────────────────
+
+// Walks the top level atoms and loads only `moov` into memory, mdat is skipped over
+fn read_mp4_chapters(file: &mut File) -> Result<Vec<ExtractedChapter>, String> {
+    let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut pos = 0u64;
+    let mut moov = None;
+
+    while pos + 8 <= file_len {
+        file.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8]).map_err(|e| e.to_string())?;
+
+        let mut size = be_u32(&header[0..4]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut header[8..16]).map_err(|e| e.to_string())?;
+            size = be_u64(&header[8..16]);
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - pos;
+        }
+        if size < header_len || size > file_len - pos {
+            break;
+        }
+
+        if &header[4..8] == b"moov" {
+            if size - header_len > MAX_MOOV_SIZE {
+                break;
+            }
+            let mut body = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut body).map_err(|e| e.to_string())?;
+            moov = Some(body);
+            break;
+        }
+        pos += size;
+    }
+
+    let Some(moov) = moov else {
+        return Ok(Vec::new());
+    };
+
+    if let Some(chpl) = find_atom_path(&moov, &[b"udta", b"chpl"]) {
+        let chapters = parse_chpl(chpl);
+        if !chapters.is_empty() {
+            return Ok(chapters);
+        }
+    }
+
+    read_quicktime_chapter_track(file, &moov)
+}
+
+// Iterates the child atoms of a container body
+fn atoms(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut out = Vec::new();
+    while data.len() >= 8 {
+        let mut size = be_u32(&data[0..4]) as usize;
+        let mut header_len = 8;
+        if size == 1 && data.len() >= 16 {
+            size = be_u64(&data[8..16]) as usize;
+            header_len = 16;
+        } else if size == 0 {
+            size = data.len();
+        }
+        if size < header_len || size > data.len() {
+            break;
+        }
+        out.push((&data[4..8], &data[header_len..size]));
+        data = &data[size..];
+    }
+    out
+}
+
+fn find_atom<'a>(data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data)
+        .into_iter()
+        .find(|(kind, _)| *kind == name)
+        .map(|(_, body)| body)
+}
+
+fn find_atom_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |body, name| find_atom(body, name))
+}
+
+// Nero chapters: version, flags, [reserved], count, then (start in 100ns units, title)
+fn parse_chpl(data: &[u8]) -> Vec<ExtractedChapter> {
+    let mut chapters = Vec::new();
+    let Some(&version) = data.first() else {
+        return chapters;
+    };
+
+    // count is a single byte, so this loop is bounded already
+    let mut i = if version == 1 { 8 } else { 4 };
+    let Some(&count) = data.get(i) else {
+        return chapters;
+    };
+    i += 1;
+
+    for _ in 0..count {
+        let Some(start) = data.get(i..i + 8) else { break };
+        let Some(&len) = data.get(i + 8) else { break };
+        let Some(title) = data.get(i + 9..i + 9 + len as usize) else { break };
+
+        chapters.push(ExtractedChapter {
+            title: String::from_utf8_lossy(title).into_owned(),
+            start_ms: (be_u64(start) / 10_000) as i64,
+            end_ms: 0,
+        });
+        i += 9 + len as usize;
+    }
+
+    chapters
+}
+
+fn track_id(trak: &[u8]) -> Option<u32> {
+    let tkhd = find_atom(trak, b"tkhd")?;
+    // version 1 uses 64-bit creation/modification times
+    let offset = if tkhd.first() == Some(&1) { 20 } else { 12 };
+    tkhd.get(offset..offset + 4).map(be_u32)
+}
+
+// QuickTime chapters live in a text track referenced from another track's tref/chap
+fn read_quicktime_chapter_track(file: &mut File, moov: &[u8]) -> Result<Vec<ExtractedChapter>, String> {
+    let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+    let traks: Vec<&[u8]> = atoms(moov)
+        .into_iter()
+        .filter(|(kind, _)| *kind == b"trak")
+        .map(|(_, body)| body)
+        .collect();
+
+    let chapter_ids: Vec<u32> = traks
+        .iter()
+        .filter_map(|trak| find_atom_path(trak, &[b"tref", b"chap"]))
+        .flat_map(|chap| chap.chunks_exact(4).map(be_u32).collect::<Vec<_>>())
+        .collect();
+
+    let Some(trak) = traks
+        .iter()
+        .find(|trak| track_id(trak).is_some_and(|id| chapter_ids.contains(&id)))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let (Some(mdhd), Some(stbl)) = (
+        find_atom_path(trak, &[b"mdia", b"mdhd"]),
+        find_atom_path(trak, &[b"mdia", b"minf", b"stbl"]),
+    ) else {
+        return Ok(Vec::new());
+    };
+
+    let timescale = if mdhd.first() == Some(&1) {
+        mdhd.get(20..24).map(be_u32)
+    } else {
+        mdhd.get(12..16).map(be_u32)
+    }
+    .filter(|t| *t > 0)
+    .unwrap_or(1000) as u64;
+
+    // sample start times from stts
+    let mut starts = Vec::new();
+    if let Some(stts) = find_atom(stbl, b"stts") {
+        let mut time = 0u64;
+        'entries: for entry in stts.get(8..).unwrap_or_default().chunks_exact(8) {
+            for _ in 0..be_u32(&entry[0..4]) {
+                if starts.len() >= MAX_CHAPTERS {
+                    break 'entries;
+                }
+                starts.push(time);
+                time = time.saturating_add(be_u32(&entry[4..8]) as u64);
+            }
+        }
+    }
+
+    let sizes = sample_sizes(stbl);
+    let offsets = sample_offsets(stbl, &sizes);
+
+    let mut chapters = Vec::new();
+    for (index, offset) in offsets.iter().enumerate() {
+        let (Some(start), Some(size)) = (starts.get(index), sizes.get(index)) else {
+            break;
+        };
+
+        // a sample can't run past the end of the file, titles are never this long anyway
+        let size = (*size as u64)
+            .min(file_len.saturating_sub(*offset))
+            .min(MAX_TEXT_SAMPLE_SIZE);
+        let mut sample = vec![0u8; size as usize];
+        file.seek(SeekFrom::Start(*offset)).map_err(|e| e.to_string())?;
+        if file.read_exact(&mut sample).is_err() || sample.len() < 2 {
+            continue;
+        }
+
+        // text samples: u16 length, then UTF-8 (or UTF-16 with a BOM)
+        let len = u16::from_be_bytes([sample[0], sample[1]]) as usize;
+        let text = sample.get(2..2 + len).unwrap_or_default();
+        let title = if text.starts_with(&[0xFE, 0xFF]) || text.starts_with(&[0xFF, 0xFE]) {
+            decode_utf16(text, true)
+        } else {
+            String::from_utf8_lossy(text).into_owned()
+        };
+
+        chapters.push(ExtractedChapter {
+            title,
+            start_ms: (start.saturating_mul(1000) / timescale) as i64,
+            end_ms: 0,
+        });
+    }
+
+    Ok(chapters)
+}
+
+fn sample_sizes(stbl: &[u8]) -> Vec<u32> {
+    let Some(stsz) = find_atom(stbl, b"stsz").filter(|s| s.len() >= 12) else {
+        return Vec::new();
+    };
+    let fixed = be_u32(&stsz[4..8]);
+    let count = (be_u32(&stsz[8..12]) as usize).min(MAX_CHAPTERS);
+    if fixed != 0 {
+        return vec![fixed; count];
+    }
+    stsz[12..].chunks_exact(4).take(count).map(be_u32).collect()
+}
+
+// Resolves each sample's file offset through stsc (samples per chunk) and stco/co64
+fn sample_offsets(stbl: &[u8], sizes: &[u32]) -> Vec<u64> {
+    let chunk_offsets: Vec<u64> = if let Some(stco) = find_atom(stbl, b"stco") {
+        stco.get(8..).unwrap_or_default().chunks_exact(4).map(|c| be_u32(c) as u64).collect()
+    } else if let Some(co64) = find_atom(stbl, b"co64") {
+        co64.get(8..).unwrap_or_default().chunks_exact(8).map(be_u64).collect()
+    } else {
+        return Vec::new();
+    };
+
+    let stsc: Vec<(usize, usize)> = find_atom(stbl, b"stsc")
+        .and_then(|s| s.get(8..))
+        .unwrap_or_default()
+        .chunks_exact(12)
+        .map(|e| (be_u32(&e[0..4]) as usize, be_u32(&e[4..8]) as usize))
+        .collect();
+
+    let mut offsets = Vec::new();
+    let mut sample = 0;
+    for (chunk_index, chunk_offset) in chunk_offsets.iter().enumerate() {
+        // stsc chunk numbers are 1-based
+        let per_chunk = stsc
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= chunk_index + 1)
+            .map(|(_, n)| *n)
+            .unwrap_or(1);
+
+        let mut offset = *chunk_offset;
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.get(sample) else {
+                return offsets;
+            };
+            offsets.push(offset);
+            offset = offset.saturating_add(*size as u64);
+            sample += 1;
+        }
+    }
+
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn full_atom(kind: &[u8; 4], fields: &[u32]) -> Vec<u8> {
+        let body: Vec<u8> = fields.iter().flat_map(|f| f.to_be_bytes()).collect();
+        atom(kind, &body)
+    }
+
+    fn read(name: &str, bytes: &[u8]) -> Vec<ExtractedChapter> {
+        let dir = temp_dir(name);
+        let path = dir.join("book.m4b");
+        std::fs::write(&path, bytes).unwrap();
+        let chapters = read_chapters(&path, 60_000).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        chapters
+    }
+
+    #[test]
+    fn moov_larger_than_the_file_is_ignored() {
+        let mut file = atom(b"ftyp", b"M4B \0\0\0\0");
+        file.extend_from_slice(&u32::MAX.to_be_bytes());
+        file.extend_from_slice(b"moov");
+        file.extend_from_slice(&[0u8; 32]);
+
+        assert!(read("chapters-moov", &file).is_empty());
+    }
+
+    #[test]
+    fn sample_counts_and_sizes_are_clamped_to_the_file() {
+        let ftyp = atom(b"ftyp", b"M4B \0\0\0\0");
+
+        // audio track 1 points at the text track 2 through tref/chap
+        let audio = atom(
+            b"trak",
+            &[full_atom(b"tkhd", &[0, 0, 0, 1]), atom(b"tref", &full_atom(b"chap", &[2]))].concat(),
+        );
+
+        let build = |text_offset: u32| {
+            let stbl = atom(
+                b"stbl",
+                &[
+                    // one entry claiming four billion samples
+                    full_atom(b"stts", &[0, 1, u32::MAX, 1000]),
+                    // every sample claims 16 MiB, four billion of them
+                    full_atom(b"stsz", &[0, 16 * 1024 * 1024, u32::MAX]),
+                    full_atom(b"stco", &[0, 1, text_offset]),
+                ]
+                .concat(),
+            );
+            let text = atom(
+                b"trak",
+                &[
+                    full_atom(b"tkhd", &[0, 0, 0, 2]),
+                    atom(b"mdia", &[full_atom(b"mdhd", &[0, 0, 0, 1000]), atom(b"minf", &stbl)].concat()),
+                ]
+                .concat(),
+            );
+            atom(b"moov", &[audio.clone(), text].concat())
+        };
+
+        let text_offset = (ftyp.len() + build(0).len() + 8) as u32;
+        let mut sample = 5u16.to_be_bytes().to_vec();
+        sample.extend_from_slice(b"Intro");
+
+        let file = [ftyp.clone(), build(text_offset), atom(b"mdat", &sample)].concat();
+        let chapters = read("chapters-stts", &file);
+
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].title, "Intro");
+        assert_eq!((chapters[0].start_ms, chapters[0].end_ms), (0, 60_000));
+    }
+
+    #[test]
+    fn id3_tag_size_is_clamped_to_the_file() {
+        // v2.4 header claiming a 256 MiB tag in front of a single CHAP frame
+        let title = [&b"TIT2"[..], &[0, 0, 0, 6, 0, 0, 3], b"Intro"].concat();
+        let chap_body = [&b"ch0\0"[..], &[0, 0, 0, 0, 0, 0, 0x03, 0xE8], &[0xFF; 8], &title].concat();
+        let frame = [&b"CHAP"[..], &[0, 0, 0, chap_body.len() as u8, 0, 0], &chap_body].concat();
+        let file = [&b"ID3"[..], &[4, 0, 0, 0x7F, 0x7F, 0x7F, 0x7F], &frame].concat();
+
+        let chapters = read("chapters-id3", &file);
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].title, "Intro");
+        assert_eq!(chapters[0].end_ms, 1000);
+    }
+}
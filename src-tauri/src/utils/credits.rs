@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+use tauri::{AppHandle, State};
+
+use crate::{
+    models::{AppState as Database, ArtistRole, TrackCredit},
+    user_config::{self, ArtistConfig},
+};
+
+// Read by the tag reader on every file, which has no AppHandle to load it with.
+// Set from setup() and whenever the user changes it.
+static CONFIG: RwLock<Option<ArtistConfig>> = RwLock::new(None);
+
+pub fn use_artist_config(config: ArtistConfig) {
+    *CONFIG.write().unwrap() = Some(config);
+}
+
+fn artist_config() -> ArtistConfig {
+    CONFIG.read().unwrap().clone().unwrap_or_default()
+}
+
+// Everyone on a track besides the artist it's filed under, which is artists[0]:
+// the rest of a multi-value artist tag, names split out of the artist tag and
+// the title, then remixers and composers
+pub fn track_credits(artists: &[String], title: &str, remixers: &[String], composers: &[String]) -> Vec<TrackCredit> {
+    credits_with(&artist_config(), artists, title, remixers, composers)
+}
+
+fn credits_with(
+    config: &ArtistConfig,
+    artists: &[String],
+    title: &str,
+    remixers: &[String],
+    composers: &[String],
+) -> Vec<TrackCredit> {
+    let mut credits = Vec::new();
+
+    for (i, artist) in artists.iter().enumerate() {
+        if !config.split_names {
+            if i > 0 {
+                credits.push(credit(artist, ArtistRole::Primary));
+            }
+            continue;
+        }
+
+        let (main, featured) = split_featuring(artist, &config.featuring);
+        let names = split_names(main, &config.separators);
+        // the artist as tagged is already on the track, its parts only count if it had any
+        if i > 0 || names.len() > 1 || featured.is_some() {
+            credits.extend(names.iter().map(|name| credit(name, ArtistRole::Primary)));
+        }
+        if let Some(featured) = featured {
+            credits.extend(split_featured(featured, &config.separators).map(|name| credit(name, ArtistRole::Featured)));
+        }
+    }
+
+    if config.split_names && config.parse_titles {
+        if let (_, Some(featured)) = split_featuring(title, &config.featuring) {
+            credits.extend(split_featured(featured, &config.separators).map(|name| credit(name, ArtistRole::Featured)));
+        }
+    }
+
+    for (names, role) in [(remixers, ArtistRole::Remixer), (composers, ArtistRole::Composer)] {
+        for name in names {
+            if config.split_names {
+                credits.extend(split_names(name, &config.separators).iter().map(|name| credit(name, role)));
+            } else {
+                credits.push(credit(name, role));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    credits.retain(|c| !c.name.is_empty() && seen.insert((c.name.to_lowercase(), c.role)));
+    credits
+}
+
+fn credit(name: &str, role: ArtistRole) -> TrackCredit {
+    TrackCredit {
+        name: name.trim().to_string(),
+        role,
+    }
+}
+
+// "A feat. B" -> ("A", Some("B")), "Song (ft. B)" -> ("Song", Some("B")).
+// Markers only count as whole words, "Defeat" isn't a featuring credit.
+fn split_featuring<'a>(text: &'a str, markers: &[String]) -> (&'a str, Option<&'a str>) {
+    // ASCII lowercasing keeps byte offsets, so positions carry over to text
+    let lower = text.to_ascii_lowercase();
+    let found = markers
+        .iter()
+        .map(|marker| marker.trim().to_ascii_lowercase())
+        .filter(|marker| !marker.is_empty())
+        .filter_map(|marker| {
+            lower
+                .match_indices(marker.as_str())
+                .map(|(start, _)| start)
+                .find(|&start| {
+                    let before = lower[..start].chars().next_back();
+                    let after = lower[start + marker.len()..].chars().next();
+                    before.is_none_or(|c| c.is_whitespace() || c == '(' || c == '[')
+                        && after.is_none_or(|c| c.is_whitespace())
+                })
+                .map(|start| (start, marker.len()))
+        })
+        .min_by_key(|(start, _)| *start);
+
+    let Some((start, len)) = found else {
+        return (text, None);
+    };
+    let main = text[..start].trim_end().trim_end_matches(['(', '[']).trim();
+    let featured = text[start + len..].split([')', ']']).next().unwrap_or_default().trim();
+    (main, Some(featured).filter(|featured| !featured.is_empty()))
+}
+
+fn split_names<'a>(text: &'a str, separators: &[String]) -> Vec<&'a str> {
+    let mut names = vec![text];
+    for separator in separators.iter().filter(|separator| !separator.trim().is_empty()) {
+        names = names.into_iter().flat_map(|name| name.split(separator.as_str())).collect();
+    }
+    names.into_iter().map(str::trim).filter(|name| !name.is_empty()).collect()
+}
+
+// "feat. B, C & D" lists people, so "," and "&" split there even when they
+// aren't separators
+fn split_featured<'a>(text: &'a str, separators: &[String]) -> impl Iterator<Item = &'a str> {
+    let mut separators = separators.to_vec();
+    separators.extend([",".to_string(), " & ".to_string()]);
+    split_names(text, &separators).into_iter()
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_artist_config(app: AppHandle) -> ArtistConfig {
+    user_config::load_artist_config(&app)
+}
+
+// Changing how names are split means reading every file again, which the tag
+// backfill does in the background
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn set_artist_config(
+    app: AppHandle,
+    state: State<'_, Database>,
+    config: ArtistConfig,
+) -> Result<ArtistConfig, String> {
+    user_config::save_artist_config(&app, &config)?;
+    use_artist_config(config.clone());
+
+    state.mark_tags_stale().await?;
+    crate::scanner::start_tag_backfill(app);
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // artists, title and the credits expected
+    type Case<'a> = (&'a [&'a str], &'a str, &'a [(&'a str, ArtistRole)]);
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn credits(config: &ArtistConfig, artists: &[&str], title: &str) -> Vec<(String, ArtistRole)> {
+        credits_with(config, &names(artists), title, &[], &[])
+            .into_iter()
+            .map(|credit| (credit.name, credit.role))
+            .collect()
+    }
+
+    #[test]
+    fn featured_artists_by_default() {
+        use ArtistRole::{Featured, Primary};
+        let config = ArtistConfig::default();
+        let cases: &[Case] = &[
+            (&["Artist"], "Song", &[]),
+            (&["Artist feat. Guest"], "Song", &[("Artist", Primary), ("Guest", Featured)]),
+            (&["Artist FT. Guest"], "Song", &[("Artist", Primary), ("Guest", Featured)]),
+            (&["Artist featuring Guest"], "Song", &[("Artist", Primary), ("Guest", Featured)]),
+            (&["Artist"], "Song (feat. Guest)", &[("Guest", Featured)]),
+            (&["Artist"], "Song [ft. Guest] (Remix)", &[("Guest", Featured)]),
+            (&["Artist"], "Song feat. One, Two & Three", &[("One", Featured), ("Two", Featured), ("Three", Featured)]),
+            (&["Artist; Other"], "Song", &[("Artist", Primary), ("Other", Primary)]),
+            (&["Artist", "Other"], "Song", &[("Other", Primary)]),
+            // the same guest in the tag and the title is credited once
+            (&["Artist feat. Guest"], "Song (feat. guest)", &[("Artist", Primary), ("Guest", Featured)]),
+            // markers are whole words
+            (&["Defeat."], "Aft. Midnight", &[]),
+            (&["Artist"], "Loft.", &[]),
+        ];
+        for (artists, title, expected) in cases {
+            let expected: Vec<_> = expected.iter().map(|(name, role)| (name.to_string(), *role)).collect();
+            assert_eq!(credits(&config, artists, title), expected, "{:?} {}", artists, title);
+        }
+    }
+
+    #[test]
+    fn ampersands_are_part_of_names() {
+        use ArtistRole::{Featured, Primary};
+        let config = ArtistConfig::default();
+        let cases: &[Case] = &[
+            (&["Simon & Garfunkel"], "The Boxer", &[]),
+            (&["Earth, Wind & Fire"], "September", &[]),
+            (&["Mumford & Sons feat. Guest"], "Song", &[("Mumford & Sons", Primary), ("Guest", Featured)]),
+            (&["Artist", "Simon & Garfunkel"], "Song", &[("Simon & Garfunkel", Primary)]),
+        ];
+        for (artists, title, expected) in cases {
+            let expected: Vec<_> = expected.iter().map(|(name, role)| (name.to_string(), *role)).collect();
+            assert_eq!(credits(&config, artists, title), expected, "{:?} {}", artists, title);
+        }
+    }
+
+    #[test]
+    fn separators_and_markers_are_configurable() {
+        use ArtistRole::{Featured, Primary};
+        let config = ArtistConfig {
+            split_names: true,
+            featuring: names(&["with"]),
+            separators: names(&[" & ", " x ", "/"]),
+            parse_titles: false,
+        };
+        let cases: &[Case] = &[
+            (&["Simon & Garfunkel"], "Song", &[("Simon", Primary), ("Garfunkel", Primary)]),
+            (&["One x Two/Three"], "Song", &[("One", Primary), ("Two", Primary), ("Three", Primary)]),
+            // separators need their spaces, "&" inside a word isn't one
+            (&["AT&T Band", "Rock&Roll"], "Song", &[("Rock&Roll", Primary)]),
+            (&["Artist with Guest"], "Song", &[("Artist", Primary), ("Guest", Featured)]),
+            // "feat." isn't a marker any more and titles aren't read
+            (&["Artist feat. Guest"], "Song (with Other)", &[]),
+        ];
+        for (artists, title, expected) in cases {
+            let expected: Vec<_> = expected.iter().map(|(name, role)| (name.to_string(), *role)).collect();
+            assert_eq!(credits(&config, artists, title), expected, "{:?} {}", artists, title);
+        }
+
+        let unsplit = ArtistConfig { split_names: false, ..config };
+        assert_eq!(
+            credits(&unsplit, &["One & Two feat. Guest", "Three & Four"], "Song (with Other)"),
+            [("Three & Four".to_string(), Primary)]
+        );
+    }
+
+    #[test]
+    fn remixers_and_composers_are_credited() {
+        let config = ArtistConfig::default();
+        let composers = names(&["A; B", "Artist"]);
+        let credited: Vec<_> = credits_with(&config, &names(&["Artist"]), "Song", &names(&["DJ"]), &composers)
+            .into_iter()
+            .map(|credit| (credit.name, credit.role))
+            .collect();
+        assert_eq!(
+            credited,
+            [
+                ("DJ".to_string(), ArtistRole::Remixer),
+                ("A".to_string(), ArtistRole::Composer),
+                ("B".to_string(), ArtistRole::Composer),
+                ("Artist".to_string(), ArtistRole::Composer),
+            ]
+        );
+    }
+}
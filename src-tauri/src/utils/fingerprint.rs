@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::path::Path;
+
+use base64::{engine::general_purpose, Engine as _};
+use rustfft::{num_complex::Complex, FftPlanner};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+// Chromaprint's default algorithm (TEST2), so fingerprints can be looked up on
+// AcoustID. Audio is mixed to mono at 11025 Hz, cut into overlapping frames,
+// turned into 12 pitch classes and run through 16 classifiers, each giving 2
+// bits of a 32-bit value per frame.
+const ALGORITHM: u8 = 1;
+const SAMPLE_RATE: u32 = 11025;
+const FRAME_SIZE: usize = 4096;
+const FRAME_STEP: usize = FRAME_SIZE / 3;
+const MIN_FREQ: f64 = 28.0;
+const MAX_FREQ: f64 = 3520.0;
+const NOTES: usize = 12;
+const CHROMA_FILTER: [f64; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
+// like fpcalc, only the start of a track is fingerprinted
+const MAX_SECONDS: usize = 120;
+
+// (filter type, y, height, width) and the quantizer's thresholds
+type Classifier = ((u8, usize, usize, usize), [f64; 3]);
+const CLASSIFIERS: [Classifier; 16] = [
+    ((0, 4, 3, 15), [1.98215, 2.35817, 2.63523]),
+    ((4, 4, 6, 15), [-1.03809, -0.651211, -0.282167]),
+    ((1, 0, 4, 16), [-0.298702, 0.119262, 0.558497]),
+    ((3, 8, 2, 12), [-0.105439, 0.0153946, 0.135898]),
+    ((3, 4, 4, 8), [-0.142891, 0.0258736, 0.200632]),
+    ((4, 0, 3, 5), [-0.826319, -0.590612, -0.368214]),
+    ((1, 2, 2, 9), [-0.557409, -0.233035, 0.0534525]),
+    ((2, 7, 3, 4), [-0.0646826, 0.00620476, 0.0784847]),
+    ((2, 6, 2, 16), [-0.192387, -0.029699, 0.215855]),
+    ((2, 1, 3, 2), [-0.0397818, -0.00568076, 0.0292026]),
+    ((5, 10, 1, 15), [-0.53823, -0.369934, -0.190235]),
+    ((3, 6, 2, 10), [-0.124877, 0.0296483, 0.139239]),
+    ((2, 1, 1, 14), [-0.101475, 0.0225617, 0.231971]),
+    ((3, 5, 6, 4), [-0.0799915, -0.00729616, 0.063262]),
+    ((1, 9, 2, 12), [-0.272556, 0.019424, 0.302559]),
+    ((3, 4, 2, 14), [-0.164292, -0.0321188, 0.0846339]),
+];
+const MAX_FILTER_WIDTH: usize = 16;
+
+// taps on each side of the resampling filter, at the output rate
+const RESAMPLE_TAPS: usize = 8;
+const RESAMPLE_PHASES: usize = 256;
+
+// Two fingerprints are the same recording when this share of their bits agree
+pub const MIN_SIMILARITY: f64 = 0.85;
+// how far apart two copies may start, in frames (about 10 s)
+const MAX_ALIGNMENT: i64 = 80;
+
+pub fn compute_fingerprint(path: &Path) -> Result<Vec<u32>, String> {
+    let (samples, rate) = decode(path)?;
+    let samples = resample(&samples, rate);
+    Ok(fingerprint_samples(&samples))
+}
+
+// Mono samples from the start of the file, at its own rate
+fn decode(path: &Path) -> Result<(Vec<f32>, u32), String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| format!("Unsupported file: {}", e))?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| "No audio track".to_string())?;
+    let track_id = track.id;
+    let rate = track.codec_params.sample_rate.ok_or_else(|| "Unknown sample rate".to_string())?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| format!("Unsupported codec: {}", e))?;
+
+    let limit = rate as usize * MAX_SECONDS;
+    let mut samples = Vec::with_capacity(limit);
+    while samples.len() < limit {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(format!("Failed to read file: {}", e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // players skip a damaged packet, so can we
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(format!("Failed to decode file: {}", e)),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend(buffer.samples().chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32));
+    }
+    samples.truncate(limit);
+    Ok((samples, rate))
+}
+
+// Windowed sinc resampling to SAMPLE_RATE, from a table of filter phases
+fn resample(samples: &[f32], rate: u32) -> Vec<f32> {
+    if rate == SAMPLE_RATE || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let step = rate as f64 / SAMPLE_RATE as f64;
+    // stay under the lower of the two Nyquist frequencies
+    let cutoff = (1.0 / step).min(1.0) * 0.9;
+    let radius = (RESAMPLE_TAPS as f64 / cutoff).ceil() as i64;
+    let sinc = |x: f64| if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+    let phases: Vec<Vec<f32>> = (0..RESAMPLE_PHASES)
+        .map(|phase| {
+            let fraction = phase as f64 / RESAMPLE_PHASES as f64;
+            (-radius + 1..=radius)
+                .map(|tap| {
+                    let x = (tap as f64 - fraction) * cutoff;
+                    let window = 0.5 + 0.5 * (PI * x / RESAMPLE_TAPS as f64).cos();
+                    let weight = if x.abs() < RESAMPLE_TAPS as f64 { cutoff * sinc(x) * window } else { 0.0 };
+                    weight as f32
+                })
+                .collect()
+        })
+        .collect();
+
+    let out_len = (samples.len() as f64 / step) as usize;
+    (0..out_len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let center = position.floor() as i64;
+            let phase = (((position - center as f64) * RESAMPLE_PHASES as f64) as usize).min(RESAMPLE_PHASES - 1);
+            phases[phase]
+                .iter()
+                .zip(center - radius + 1..)
+                .filter(|(_, j)| *j >= 0 && (*j as usize) < samples.len())
+                .map(|(weight, j)| weight * samples[j as usize])
+                .sum()
+        })
+        .collect()
+}
+
+fn fingerprint_samples(samples: &[f32]) -> Vec<u32> {
+    let hamming: Vec<f64> = (0..FRAME_SIZE)
+        .map(|i| 0.54 - 0.46 * (2.0 * PI * i as f64 / (FRAME_SIZE - 1) as f64).cos())
+        .collect();
+
+    // the pitch class of each FFT bin in range
+    let bin = |freq: f64| (FRAME_SIZE as f64 * freq / SAMPLE_RATE as f64).round() as usize;
+    let (min_bin, max_bin) = (bin(MIN_FREQ).max(1), bin(MAX_FREQ).min(FRAME_SIZE / 2));
+    let notes: Vec<usize> = (0..max_bin)
+        .map(|i| {
+            let freq = i as f64 * SAMPLE_RATE as f64 / FRAME_SIZE as f64;
+            let octave = (freq / (440.0 / 16.0)).log2();
+            (NOTES as f64 * (octave - octave.floor())) as usize
+        })
+        .collect();
+
+    let fft = FftPlanner::new().plan_fft_forward(FRAME_SIZE);
+    let mut history: Vec<[f64; NOTES]> = Vec::new();
+    let mut image: Vec<[f64; NOTES]> = Vec::new();
+    let mut buffer = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+    let mut start = 0;
+    while start + FRAME_SIZE <= samples.len() {
+        for (i, value) in buffer.iter_mut().enumerate() {
+            *value = Complex::new(samples[start + i] as f64 * hamming[i], 0.0);
+        }
+        fft.process(&mut buffer);
+
+        let mut chroma = [0.0; NOTES];
+        for i in min_bin..max_bin {
+            chroma[notes[i]] += buffer[i].norm_sqr();
+        }
+
+        // smoothed over 5 frames; like chromaprint, the first one never makes it in
+        history.push(chroma);
+        if history.len() > CHROMA_FILTER.len() {
+            let window = &history[history.len() - CHROMA_FILTER.len()..];
+            let mut row = [0.0; NOTES];
+            for (frame, coefficient) in window.iter().zip(CHROMA_FILTER) {
+                for note in 0..NOTES {
+                    row[note] += frame[note] * coefficient;
+                }
+            }
+            let norm = row.iter().map(|v| v * v).sum::<f64>().sqrt();
+            for value in row.iter_mut() {
+                *value = if norm < 0.01 { 0.0 } else { *value / norm };
+            }
+            image.push(row);
+        }
+        start += FRAME_STEP;
+    }
+
+    let integral = IntegralImage::new(&image);
+    (0..(image.len() + 1).saturating_sub(MAX_FILTER_WIDTH))
+        .map(|x| {
+            CLASSIFIERS.iter().fold(0u32, |bits, ((kind, y, height, width), thresholds)| {
+                let value = apply_filter(&integral, *kind, x, *y, *width, *height);
+                let quantized = thresholds.iter().filter(|t| value >= **t).count();
+                // gray code
+                (bits << 2) | [0, 1, 3, 2][quantized]
+            })
+        })
+        .collect()
+}
+
+// sums of any rectangle of the image, rows are frames and columns notes
+struct IntegralImage {
+    sums: Vec<[f64; NOTES + 1]>,
+}
+
+impl IntegralImage {
+    fn new(image: &[[f64; NOTES]]) -> Self {
+        let mut sums = vec![[0.0; NOTES + 1]; image.len() + 1];
+        for (r, row) in image.iter().enumerate() {
+            for c in 0..NOTES {
+                sums[r + 1][c + 1] = row[c] + sums[r][c + 1] + sums[r + 1][c] - sums[r][c];
+            }
+        }
+        IntegralImage { sums }
+    }
+
+    fn area(&self, r1: usize, c1: usize, r2: usize, c2: usize) -> f64 {
+        self.sums[r2][c2] - self.sums[r1][c2] - self.sums[r2][c1] + self.sums[r1][c1]
+    }
+}
+
+fn apply_filter(image: &IntegralImage, kind: u8, x: usize, y: usize, w: usize, h: usize) -> f64 {
+    let area = |x1, y1, x2, y2| image.area(x1, y1, x2, y2);
+    let (a, b) = match kind {
+        0 => (area(x, y, x + w, y + h), 0.0),
+        1 => (area(x, y + h / 2, x + w, y + h), area(x, y, x + w, y + h / 2)),
+        2 => (area(x + w / 2, y, x + w, y + h), area(x, y, x + w / 2, y + h)),
+        3 => (
+            area(x, y + h / 2, x + w / 2, y + h) + area(x + w / 2, y, x + w, y + h / 2),
+            area(x, y, x + w / 2, y + h / 2) + area(x + w / 2, y + h / 2, x + w, y + h),
+        ),
+        4 => (
+            area(x, y + h / 3, x + w, y + 2 * (h / 3)),
+            area(x, y, x + w, y + h / 3) + area(x, y + 2 * (h / 3), x + w, y + h),
+        ),
+        _ => (
+            area(x + w / 3, y, x + 2 * (w / 3), y + h),
+            area(x, y, x + w / 3, y + h) + area(x + 2 * (w / 3), y, x + w, y + h),
+        ),
+    };
+    ((1.0 + a) / (1.0 + b)).ln()
+}
+
+// The compressed form fpcalc prints and AcoustID takes: the XOR of each value
+// with the one before as gaps between set bits, packed in 3 bits with
+// anything larger spilled into a 5-bit list, then URL-safe base64
+pub fn encode_fingerprint(fingerprint: &[u32]) -> String {
+    let mut normal = Vec::new();
+    let mut exceptional = Vec::new();
+    let mut previous = 0;
+    for &value in fingerprint {
+        let (mut x, mut bit, mut last_bit) = (value ^ previous, 1, 0);
+        while x != 0 {
+            if x & 1 != 0 {
+                let gap = bit - last_bit;
+                if gap >= 7 {
+                    normal.push(7);
+                    exceptional.push(gap - 7);
+                } else {
+                    normal.push(gap);
+                }
+                last_bit = bit;
+            }
+            x >>= 1;
+            bit += 1;
+        }
+        normal.push(0);
+        previous = value;
+    }
+
+    let len = fingerprint.len();
+    let mut bytes = vec![ALGORITHM, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+    bytes.extend(pack_bits(&normal, 3));
+    bytes.extend(pack_bits(&exceptional, 5));
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// least significant bit first, across byte boundaries
+fn pack_bits(values: &[u32], width: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity((values.len() * width as usize).div_ceil(8));
+    let (mut acc, mut filled) = (0u32, 0);
+    for value in values {
+        acc |= (value & ((1 << width) - 1)) << filled;
+        filled += width;
+        while filled >= 8 {
+            bytes.push(acc as u8);
+            acc >>= 8;
+            filled -= 8;
+        }
+    }
+    if filled > 0 {
+        bytes.push(acc as u8);
+    }
+    bytes
+}
+
+// stored as a BLOB of little-endian values
+pub fn fingerprint_to_blob(fingerprint: &[u32]) -> Vec<u8> {
+    fingerprint.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub fn fingerprint_from_blob(blob: &[u8]) -> Vec<u32> {
+    blob.chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+// Share of bits that agree where the two line up best. Copies rarely start on
+// the same sample, the offset with the most identical values wins.
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let mut positions: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, value) in a.iter().enumerate() {
+        positions.entry(*value).or_default().push(i);
+    }
+    let mut votes: HashMap<i64, usize> = HashMap::new();
+    for (j, value) in b.iter().enumerate() {
+        for i in positions.get(value).into_iter().flatten() {
+            let offset = *i as i64 - j as i64;
+            if offset.abs() <= MAX_ALIGNMENT {
+                *votes.entry(offset).or_default() += 1;
+            }
+        }
+    }
+    let offset = votes.into_iter().max_by_key(|(offset, count)| (*count, -offset.abs())).map_or(0, |(offset, _)| offset);
+
+    let pairs: Vec<(u32, u32)> = if offset >= 0 {
+        a.iter().skip(offset as usize).copied().zip(b.iter().copied()).collect()
+    } else {
+        a.iter().copied().zip(b.iter().skip(-offset as usize).copied()).collect()
+    };
+    // a short overlap says little, half of the shorter one at least
+    if pairs.is_empty() || pairs.len() * 2 < a.len().min(b.len()) {
+        return 0.0;
+    }
+    let differing: u32 = pairs.iter().map(|(x, y)| (x ^ y).count_ones()).sum();
+    1.0 - differing as f64 / (pairs.len() * 32) as f64
+}
+
+// Pairs of fingerprints, by index, that are the same recording. Only pairs
+// sharing some identical values are compared, values most tracks have (like
+// silence) are left out of that.
+pub fn matching_pairs(fingerprints: &[&[u32]]) -> Vec<(usize, usize, f64)> {
+    const MIN_SHARED: usize = 10;
+    let max_postings = (fingerprints.len() / 10).max(50);
+
+    let mut postings: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, fingerprint) in fingerprints.iter().enumerate() {
+        let mut values = fingerprint.to_vec();
+        values.sort_unstable();
+        values.dedup();
+        for value in values {
+            postings.entry(value).or_default().push(i);
+        }
+    }
+
+    let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+    for holders in postings.values().filter(|holders| holders.len() <= max_postings) {
+        for (n, &a) in holders.iter().enumerate() {
+            for &b in &holders[n + 1..] {
+                *shared.entry((a, b)).or_default() += 1;
+            }
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize, f64)> = shared
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_SHARED)
+        .map(|((a, b), _)| (a, b, similarity(fingerprints[a], fingerprints[b])))
+        .filter(|(_, _, score)| *score >= MIN_SIMILARITY)
+        .collect();
+    pairs.sort_by_key(|(a, b, _)| (*a, *b));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // six seconds of chords at the fingerprint's own rate, so nothing is resampled
+    fn fixture() -> &'static Path {
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/fingerprint.wav"))
+    }
+
+    fn encoded_bytes(fingerprint: &[u32]) -> Vec<u8> {
+        general_purpose::URL_SAFE_NO_PAD.decode(encode_fingerprint(fingerprint)).unwrap()
+    }
+
+    // Chromaprint's own compressor cases, after the algorithm and length header
+    #[test]
+    fn encodes_like_chromaprint() {
+        assert_eq!(encoded_bytes(&[1]), [ALGORITHM, 0, 0, 1, 1]);
+        assert_eq!(encoded_bytes(&[7]), [ALGORITHM, 0, 0, 1, 73, 0]);
+        assert_eq!(encoded_bytes(&[1 << 6]), [ALGORITHM, 0, 0, 1, 7, 0]);
+        assert_eq!(encoded_bytes(&[1 << 8]), [ALGORITHM, 0, 0, 1, 7, 2]);
+        assert_eq!(encoded_bytes(&[1, 0]), [ALGORITHM, 0, 0, 2, 65, 0]);
+        assert_eq!(encoded_bytes(&[1, 1]), [ALGORITHM, 0, 0, 2, 1, 0]);
+    }
+
+    #[test]
+    fn fingerprints_the_fixture() {
+        let fingerprint = compute_fingerprint(fixture()).unwrap();
+
+        assert!(fingerprint.len() > 10, "only {} values", fingerprint.len());
+        assert_eq!(fingerprint_from_blob(&fingerprint_to_blob(&fingerprint)), fingerprint);
+        assert_eq!(similarity(&fingerprint, &fingerprint), 1.0);
+    }
+
+    // The golden values come from Chromaprint itself, run with
+    // `cargo test -- --ignored` where fpcalc is installed
+    #[test]
+    #[ignore = "needs fpcalc on PATH"]
+    fn matches_fpcalc() {
+        let fpcalc = |args: &[&str]| {
+            let output = std::process::Command::new("fpcalc").args(args).arg(fixture()).output().unwrap();
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            stdout
+                .lines()
+                .find_map(|line| line.strip_prefix("FINGERPRINT="))
+                .unwrap()
+                .to_string()
+        };
+        let fingerprint = compute_fingerprint(fixture()).unwrap();
+
+        let raw: Vec<u32> = fpcalc(&["-raw"])
+            .split(',')
+            .map(|value| value.parse::<i64>().unwrap() as u32)
+            .collect();
+        assert_eq!(fingerprint, raw);
+        assert_eq!(encode_fingerprint(&fingerprint), fpcalc(&[]));
+    }
+}
@@ -1,7 +1,14 @@
 use chrono::{Datelike, Local};
 use tauri::Manager;
 
+pub mod acoustid;
+pub mod artwork;
+pub mod chapter_reader;
+pub mod credits;
+pub mod fingerprint;
+pub mod patterns;
 pub mod tag_reader;
+pub mod tag_writer;
 
 
 // TODO: add check for if src path and dest path are identical
@@ -32,6 +39,211 @@ pub async fn move_file_to_dir(
     Ok(dest_path.to_string_lossy().into_owned())
 }
 
+// Strips characters that are illegal in file names on any of the desktop platforms
+pub fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // windows refuses trailing dots and spaces
+    let cleaned = cleaned.trim().trim_end_matches('.').trim_end().to_string();
+    if cleaned.is_empty() {
+        "Untitled".to_string()
+    } else {
+        cleaned
+    }
+}
+
+// Extensions the player can decode, lowercase
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "ogg", "oga", "opus", "m4a", "m4b", "mp4", "aac", "wav", "aif", "aiff",
+];
+
+pub fn is_audio_file(path: &std::path::Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+// Every audio file under `dir`, recursively. Unreadable folders are skipped.
+pub fn collect_audio_files(dir: &std::path::Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => pending.push(path),
+                Ok(t) if t.is_file() && is_audio_file(&path) => {
+                    files.push(path.to_string_lossy().into_owned())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+// Modification time in unix seconds, None where the platform doesn't keep one
+pub fn modified_secs(metadata: &std::fs::Metadata) -> Option<i64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+
+// (mtime, size in bytes) of a file, what library scans compare to spot changes
+pub fn file_stats(path: &std::path::Path) -> Option<(Option<i64>, i64)> {
+    let metadata = path.metadata().ok()?;
+    Some((modified_secs(&metadata), metadata.len() as i64))
+}
+
+// Fingerprint of a file's bytes that survives moves and renames. Only the size
+// and both ends are hashed, tags usually sit there so edits still change it.
+pub fn content_hash(path: &std::path::Path) -> Option<String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const CHUNK: u64 = 64 * 1024;
+    let mut file = std::fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    let mut context = md5::Context::new();
+    context.consume(len.to_le_bytes());
+    let mut buf = Vec::with_capacity(CHUNK as usize);
+    (&mut file).take(CHUNK).read_to_end(&mut buf).ok()?;
+    if len > CHUNK {
+        file.seek(SeekFrom::Start(len.saturating_sub(CHUNK).max(CHUNK))).ok()?;
+        file.take(CHUNK).read_to_end(&mut buf).ok()?;
+    }
+    context.consume(&buf);
+    Some(format!("{:x}", context.compute()))
+}
+
+// Compares secrets in constant time so they can't be guessed byte by byte
+pub fn constant_time_eq(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// MIME type for the audio formats the library accepts, keyed by file extension
+pub fn audio_content_type(format: &str) -> &'static str {
+    match format.to_lowercase().as_str() {
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "m4a" | "m4b" | "mp4" | "aac" | "alac" => "audio/mp4",
+        "wav" => "audio/wav",
+        "aif" | "aiff" => "audio/aiff",
+        "wma" => "audio/x-ms-wma",
+        _ => "application/octet-stream",
+    }
+}
+
+// Parses a single `bytes=start-end` range (including the open-ended and suffix forms)
+// into an inclusive (start, end) pair. Multiple ranges aren't supported and, like an
+// unsatisfiable range, give None so callers can fall back to the whole file.
+pub fn parse_byte_range(header: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len - 1)),
+    };
+
+    (start <= end && start < len).then_some((start, end))
+}
+
+// Streams an open file as an HTTP response, honouring a `Range` header so clients
+// can seek. Shared by the servers that hand out audio files.
+pub async fn ranged_file_response(
+    mut file: tokio::fs::File,
+    content_type: &str,
+    range_header: Option<&str>,
+) -> Result<axum::response::Response, String> {
+    use axum::{
+        body::Body,
+        http::{header, StatusCode},
+        response::IntoResponse,
+    };
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let len = file
+        .metadata()
+        .await
+        .map_err(|e| format!("Failed to read file metadata: {}", e))?
+        .len();
+    let range = range_header.and_then(|v| parse_byte_range(v, len));
+
+    let (status, start, body_len) = match range {
+        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        None => (StatusCode::OK, 0, len),
+    };
+
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|e| format!("Failed to seek file: {}", e))?;
+    let body = Body::from_stream(tokio_util::io::ReaderStream::new(file.take(body_len)));
+
+    let mut response = (
+        status,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_LENGTH, body_len.to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ],
+        body,
+    )
+        .into_response();
+
+    if let Some((start, end)) = range {
+        if let Ok(value) = format!("bytes {}-{}/{}", start, end, len).parse() {
+            response.headers_mut().insert(header::CONTENT_RANGE, value);
+        }
+    }
+
+    Ok(response)
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 pub fn current_date_as_int() -> i64 {
     let now = Local::now();
     (now.year() as i64) * 10000 
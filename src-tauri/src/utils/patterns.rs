@@ -0,0 +1,443 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use regex::Regex;
+use tauri::{AppHandle, State};
+
+use crate::{
+    db::VARIOUS_ARTISTS,
+    editor::current_value,
+    models::{AppState as Database, EditField, ExtractedTrack, NamingPreview, Track},
+    user_config::{self, load_music_dir, NamingConfig},
+    utils::sanitize_file_name,
+};
+
+// what set_naming_config accepts for max_name_length
+const MIN_NAME_LENGTH: usize = 16;
+
+// %ignore% matches text that isn't kept, like a catalogue number, and renders
+// as nothing
+const PLACEHOLDERS: &[(&str, Option<EditField>)] = &[
+    ("title", Some(EditField::Title)),
+    ("artist", Some(EditField::Artist)),
+    ("album", Some(EditField::Album)),
+    ("albumartist", Some(EditField::AlbumArtist)),
+    ("genre", Some(EditField::Genre)),
+    ("composer", Some(EditField::Composer)),
+    ("year", Some(EditField::Year)),
+    ("track", Some(EditField::TrackNumber)),
+    ("tracktotal", Some(EditField::TrackTotal)),
+    ("disc", Some(EditField::DiscNumber)),
+    ("disctotal", Some(EditField::DiscTotal)),
+    ("ignore", None),
+];
+
+enum Part {
+    Text(String),
+    Field(Option<EditField>),
+}
+
+// A pattern like "%artist%/%album%/%track% - %title%", "/" separates folders
+// and "%%" is a percent sign. Paths are matched without their extension and
+// from the end, so a pattern only has to cover the last few folders of one.
+// A field never spans folders.
+pub struct PathPattern {
+    parts: Vec<Part>,
+    regex: Regex,
+    // the field each capture group is
+    groups: Vec<EditField>,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim().replace('\\', "/");
+        let pattern = pattern.trim_matches('/');
+        if pattern.is_empty() {
+            return Err("The pattern is empty".to_string());
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(start) = rest.find('%') {
+            push_text(&mut parts, &rest[..start]);
+            let after = &rest[start + 1..];
+            let end = after
+                .find('%')
+                .ok_or_else(|| format!("Unclosed % in {}", pattern))?;
+            let name = &after[..end];
+            if name.is_empty() {
+                push_text(&mut parts, "%");
+            } else {
+                let field = PLACEHOLDERS
+                    .iter()
+                    .find(|(placeholder, _)| placeholder.eq_ignore_ascii_case(name))
+                    .map(|(_, field)| *field)
+                    .ok_or_else(|| format!("Unknown placeholder %{}%", name))?;
+                parts.push(Part::Field(field));
+            }
+            rest = &after[end + 1..];
+        }
+        push_text(&mut parts, rest);
+        if !parts.iter().any(|part| matches!(part, Part::Field(Some(_)))) {
+            return Err(format!("{} has no placeholders", pattern));
+        }
+
+        let mut expression = String::from("(?:^|/)");
+        let mut groups = Vec::new();
+        for part in &parts {
+            match part {
+                Part::Text(text) => expression.push_str(&regex::escape(text)),
+                Part::Field(None) => expression.push_str("[^/]*?"),
+                Part::Field(Some(field)) => {
+                    expression.push_str(match field {
+                        EditField::Year => r"(\d{4})",
+                        EditField::TrackNumber | EditField::TrackTotal | EditField::DiscNumber | EditField::DiscTotal => {
+                            r"(\d+)"
+                        }
+                        _ => "([^/]+?)",
+                    });
+                    groups.push(*field);
+                }
+            }
+        }
+        expression.push('$');
+        let regex = Regex::new(&expression).map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+
+        Ok(Self { parts, regex, groups })
+    }
+
+    // The fields a path gives, None when it doesn't match. `path` is what
+    // relative_path makes of the file.
+    pub fn read(&self, path: &str) -> Option<Vec<(EditField, String)>> {
+        let captures = self.regex.captures(path)?;
+        let mut values: Vec<(EditField, String)> = Vec::new();
+
+        for (field, capture) in self.groups.iter().zip(captures.iter().skip(1)) {
+            let Some(capture) = capture else {
+                continue;
+            };
+            let value = capture.as_str().trim();
+            // "01" is track 1, a field used twice keeps its first value
+            let value = match value.parse::<u32>() {
+                Ok(n) if *field != EditField::Year => n.to_string(),
+                _ => value.to_string(),
+            };
+            if !value.is_empty() && !values.iter().any(|(seen, _)| seen == field) {
+                values.push((*field, value));
+            }
+        }
+        Some(values)
+    }
+
+    // The path the values give, relative and without an extension. Values
+    // can't add folders, and each folder is tidied once filled in so a missing
+    // year turns "%year% - %album%" into just the album.
+    pub fn render(&self, value: impl Fn(EditField) -> Option<String>) -> String {
+        let mut path = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => path.push_str(text),
+                Part::Field(None) => {}
+                Part::Field(Some(field)) => {
+                    let Some(value) = value(*field) else {
+                        continue;
+                    };
+                    let value = match field {
+                        EditField::TrackNumber => value.parse::<u32>().map(|n| format!("{:02}", n)).unwrap_or(value),
+                        _ => value,
+                    };
+                    path.extend(value.chars().map(|c| if matches!(c, '/' | '\\') { '_' } else { c }));
+                }
+            }
+        }
+        path.split('/').map(tidy_folder).collect::<Vec<_>>().join("/")
+    }
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match parts.last_mut() {
+        Some(Part::Text(last)) => last.push_str(text),
+        _ => parts.push(Part::Text(text.to_string())),
+    }
+}
+
+// what's left of separators and brackets around fields that had no value
+fn tidy_folder(name: &str) -> String {
+    let mut name = name.replace("()", "").replace("[]", "");
+    while name.contains(" -  - ") {
+        name = name.replace(" -  - ", " - ");
+    }
+    let separators = |c: char| c == '-' || c.is_whitespace();
+    // a trailing dot is dropped anyway, what was before it mustn't be left dangling
+    sanitize_file_name(name.trim_matches(separators).trim_end_matches('.').trim_matches(separators))
+}
+
+// `path` the way patterns see it: relative to the music folder, "/" between
+// folders and no extension. A file outside of it only has its name.
+pub fn relative_path(path: &Path, music_dir: Option<&Path>) -> String {
+    let relative = music_dir
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from(path.file_name().unwrap_or_default()));
+    relative.with_extension("").to_string_lossy().replace('\\', "/")
+}
+
+// A track's value for a placeholder when naming its file, with the names the
+// library files untagged tracks under
+pub fn naming_value(track: &Track, field: EditField) -> Option<String> {
+    let value = current_value(track, field).filter(|value| !value.trim().is_empty());
+    match field {
+        EditField::Artist => value.or_else(|| Some("Unknown Artist".to_string())),
+        EditField::Album => value.or_else(|| Some("Unknown Album".to_string())),
+        EditField::AlbumArtist if value.is_none() && track.tags.compilation => Some(VARIOUS_ARTISTS.to_string()),
+        EditField::AlbumArtist => value.or_else(|| naming_value(track, EditField::Artist)),
+        _ => value,
+    }
+}
+
+// The first pattern the file's path matches and what it read
+pub fn read_path(patterns: &[PathPattern], path: &Path, music_dir: Option<&Path>) -> Option<Vec<(EditField, String)>> {
+    let relative = relative_path(path, music_dir);
+    patterns.iter().find_map(|pattern| pattern.read(&relative))
+}
+
+pub fn compile_patterns(patterns: &[String]) -> Result<Vec<PathPattern>, String> {
+    patterns.iter().map(|pattern| PathPattern::parse(pattern)).collect()
+}
+
+struct ImportPatterns {
+    music_dir: Option<PathBuf>,
+    patterns: Vec<PathPattern>,
+}
+
+// Read by the tag reader on every file, which has no AppHandle to load it with.
+// Set from setup() and whenever the patterns or the music folder change.
+static IMPORT: RwLock<Option<ImportPatterns>> = RwLock::new(None);
+
+pub fn use_naming_config(app: &AppHandle) {
+    let config = user_config::load_naming_config(app);
+    let patterns = if config.tags_from_path {
+        // saved patterns were checked by set_naming_config, a hand-edited bad one is skipped
+        config
+            .read_patterns
+            .iter()
+            .filter_map(|pattern| PathPattern::parse(pattern).ok())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let music_dir = load_music_dir(app.clone()).map(|dir| PathBuf::from(dir.trim()));
+
+    *IMPORT.write().unwrap() = Some(ImportPatterns { music_dir, patterns });
+}
+
+// Fills in what a file's tags leave out from its path. Tags the file has
+// always win, so a well tagged file comes out the same.
+pub fn fill_from_path(track: &mut ExtractedTrack) {
+    let import = IMPORT.read().unwrap();
+    let Some(import) = import.as_ref() else {
+        return;
+    };
+    let Some(values) = read_path(&import.patterns, Path::new(&track.file_path), import.music_dir.as_deref()) else {
+        return;
+    };
+
+    let tags = &mut track.tags;
+    for (field, value) in values {
+        let number = value.parse::<i64>().ok();
+        let text = |current: &mut String| {
+            if current.trim().is_empty() {
+                *current = value.clone();
+            }
+        };
+        match field {
+            EditField::Title => text(&mut track.title),
+            EditField::Artist => text(&mut track.artist),
+            EditField::Album => text(&mut track.album),
+            EditField::AlbumArtist => tags.album_artist = tags.album_artist.take().or(Some(value)),
+            EditField::Genre => tags.genre = tags.genre.take().or(Some(value)),
+            EditField::Composer => tags.composer = tags.composer.take().or(Some(value)),
+            EditField::Year => tags.year = tags.year.or(number),
+            EditField::TrackNumber => tags.track_number = tags.track_number.or(number),
+            EditField::TrackTotal => tags.track_total = tags.track_total.or(number),
+            EditField::DiscNumber => tags.disc_number = tags.disc_number.or(number),
+            EditField::DiscTotal => tags.disc_total = tags.disc_total.or(number),
+            _ => {}
+        }
+    }
+
+    // a folder named for the album artist is the best guess at the artist too
+    if track.artist.trim().is_empty() {
+        if let Some(album_artist) = &tags.album_artist {
+            track.artist = album_artist.clone();
+        }
+    }
+}
+
+// Tauri Commands
+#[allow(dead_code)]
+#[tauri::command]
+pub fn get_naming_config(app: AppHandle) -> NamingConfig {
+    user_config::load_naming_config(&app)
+}
+
+// Only files imported from now on are read with new patterns, the library
+// already has tags_from_file_names for the rest
+#[allow(dead_code)]
+#[tauri::command]
+pub fn set_naming_config(app: AppHandle, config: NamingConfig) -> Result<NamingConfig, String> {
+    compile_patterns(&config.read_patterns)?;
+    PathPattern::parse(&config.file_pattern)?;
+    if config.max_name_length < MIN_NAME_LENGTH {
+        return Err(format!("Names can't be cut shorter than {} characters", MIN_NAME_LENGTH));
+    }
+
+    user_config::save_naming_config(&app, &config)?;
+    use_naming_config(&app);
+    Ok(config)
+}
+
+// What a pattern makes of some tracks both ways, for trying one out: the tags
+// read from each path and the path rendered from each track's tags
+#[allow(dead_code)]
+#[tauri::command]
+pub async fn preview_naming_pattern(
+    app: AppHandle,
+    state: State<'_, Database>,
+    pattern: String,
+    track_ids: Vec<i64>,
+) -> Result<Vec<NamingPreview>, String> {
+    let pattern = PathPattern::parse(&pattern)?;
+    let music_dir = load_music_dir(app).map(|dir| PathBuf::from(dir.trim()));
+
+    let mut previews = Vec::new();
+    for track_id in track_ids {
+        let Some(track) = state.get_track(track_id).await? else {
+            continue;
+        };
+        let path = Path::new(&track.file_path);
+        let tags = pattern
+            .read(&relative_path(path, music_dir.as_deref()))
+            .map(|values| values.into_iter().collect::<HashMap<_, _>>());
+        let mut rendered = pattern.render(|field| naming_value(&track, field));
+        if let Some(extension) = path.extension() {
+            rendered = format!("{}.{}", rendered, extension.to_string_lossy());
+        }
+
+        previews.push(NamingPreview {
+            track_id,
+            file_path: track.file_path.clone(),
+            tags,
+            rendered,
+        });
+    }
+    Ok(previews)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track;
+
+    const PATTERN: &str = "%albumartist%/%year% - %album%/%track% - %title%";
+
+    fn values(pairs: &[(EditField, &str)]) -> Vec<(EditField, String)> {
+        pairs.iter().map(|(field, value)| (*field, value.to_string())).collect()
+    }
+
+    #[test]
+    fn reads_a_matching_path() {
+        let pattern = PathPattern::parse(PATTERN).unwrap();
+        assert_eq!(
+            pattern.read("Incoming/Miles Davis/1959 - Kind of Blue/01 - So What"),
+            Some(values(&[
+                (EditField::AlbumArtist, "Miles Davis"),
+                (EditField::Year, "1959"),
+                (EditField::Album, "Kind of Blue"),
+                (EditField::TrackNumber, "1"),
+                (EditField::Title, "So What"),
+            ]))
+        );
+        // no year folder, and a title isn't a track number
+        assert_eq!(pattern.read("Miles Davis/Kind of Blue/01 - So What"), None);
+        assert_eq!(pattern.read("Miles Davis/1959 - Kind of Blue/So What"), None);
+    }
+
+    #[test]
+    fn double_percent_is_a_percent_sign() {
+        let pattern = PathPattern::parse("%artist%/%title% (100%%)").unwrap();
+        assert_eq!(
+            pattern.read("Artist/Song (100%)"),
+            Some(values(&[(EditField::Artist, "Artist"), (EditField::Title, "Song")]))
+        );
+        assert_eq!(pattern.read("Artist/Song (100)"), None);
+        assert_eq!(pattern.render(|_| Some("x".to_string())), "x/x (100%)");
+        assert!(PathPattern::parse("%artist%/%title%%").is_err());
+    }
+
+    #[test]
+    fn renders_without_a_missing_year() {
+        let pattern = PathPattern::parse(PATTERN).unwrap();
+        let value = |field| match field {
+            EditField::AlbumArtist => Some("Miles Davis".to_string()),
+            EditField::Album => Some("Kind of Blue".to_string()),
+            EditField::TrackNumber => Some("1".to_string()),
+            EditField::Title => Some("So What".to_string()),
+            _ => None,
+        };
+        assert_eq!(pattern.render(value), "Miles Davis/Kind of Blue/01 - So What");
+    }
+
+    #[test]
+    fn values_do_not_add_folders() {
+        let pattern = PathPattern::parse(PATTERN).unwrap();
+        let value = |field| match field {
+            EditField::AlbumArtist => Some("AC/DC".to_string()),
+            EditField::Year => Some("1980".to_string()),
+            EditField::Album => Some("Back in Black".to_string()),
+            EditField::TrackNumber => Some("6".to_string()),
+            EditField::Title => Some("Back\\in Black".to_string()),
+            _ => None,
+        };
+        assert_eq!(pattern.render(value), "AC_DC/1980 - Back in Black/06 - Back_in Black");
+        // and a field never reads across one
+        assert_eq!(
+            pattern.read("AC/DC/1980 - Back in Black/06 - Back in Black").unwrap()[0],
+            (EditField::AlbumArtist, "DC".to_string())
+        );
+    }
+
+    #[test]
+    fn path_fills_only_what_tags_leave_out() {
+        let music_dir = PathBuf::from("/patterns-test-music");
+        *IMPORT.write().unwrap() = Some(ImportPatterns {
+            music_dir: Some(music_dir.clone()),
+            patterns: vec![PathPattern::parse(PATTERN).unwrap()],
+        });
+        let path = music_dir.join("Miles Davis/1959 - Kind of Blue/01 - So What.flac");
+
+        let mut tagged = track(&path.to_string_lossy(), "So What (Live)", "Miles Davis Sextet", "");
+        tagged.tags.year = Some(1997);
+        fill_from_path(&mut tagged);
+        assert_eq!(
+            (tagged.title.as_str(), tagged.artist.as_str(), tagged.album.as_str()),
+            ("So What (Live)", "Miles Davis Sextet", "Kind of Blue")
+        );
+        assert_eq!(tagged.tags.year, Some(1997));
+        assert_eq!(tagged.tags.track_number, Some(1));
+        assert_eq!(tagged.tags.album_artist.as_deref(), Some("Miles Davis"));
+
+        let mut untagged = track(&path.to_string_lossy(), "", "", "");
+        fill_from_path(&mut untagged);
+        assert_eq!(
+            (untagged.title.as_str(), untagged.artist.as_str(), untagged.tags.year),
+            ("So What", "Miles Davis", Some(1959))
+        );
+
+        *IMPORT.write().unwrap() = None;
+    }
+}
@@ -1,15 +1,31 @@
 use base64::{engine::general_purpose, Engine as _};
 use chrono::prelude::*;
-use lofty::prelude::{Accessor, AudioFile, TaggedFileExt};
+use lofty::prelude::{Accessor, AudioFile, ItemKey, TaggedFileExt};
 use lofty::probe::Probe;
+use lofty::tag::Tag;
 use std::path::Path;
 
-use crate::models::ExtractedTrack;
+use crate::models::{ExtractedTrack, TrackCredit, TrackTags};
+use crate::utils::{artwork::prepare_artwork, chapter_reader::read_chapters, content_hash, credits::track_credits, modified_secs, patterns::fill_from_path};
 
-const DEFAULT_COVER: &[u8] = include_bytes!("../../../public/default-cover.png");
+// Bump whenever this reads something new, rows read by an older version get
+// their files re-read in the background (see LibraryScanner::backfill_tags)
+pub const TAG_VERSION: i64 = 4;
 
 #[allow(dead_code)]
 pub fn extract_track_metadata(path: &str) -> Result<ExtractedTrack, String> {
+    read_track(path, true)
+}
+
+// Exactly what the file's tags say, for reading a file back after writing them.
+// A field the user just cleared mustn't come back from the path.
+pub fn extract_file_tags(path: &str) -> Result<ExtractedTrack, String> {
+    read_track(path, false)
+}
+
+// `from_path` fills in what the tags leave out from the file's path, see
+// utils::patterns
+fn read_track(path: &str, from_path: bool) -> Result<ExtractedTrack, String> {
     let path = Path::new(&path);
 
     // basic info
@@ -17,24 +33,27 @@ pub fn extract_track_metadata(path: &str) -> Result<ExtractedTrack, String> {
         .metadata()
         .map_err(|e| format!("Cannot read file metadata {}", e))?;
     let file_size_bytes = metadata.len();
+    let file_mtime = modified_secs(&metadata);
     let file_size_mb = file_size_bytes as f64 / 1_048_576.0; // convert to mb
     let file_size_mb = (file_size_mb * 100.0).round() / 100.0;
 
     // probe and read file
     let tagged_file = Probe::open(path)
-        .expect("ERROR: Bad path provided.")
+        .map_err(|e| format!("Bad path provided: {}", e))?
         .read()
-        .expect("ERROR: Failed to read file");
+        .map_err(|e| format!("Failed to read file: {}", e))?;
 
     // get duration
 
     let properties = tagged_file.properties();
     let duration_ms = (properties.duration().as_millis() as i64).max(0);
 
-    let tag = match tagged_file.primary_tag() {
-        Some(primary_tag) => primary_tag,
-        None => tagged_file.first_tag().expect("ERROR: No tags found"),
-    };
+    // an untagged file still imports, its path can stand in for the tags
+    let untagged = Tag::new(tagged_file.primary_tag_type());
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+        .unwrap_or(&untagged);
 
     // get common fields
     let title = tag.title().unwrap_or_default().to_string();
@@ -49,22 +68,24 @@ pub fn extract_track_metadata(path: &str) -> Result<ExtractedTrack, String> {
         .unwrap_or(19700101);
 
     // attempt to get best picture
-    let picture_opt = tag
+    let artwork = tag
         .pictures()
         .iter()
         .find(|p| p.pic_type() == lofty::picture::PictureType::CoverFront)
-        .or_else(|| tag.pictures().first());
-
-    let (thumbnail_base64, thumbnail_mime) = if let Some(pic) = picture_opt {
-        let mime = pic.mime_type().as_ref().map(|m| m.to_string());
-        let base64 = general_purpose::STANDARD.encode(&pic.data());
-        (Some(base64), mime)
-    } else {
-        let base64 = general_purpose::STANDARD.encode(DEFAULT_COVER);
-        (Some(base64), Some("image/png".to_string()))
+        .or_else(|| tag.pictures().first())
+        .and_then(|pic| prepare_artwork(pic.data().to_vec(), pic.mime_type().map(|m| m.to_string())));
+
+    // audiobooks: chapters are optional, a file we can't parse just has none
+    let chapters = read_chapters(path, duration_ms).unwrap_or_default();
+
+    let tags = TrackTags {
+        bitrate: properties.audio_bitrate().map(i64::from),
+        sample_rate: properties.sample_rate().map(i64::from),
+        bit_depth: properties.bit_depth().map(i64::from),
+        ..read_extended_tags(tag)
     };
 
-    Ok(ExtractedTrack {
+    let mut track = ExtractedTrack {
         file_path: path.to_string_lossy().into_owned(),
         title,
         artist,
@@ -73,13 +94,78 @@ pub fn extract_track_metadata(path: &str) -> Result<ExtractedTrack, String> {
         file_format: path.extension().and_then(|e| e.to_str()).map(|s| s.to_lowercase()).unwrap_or_else(|| "unknown".to_string()),
         file_size: file_size_mb as f64,
         date_added: Some(date_added),
-        thumbnail_base64,
-        thumbnail_mime,
-    })
+        thumbnail_base64: None,
+        thumbnail_mime: None,
+        artwork,
+        chapters,
+        source_id: None,
+        file_mtime,
+        file_bytes: Some(file_size_bytes as i64),
+        content_hash: content_hash(path),
+        credits: Vec::new(),
+        tags,
+    };
+    if from_path {
+        fill_from_path(&mut track);
+    }
+    track.credits = read_credits(tag, &track.artist, &track.title);
+
+    Ok(track)
+}
+
+// multi-value tags come back as one item per value
+fn read_credits(tag: &Tag, artist: &str, title: &str) -> Vec<TrackCredit> {
+    let values = |key: &ItemKey| tag.get_strings(key).map(str::to_string).collect::<Vec<_>>();
+
+    let mut artists = vec![artist.to_string()];
+    artists.extend(values(&ItemKey::TrackArtist));
+    artists.extend(values(&ItemKey::TrackArtists));
+
+    track_credits(&artists, title, &values(&ItemKey::Remixer), &values(&ItemKey::Composer))
+}
+
+fn read_extended_tags(tag: &Tag) -> TrackTags {
+    let text = |key: &ItemKey| {
+        tag.get_string(key)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let release_date = text(&ItemKey::RecordingDate).or_else(|| text(&ItemKey::Year));
+    // some formats only keep the full date, the year is its first four digits
+    let year = tag
+        .year()
+        .map(i64::from)
+        .or_else(|| release_date.as_deref()?.get(..4)?.parse().ok());
+
+    TrackTags {
+        track_number: tag.track().map(i64::from),
+        track_total: tag.track_total().map(i64::from),
+        disc_number: tag.disk().map(i64::from),
+        disc_total: tag.disk_total().map(i64::from),
+        year,
+        release_date,
+        genre: tag.genre().map(|genre| genre.trim().to_string()).filter(|genre| !genre.is_empty()),
+        album_artist: text(&ItemKey::AlbumArtist),
+        composer: text(&ItemKey::Composer),
+        bpm: text(&ItemKey::Bpm)
+            .or_else(|| text(&ItemKey::IntegerBpm))
+            .and_then(|bpm| bpm.parse::<f64>().ok())
+            .filter(|bpm| *bpm > 0.0),
+        comment: tag.comment().map(|comment| comment.trim().to_string()).filter(|comment| !comment.is_empty()),
+        compilation: text(&ItemKey::FlagCompilation).is_some_and(|flag| flag != "0"),
+    }
 }
 
 #[allow(dead_code)]
 #[tauri::command]
 pub fn get_track_metadata(path: String) -> Result<ExtractedTrack, String> {
-    extract_track_metadata(&path)
+    let mut track = extract_track_metadata(&path)?;
+    // the upload form previews the picture and sends it back with add_track
+    if let Some(artwork) = &track.artwork {
+        track.thumbnail_base64 = Some(general_purpose::STANDARD.encode(&artwork.data));
+        track.thumbnail_mime = Some(artwork.mime.clone());
+    }
+    Ok(track)
 }
\ No newline at end of file
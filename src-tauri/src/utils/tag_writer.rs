@@ -0,0 +1,121 @@
+use lofty::config::WriteOptions;
+use lofty::prelude::{Accessor, AudioFile, ItemKey, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::Tag;
+use std::path::{Path, PathBuf};
+
+use crate::models::TrackMetadataEdit;
+
+// Writes the edited fields into the file's main tag, creating one if it has
+// none. The tags are saved into a copy next to the file which then replaces
+// it, so a failed write never leaves a half-written file behind.
+pub fn write_tags(path: &Path, edit: &TrackMetadataEdit) -> Result<(), String> {
+    let mut tagged_file = Probe::open(path)
+        .map_err(|e| format!("Bad path provided: {}", e))?
+        .read()
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file
+        .primary_tag_mut()
+        .ok_or_else(|| format!("Cannot write tags to {}", path.display()))?;
+    apply_edit(tag, edit);
+
+    let temp = temp_path(path)?;
+    let result = std::fs::copy(path, &temp)
+        .map_err(|e| format!("Failed to copy {}: {}", path.display(), e))
+        .and_then(|_| {
+            tagged_file
+                .save_to_path(&temp, WriteOptions::default())
+                .map_err(|e| format!("Failed to write tags: {}", e))
+        })
+        .and_then(|_| std::fs::rename(&temp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e)));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+// hidden and not an audio extension, so scans and the watcher leave it alone
+fn temp_path(path: &Path) -> Result<PathBuf, String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Not a file: {}", path.display()))?
+        .to_string_lossy();
+    Ok(path.with_file_name(format!(".{}.tmp", name)))
+}
+
+fn apply_edit(tag: &mut Tag, edit: &TrackMetadataEdit) {
+    if let Some(title) = &edit.title {
+        tag.set_title(title.clone());
+    }
+    match &edit.artist {
+        Some(artist) if artist.is_empty() => tag.remove_artist(),
+        Some(artist) => tag.set_artist(artist.clone()),
+        None => {}
+    }
+    match &edit.album {
+        Some(album) if album.is_empty() => tag.remove_album(),
+        Some(album) => tag.set_album(album.clone()),
+        None => {}
+    }
+
+    match &edit.genre {
+        Some(Some(genre)) => tag.set_genre(genre.clone()),
+        Some(None) => tag.remove_genre(),
+        None => {}
+    }
+    match &edit.comment {
+        Some(Some(comment)) => tag.set_comment(comment.clone()),
+        Some(None) => tag.remove_comment(),
+        None => {}
+    }
+    match edit.year {
+        Some(Some(year)) => tag.set_year(year as u32),
+        Some(None) => {
+            tag.remove_year();
+            set_text(tag, ItemKey::RecordingDate, None);
+        }
+        None => {}
+    }
+
+    let numbers: [(Option<Option<i64>>, fn(&mut Tag, u32), fn(&mut Tag)); 4] = [
+        (edit.track_number, Tag::set_track, Tag::remove_track),
+        (edit.track_total, Tag::set_track_total, Tag::remove_track_total),
+        (edit.disc_number, Tag::set_disk, Tag::remove_disk),
+        (edit.disc_total, Tag::set_disk_total, Tag::remove_disk_total),
+    ];
+    for (value, set, remove) in numbers {
+        match value {
+            Some(Some(n)) => set(tag, n as u32),
+            Some(None) => remove(tag),
+            None => {}
+        }
+    }
+
+    if let Some(album_artist) = &edit.album_artist {
+        set_text(tag, ItemKey::AlbumArtist, album_artist.clone());
+    }
+    if let Some(composer) = &edit.composer {
+        set_text(tag, ItemKey::Composer, composer.clone());
+    }
+    if let Some(bpm) = edit.bpm {
+        // the tag reader falls back to the integer key, it mustn't outlive an edit
+        set_text(tag, ItemKey::IntegerBpm, None);
+        set_text(tag, ItemKey::Bpm, bpm.map(|bpm| bpm.to_string()));
+    }
+    if let Some(compilation) = edit.compilation {
+        set_text(tag, ItemKey::FlagCompilation, compilation.then(|| "1".to_string()));
+    }
+}
+
+fn set_text(tag: &mut Tag, key: ItemKey, value: Option<String>) {
+    tag.retain(|item| *item.key() != key);
+    if let Some(value) = value.filter(|value| !value.trim().is_empty()) {
+        tag.insert_text(key, value);
+    }
+}
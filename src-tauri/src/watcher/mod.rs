@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode,
+};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use crate::{
+    models::{AppState as Database, ExtractedTrack, LibraryChanges},
+    scanner::fingerprints::start_fingerprinting,
+    user_config::load_music_dir,
+    utils::{collect_audio_files, file_stats, is_audio_file, tag_reader::extract_track_metadata},
+};
+
+// files are copied in many small writes, wait for them to settle before reading tags
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+// Keeps the database in step with the music folder while the app runs.
+// Changes made while it wasn't running are picked up by scan_library.
+#[derive(Default)]
+pub struct LibraryWatcher {
+    debouncer: Mutex<Option<Debouncer<RecommendedWatcher, RecommendedCache>>>,
+}
+
+#[allow(dead_code)]
+impl LibraryWatcher {
+    pub fn is_running(&self) -> bool {
+        self.debouncer.lock().unwrap().is_some()
+    }
+
+    pub fn watch(&self, app: AppHandle, db: Database, music_dir: &Path) -> Result<(), String> {
+        self.stop();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut debouncer = new_debouncer(DEBOUNCE, None, move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                let _ = tx.send(events);
+            }
+            Err(errors) => {
+                for e in errors {
+                    log::warn!("File watcher error: {}", e);
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start file watcher: {}", e))?;
+        debouncer
+            .watch(music_dir, RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch {}: {}", music_dir.display(), e))?;
+
+        // ends once the debouncer, and with it the sender, is dropped
+        tauri::async_runtime::spawn(async move {
+            while let Some(events) = rx.recv().await {
+                let changes = apply(&db, events).await;
+                if changes.added + changes.updated + changes.missing + changes.moved > 0 {
+                    if let Err(e) = db.refresh_smart_playlists().await {
+                        log::error!("Failed to refresh smart playlists: {}", e);
+                    }
+                    if changes.added + changes.updated > 0 {
+                        start_fingerprinting(app.clone());
+                    }
+                    let _ = app.emit("library_changed", changes);
+                }
+            }
+        });
+
+        *self.debouncer.lock().unwrap() = Some(debouncer);
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(debouncer) = self.debouncer.lock().unwrap().take() {
+            debouncer.stop_nonblocking();
+        }
+    }
+}
+
+// (Re)starts watching whatever the music folder is set to, from setup() and
+// whenever the user picks a new folder
+pub fn watch_music_dir(app: &AppHandle) {
+    let watcher = app.state::<LibraryWatcher>();
+    let Some(music_dir) = load_music_dir(app.clone()).map(|dir| PathBuf::from(dir.trim())) else {
+        watcher.stop();
+        return;
+    };
+
+    let db = app.state::<Database>().inner().clone();
+    if let Err(e) = watcher.watch(app.clone(), db, &music_dir) {
+        log::error!("{}", e);
+    }
+}
+
+// One failed file shouldn't hold up the rest of the batch, so errors are
+// logged here rather than passed up
+async fn apply(db: &Database, events: Vec<DebouncedEvent>) -> LibraryChanges {
+    let mut changes = LibraryChanges::default();
+
+    for event in events {
+        let paths = &event.paths;
+        let result = match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                renamed(db, &paths[0], &paths[1], &mut changes).await
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+                removed(db, paths, &mut changes).await
+            }
+            // renames only seen from one side land here too, whether the path
+            // still exists tells which side it was
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                appeared(db, paths, &mut changes).await
+            }
+            EventKind::Modify(_) => changed(db, paths, &mut changes).await,
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            log::warn!("Failed to sync {:?}: {}", paths, e);
+        }
+    }
+    changes
+}
+
+async fn appeared(db: &Database, paths: &[PathBuf], changes: &mut LibraryChanges) -> Result<(), String> {
+    for path in paths {
+        if path.is_dir() {
+            let dir = path.clone();
+            let files = tauri::async_runtime::spawn_blocking(move || collect_audio_files(&dir))
+                .await
+                .map_err(|e| e.to_string())?;
+            for file in files {
+                file_appeared(db, Path::new(&file), changes).await?;
+            }
+        } else if path.exists() {
+            file_appeared(db, path, changes).await?;
+        } else {
+            removed(db, std::slice::from_ref(path), changes).await?;
+        }
+    }
+    Ok(())
+}
+
+// plain writes, only files matter here
+async fn changed(db: &Database, paths: &[PathBuf], changes: &mut LibraryChanges) -> Result<(), String> {
+    for path in paths.iter().filter(|path| path.is_file()) {
+        file_appeared(db, path, changes).await?;
+    }
+    Ok(())
+}
+
+async fn removed(db: &Database, paths: &[PathBuf], changes: &mut LibraryChanges) -> Result<(), String> {
+    for path in paths {
+        changes.missing += db.mark_missing(&path.to_string_lossy()).await? as usize;
+    }
+    Ok(())
+}
+
+async fn renamed(db: &Database, from: &Path, to: &Path, changes: &mut LibraryChanges) -> Result<(), String> {
+    if to.is_dir() {
+        changes.moved += db.move_folder(&from.to_string_lossy(), &to.to_string_lossy()).await? as usize;
+        return Ok(());
+    }
+    if !is_audio_file(to) {
+        return removed(db, &[from.to_path_buf()], changes).await;
+    }
+
+    if let Some(known) = db.get_known_file(&from.to_string_lossy()).await? {
+        db.relink_track(known.id, &to.to_string_lossy()).await?;
+        changes.moved += 1;
+    }
+    // picks up files renamed into the library's formats, and edits made on the way
+    file_appeared(db, to, changes).await
+}
+
+// A file is where it should be: add it, re-read it if it changed since it was
+// imported, or relink the missing track it turned out to be
+async fn file_appeared(db: &Database, path: &Path, changes: &mut LibraryChanges) -> Result<(), String> {
+    if !is_audio_file(path) {
+        return Ok(());
+    }
+    let Some((mtime, bytes)) = file_stats(path) else {
+        return Ok(());
+    };
+    let file_path = path.to_string_lossy().into_owned();
+
+    match db.get_known_file(&file_path).await? {
+        Some(known) if known.file_mtime == mtime && known.file_bytes == Some(bytes) => {
+            if known.missing_since.is_some() {
+                db.clear_missing(&[known.id]).await?;
+                changes.moved += 1;
+            }
+        }
+        Some(known) => {
+            let track = read_tags(file_path).await?;
+            db.update_tracks_from_files(vec![(known.id, track)]).await?;
+            changes.updated += 1;
+        }
+        None => match db.find_moved_track(bytes, mtime).await? {
+            Some(id) => {
+                db.relink_track(id, &file_path).await?;
+                changes.moved += 1;
+            }
+            None => {
+                let track = read_tags(file_path).await?;
+                let ids = db.add_tracks(vec![track]).await?;
+                changes.added += ids.iter().filter(|id| **id > 0).count();
+            }
+        },
+    }
+    Ok(())
+}
+
+async fn read_tags(file_path: String) -> Result<ExtractedTrack, String> {
+    tauri::async_runtime::spawn_blocking(move || extract_track_metadata(&file_path))
+        .await
+        .map_err(|e| e.to_string())?
+}